@@ -0,0 +1,41 @@
+//! What the benchmarks share. They are plain programs, run with
+//! `cargo bench`, timing each case a few times and reporting the fastest run.
+
+#![allow(dead_code)]
+
+use std::time::{ Duration, Instant };
+
+use yal::Environment;
+
+/// How many times each case runs.
+const RUNS: usize = 3;
+
+/// An environment with the standard library, printing to nowhere.
+pub fn env() -> Environment {
+    let mut env = Environment::with_std_lib().expect("the standard library registers");
+    env.set_output(Box::new(std::io::sink()));
+    env
+}
+
+/// Times `run` on what `setup` gives, reporting the fastest of a few runs
+/// as `name`. Setting up is not timed.
+pub fn bench<T>(name: &str, mut setup: impl FnMut() -> T, mut run: impl FnMut(T)) -> Duration {
+    let fastest = (0..RUNS)
+        .map(|_| {
+            let input = setup();
+            let start = Instant::now();
+            run(input);
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    println!("{:<48} {:>12.3?}", name, fastest);
+    fastest
+}
+
+/// Evaluates `src` in `env`, which must not fail.
+pub fn eval(env: &mut Environment, src: &str) {
+    if let Err(err) = env.eval_str(src) {
+        panic!("evaluating {:?} failed:\n{}", src, err);
+    }
+}
@@ -0,0 +1,26 @@
+//! Lists built one element at a time with `cons`, which should take time
+//! linear in their length.
+
+mod common;
+
+use common::*;
+
+fn main() {
+    let src = "(let 'build (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons n acc)))))";
+    let mut per_element = Vec::new();
+    for n in [25_000, 50_000, 100_000] {
+        let took = bench(
+            &format!("cons {} elements", n),
+            || {
+                let mut env = env();
+                eval(&mut env, src);
+                env
+            },
+            |mut env| eval(&mut env, &format!("(let 'xs (build {} '()))", n)),
+        );
+        per_element.push(took / n);
+    }
+    for (n, took) in [25_000, 50_000, 100_000].iter().zip(&per_element) {
+        println!("{:<48} {:>12.3?}", format!("per element, {} elements", n), took);
+    }
+}
@@ -0,0 +1,39 @@
+//! Printing many lines, which is buffered rather than flushed line by line.
+
+mod common;
+
+use common::*;
+
+fn main() {
+    // `print` gives nil, so the inner `if` only sequences.
+    let src = "
+        (let 'snd (fn '(a b) 'b))
+        (let 'lines (fn '(n)
+            '(if (= n 0) 'nil '(if (snd (print n) (print \"\\n\")) 'nil '(recur (- n 1))))))";
+    for lines in [10_000, 100_000, 1_000_000] {
+        bench(
+            &format!("print {} lines", lines),
+            || {
+                let mut env = env();
+                eval(&mut env, src);
+                env
+            },
+            |mut env| eval(&mut env, &format!("(lines {})", lines)),
+        );
+    }
+
+    // Through a real file, buffered as standard output is.
+    let path = std::env::temp_dir().join("yal-bench-print");
+    bench(
+        "print 100000 lines to a file",
+        || {
+            let mut env = env();
+            let file = std::fs::File::create(&path).unwrap();
+            env.set_output(Box::new(std::io::BufWriter::new(file)));
+            eval(&mut env, src);
+            env
+        },
+        |mut env| eval(&mut env, "(lines 100000) (flush)"),
+    );
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,34 @@
+//! A quoted list passed down a chain of calls, and taken apart with `car`
+//! and `cdr`. Quoted data is shared rather than copied, so both should cost
+//! the same per call however long the list is. Dropping the list at the end
+//! of each run is timed too, so the cost per call is the difference between
+//! a short chain and a long one.
+
+mod common;
+
+use common::*;
+
+fn main() {
+    let src = "
+        (let 'build (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons n acc)))))
+        (let 'id (fn '(xs) 'xs))
+        (let 'pass (fn '(n xs) '(if (= n 0) 'xs '(pass (- n 1) (id xs)))))
+        (let 'peel (fn '(n xs) '(if (= n 0) '(car xs) '(peel (- n 1) (cdr xs)))))";
+    for len in [100, 100_000] {
+        let setup = || {
+            let mut env = env();
+            eval(&mut env, src);
+            eval(&mut env, &format!("(let 'xs (build {} '()))", len));
+            env
+        };
+        for (what, call, counts) in [("calls", "pass", [10, 1_000]), ("cdrs", "peel", [10, 90])] {
+            let [short, long] = counts.map(|count| {
+                bench(&format!("{} elements through {} {}", len, count, what), setup, |mut env| {
+                    eval(&mut env, &format!("({} {} xs)", call, count))
+                })
+            });
+            let per = long.saturating_sub(short) / (counts[1] - counts[0]);
+            println!("{:<48} {:>12.3?}", format!("per one of the {}", what), per);
+        }
+    }
+}
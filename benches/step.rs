@@ -0,0 +1,45 @@
+//! The cost of the checks every step makes, for the interrupt flag, the
+//! timeout and fuel, on a tight loop that does little else.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use common::*;
+
+const SPIN: &str = "(let 'spin (fn '(n) '(if (= n 0) 'n '(recur (- n 1)))))";
+
+/// Sets one of the limits on an environment.
+type Limit = fn(&mut yal::Environment);
+
+fn main() {
+    let n = 1_000_000;
+    let cases: [(&str, Limit); 4] = [
+        ("no limits", |_| ()),
+        ("interrupt flag", |env| env.set_interrupt_flag(Arc::new(AtomicBool::new(false)))),
+        ("timeout", |env| env.set_timeout(Duration::from_secs(3600))),
+        ("fuel", |env| env.set_fuel(u64::MAX)),
+    ];
+    let mut times = Vec::new();
+    for (name, limit) in cases {
+        let took = bench(
+            &format!("spin {} times, {}", n, name),
+            || {
+                let mut env = env();
+                eval(&mut env, SPIN);
+                limit(&mut env);
+                env
+            },
+            |mut env| eval(&mut env, &format!("(spin {})", n)),
+        );
+        times.push((name, took));
+    }
+
+    let (_, base) = times[0];
+    for (name, took) in &times[1..] {
+        let overhead = took.as_secs_f64() / base.as_secs_f64() - 1.0;
+        println!("{:<48} {:>11.1}%", format!("overhead of the {}", name), overhead * 100.0);
+    }
+}
@@ -0,0 +1,29 @@
+//! A string passed down a chain of calls, which should cost the same per
+//! call however long the string is.
+
+mod common;
+
+use common::*;
+use yal::{ RefVal, Value };
+
+fn main() {
+    let src = "
+        (let 'id (fn '(s) 's))
+        (let 'pass (fn '(n s) '(if (= n 0) 's '(pass (- n 1) (id s)))))";
+    for depth in [100, 200, 400] {
+        for (size, len) in [("16 B", 16), ("1 MB", 1 << 20)] {
+            let took = bench(
+                &format!("{} string through {} calls", size, depth),
+                || {
+                    let mut env = env();
+                    eval(&mut env, src);
+                    let s = "x".repeat(len);
+                    env.define_var("s", RefVal::owned(Value::String(s.into()))).unwrap();
+                    env
+                },
+                |mut env| eval(&mut env, &format!("(pass {} s)", depth)),
+            );
+            println!("{:<48} {:>12.3?}", "per call", took / depth);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! A program heavy on names: binding, looking up and comparing them, with
+//! short names and with long ones. Names are interned, so what is allocated
+//! per iteration shouldn't depend on how long they are.
+
+mod common;
+
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+use common::*;
+
+/// The system allocator, counting what is allocated through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A loop binding `name` and comparing it, as a quoted symbol, `n` times.
+fn program(name: &str) -> String {
+    format!(
+        "(let 'count (fn '({name} n) '(if (= n 0) '{name} '(if (eq '{name} {name}) '(recur {name} (- n 1)) 'nil))))"
+    )
+}
+
+fn main() {
+    let n = 100_000;
+    for (size, len) in [("4 char", 4), ("1 KB", 1 << 10)] {
+        let name = "s".repeat(len);
+        let src = program(&name);
+        let call = format!("(count '{} {})", name, n);
+        bench(
+            &format!("{} names, {} iterations", size, n),
+            || {
+                let mut env = env();
+                eval(&mut env, &src);
+                env
+            },
+            |mut env| eval(&mut env, &call),
+        );
+
+        let mut env = env();
+        eval(&mut env, &src);
+        let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+        eval(&mut env, &call);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        let bytes = BYTES.load(Ordering::Relaxed) - bytes;
+        println!("{:<48} {:>12.1}", "allocations per iteration", allocations as f64 / n as f64);
+        println!("{:<48} {:>12.1}", "bytes allocated per iteration", bytes as f64 / n as f64);
+    }
+}
@@ -0,0 +1,33 @@
+//! A tight arithmetic loop, walked by the evaluator and run on the VM.
+
+mod common;
+
+use common::*;
+
+fn main() {
+    let src = "
+        (let 'sum-to (fn '(n acc)
+            '(if (= n 0) 'acc '(recur (- n 1) (+ acc (* 2 n))))))";
+    for n in [10_000, 100_000, 1_000_000] {
+        let walked = bench(
+            &format!("sum {} terms, tree-walker", n),
+            || {
+                let mut env = env();
+                eval(&mut env, src);
+                env
+            },
+            |mut env| eval(&mut env, &format!("(sum-to {} 0)", n)),
+        );
+        let compiled = bench(
+            &format!("sum {} terms, vm", n),
+            || {
+                let mut env = env();
+                env.set_use_vm(true);
+                eval(&mut env, src);
+                env
+            },
+            |mut env| eval(&mut env, &format!("(sum-to {} 0)", n)),
+        );
+        println!("{:<48} {:>11.2}x", "speedup", walked.as_secs_f64() / compiled.as_secs_f64());
+    }
+}
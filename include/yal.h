@@ -0,0 +1,64 @@
+/* The C interface to yal, built with
+ * `cargo rustc --lib --crate-type cdylib --release --features ffi`, which
+ * makes `target/release/libyal.so`. See `src/ffi.rs` for the details. */
+
+#ifndef YAL_H
+#define YAL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define YAL_OK        0
+#define YAL_ERROR     1
+#define YAL_TRUNCATED 2
+#define YAL_INVALID   3
+#define YAL_PANIC     4
+
+typedef struct YalEnv YalEnv;
+
+typedef enum {
+    YAL_NIL,
+    YAL_INT,
+    YAL_FLOAT,
+    YAL_BOOL,
+    YAL_STRING,
+    /* Any other value, as the text `write` prints for it. Arguments only. */
+    YAL_OTHER,
+} YalTag;
+
+typedef struct {
+    YalTag tag;
+    union {
+        int64_t int_;
+        double float_;
+        bool boolean;
+        const char *string;
+    } data;
+} YalValue;
+
+/* Puts its result in `out`, which starts out nil. Returning anything but
+ * YAL_OK fails the call, with `out` as the message if it is a string. */
+typedef int (*YalNative)(void *user_data, const YalValue *args, size_t argc, YalValue *out);
+
+/* Returns NULL on failure. */
+YalEnv *yal_env_new(void);
+void yal_env_free(YalEnv *env);
+
+/* Writes the value of the last expression in `src` to `out_buf`. */
+int yal_eval_cstr(YalEnv *env, const char *src, char *out_buf, size_t out_len);
+
+int yal_register_native(YalEnv *env, const char *name, size_t arity, YalNative callback, void *user_data);
+
+/* The message of the last failure, or NULL. Valid until the next call. */
+const char *yal_last_error(const YalEnv *env);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,624 @@
+//! Finding what a program defines, where names are used and obvious
+//! mistakes, without running it, for editors, `--symbols`, `--check` and
+//! `--warn-unused`.
+//!
+//! Only the forms that always define something are understood: `let`,
+//! `defconst`, `letfn`, `module`, `deftest` and `defbench` with a quoted
+//! name. Anything
+//! else is skipped, so definitions made by other means, like `eval`, are
+//! missed.
+
+use std::collections::{ HashMap, HashSet };
+
+use crate::ast::*;
+use crate::error;
+use crate::pattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Variable,
+    Function,
+    Module,
+    Test,
+    Bench,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    /// Qualified with the module's name inside a module, like `m/name`.
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// Where the name is, in the defining form.
+    pub span: Span,
+}
+
+/// An identifier anywhere in the program, quoted or not, that isn't the
+/// name of a definition.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+}
+
+/// A mistake found by `check`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+/// The definitions made by the top-level forms `exprs` and the modules they
+/// declare, in order.
+pub fn index_definitions(exprs: &[SExpr]) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    for expr in exprs {
+        define(expr, None, &mut definitions);
+    }
+    definitions
+}
+
+/// Every use of an identifier in `exprs`, in order.
+pub fn index_references(exprs: &[SExpr]) -> Vec<Reference> {
+    let names: HashSet<(usize, usize)> = index_definitions(exprs)
+        .into_iter()
+        .map(|def| (def.span.start, def.span.end))
+        .collect();
+    let is_definition = |span: Span| names.contains(&(span.start, span.end));
+
+    let mut references = Vec::new();
+    let mut pending: Vec<&SExpr> = exprs.iter().rev().collect();
+    while let Some(expr) = pending.pop() {
+        match expr {
+            SExpr::List(list, _) => {
+                let elements: Vec<&SExpr> = list.iter().collect();
+                pending.extend(elements.into_iter().rev());
+            }
+            SExpr::Atom(Atom::Quote(quoted), _) => pending.push(quoted),
+            SExpr::Atom(Atom::Ident(name), span) if span.is_known() && !is_definition(*span) => {
+                references.push(Reference { name: name.to_string(), span: *span });
+            }
+            SExpr::Atom(..) => (),
+        }
+    }
+    references
+}
+
+/// The uses of `name` in `exprs`.
+pub fn find_references(exprs: &[SExpr], name: &str) -> Vec<Reference> {
+    index_references(exprs).into_iter().filter(|reference| reference.name == name).collect()
+}
+
+fn define(expr: &SExpr, module: Option<&str>, definitions: &mut Vec<Definition>) {
+    let Some(list) = expr.as_list() else { return };
+    let list: Vec<&SExpr> = list.iter().collect();
+    let [head, name, args @ ..] = list.as_slice() else { return };
+    let Some(head) = head.as_atom().and_then(Atom::as_ident) else { return };
+    let Some(name) = name.as_atom().and_then(Atom::as_quote) else { return };
+    if head == "let" && name.as_list().is_some() {
+        for name in pattern::bindings(name).unwrap_or_default() {
+            let qualified = match module {
+                Some(module) => format!("{}/{}", module, name),
+                None => name.to_string(),
+            };
+            definitions.push(Definition { name: qualified, kind: DefinitionKind::Variable, span: name.span() });
+        }
+        return;
+    }
+    let Some(ident) = name.as_atom().and_then(Atom::as_ident) else { return };
+
+    let kind = match (head, args) {
+        ("let" | "defconst", [value]) if is_fn(value) => DefinitionKind::Function,
+        ("let" | "defconst", [_]) => DefinitionKind::Variable,
+        ("define-multi", [_]) => DefinitionKind::Function,
+        ("letfn", [_, _]) => DefinitionKind::Function,
+        ("module", [_]) => DefinitionKind::Module,
+        ("deftest", [_]) => DefinitionKind::Test,
+        ("defbench", [_, _]) => DefinitionKind::Bench,
+        _ => return,
+    };
+
+    let qualified = match module {
+        Some(module) if kind != DefinitionKind::Module => format!("{}/{}", module, ident),
+        _ => ident.to_string(),
+    };
+    definitions.push(Definition { name: qualified, kind, span: name.span() });
+
+    if kind == DefinitionKind::Module {
+        let forms = args[0].as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+        for form in forms.into_iter().flatten() {
+            define(form, Some(ident), definitions);
+        }
+    }
+}
+
+/// Whether `expr` is a call to `fn`.
+fn is_fn(expr: &SExpr) -> bool {
+    expr.as_list()
+        .and_then(|list| list.front())
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_ident)
+        == Some("fn")
+}
+
+/// Looks for mistakes in `exprs` that are certain without running them:
+/// `fn` argument lists that aren't lists of names, `let`, `defconst` and
+/// `letfn` with a name that is a literal, and calls with the wrong number of arguments to
+/// the functions in `arities`, usually the builtins. Calls to names the
+/// program defines itself, or that are arguments of the enclosing
+/// function, aren't checked.
+///
+/// Quoted code is only looked into where it is sure to be code: function
+/// bodies, loop bodies, the branches of `if` and the forms of a module or test.
+pub fn check(exprs: &[SExpr], arities: &HashMap<String, usize>) -> Vec<Diagnostic> {
+    let mut checker = Checker { arities, shadowed: Vec::new(), diagnostics: Vec::new() };
+    checker.shadowed.extend(
+        index_definitions(exprs)
+            .into_iter()
+            .map(|def| def.name.rsplit('/').next().unwrap_or(&def.name).to_string()),
+    );
+    for expr in exprs {
+        checker.expr(expr);
+    }
+    checker.diagnostics
+}
+
+struct Checker<'a> {
+    arities: &'a HashMap<String, usize>,
+    /// Names that don't refer to the functions in `arities` here.
+    shadowed: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic { message, span });
+    }
+
+    fn expr(&mut self, expr: &SExpr) {
+        let Some(list) = expr.as_list() else { return };
+        let list: Vec<&SExpr> = list.iter().collect();
+        let Some((head, args)) = list.split_first() else { return };
+
+        match head.as_atom().and_then(Atom::as_ident) {
+            Some(name) if self.shadowed.iter().any(|shadowed| shadowed == name) => (),
+            Some(name) => self.call(name, head.span(), args),
+            None => self.expr(head),
+        }
+        for arg in args {
+            self.expr(arg);
+        }
+    }
+
+    fn call(&mut self, name: &str, span: Span, args: &[&SExpr]) {
+        // `recur` takes as many values as its loop binds.
+        if let Some(&arity) = self.arities.get(name).filter(|_| name != "recur") {
+            if arity != args.len() {
+                let plural = if arity == 1 { "" } else { "s" };
+                self.report(span, format!("'{}' takes {} argument{}, got {}", name, arity, plural, args.len()));
+                return;
+            }
+        }
+
+        match (name, args) {
+            ("let", [SExpr::Atom(Atom::Quote(pattern), _), _]) if pattern.as_list().is_some() => {
+                if let Err(err) = pattern::check(pattern, &mut Vec::new()) {
+                    self.report(err.span().unwrap_or(span), err.root().to_string());
+                }
+            }
+            ("let", [name, _]) => self.name(name, "let"),
+            ("defconst", [name, _]) => self.name(name, "defconst"),
+            ("define-multi", [name, _]) => self.name(name, "define-multi"),
+            ("fn", [params, body]) => self.function(params, body),
+            ("letfn", [name, params, body]) => {
+                self.name(name, "letfn");
+                self.function(params, body);
+            }
+            ("if", [_, then, otherwise]) => {
+                self.quoted(then);
+                self.quoted(otherwise);
+            }
+            ("loop", [_, body]) => self.quoted(body),
+            ("match", [_, clauses]) => {
+                let clauses = clauses.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                for clause in clauses.into_iter().flatten() {
+                    if let Some(clause) = clause.as_list().filter(|clause| clause.len() == 2) {
+                        self.expr(&clause[1]);
+                    }
+                }
+            }
+            ("unwind-protect", [body, cleanup]) => {
+                self.quoted(body);
+                self.quoted(cleanup);
+            }
+            ("deftest", [_, body]) => self.quoted(body),
+            ("module", [_, forms]) => {
+                let forms = forms.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                for form in forms.into_iter().flatten() {
+                    self.expr(form);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Checks the name given to `form`, which is computed unless it is a
+    /// literal.
+    fn name(&mut self, name: &SExpr, form: &str) {
+        let literal = match name {
+            SExpr::Atom(Atom::Quote(quoted), _) => quoted.as_atom().and_then(Atom::as_ident).is_none(),
+            SExpr::Atom(Atom::Ident(_), _) | SExpr::List(..) => false,
+            SExpr::Atom(..) => true,
+        };
+        if literal {
+            self.report(name.span(), format!("'{}' expects a quoted name, got `{}`", form, name));
+        }
+    }
+
+    /// Checks the argument list and body of a function, if they are quoted.
+    fn function(&mut self, params: &SExpr, body: &SExpr) {
+        let names = match params {
+            SExpr::Atom(Atom::Quote(quoted), _) => match quoted.as_list() {
+                Some(list) => {
+                    // A docstring may lead the argument list.
+                    let skip = list.front().is_some_and(|first| matches!(first, SExpr::Atom(Atom::String(_), _)));
+                    let mut names = Vec::new();
+                    let mut keys = false;
+                    for param in list.iter().skip(skip as usize) {
+                        if !keys && param.as_atom().and_then(Atom::as_ident) == Some("&key") {
+                            keys = true;
+                            continue;
+                        }
+
+                        // Keyword arguments may come with a default, and the
+                        // others may be patterns.
+                        let name = match param.as_list() {
+                            Some(pair) if keys && pair.len() == 2 => {
+                                self.expr(&pair[1]);
+                                &pair[0]
+                            }
+                            Some(_) if !keys => {
+                                let mut bound = Vec::new();
+                                match pattern::check(param, &mut bound) {
+                                    Ok(()) => names.extend(bound.into_iter().map(str::to_string)),
+                                    Err(err) => self.report(err.span().unwrap_or(param.span()), err.root().to_string()),
+                                }
+                                continue;
+                            }
+                            _ => param,
+                        };
+                        match name.as_atom().and_then(Atom::as_ident) {
+                            Some(name) => names.push(name.to_string()),
+                            None => self.report(param.span(), format!("expected an argument name, got `{}`", param)),
+                        }
+                    }
+                    names
+                }
+                None => {
+                    self.report(params.span(), format!("expected a quoted argument list, got `{}`", params));
+                    Vec::new()
+                }
+            },
+            SExpr::Atom(Atom::Ident(_), _) | SExpr::List(..) => Vec::new(),
+            SExpr::Atom(..) => {
+                self.report(params.span(), format!("expected a quoted argument list, got `{}`", params));
+                Vec::new()
+            }
+        };
+
+        let outer = self.shadowed.len();
+        self.shadowed.extend(names);
+        self.quoted(body);
+        self.shadowed.truncate(outer);
+    }
+
+    /// Checks quoted code, if `expr` is a quote.
+    fn quoted(&mut self, expr: &SExpr) {
+        if let Some(quoted) = expr.as_atom().and_then(Atom::as_quote) {
+            self.expr(quoted);
+        }
+    }
+}
+
+/// Finds the names nothing refers to: those bound with `let`, `defconst`,
+/// `define-multi` and `loop`, and the arguments of functions. Names starting
+/// with `_` and the definitions of modules are left out.
+///
+/// References are only looked for in code: unquoted expressions, and the
+/// quotes that `check` looks into, along with the bindings of `loop` and
+/// what `eval` and `trace` are given. Names in other quotes are data.
+/// `let`, `defconst` and `define-multi` bind globals wherever they run,
+/// in function bodies too. A global is used if it is referred to anywhere,
+/// before its definition too, while other names are only used by what
+/// follows them in their scope, the innermost one binding them.
+pub fn unused(exprs: &[SExpr]) -> Vec<Diagnostic> {
+    let mut lint = Lint::new(None);
+    for expr in exprs {
+        lint.code(expr);
+    }
+
+    let globals = std::mem::take(&mut lint.globals);
+    for global in globals {
+        if !global.used && !lint.referenced.contains(&global.name) {
+            lint.report(&global);
+        }
+    }
+    lint.unused.sort_by_key(|diagnostic| diagnostic.span.start);
+    lint.unused
+}
+
+/// Finds the references in code, as `unused` sees them, to names that
+/// aren't bound there: neither by an enclosing scope before them, nor by
+/// the program as a global, later and in function bodies too, nor in
+/// `builtins`.
+/// Names a module defines count both qualified and not, for `import`.
+/// Keywords are never undefined. Names bound by other means, like `eval` or
+/// the code calling a function, are missed.
+pub fn undefined(exprs: &[SExpr], builtins: &HashSet<String>) -> Vec<Diagnostic> {
+    let mut known = builtins.clone();
+    for def in index_definitions(exprs) {
+        known.extend(def.name.rsplit_once('/').map(|(_, name)| name.to_string()));
+        known.insert(def.name);
+    }
+    known.extend(globals(exprs));
+
+    let mut lint = Lint::new(Some(&known));
+    for expr in exprs {
+        lint.code(expr);
+    }
+    lint.undefined
+}
+
+/// The names `exprs` bind as globals with `let`, `defconst` and
+/// `define-multi`, wherever they are, in function bodies too. Those in
+/// modules are left out, `index_definitions` has them.
+pub fn globals(exprs: &[SExpr]) -> HashSet<String> {
+    let mut lint = Lint::new(None);
+    for expr in exprs {
+        lint.code(expr);
+    }
+    lint.globals.into_iter().map(|global| global.name).collect()
+}
+
+struct Binding {
+    name: String,
+    /// What the name is, in the message.
+    what: &'static str,
+    span: Span,
+    used: bool,
+}
+
+impl Binding {
+    /// The binding of `name`, quoted or not, if it is an identifier.
+    fn new(name: &SExpr, what: &'static str) -> Option<Binding> {
+        let name = quoted_ident(name).unwrap_or(name);
+        let ident = name.as_atom().and_then(Atom::as_ident)?;
+        Some(Binding { name: ident.to_string(), what, span: name.span(), used: ident.starts_with('_') })
+    }
+}
+
+/// Walks code keeping track of the scopes, for `unused` and `undefined`.
+struct Lint<'a> {
+    /// The names bound by the functions and loops being walked, innermost
+    /// last, each in the order they were bound.
+    scopes: Vec<Vec<Binding>>,
+    globals: Vec<Binding>,
+    /// The names referred to that aren't bound by an enclosing scope.
+    referenced: HashSet<String>,
+    /// The names bound outside of any scope, if looking for undefined ones.
+    known: Option<&'a HashSet<String>>,
+    in_module: bool,
+    unused: Vec<Diagnostic>,
+    undefined: Vec<Diagnostic>,
+}
+
+impl<'a> Lint<'a> {
+    fn new(known: Option<&'a HashSet<String>>) -> Self {
+        Lint {
+            scopes: Vec::new(),
+            globals: Vec::new(),
+            referenced: HashSet::new(),
+            known,
+            in_module: false,
+            unused: Vec::new(),
+            undefined: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, binding: &Binding) {
+        let message = format!("{} '{}' is never used", binding.what, binding.name);
+        self.unused.push(Diagnostic { message, span: binding.span });
+    }
+
+    fn code(&mut self, expr: &SExpr) {
+        let list = match expr {
+            SExpr::Atom(Atom::Ident(name), span) => return self.reference(name, *span),
+            SExpr::Atom(..) => return,
+            SExpr::List(list, _) => list,
+        };
+        let list: Vec<&SExpr> = list.iter().collect();
+        let Some((head, args)) = list.split_first() else { return };
+        self.code(head);
+
+        match (head.as_atom().and_then(Atom::as_ident), args) {
+            (Some("let" | "defconst"), [name, value]) if quoted_ident(name).is_some() => {
+                self.code(value);
+                self.bind_global(name, "variable");
+            }
+            (Some("let"), [SExpr::Atom(Atom::Quote(pattern), _), value]) if pattern.as_list().is_some() => {
+                self.code(value);
+                for name in pattern::bindings(pattern).unwrap_or_default() {
+                    self.bind_global(name, "variable");
+                }
+            }
+            (Some("fn"), [params, body]) => match params.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list) {
+                Some(params) => {
+                    self.scopes.push(Vec::new());
+                    self.params(params.iter());
+                    self.quoted(body);
+                    self.pop_scope();
+                }
+                None => args.iter().for_each(|arg| self.code(arg)),
+            },
+            (Some("loop"), [bindings, body]) => {
+                self.scopes.push(Vec::new());
+                let bindings = bindings.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                for binding in bindings.into_iter().flatten() {
+                    match binding.as_list() {
+                        Some(pair) if pair.len() == 2 => {
+                            self.code(&pair[1]);
+                            self.bind(&pair[0], "loop variable");
+                        }
+                        _ => self.code(binding),
+                    }
+                }
+                self.quoted(body);
+                self.pop_scope();
+            }
+            (Some("define-multi"), [name, clauses]) if quoted_ident(name).is_some() => {
+                let clauses = clauses.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                for clause in clauses.into_iter().flatten() {
+                    match clause.as_list() {
+                        Some(clause) if clause.len() == 2 => {
+                            self.scopes.push(Vec::new());
+                            self.params(clause[0].as_list().into_iter().flatten());
+                            self.code(&clause[1]);
+                            self.pop_scope();
+                        }
+                        _ => self.code(clause),
+                    }
+                }
+                self.bind_global(name, "function");
+            }
+            (Some("match"), [value, clauses]) => {
+                self.code(value);
+                let clauses = clauses.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                for clause in clauses.into_iter().flatten() {
+                    match clause.as_list() {
+                        Some(clause) if clause.len() == 2 => {
+                            self.scopes.push(Vec::new());
+                            self.pattern(&clause[0], "pattern variable");
+                            self.code(&clause[1]);
+                            self.pop_scope();
+                        }
+                        _ => self.code(clause),
+                    }
+                }
+            }
+            (Some("if"), [cond, then, otherwise]) => {
+                self.code(cond);
+                self.quoted(then);
+                self.quoted(otherwise);
+            }
+            (Some("module"), [_, forms]) => {
+                let forms = forms.as_atom().and_then(Atom::as_quote).and_then(SExpr::as_list);
+                let outer = std::mem::replace(&mut self.in_module, true);
+                for form in forms.into_iter().flatten() {
+                    self.code(form);
+                }
+                self.in_module = outer;
+            }
+            (Some("deftest"), [_, body]) => self.quoted(body),
+            (Some("unwind-protect"), [body, cleanup]) => {
+                self.quoted(body);
+                self.quoted(cleanup);
+            }
+            (Some("defbench"), [_, iterations, body]) => {
+                self.code(iterations);
+                self.quoted(body);
+            }
+            (Some("eval" | "trace" | "delay"), [code]) => self.quoted(code),
+            _ => args.iter().for_each(|arg| self.code(arg)),
+        }
+    }
+
+    /// Walks `expr` as code if it is quoted, like the bodies of functions.
+    fn quoted(&mut self, expr: &SExpr) {
+        match expr.as_atom().and_then(Atom::as_quote) {
+            Some(quoted) => self.code(quoted),
+            None => self.code(expr),
+        }
+    }
+
+    /// Binds the arguments of a function in the innermost scope, those of
+    /// its patterns included, along with the defaults of its keyword
+    /// arguments.
+    fn params<'e>(&mut self, params: impl Iterator<Item = &'e SExpr>) {
+        let mut keys = false;
+        for param in params {
+            match param {
+                SExpr::List(pair, _) if keys && pair.len() == 2 => {
+                    self.code(&pair[1]);
+                    self.bind(&pair[0], "parameter");
+                }
+                SExpr::List(..) => self.pattern(param, "parameter"),
+                SExpr::Atom(Atom::Ident(name), _) if &**name == "&key" => keys = true,
+                SExpr::Atom(Atom::Ident(name), _) if &**name == "&" => (),
+                _ => self.bind(param, "parameter"),
+            }
+        }
+    }
+
+    /// Binds the names a pattern binds in the innermost scope.
+    fn pattern(&mut self, pattern: &SExpr, what: &'static str) {
+        for name in pattern::bindings(pattern).unwrap_or_default() {
+            self.bind(name, what);
+        }
+    }
+
+    /// Binds the name, quoted or not, in the innermost scope.
+    fn bind(&mut self, name: &SExpr, what: &'static str) {
+        let Some(binding) = Binding::new(name, what) else { return };
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push(binding),
+            None if self.in_module => (),
+            None => self.globals.push(binding),
+        }
+    }
+
+    /// Binds the name, quoted or not, as a global whatever the scope.
+    fn bind_global(&mut self, name: &SExpr, what: &'static str) {
+        let Some(binding) = Binding::new(name, what) else { return };
+        if !self.in_module {
+            self.globals.push(binding);
+        }
+    }
+
+    fn reference(&mut self, name: &str, span: Span) {
+        let bound = self.scopes.iter_mut().rev().find_map(|scope| scope.iter_mut().rev().find(|binding| binding.name == name));
+        if let Some(binding) = bound {
+            binding.used = true;
+            return;
+        }
+
+        self.referenced.insert(name.to_string());
+        let Some(known) = self.known else { return };
+        if known.contains(name) || name.starts_with(':') {
+            return;
+        }
+
+        let mut message = format!("name '{}' is not defined", name);
+        let bound = self.scopes.iter().flatten().map(|binding| &binding.name);
+        let closest = known
+            .iter()
+            .chain(bound)
+            .filter_map(|candidate| Some((error::near_miss(name, candidate)?, candidate)))
+            .min();
+        if let Some((_, candidate)) = closest {
+            message.push_str(&format!("; did you mean '{}'?", candidate));
+        }
+        self.undefined.push(Diagnostic { message, span });
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if !binding.used {
+                self.report(&binding);
+            }
+        }
+    }
+}
+
+/// The identifier quoted by `expr`, if it is a quoted identifier.
+fn quoted_ident(expr: &SExpr) -> Option<&SExpr> {
+    expr.as_atom().and_then(Atom::as_quote).filter(|quoted| quoted.as_atom().and_then(Atom::as_ident).is_some())
+}
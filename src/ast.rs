@@ -1,51 +1,255 @@
+//! Expressions and the values they evaluate to. `SExpr` is code as read,
+//! `Value` what evaluating it gives, and `RefVal` how values are passed
+//! around, owned or shared.
+
 use std::rc::Rc;
 use std::borrow::{ ToOwned, Borrow };
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::ops::Deref;
-use std::collections::VecDeque;
+use std::sync::Arc;
 
+use crate::bigint::BigInt;
 use crate::evaluator::Environment;
+use crate::error::RuntimeError;
+use crate::channel::Channel;
+use crate::net::{ Connection, Listener };
+use crate::symbol::Symbol;
+
+pub use crate::list::List;
+use crate::list::Nested;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Atom {
-    String(String),
-    Number(f64),
-    Quote(Box<SExpr>),
-    Ident(String),
+    String(Rc<str>),
+    Int(i64),
+    /// An int that doesn't fit in an `i64`, see `Atom::big_int`.
+    BigInt(Rc<BigInt>),
+    /// A fraction in lowest terms, with a denominator above 1, see
+    /// `Atom::rational`.
+    Rational(i64, i64),
+    Float(f64),
+    Quote(Rc<SExpr>),
+    Ident(Symbol),
 }
 
+/// Every expression carries the span of source it was read from.
 #[derive(Debug, Clone, PartialEq)]
 pub enum SExpr {
-    List(VecDeque<SExpr>),
-    Atom(Atom),
+    List(List<SExpr>, Span),
+    Atom(Atom, Span),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum List {
-    Cons(Box<SExpr>, Box<SExpr>),
-    Nil,
+/// A byte range in the source code. Expressions built at runtime have an
+/// empty span, meaning they don't come from anywhere in the source.
+///
+/// Spans are only informative: two expressions that differ in nothing but
+/// their spans are equal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.start < self.end
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
 }
 
+/// Values are freed by counting references. Functions defined in yal don't
+/// capture the scopes they are made in, they look names up when called, and
+/// nothing but promises is mutated once shared, so only a native closure
+/// holding a value can lead back to itself: through a promise whose value
+/// is the closure. `Environment::collect_garbage` breaks those cycles.
+/// Promises keep their value once forced, which is never a promise, and
+/// they can't be put in data.
 #[derive(Debug)]
 pub enum Value {
-    String(String),
-    Number(f64),
-    Quote(SExpr),
+    String(Rc<str>),
+    Int(i64),
+    /// An int that doesn't fit in an `i64`, so that every int has a single
+    /// form. See `Value::big_int`.
+    BigInt(Rc<BigInt>),
+    /// An exact fraction, in lowest terms and with a denominator above 1, so
+    /// that every number has a single form. See `Value::rational`.
+    Rational(i64, i64),
+    Float(f64),
+    Bool(bool),
+    Nil,
+    Quote(Rc<SExpr>),
     Function(Function),
+    Handle(Handle),
+    Promise(Rc<Promise>),
+}
+
+/// A value made by `delay`, whose expression is evaluated the first time it
+/// is forced, after which it stands for the value it gave. Promises are only
+/// equal to themselves.
+#[derive(Debug)]
+pub struct Promise(pub RefCell<PromiseState>);
+
+#[derive(Debug)]
+pub enum PromiseState {
+    Delayed(Rc<SExpr>),
+    /// Being forced, so forcing it again would never end.
+    Forcing,
+    /// The value, and the expression it came from, to evaluate again if the
+    /// value is dropped by `Environment::collect_garbage`.
+    Forced(RefVal, Rc<SExpr>),
+}
+
+impl Promise {
+    pub fn new(expr: Rc<SExpr>) -> Promise {
+        Promise(RefCell::new(PromiseState::Delayed(expr)))
+    }
+
+    pub fn is_forced(&self) -> bool {
+        matches!(*self.0.borrow(), PromiseState::Forced(..))
+    }
+
+    /// Drops the value of the promise, if it was forced, so that forcing it
+    /// again evaluates its expression again. Whether it had a value.
+    pub fn unforce(&self) -> bool {
+        let mut state = self.0.borrow_mut();
+        let PromiseState::Forced(_, expr) = &*state else { return false };
+        *state = PromiseState::Delayed(expr.clone());
+        true
+    }
+}
+
+/// The signature of functions implemented in Rust. They take their arguments
+/// from the environment's stack.
+pub type LibFn = fn(&mut Environment) -> Result<RefVal, RuntimeError>;
+
+/// The code of a `Function::Lib`: a `LibFn`, or a closure, which can keep
+/// state of its own between calls.
+pub type NativeFn = Rc<dyn Fn(&mut Environment) -> Result<RefVal, RuntimeError>>;
+
+/// What is known of a function implemented in Rust besides its code, kept by
+/// the environment it is registered in for `help`, `--check` and the passes
+/// that run code ahead of time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinSpec {
+    pub name: &'static str,
+    pub arity: usize,
+    /// Whether it takes any number of arguments past `arity`.
+    pub variadic: bool,
+    pub doc: Option<&'static str>,
+    /// Whether calling it does nothing but compute its result from its
+    /// arguments, so it can be run before the program is.
+    pub pure: bool,
+    /// What it is for, like "lists" or "network", to group it by.
+    pub category: &'static str,
+}
+
+impl BuiltinSpec {
+    /// An impure builtin without documentation, of the "native" category,
+    /// that of the functions the program embedding yal adds.
+    pub fn new(name: &'static str, arity: usize) -> BuiltinSpec {
+        BuiltinSpec { name, arity, variadic: false, doc: None, pure: false, category: "native" }
+    }
+
+    /// Makes it take `arity` arguments or more, as many as
+    /// `Environment::arg_count` says.
+    pub fn variadic(self) -> BuiltinSpec {
+        BuiltinSpec { variadic: true, ..self }
+    }
+
+    pub fn doc(self, doc: &'static str) -> BuiltinSpec {
+        BuiltinSpec { doc: Some(doc), ..self }
+    }
+
+    pub fn pure(self) -> BuiltinSpec {
+        BuiltinSpec { pure: true, ..self }
+    }
+
+    pub fn category(self, category: &'static str) -> BuiltinSpec {
+        BuiltinSpec { category, ..self }
+    }
 }
 
 #[derive(Clone)]
 pub enum Function {
     UserDefined {
-        arg_names: Vec<String>,
-        body: SExpr,
+        /// Set when the function is first bound to a name.
+        name: Option<Symbol>,
+        /// The module the function was defined in, whose names its body sees.
+        module: Option<Symbol>,
+        arg_names: Vec<Symbol>,
+        /// The arguments given as list patterns, by the name they are bound
+        /// to, which `pattern::argument` makes, and their pattern. They are
+        /// destructured on each call.
+        patterns: Vec<(Symbol, SExpr)>,
+        /// The code giving the last `defaults.len()` arguments their values
+        /// when they aren't passed. Those are the keyword arguments, passed
+        /// as `:name value` after the others.
+        defaults: Vec<SExpr>,
+        body: Rc<SExpr>,
+        /// The string leading the argument list, if any.
+        doc: Option<Rc<str>>,
     },
     Lib {
         name: &'static str,
-        ptr: fn(&mut Environment) -> Result<RefVal, String>,
+        ptr: NativeFn,
         arity: usize,
+        variadic: bool,
+        doc: Option<&'static str>,
+    },
+    /// A function with a clause for each number of arguments it takes, made
+    /// by `define-multi`.
+    MultiArity {
+        name: Option<Symbol>,
+        module: Option<Symbol>,
+        /// The clauses taking a fixed number of arguments, by that number.
+        clauses: BTreeMap<usize, Clause>,
+        /// The clause taking the arguments past its own as a list, for the
+        /// numbers no other clause takes that are enough for it.
+        rest: Option<Clause>,
     },
 }
 
+/// A clause of a `Function::MultiArity`.
+#[derive(Debug, Clone)]
+pub struct Clause {
+    /// The names of the arguments, the last one taking the rest of them if
+    /// the clause is variadic.
+    pub params: Vec<Symbol>,
+    /// The arguments given as list patterns, as in `Function::UserDefined`.
+    pub patterns: Vec<(Symbol, SExpr)>,
+    pub variadic: bool,
+    pub body: Rc<SExpr>,
+}
+
+impl Clause {
+    /// The number of arguments the clause takes, the least of them if it is
+    /// variadic.
+    pub fn arity(&self) -> usize {
+        self.params.len() - self.variadic as usize
+    }
+}
+
+/// A value standing for something outside the interpreter. Handles are only
+/// equal to themselves.
+#[derive(Clone)]
+pub enum Handle {
+    /// See `net`.
+    Connection(Rc<RefCell<Connection>>),
+    Listener(Rc<RefCell<Listener>>),
+    /// See `channel`. Channels can be shared with other threads.
+    Channel(Arc<Channel>),
+}
+
 #[derive(Debug, Clone)]
 pub struct BoxedVal(Rc<Value>);
 
@@ -55,8 +259,43 @@ pub enum RefVal {
     Owned(BoxedVal),
 }
 
+/// Reduces the fraction `num/den` to lowest terms with a positive
+/// denominator, which is 1 if it is whole. `None` if `den` is zero or the
+/// result doesn't fit.
+pub fn reduce_fraction(num: i128, den: i128) -> Option<(i64, i64)> {
+    match den {
+        0 => return None,
+        // Ints come up the most, and need no reducing.
+        1 => return Some((i64::try_from(num).ok()?, 1)),
+        _ => (),
+    }
+    let (mut a, mut b) = (num.unsigned_abs(), den.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let divisor = a as i128 * den.signum();
+    Some((i64::try_from(num / divisor).ok()?, i64::try_from(den / divisor).ok()?))
+}
+
 impl Atom {
-    pub fn as_quote(&self) -> Option<&Box<SExpr>> {
+    /// The number `num/den`: a rational, or an int if it is whole. `None`
+    /// if `den` is zero or the result doesn't fit.
+    pub fn rational(num: i128, den: i128) -> Option<Atom> {
+        match reduce_fraction(num, den)? {
+            (num, 1) => Some(Atom::Int(num)),
+            (num, den) => Some(Atom::Rational(num, den)),
+        }
+    }
+
+    /// The int `n`, big only if it doesn't fit in an `i64`.
+    pub fn big_int(n: BigInt) -> Atom {
+        match n.to_i64() {
+            Some(n) => Atom::Int(n),
+            None => Atom::BigInt(Rc::new(n)),
+        }
+    }
+
+    pub fn as_quote(&self) -> Option<&SExpr> {
         if let Self::Quote(v) = self {
             Some(v)
         } else {
@@ -64,7 +303,29 @@ impl Atom {
         }
     }
 
-    pub fn as_ident(&self) -> Option<&String> {
+    pub fn as_int(&self) -> Option<i64> {
+        if let Self::Int(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_ident(&self) -> Option<&str> {
+        if let Self::Ident(v) = self {
+            Some(&**v)
+        } else {
+            None
+        }
+    }
+
+    /// The name of a keyword, `width` for `:width`. Keywords are identifiers
+    /// starting with a colon, which evaluate to themselves.
+    pub fn as_keyword(&self) -> Option<&str> {
+        self.as_ident()?.strip_prefix(':').filter(|name| !name.is_empty())
+    }
+
+    pub fn as_symbol(&self) -> Option<&Symbol> {
         if let Self::Ident(v) = self {
             Some(v)
         } else {
@@ -72,7 +333,7 @@ impl Atom {
         }
     }
 
-    pub fn try_into_ident(self) -> Result<String, Self> {
+    pub fn try_into_ident(self) -> Result<Symbol, Self> {
         if let Self::Ident(v) = self {
             Ok(v)
         } else {
@@ -81,9 +342,64 @@ impl Atom {
     }
 }
 
+impl Nested for SExpr {
+    fn take_lists(&mut self, lists: &mut Vec<List<SExpr>>) {
+        // Quotes of quotes are followed in a loop, as the list may be deep
+        // inside them.
+        let mut expr = self;
+        loop {
+            match expr {
+                SExpr::List(list, _) => return lists.push(std::mem::take(list)),
+                SExpr::Atom(Atom::Quote(quoted), _) => match Rc::get_mut(quoted) {
+                    Some(inner) => expr = inner,
+                    None => return,
+                },
+                SExpr::Atom(..) => return,
+            }
+        }
+    }
+}
+
 impl SExpr {
-    pub fn as_list(&self) -> Option<&VecDeque<SExpr>> {
-        if let Self::List(v) = self {
+    /// Like `Value::size`, for the expression as quoted data.
+    pub fn size(&self) -> usize {
+        let mut size = 0;
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            size += match expr {
+                SExpr::List(list, _) => {
+                    pending.extend(list);
+                    1
+                }
+                SExpr::Atom(Atom::String(s), _) => s.len(),
+                SExpr::Atom(Atom::Quote(q), _) => {
+                    pending.push(q);
+                    1
+                }
+                SExpr::Atom(..) => 1,
+            };
+        }
+        size
+    }
+
+    /// A list with no source location.
+    pub fn list(list: List<SExpr>) -> SExpr {
+        SExpr::List(list, Span::default())
+    }
+
+    /// An atom with no source location.
+    pub fn atom(atom: Atom) -> SExpr {
+        SExpr::Atom(atom, Span::default())
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            SExpr::List(_, span) | SExpr::Atom(_, span) => *span,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List<SExpr>> {
+        if let Self::List(v, _) = self {
             Some(v)
         } else {
             None
@@ -91,7 +407,7 @@ impl SExpr {
     }
 
     pub fn as_atom(&self) -> Option<&Atom> {
-        if let Self::Atom(v) = self {
+        if let Self::Atom(v, _) = self {
             Some(v)
         } else {
             None
@@ -113,13 +429,64 @@ impl Value {
 
         match self {
             String(_)   => "string",
-            Number(_)   => "number",
+            Int(_) | Value::BigInt(_) => "int",
+            Rational(..) => "rational",
+            Float(_)    => "float",
+            Bool(_)     => "bool",
+            Nil         => "nil",
             Quote(_)    => "quote",
             Function(_) => "function",
+            Handle(handle) => handle.get_type(),
+            Promise(_)  => "promise",
+        }
+    }
+
+    /// Roughly how much memory the value takes: a byte per character of a
+    /// string, a unit per nine digits of a big int and a unit per element of
+    /// a quoted list, counting nested ones.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::String(s) => s.len(),
+            Value::BigInt(n) => n.size(),
+            Value::Quote(q) => q.size(),
+            _ => 1,
         }
     }
 
-    pub fn as_string(&self) -> Option<&String> {
+    /// Whether the value counts as true in a condition. `f`, `nil` and the
+    /// empty list are false, whether they were produced by a builtin or
+    /// quoted in the source, everything else is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Nil => false,
+            Value::Quote(quote) => match &**quote {
+                SExpr::Atom(Atom::Ident(name), _) => !matches!(&**name, "f" | "false" | "nil"),
+                SExpr::List(list, _) => !list.is_empty(),
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+
+    /// The number `num/den`: a rational, or an int if it is whole. `None`
+    /// if `den` is zero or the result doesn't fit.
+    pub fn rational(num: i128, den: i128) -> Option<Value> {
+        match reduce_fraction(num, den)? {
+            (num, 1) => Some(Value::Int(num)),
+            (num, den) => Some(Value::Rational(num, den)),
+        }
+    }
+
+    /// The int `n`, big only if it doesn't fit in an `i64`.
+    pub fn big_int(n: BigInt) -> Value {
+        match n.to_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::BigInt(Rc::new(n)),
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
         if let Self::String(v) = self {
             Some(v)
         } else {
@@ -128,6 +495,73 @@ impl Value {
     }
 }
 
+impl Handle {
+    pub fn get_type(&self) -> &'static str {
+        match self {
+            Handle::Connection(_) => "connection",
+            Handle::Listener(_) => "listener",
+            Handle::Channel(_) => "channel",
+        }
+    }
+
+    /// Whether both are the same handle, not merely alike.
+    pub fn ptr_eq(&self, other: &Handle) -> bool {
+        match (self, other) {
+            (Handle::Connection(lhs), Handle::Connection(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Handle::Listener(lhs), Handle::Listener(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Handle::Channel(lhs), Handle::Channel(rhs)) => lhs.same(rhs),
+            _ => false,
+        }
+    }
+}
+
+/// Turns a runtime value into the code that evaluates back to it:
+///
+/// - strings and numbers become the matching literal atoms;
+/// - `t`, `f` and `nil` become the identifiers bound to them;
+/// - a quote becomes the quoted expression itself, unwrapping one level;
+/// - functions and handles have no source form and are an error.
+///
+/// This is what lets a list built out of evaluated pieces be passed to
+/// `eval` and behave exactly as if the reader had produced it.
+pub fn value_to_sexpr(value: &Value, symbols: &crate::symbol::SymbolTable) -> Result<SExpr, RuntimeError> {
+    let atom = match value {
+        Value::String(s) => Atom::String(s.clone()),
+        Value::Int(n) => Atom::Int(*n),
+        Value::BigInt(n) => Atom::BigInt(n.clone()),
+        Value::Rational(num, den) => Atom::Rational(*num, *den),
+        Value::Float(n) => Atom::Float(*n),
+        Value::Bool(true) => Atom::Ident(symbols.intern("t")),
+        Value::Bool(false) => Atom::Ident(symbols.intern("f")),
+        Value::Nil => Atom::Ident(symbols.intern("nil")),
+        Value::Quote(q) => return Ok((**q).clone()),
+        Value::Function(fun) => {
+            return Err(RuntimeError::type_mismatch("data", fun, "conversion to code"))
+        }
+        Value::Handle(handle) => {
+            return Err(RuntimeError::type_mismatch("data", handle, "conversion to code"))
+        }
+        Value::Promise(promise) => {
+            return Err(RuntimeError::type_mismatch("data", promise, "conversion to code"))
+        }
+    };
+    Ok(SExpr::atom(atom))
+}
+
+/// The inverse of `value_to_sexpr`, turning an element of quoted data into a
+/// value. Literals are self-evaluating so they become plain values, anything
+/// else (symbols, lists and quote forms) stays quoted.
+pub fn sexpr_to_value(expr: &SExpr) -> Value {
+    match expr {
+        SExpr::Atom(Atom::String(s), _) => Value::String(s.clone()),
+        SExpr::Atom(Atom::Int(n), _) => Value::Int(*n),
+        SExpr::Atom(Atom::BigInt(n), _) => Value::BigInt(n.clone()),
+        SExpr::Atom(Atom::Rational(num, den), _) => Value::Rational(*num, *den),
+        SExpr::Atom(Atom::Float(n), _) => Value::Float(*n),
+        expr => Value::Quote(Rc::new(expr.clone())),
+    }
+}
+
 impl ToOwned for Value {
     type Owned = BoxedVal;
 
@@ -136,9 +570,16 @@ impl ToOwned for Value {
 
         match self {
             String(s) => BoxedVal::new(String(s.clone())),
-            Number(n) => BoxedVal::new(Number(n.clone())),
+            Int(n)    => BoxedVal::new(Int(*n)),
+            Value::BigInt(n) => BoxedVal::new(Value::BigInt(n.clone())),
+            Rational(num, den) => BoxedVal::new(Rational(*num, *den)),
+            Float(n)  => BoxedVal::new(Float(*n)),
+            Bool(b)   => BoxedVal::new(Bool(*b)),
+            Nil       => BoxedVal::new(Nil),
             Quote(q)  => BoxedVal::new(Quote(q.clone())),
             Function(f) => BoxedVal::new(Function(f.clone())),
+            Handle(h) => BoxedVal::new(Handle(h.clone())),
+            Promise(p) => BoxedVal::new(Promise(p.clone())),
         }
     }
 }
@@ -183,6 +624,32 @@ impl RefVal {
     pub fn get_type(&self) -> &'static str {
         self.deref().get_type()
     }
+
+    /// Mutable access to the value, if nothing else refers to it.
+    pub fn get_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            RefVal::Owned(BoxedVal(rc)) => Rc::get_mut(rc),
+            RefVal::Borrowed(_) => None,
+        }
+    }
+
+    /// Takes the quoted expression out of the value, without copying it when
+    /// this is the only reference to the value. Callers can then modify it
+    /// in place with `Rc::make_mut`, which only copies if it is still shared.
+    pub fn into_quote(self) -> Result<Rc<SExpr>, RefVal> {
+        match self {
+            RefVal::Owned(BoxedVal(rc)) => match Rc::try_unwrap(rc) {
+                Ok(Value::Quote(quote)) => Ok(quote),
+                Ok(other) => Err(RefVal::owned(other)),
+                Err(rc) => match &*rc {
+                    Value::Quote(quote) => Ok(quote.clone()),
+                    _ => Err(RefVal::Owned(BoxedVal(rc))),
+                },
+            },
+            RefVal::Borrowed(Value::Quote(quote)) => Ok(quote.clone()),
+            RefVal::Borrowed(_) => Err(self),
+        }
+    }
 }
 
 impl Deref for RefVal {
@@ -197,86 +664,91 @@ impl Deref for RefVal {
 }
 
 impl Function {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Function::UserDefined { name, .. } | Function::MultiArity { name, .. } => name.as_deref(),
+            Function::Lib { name, .. } => Some(name),
+        }
+    }
+
+    /// The number of arguments the function takes, the least of them if it
+    /// takes several.
     pub fn arity(&self) -> usize {
         use Function::*;
 
         match self {
-            UserDefined { arg_names, .. } => arg_names.len(),
+            UserDefined { arg_names, defaults, .. } => arg_names.len() - defaults.len(),
             Lib { arity, .. } => *arity,
+            MultiArity { clauses, rest, .. } => {
+                clauses.keys().copied().chain(rest.as_ref().map(Clause::arity)).min().unwrap_or(0)
+            }
         }
     }
-}
-
-use std::fmt::{ self, Debug, Display, Formatter };
 
-impl Display for Value {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        use Value::*;
+    /// Whether the function can be called with `argc` arguments.
+    pub fn takes(&self, argc: usize) -> bool {
         match self {
-            String(s)     => Display::fmt(s, f),
-            Number(n)     => Display::fmt(n, f),
-            Quote(q)      => write!(f, "'{}", q),
-            Function(fun) => Display::fmt(fun, f),
+            Function::MultiArity { .. } => self.clause(argc).is_some(),
+            Function::UserDefined { defaults, .. } if !defaults.is_empty() => argc >= self.arity(),
+            Function::Lib { arity, variadic: true, .. } => argc >= *arity,
+            _ => self.arity() == argc,
         }
     }
-}
 
-impl Display for SExpr {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    /// The clause of a `Function::MultiArity` taking `argc` arguments.
+    pub fn clause(&self, argc: usize) -> Option<&Clause> {
         match self {
-            SExpr::Atom(atom) => Display::fmt(atom, f),
-            SExpr::List(list) => {
-                if list.len() == 0 {
-                    write!(f, "()")
-                } else {
-                    write!(f, "({}", list[0])?;
-                    for el in list.iter().skip(1) {
-                        write!(f, " {}", el)?;
-                    }
-                    write!(f, ")")
-                }
-            }
+            Function::MultiArity { clauses, rest, .. } => clauses
+                .get(&argc)
+                .or_else(|| rest.as_ref().filter(|rest| rest.arity() <= argc)),
+            _ => None,
         }
     }
-}
-
-impl Display for Atom {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        use Atom::*;
 
-        match self {
-            String(s) => Display::fmt(s, f),
-            Number(n) => Display::fmt(n, f),
-            Quote(q)  => write!(f, "'{}", q),
-            Ident(i)  => Display::fmt(i, f),
+    /// The numbers of arguments the function takes, as in `1, 2 or at
+    /// least 4`.
+    pub fn arities(&self) -> String {
+        let (clauses, rest) = match self {
+            Function::MultiArity { clauses, rest, .. } => (clauses, rest),
+            Function::UserDefined { defaults, .. } if !defaults.is_empty() => {
+                return format!("{} plus keyword", self.arity());
+            }
+            Function::Lib { arity, variadic: true, .. } => return format!("at least {}", arity),
+            _ => return self.arity().to_string(),
+        };
+
+        // The fixed clauses past the variadic one's arity are among the
+        // numbers it takes.
+        let least = rest.as_ref().map_or(usize::MAX, Clause::arity);
+        let mut arities: Vec<String> = clauses.keys().filter(|&&arity| arity < least).map(usize::to_string).collect();
+        arities.extend(rest.as_ref().map(|rest| format!("at least {}", rest.arity())));
+        match arities.split_last() {
+            Some((last, init)) if !init.is_empty() => format!("{} or {}", init.join(", "), last),
+            _ => arities.concat(),
         }
     }
-}
 
-impl Debug for Function {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        use Function::*;
+    /// `arities` and the word "argument", as in `1 argument` or `1 or 2
+    /// arguments`.
+    pub fn arguments(&self) -> String {
+        arguments(&self.arities())
+    }
 
+    pub fn doc(&self) -> Option<&str> {
         match self {
-            UserDefined { arg_names, .. } => {
-                write!(f, "user function with {} arguments", arg_names.len())
-            }
-
-            Lib { name, arity, .. } => {
-                write!(f, "lib function '{}' with {} arguments", name, arity)
-            }
+            Function::UserDefined { doc, .. } => doc.as_deref(),
+            Function::Lib { doc, .. } => *doc,
+            Function::MultiArity { .. } => None,
         }
     }
 }
 
-impl Display for Function {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        Debug::fmt(self, f)
-    }
-}
-
-impl Display for RefVal {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        Display::fmt(self.deref(), f)
+/// A number of arguments, as `Function::arities` gives them, and the word
+/// "argument", singular when the last number is 1.
+pub(crate) fn arguments(arities: &str) -> String {
+    if arities == "1" || arities.ends_with(" 1") {
+        format!("{} argument", arities)
+    } else {
+        format!("{} arguments", arities)
     }
 }
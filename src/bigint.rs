@@ -0,0 +1,238 @@
+//! Integers past the range of an `i64`, which ints are promoted to when
+//! arithmetic overflows in `IntOverflow::Promote` mode.
+//!
+//! Only what the standard library needs is here: adding, subtracting,
+//! multiplying, negating, comparing, and reading and writing in decimal. The
+//! digits are kept in base 10^9, so writing one out needs no division.
+
+use std::cmp::Ordering;
+use std::fmt::{ self, Display, Formatter };
+use std::str::FromStr;
+
+/// The base of the limbs, the largest power of ten that fits in a `u32`.
+const BASE: u64 = 1_000_000_000;
+
+/// An integer of any size, as a sign and the limbs of its magnitude, least
+/// significant first and without leading zero limbs. Zero has no limbs and
+/// is never negative, so equal numbers have equal representations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// How many limbs, of nine digits each, the number takes.
+    pub fn size(&self) -> usize {
+        self.limbs.len()
+    }
+
+    /// The number as an `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+        for &limb in self.limbs.iter().rev() {
+            magnitude = magnitude.checked_mul(BASE as i128)? + limb as i128;
+            if magnitude > 1 << 63 {
+                return None;
+            }
+        }
+        i64::try_from(if self.negative { -magnitude } else { magnitude }).ok()
+    }
+
+    /// The float nearest the number, or nearly.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.limbs.iter().rev().fold(0.0, |acc, &limb| acc * BASE as f64 + limb as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// The integer a float stands for, if it is whole.
+    pub fn from_f64(x: f64) -> Option<BigInt> {
+        if !x.is_finite() || x.fract() != 0.0 {
+            return None;
+        }
+        if x.abs() < 9223372036854775808.0 {
+            return Some(BigInt::from(x as i64));
+        }
+        // Past 2^63 it is the 53 bits of its mantissa times a power of two.
+        let bits = x.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32 - 1075;
+        let mut n = BigInt::from(((bits & ((1 << 52) - 1)) | (1 << 52)) as i64);
+        let doubling = BigInt::from(2i64);
+        for _ in 0..exponent {
+            n = &n * &doubling;
+        }
+        Some(if x < 0.0 { -&n } else { n })
+    }
+
+    /// The float equal to the number, if there is one.
+    pub fn to_exact_f64(&self) -> Option<f64> {
+        let x = self.to_f64();
+        (BigInt::from_f64(x).as_ref() == Some(self)).then_some(x)
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt { negative: false, limbs: self.limbs.clone() }
+    }
+
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt { negative: negative && !limbs.is_empty(), limbs }
+    }
+}
+
+/// Compares two magnitudes.
+fn cmp_limbs(lhs: &[u32], rhs: &[u32]) -> Ordering {
+    lhs.len().cmp(&rhs.len()).then_with(|| lhs.iter().rev().cmp(rhs.iter().rev()))
+}
+
+fn add_limbs(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(lhs.len().max(rhs.len()) + 1);
+    let mut carry = 0;
+    for i in 0..lhs.len().max(rhs.len()) {
+        let digit = *lhs.get(i).unwrap_or(&0) as u64 + *rhs.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((digit % BASE) as u32);
+        carry = digit / BASE;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `lhs - rhs`, where `lhs` is the larger magnitude.
+fn sub_limbs(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(lhs.len());
+    let mut borrow = 0;
+    for (i, &limb) in lhs.iter().enumerate() {
+        let take = *rhs.get(i).unwrap_or(&0) as i64 + borrow;
+        let digit = limb as i64 - take;
+        borrow = (digit < 0) as i64;
+        difference.push((digit + borrow * BASE as i64) as u32);
+    }
+    difference
+}
+
+impl From<i128> for BigInt {
+    fn from(n: i128) -> BigInt {
+        let mut magnitude = n.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE as u128) as u32);
+            magnitude /= BASE as u128;
+        }
+        BigInt::from_parts(n < 0, limbs)
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        BigInt::from(n as i128)
+    }
+}
+
+impl std::ops::Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+}
+
+impl std::ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(self.negative, add_limbs(&self.limbs, &rhs.limbs));
+        }
+        match cmp_limbs(&self.limbs, &rhs.limbs) {
+            Ordering::Less => BigInt::from_parts(rhs.negative, sub_limbs(&rhs.limbs, &self.limbs)),
+            _ => BigInt::from_parts(self.negative, sub_limbs(&self.limbs, &rhs.limbs)),
+        }
+    }
+}
+
+impl std::ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl std::ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        let mut product = vec![0u64; self.limbs.len() + rhs.limbs.len()];
+        for (i, &lhs) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &rhs) in rhs.limbs.iter().enumerate() {
+                let digit = product[i + j] + lhs as u64 * rhs as u64 + carry;
+                product[i + j] = digit % BASE;
+                carry = digit / BASE;
+            }
+            product[i + rhs.limbs.len()] += carry;
+        }
+        BigInt::from_parts(self.negative != rhs.negative, product.into_iter().map(|limb| limb as u32).collect())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_limbs(&self.limbs, &other.limbs),
+            (true, true) => cmp_limbs(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else { return write!(f, "0") };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", last)?;
+        rest.iter().rev().try_for_each(|limb| write!(f, "{:09}", limb))
+    }
+}
+
+/// The number isn't an optional sign followed by decimal digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(text: &str) -> Result<BigInt, ParseBigIntError> {
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let limbs = digits
+            .as_bytes()
+            .rchunks(9)
+            .map(|chunk| chunk.iter().fold(0, |limb, digit| limb * 10 + (digit - b'0') as u32))
+            .collect();
+        Ok(BigInt::from_parts(negative, limbs))
+    }
+}
@@ -0,0 +1,218 @@
+//! A compact binary form of parsed programs, so that big programs don't have
+//! to be parsed again every time they are run. See `--compile-cache`.
+//!
+//! A cache file starts with a magic number, a version, a hash of the source
+//! it was made from and a hash of the rest of the file. Then come the number
+//! of top-level expressions and the expressions themselves, each a tag byte
+//! followed by its span and contents. Integers are LEB128 encoded, except for the
+//! payloads of `Int` and `Float`, which are 8 bytes little endian.
+
+use std::collections::VecDeque;
+use std::path::{ Path, PathBuf };
+use std::{ fs, io };
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::symbol::SymbolTable;
+
+const MAGIC: &[u8; 4] = b"YALC";
+const VERSION: u8 = 2;
+
+/// Deeper expressions than this are taken for a corrupt file.
+const MAX_DEPTH: usize = 10_000;
+
+const TAG_LIST: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_IDENT: u8 = 4;
+const TAG_QUOTE: u8 = 5;
+const TAG_RATIONAL: u8 = 6;
+const TAG_BIG_INT: u8 = 7;
+
+/// Where the cache of the program in `file` goes.
+pub fn cache_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".bin");
+    PathBuf::from(path)
+}
+
+/// Reads the expressions cached in `path`, if the cache was made from `src`.
+/// Missing, stale and corrupt caches all give `None`.
+pub fn load(path: &Path, src: &str, symbols: &SymbolTable) -> Option<VecDeque<SExpr>> {
+    let bytes = fs::read(path).ok()?;
+    let mut decoder = Decoder { bytes: &bytes, symbols };
+
+    if decoder.take(MAGIC.len())? != MAGIC || decoder.byte()? != VERSION {
+        return None;
+    }
+    if decoder.take(8)? != hash(src.as_bytes()).to_le_bytes() {
+        return None;
+    }
+    if decoder.take(8)? != hash(decoder.bytes).to_le_bytes() {
+        return None;
+    }
+
+    let count = decoder.uint()?;
+    let mut exprs = VecDeque::new();
+    for _ in 0..count {
+        exprs.push_back(decoder.expr(0)?);
+    }
+    decoder.bytes.is_empty().then_some(exprs)
+}
+
+/// Writes the expressions parsed from `src` to a cache at `path`.
+pub fn store(path: &Path, src: &str, exprs: &VecDeque<SExpr>) -> io::Result<()> {
+    let mut body = Vec::new();
+    write_uint(&mut body, exprs.len() as u64);
+    for expr in exprs {
+        write_expr(&mut body, expr);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&hash(src.as_bytes()).to_le_bytes());
+    out.extend_from_slice(&hash(&body).to_le_bytes());
+    out.extend_from_slice(&body);
+    fs::write(path, out)
+}
+
+/// FNV-1a, which unlike the standard hasher is the same across builds.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn write_uint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_uint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_expr(out: &mut Vec<u8>, expr: &SExpr) {
+    let tag = match expr {
+        SExpr::List(..) => TAG_LIST,
+        SExpr::Atom(Atom::Int(_), _) => TAG_INT,
+        SExpr::Atom(Atom::BigInt(_), _) => TAG_BIG_INT,
+        SExpr::Atom(Atom::Rational(..), _) => TAG_RATIONAL,
+        SExpr::Atom(Atom::Float(_), _) => TAG_FLOAT,
+        SExpr::Atom(Atom::String(_), _) => TAG_STRING,
+        SExpr::Atom(Atom::Ident(_), _) => TAG_IDENT,
+        SExpr::Atom(Atom::Quote(_), _) => TAG_QUOTE,
+    };
+    out.push(tag);
+
+    let span = expr.span();
+    write_uint(out, span.start as u64);
+    write_uint(out, span.end as u64);
+
+    match expr {
+        SExpr::List(list, _) => {
+            write_uint(out, list.len() as u64);
+            for el in list {
+                write_expr(out, el);
+            }
+        }
+        SExpr::Atom(Atom::Int(n), _) => out.extend_from_slice(&n.to_le_bytes()),
+        SExpr::Atom(Atom::BigInt(n), _) => write_str(out, &n.to_string()),
+        SExpr::Atom(Atom::Rational(num, den), _) => {
+            out.extend_from_slice(&num.to_le_bytes());
+            out.extend_from_slice(&den.to_le_bytes());
+        }
+        SExpr::Atom(Atom::Float(n), _) => out.extend_from_slice(&n.to_le_bytes()),
+        SExpr::Atom(Atom::String(s), _) => write_str(out, s),
+        SExpr::Atom(Atom::Ident(name), _) => write_str(out, name),
+        SExpr::Atom(Atom::Quote(quoted), _) => write_expr(out, quoted),
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn uint(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(n);
+            }
+        }
+        None
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        usize::try_from(self.uint()?).ok()
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn expr(&mut self, depth: usize) -> Option<SExpr> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let tag = self.byte()?;
+        let span = Span::new(self.len()?, self.len()?);
+
+        let atom = match tag {
+            TAG_LIST => {
+                let len = self.len()?;
+                // Every element takes at least three bytes.
+                let mut list = Vec::with_capacity(len.min(self.bytes.len() / 3));
+                for _ in 0..len {
+                    list.push(self.expr(depth + 1)?);
+                }
+                return Some(SExpr::List(list.into(), span));
+            }
+            TAG_INT => Atom::Int(i64::from_le_bytes(self.array()?)),
+            TAG_BIG_INT => Atom::big_int(self.str()?.parse().ok()?),
+            TAG_RATIONAL => {
+                let num = i64::from_le_bytes(self.array()?);
+                let den = i64::from_le_bytes(self.array()?);
+                Atom::rational(num.into(), den.into())?
+            }
+            TAG_FLOAT => Atom::Float(f64::from_le_bytes(self.array()?)),
+            TAG_STRING => Atom::String(self.str()?.into()),
+            TAG_IDENT => Atom::Ident(self.symbols.intern(self.str()?)),
+            TAG_QUOTE => Atom::Quote(Rc::new(self.expr(depth + 1)?)),
+            _ => return None,
+        };
+        Some(SExpr::Atom(atom, span))
+    }
+}
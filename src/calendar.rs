@@ -0,0 +1,177 @@
+//! Dates and times of the proleptic Gregorian calendar, in UTC, for
+//! `time-now`, `time-format` and `time-parse`. Times are milliseconds since
+//! the Unix epoch, and every `i64` of them has a date, far past year 9999 and
+//! before year 0.
+
+/// A point in time, down to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    /// From 1 to 12.
+    pub month: u32,
+    /// From 1 to the number of days in the month.
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+impl DateTime {
+    /// The date and time `millis` milliseconds after the epoch, rounded
+    /// down to the second.
+    pub fn from_millis(millis: i64) -> DateTime {
+        let (year, month, day) = civil_from_days(millis.div_euclid(MILLIS_PER_DAY));
+        let seconds = millis.rem_euclid(MILLIS_PER_DAY) / 1000;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (seconds / 3600) as u32,
+            minute: (seconds / 60 % 60) as u32,
+            second: (seconds % 60) as u32,
+        }
+    }
+
+    /// Milliseconds since the epoch, if the date is valid and they fit.
+    pub fn to_millis(self) -> Option<i64> {
+        if !self.is_valid() {
+            return None;
+        }
+        // In i128, since the first day in range starts before `i64::MIN`.
+        let seconds = (self.hour * 3600 + self.minute * 60 + self.second) as i128;
+        let days = days_from_civil(self.year, self.month, self.day)? as i128;
+        i64::try_from(days * MILLIS_PER_DAY as i128 + seconds * 1000).ok()
+    }
+
+    /// Whether every component is in its range, leap days included. Leap
+    /// seconds aren't.
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Formats the date like `strftime`: `%Y` is the year, `%m`, `%d`, `%H`,
+    /// `%M` and `%S` the month, day, hour, minute and second on two digits,
+    /// and `%%` a percent sign. Anything else after a `%` is an error.
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(chr) = chars.next() {
+            if chr != '%' {
+                out.push(chr);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') if self.year < 0 => out.push_str(&format!("-{:04}", self.year.unsigned_abs())),
+                Some('Y') => out.push_str(&format!("{:04}", self.year)),
+                Some('m') => out.push_str(&format!("{:02}", self.month)),
+                Some('d') => out.push_str(&format!("{:02}", self.day)),
+                Some('H') => out.push_str(&format!("{:02}", self.hour)),
+                Some('M') => out.push_str(&format!("{:02}", self.minute)),
+                Some('S') => out.push_str(&format!("{:02}", self.second)),
+                Some('%') => out.push('%'),
+                Some(other) => return Err(format!("unknown directive '%{}'", other)),
+                None => return Err("the format ends with a lone '%'".to_string()),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Reads a date written with `format`, as `format` writes them, if
+    /// `text` matches it and is a valid date. `%Y` takes four digits when
+    /// another directive follows it right away, and as many as there are
+    /// otherwise. The components `format` leaves out are those of the epoch.
+    pub fn parse(text: &str, format: &str) -> Option<DateTime> {
+        let mut date = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+        let mut text = text;
+        let mut format = format.chars().peekable();
+        while let Some(chr) = format.next() {
+            if chr != '%' {
+                text = text.strip_prefix(chr)?;
+                continue;
+            }
+
+            let directive = format.next()?;
+            let followed = format.peek() == Some(&'%');
+            match directive {
+                'Y' => {
+                    let (negative, rest) = match text.strip_prefix('-') {
+                        Some(rest) => (true, rest),
+                        None => (false, text),
+                    };
+                    let (year, rest) = digits(rest, if followed { 4 } else { usize::MAX })?;
+                    date.year = if negative { year.checked_neg()? } else { year };
+                    text = rest;
+                }
+                'm' | 'd' | 'H' | 'M' | 'S' => {
+                    let (value, rest) = digits(text, 2)?;
+                    let field = match directive {
+                        'm' => &mut date.month,
+                        'd' => &mut date.day,
+                        'H' => &mut date.hour,
+                        'M' => &mut date.minute,
+                        _ => &mut date.second,
+                    };
+                    *field = value as u32;
+                    text = rest;
+                }
+                '%' => text = text.strip_prefix('%')?,
+                _ => return None,
+            }
+        }
+        (text.is_empty() && date.is_valid()).then_some(date)
+    }
+}
+
+/// Reads from one to `max` digits off the start of `text`.
+fn digits(text: &str, max: usize) -> Option<(i64, &str)> {
+    let len = text.bytes().take(max).take_while(u8::is_ascii_digit).count();
+    let value = text[..len].parse().ok()?;
+    Some((value, &text[len..]))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Both conversions are Howard Hinnant's algorithms, which count in eras of
+// 400 years starting on March 1st, so that leap days end the year.
+
+/// Days since the epoch of a valid date, if they fit.
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let year = if month <= 2 { year.checked_sub(1)? } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?.checked_add(day_of_era - 719_468)
+}
+
+/// The date of a number of days since the epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
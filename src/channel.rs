@@ -0,0 +1,174 @@
+//! Channels, queues of values that any part of a program can send to and
+//! receive from, including the threads of `pmap`, which get the channels
+//! their function refers to as they are.
+//!
+//! Values go through a channel as code, like everything `pmap` sends to its
+//! threads, so only data can: no functions and no handles. Once a channel is
+//! closed nothing more can be sent, and receiving gives the values still in
+//! it and then `f`, without waiting. The same goes once every other thread
+//! having the channel is gone or waiting to receive too, since nothing can
+//! send to it then.
+
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError, Sender, TryRecvError };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+
+use crate::ast::*;
+use crate::error::RuntimeError;
+use crate::evaluator::Environment;
+use crate::parallel;
+use crate::std_lib;
+
+/// How long `channel-recv` waits at a time before checking whether the
+/// program was interrupted or ran out of time.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// One thread's end of a channel. Each thread having the channel has its
+/// own, so that a receiver can tell whether anyone else could still send.
+pub struct Channel {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// `None` once closed, which ends the channel for the receivers when
+    /// they have taken what is left.
+    sender: Mutex<Option<Sender<String>>>,
+    receiver: Mutex<Receiver<String>>,
+    /// How many ends of the channel there are that can send, which those
+    /// waiting to receive can't.
+    ends: AtomicUsize,
+}
+
+impl Channel {
+    pub fn new() -> Channel {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Shared { sender: Mutex::new(Some(sender)), receiver: Mutex::new(receiver), ends: AtomicUsize::new(1) };
+        Channel { shared: Arc::new(shared) }
+    }
+
+    /// Another end of the channel, for another thread.
+    pub fn end(&self) -> Channel {
+        self.shared.ends.fetch_add(1, Ordering::SeqCst);
+        Channel { shared: self.shared.clone() }
+    }
+
+    /// Keeps this end from counting as one that can send while the thread
+    /// having it waits, and can't.
+    pub fn lend(&self) -> Lent<'_> {
+        self.shared.ends.fetch_sub(1, Ordering::SeqCst);
+        Lent(self)
+    }
+
+    /// Whether both are ends of the same channel.
+    pub fn same(&self, other: &Channel) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.sender.lock().unwrap().is_none()
+    }
+
+    /// Whether some end of the channel that isn't lent could still send to
+    /// it.
+    fn can_be_sent_to(&self) -> bool {
+        self.shared.ends.load(Ordering::SeqCst) > 0 && !self.is_closed()
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.shared.ends.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// An end of a channel lent with `Channel::lend`, until dropped.
+pub struct Lent<'a>(&'a Channel);
+
+impl Drop for Lent<'_> {
+    fn drop(&mut self) {
+        self.0.shared.ends.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Channel::new()
+    }
+}
+
+pub fn register(env: &mut Environment) {
+    let builtins: &[(&'static str, usize, &'static str, LibFn)] = &[
+        ("make-channel", 0, "Makes an empty channel.", make_channel_impl),
+        ("channel-send", 2, "Puts a value at the end of a channel.", channel_send_impl),
+        ("channel-recv", 1, "Takes the first value of a channel, waiting for one if it is empty, or f once it is empty and nothing else can send to it.", channel_recv_impl),
+        ("channel-try-recv", 1, "Takes the first value of a channel, or f if it is empty.", channel_try_recv_impl),
+        ("channel-close", 1, "Closes a channel, so that receiving stops waiting once it is empty.", channel_close_impl),
+    ];
+    for &(name, arity, doc, ptr) in builtins {
+        env.register_builtin(BuiltinSpec::new(name, arity).doc(doc).category("channels"), ptr);
+    }
+}
+
+fn channel(val: &RefVal, context: &str) -> Result<Arc<Channel>, RuntimeError> {
+    match &**val {
+        Value::Handle(Handle::Channel(channel)) => Ok(channel.clone()),
+        _ => Err(std_lib::mismatch("a channel", val, context)),
+    }
+}
+
+pub fn make_channel_impl(_env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    Ok(RefVal::owned(Value::Handle(Handle::Channel(Arc::new(Channel::new())))))
+}
+
+pub fn channel_send_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    let channel = channel(&env.pop_stack()?, "'channel-send'")?;
+    let code = parallel::write_data(&val, env.symbols()).ok_or_else(|| std_lib::mismatch("data", &val, "'channel-send'"))?;
+
+    let sender = channel.shared.sender.lock().unwrap();
+    let sender = sender.as_ref().ok_or("the channel is closed")?;
+    // The receiver lives as long as the channel, so sending can't fail.
+    let _ = sender.send(code);
+    Ok(RefVal::reference(std_lib::nil_ref()))
+}
+
+pub fn channel_recv_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let channel = channel(&env.pop_stack()?, "'channel-recv'")?;
+    // The wait ends once the channel is closed or every other thread having
+    // it is gone or waiting too, or the program is interrupted or runs out of
+    // time, which are checked between waits. Whether others can send is
+    // checked before looking, so whatever they sent before going is still
+    // taken.
+    let _lent = channel.lend();
+    loop {
+        env.check_interrupt()?;
+        let can_be_sent_to = channel.can_be_sent_to();
+        let receiver = channel.shared.receiver.lock().unwrap();
+        let received = match can_be_sent_to {
+            true => receiver.recv_timeout(POLL_INTERVAL),
+            false => receiver.try_recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        drop(receiver);
+        match received {
+            Ok(code) => return parallel::read_data(&code, env),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return Ok(false.into()),
+        }
+    }
+}
+
+pub fn channel_try_recv_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let channel = channel(&env.pop_stack()?, "'channel-try-recv'")?;
+    let received = channel.shared.receiver.lock().unwrap().try_recv();
+    match received {
+        Ok(code) => parallel::read_data(&code, env),
+        Err(TryRecvError::Empty | TryRecvError::Disconnected) => Ok(false.into()),
+    }
+}
+
+pub fn channel_close_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let channel = channel(&env.pop_stack()?, "'channel-close'")?;
+    channel.shared.sender.lock().unwrap().take();
+    Ok(RefVal::reference(std_lib::nil_ref()))
+}
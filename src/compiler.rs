@@ -0,0 +1,174 @@
+//! Compilation of expressions to a flat sequence of instructions for the VM.
+
+use crate::ast::*;
+use crate::std_lib;
+use crate::symbol::{ self, Symbol };
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Pushes a constant from the chunk's pool.
+    Const(usize),
+    /// Pushes the value bound to `name`, looking through every scope.
+    /// `call` is the expression it is the head of, if any, for error messages.
+    Load {
+        name: Symbol,
+        span: Span,
+        call: Option<usize>,
+    },
+    /// Pushes an argument of the function being run, by position.
+    LoadArg(usize),
+    /// Pops `argc` arguments and the function below them, and pushes the
+    /// result of the call. `expr` is the call's expression.
+    Call {
+        argc: usize,
+        expr: usize,
+    },
+    /// Jumps to the given instruction.
+    Jump(usize),
+    /// Pops a value and jumps to the given instruction if it is false.
+    JumpIfFalse(usize),
+    /// Jumps to `target` unless `name` is bound to the builtin of that name.
+    JumpUnlessBuiltin {
+        name: &'static str,
+        target: usize,
+    },
+}
+
+/// A compiled expression. Running it leaves exactly one value on the stack.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub consts: Vec<RefVal>,
+    /// Call expressions, kept for error messages and stack traces.
+    pub exprs: Vec<SExpr>,
+}
+
+/// Compiles a top-level expression.
+pub fn compile(expr: &SExpr) -> Chunk {
+    compile_body(expr, &[])
+}
+
+/// Compiles the body of a function taking `arg_names`, which are loaded
+/// from the innermost scope by position rather than by name.
+pub fn compile_body(expr: &SExpr, arg_names: &[Symbol]) -> Chunk {
+    // Repeated names are bound once, like `Environment::bind_var` does.
+    let mut slots: Vec<Symbol> = Vec::new();
+    for name in arg_names {
+        if !slots.iter().any(|slot| symbol::same_symbol(slot, name)) {
+            slots.push(name.clone());
+        }
+    }
+
+    let mut compiler = Compiler { chunk: Chunk::default(), slots };
+    compiler.expr(expr);
+    compiler.chunk
+}
+
+struct Compiler {
+    chunk: Chunk,
+    slots: Vec<Symbol>,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    fn constant(&mut self, val: RefVal) {
+        self.chunk.consts.push(val);
+        let idx = self.chunk.consts.len() - 1;
+        self.emit(Op::Const(idx));
+    }
+
+    fn expr(&mut self, expr: &SExpr) {
+        match expr {
+            SExpr::Atom(atom, _) if atom.as_keyword().is_some() => {
+                self.constant(RefVal::owned(Value::Quote(SExpr::atom(atom.clone()).into())))
+            }
+            SExpr::Atom(Atom::Ident(name), span) => self.load(name, *span, None),
+            SExpr::Atom(Atom::String(s), _) => self.constant(RefVal::owned(Value::String(s.clone()))),
+            SExpr::Atom(Atom::Int(n), _) => self.constant(RefVal::owned(Value::Int(*n))),
+            SExpr::Atom(Atom::BigInt(n), _) => self.constant(RefVal::owned(Value::BigInt(n.clone()))),
+            SExpr::Atom(Atom::Rational(num, den), _) => self.constant(RefVal::owned(Value::Rational(*num, *den))),
+            SExpr::Atom(Atom::Float(n), _) => self.constant(RefVal::owned(Value::Float(*n))),
+            SExpr::Atom(Atom::Quote(q), _) => self.constant(RefVal::owned(Value::Quote(q.clone()))),
+
+            SExpr::List(list, _) if list.is_empty() => {
+                self.constant(RefVal::reference(std_lib::nil_ref()))
+            }
+
+            SExpr::List(list, _) => match as_if(expr) {
+                Some((cond, then_branch, else_branch)) if !self.is_slot("if") => {
+                    self.if_expr(expr, cond, then_branch, else_branch)
+                }
+                _ => self.call(expr, list),
+            },
+        }
+    }
+
+    fn call(&mut self, expr: &SExpr, list: &List<SExpr>) {
+        self.chunk.exprs.push(expr.clone());
+        let call = self.chunk.exprs.len() - 1;
+
+        match &list[0] {
+            SExpr::Atom(Atom::Ident(name), span) => self.load(name, *span, Some(call)),
+            head => self.expr(head),
+        }
+        for arg in list.iter().skip(1) {
+            self.expr(arg);
+        }
+        self.emit(Op::Call { argc: list.len() - 1, expr: call });
+    }
+
+    fn is_slot(&self, name: &str) -> bool {
+        self.slots.iter().any(|slot| **slot == *name)
+    }
+
+    fn load(&mut self, name: &Symbol, span: Span, call: Option<usize>) {
+        match self.slots.iter().position(|slot| symbol::same_symbol(slot, name)) {
+            Some(slot) => self.emit(Op::LoadArg(slot)),
+            None => self.emit(Op::Load { name: name.clone(), span, call }),
+        };
+    }
+
+    /// `if` with both branches quoted in place is compiled to jumps, so the
+    /// branches get compiled too instead of being evaluated by the builtin.
+    /// Where `if` turns out not to be the builtin when the code runs, it is
+    /// called like any other function.
+    fn if_expr(&mut self, expr: &SExpr, cond: &SExpr, then_branch: &SExpr, else_branch: &SExpr) {
+        let check = self.emit(Op::JumpUnlessBuiltin { name: "if", target: 0 });
+        self.expr(cond);
+        let jump_else = self.emit(Op::JumpIfFalse(0));
+        self.expr(then_branch);
+        let jump_end = self.emit(Op::Jump(0));
+
+        let else_start = self.chunk.code.len();
+        self.expr(else_branch);
+        let jump_past_call = self.emit(Op::Jump(0));
+
+        let call_start = self.chunk.code.len();
+        self.call(expr, expr.as_list().expect("an if is a list"));
+        let end = self.chunk.code.len();
+
+        self.chunk.code[check] = Op::JumpUnlessBuiltin { name: "if", target: call_start };
+        self.chunk.code[jump_else] = Op::JumpIfFalse(else_start);
+        self.chunk.code[jump_end] = Op::Jump(end);
+        self.chunk.code[jump_past_call] = Op::Jump(end);
+    }
+}
+
+/// Matches `(if cond 'then 'else)`, whatever `if` is bound to.
+fn as_if(expr: &SExpr) -> Option<(&SExpr, &SExpr, &SExpr)> {
+    let list = expr.as_list()?;
+    if list.len() != 4 || list[0].as_atom().and_then(Atom::as_ident) != Some("if") {
+        return None;
+    }
+
+    match (&list[2], &list[3]) {
+        (SExpr::Atom(Atom::Quote(then_branch), _), SExpr::Atom(Atom::Quote(else_branch), _)) => {
+            Some((&list[1], then_branch, else_branch))
+        }
+        _ => None,
+    }
+}
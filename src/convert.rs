@@ -0,0 +1,205 @@
+//! Conversions between values and Rust types, for code embedding the
+//! interpreter: `RefVal::from` makes values, and `try_from` on a `Value` or
+//! a `RefVal` gets Rust values back, failing with a type mismatch that says
+//! what was expected and what was found.
+//!
+//! `i64`, `f64`, `String` and `bool` are ints, floats, strings and `t` or
+//! `f`. A float can be had from any number, ints and rationals included, but
+//! an `i64` only from an int. A `Vec` is a quoted list, whose elements are
+//! converted in turn, so a `Vec<Vec<f64>>` is a list of lists of numbers.
+//! Nil and the empty list both give an empty `Vec`, and an empty `Vec` gives
+//! the empty list. Inside a list, `t`, `f` and `nil` are symbols, as in
+//! `'(t f nil)`, which convert like the values they name.
+
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::error::{ self, RuntimeError };
+use crate::printer::Written;
+use crate::std_lib;
+
+impl From<bool> for RefVal {
+    fn from(b: bool) -> RefVal {
+        match b {
+            true => RefVal::reference(std_lib::true_ref()),
+            false => RefVal::reference(std_lib::false_ref()),
+        }
+    }
+}
+
+impl From<String> for RefVal {
+    fn from(s: String) -> RefVal {
+        RefVal::owned(Value::String(s.into()))
+    }
+}
+
+impl From<&str> for RefVal {
+    fn from(s: &str) -> RefVal {
+        RefVal::owned(Value::String(s.into()))
+    }
+}
+
+impl From<i64> for RefVal {
+    fn from(n: i64) -> RefVal {
+        RefVal::owned(Value::Int(n))
+    }
+}
+
+impl From<f64> for RefVal {
+    fn from(n: f64) -> RefVal {
+        RefVal::owned(Value::Float(n))
+    }
+}
+
+impl<T: Into<SExpr>> From<Vec<T>> for RefVal {
+    fn from(elements: Vec<T>) -> RefVal {
+        RefVal::owned(Value::Quote(Rc::new(SExpr::from(elements))))
+    }
+}
+
+// The same, as elements of a list.
+
+impl From<bool> for SExpr {
+    fn from(b: bool) -> SExpr {
+        SExpr::atom(Atom::Ident(if b { "t" } else { "f" }.into()))
+    }
+}
+
+impl From<String> for SExpr {
+    fn from(s: String) -> SExpr {
+        SExpr::atom(Atom::String(s.into()))
+    }
+}
+
+impl From<&str> for SExpr {
+    fn from(s: &str) -> SExpr {
+        SExpr::atom(Atom::String(s.into()))
+    }
+}
+
+impl From<i64> for SExpr {
+    fn from(n: i64) -> SExpr {
+        SExpr::atom(Atom::Int(n))
+    }
+}
+
+impl From<f64> for SExpr {
+    fn from(n: f64) -> SExpr {
+        SExpr::atom(Atom::Float(n))
+    }
+}
+
+impl<T: Into<SExpr>> From<Vec<T>> for SExpr {
+    fn from(elements: Vec<T>) -> SExpr {
+        SExpr::list(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+fn mismatch(expected: &'static str, got: &Value, target: &str) -> RuntimeError {
+    let printed = error::truncate(&Written(got).to_string(), 40);
+    let got = format!("{} `{}`", got.get_type(), printed);
+    RuntimeError::type_mismatch(expected, got, format!("the conversion to {}", target))
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(val: &Value) -> Result<bool, RuntimeError> {
+        match val {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(mismatch("t or f", val, "bool")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = RuntimeError;
+
+    fn try_from(val: &Value) -> Result<String, RuntimeError> {
+        match val {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(mismatch("a string", val, "String")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for i64 {
+    type Error = RuntimeError;
+
+    fn try_from(val: &Value) -> Result<i64, RuntimeError> {
+        match val {
+            Value::Int(n) => Ok(*n),
+            Value::BigInt(_) => Err(mismatch("an int that fits", val, "i64")),
+            _ => Err(mismatch("an int", val, "i64")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(val: &Value) -> Result<f64, RuntimeError> {
+        match val {
+            Value::Float(n) => Ok(*n),
+            Value::Int(n) => Ok(*n as f64),
+            Value::BigInt(n) => Ok(n.to_f64()),
+            Value::Rational(num, den) => Ok(*num as f64 / *den as f64),
+            _ => Err(mismatch("a number", val, "f64")),
+        }
+    }
+}
+
+impl<T> TryFrom<&Value> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Value, Error = RuntimeError>,
+{
+    type Error = RuntimeError;
+
+    fn try_from(val: &Value) -> Result<Vec<T>, RuntimeError> {
+        let elements = match val {
+            Value::Nil => return Ok(Vec::new()),
+            Value::Quote(quoted) => quoted.as_list().ok_or_else(|| mismatch("a list", val, "Vec"))?,
+            _ => return Err(mismatch("a list", val, "Vec")),
+        };
+        elements.iter().map(|elem| T::try_from(&element(elem))).collect()
+    }
+}
+
+/// An element of a list as a value, the symbols `t`, `f` and `nil` being
+/// the values they name.
+fn element(elem: &SExpr) -> Value {
+    match elem.as_atom().and_then(Atom::as_ident) {
+        Some("t") => Value::Bool(true),
+        Some("f") => Value::Bool(false),
+        Some("nil") => Value::Nil,
+        _ => sexpr_to_value(elem),
+    }
+}
+
+macro_rules! try_from_ref_val {
+    ($($target:ty),*) => {
+        $(
+            impl TryFrom<RefVal> for $target {
+                type Error = RuntimeError;
+
+                fn try_from(val: RefVal) -> Result<$target, RuntimeError> {
+                    <$target>::try_from(val.deref())
+                }
+            }
+        )*
+    };
+}
+
+try_from_ref_val!(bool, String, i64, f64);
+
+impl<T> TryFrom<RefVal> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Value, Error = RuntimeError>,
+{
+    type Error = RuntimeError;
+
+    fn try_from(val: RefVal) -> Result<Vec<T>, RuntimeError> {
+        Vec::try_from(val.deref())
+    }
+}
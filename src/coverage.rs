@@ -0,0 +1,124 @@
+//! Counting how many times each expression of a program is evaluated, for
+//! `--coverage`.
+//!
+//! Expressions are told apart by their address, which works because the
+//! code quoted in a program, like function bodies and the branches of an
+//! `if`, is shared with the parsed program rather than copied. The parsed
+//! programs are kept alive with the counts, so that no address is reused.
+
+use std::collections::HashMap;
+use std::fmt::{ self, Display, Formatter };
+use std::rc::Rc;
+
+use crate::ast::*;
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    sources: Vec<Source>,
+    /// How many times each expression of the sources was evaluated.
+    hits: HashMap<*const SExpr, u64>,
+}
+
+#[derive(Debug)]
+struct Source {
+    name: String,
+    src: Rc<str>,
+    exprs: Rc<[SExpr]>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Starts counting the evaluations of the expressions parsed from `src`,
+    /// which is reported as `name`.
+    pub fn add_source(&mut self, name: &str, src: Rc<str>, exprs: Rc<[SExpr]>) {
+        let mut pending: Vec<&SExpr> = exprs.iter().collect();
+        while let Some(expr) = pending.pop() {
+            self.hits.insert(expr, 0);
+            match expr {
+                SExpr::List(list, _) => pending.extend(list),
+                SExpr::Atom(Atom::Quote(quoted), _) => pending.push(quoted),
+                SExpr::Atom(..) => (),
+            }
+        }
+
+        self.sources.push(Source { name: name.to_string(), src, exprs });
+    }
+
+    /// Counts an evaluation of `expr`, if it is part of a source.
+    pub fn hit(&mut self, expr: &SExpr) {
+        if let Some(hits) = self.hits.get_mut(&(expr as *const SExpr)) {
+            *hits += 1;
+        }
+    }
+
+    /// For each source, by name, how many times each of its lines ran, or
+    /// `None` for lines without code. A line counts as run as often as the
+    /// expression that ran the most out of those starting on it.
+    ///
+    /// Quotes aren't counted themselves, only the code in them is, so a
+    /// branch that wasn't taken shows as not run even though the quote
+    /// holding it was evaluated. This means a line holding nothing but
+    /// quoted data shows as not run either.
+    pub fn lines(&self) -> Vec<(&str, &str, Vec<Option<u64>>)> {
+        let mut files: Vec<(&str, &str, Vec<Option<u64>>)> = Vec::new();
+
+        for source in &self.sources {
+            let index = match files.iter().position(|(name, ..)| *name == source.name) {
+                Some(index) => index,
+                None => {
+                    let lines = vec![None; source.src.lines().count().max(1)];
+                    files.push((&source.name, &source.src, lines));
+                    files.len() - 1
+                }
+            };
+            let lines = &mut files[index].2;
+
+            let mut pending: Vec<&SExpr> = source.exprs.iter().collect();
+            while let Some(expr) = pending.pop() {
+                match expr {
+                    SExpr::List(list, _) => pending.extend(list),
+                    SExpr::Atom(Atom::Quote(quoted), _) => {
+                        pending.push(quoted);
+                        continue;
+                    }
+                    SExpr::Atom(..) => (),
+                }
+
+                let span = expr.span();
+                if !span.is_known() {
+                    continue;
+                }
+                // Forms `include` spliced in are located in their own file.
+                let Some(before) = source.src.get(..span.start) else { continue };
+                let line = before.matches('\n').count();
+                let hits = self.hits.get(&(expr as *const SExpr)).copied().unwrap_or(0);
+                if let Some(count) = lines.get_mut(line) {
+                    *count = Some(count.map_or(hits, |count| count.max(hits)));
+                }
+            }
+        }
+
+        files
+    }
+}
+
+/// Every source with each line preceded by how many times it ran, `#####`
+/// for code that never ran and `-` for lines without code.
+impl Display for Coverage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, src, lines) in self.lines() {
+            writeln!(f, "coverage of {}:", name)?;
+            for (text, hits) in src.lines().zip(lines) {
+                match hits {
+                    Some(0) => writeln!(f, "{:>7} | {}", "#####", text)?,
+                    Some(hits) => writeln!(f, "{:>7} | {}", hits, text)?,
+                    None => writeln!(f, "{:>7} | {}", "-", text)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
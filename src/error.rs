@@ -1,48 +1,761 @@
+//! What can go wrong: `RuntimeError` while evaluating, `EvalError` for
+//! the whole of reading and evaluating some source, and the reports that
+//! point at where in it things went wrong.
+
 use std::fmt::{ Display, Debug, Formatter, Result };
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::ast::{ self, Span };
+
+/// Everything that can go wrong while evaluating. The variants keep the
+/// details around so that callers can tell failures apart.
+#[derive(Clone, PartialEq)]
+pub enum RuntimeError {
+    UnboundVariable {
+        name: String,
+        /// Printed form of the list, when the name was in call position.
+        call: Option<String>,
+        suggestions: Vec<String>,
+    },
+    NotAFunction {
+        value: String,
+        call: String,
+    },
+    TypeMismatch {
+        expected: &'static str,
+        got: String,
+        /// The function or construct that was expecting the value.
+        context: String,
+    },
+    ArityMismatch {
+        /// The numbers of arguments the function takes, see
+        /// `Function::arities`.
+        expected: String,
+        got: usize,
+        callee: String,
+    },
+    /// A lib function tried to take more arguments than were passed to it.
+    StackUnderflow {
+        callee: Option<String>,
+    },
+    EmptyList {
+        context: String,
+    },
+    IntegerOverflow {
+        operation: String,
+    },
+    DivisionByZero,
+    DepthExceeded {
+        limit: usize,
+    },
+    /// The step budget set with `Environment::set_fuel` ran out.
+    OutOfFuel,
+    /// The flag set with `Environment::set_interrupt_flag` was raised, by
+    /// Ctrl-C in the command line interface.
+    Interrupted,
+    /// A top-level form took longer than the time set with
+    /// `Environment::set_timeout`.
+    Timeout(Duration),
+    /// A value grew past the limit set with `Environment::set_size_limit`.
+    ResourceLimit {
+        size: usize,
+        limit: usize,
+    },
+    /// A global bound with `defconst` being bound again.
+    ConstantRedefined {
+        name: String,
+        /// Where the constant was defined, when in the same source as the
+        /// error.
+        span: Option<Span>,
+        /// The file the constant was defined in, when it is another one.
+        file: Option<String>,
+    },
+    Custom(String),
+    /// Not an error, but `exit` being called: the program stops with the
+    /// given status.
+    Exit(i32),
+    /// Not an error either, but `recur` going back to the start of its loop
+    /// or function body, with the values it was given kept by the
+    /// `Environment` in the meantime.
+    Recur,
+    /// Not an error either, but `return` ending the innermost call of a
+    /// user defined function, with the value it was given kept by the
+    /// `Environment` in the meantime.
+    Return,
+    /// An error along with the calls that were active when it was raised,
+    /// outermost first.
+    Traced {
+        trace: Vec<String>,
+        error: Box<RuntimeError>,
+    },
+    /// An error raised by code loaded from another file, whose spans refer
+    /// to that file.
+    InFile {
+        file: String,
+        src: Rc<str>,
+        error: Box<RuntimeError>,
+    },
+    /// An error along with the source span of the expression that caused it.
+    At {
+        span: Span,
+        error: Box<RuntimeError>,
+    },
+}
+
+impl RuntimeError {
+    /// The error itself, without the context wrapped around it.
+    pub fn root(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traced { error, .. }
+            | RuntimeError::At { error, .. }
+            | RuntimeError::InFile { error, .. } => error.root(),
+            err => err,
+        }
+    }
+
+    /// The calls that were active when the error was raised, if recorded.
+    pub fn trace(&self) -> Option<&[String]> {
+        match self {
+            RuntimeError::Traced { trace, .. } => Some(trace),
+            RuntimeError::At { error, .. } => error.trace(),
+            _ => None,
+        }
+    }
+
+    /// Where in the source the error was raised, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RuntimeError::At { span, .. } => Some(*span),
+            RuntimeError::Traced { error, .. } => error.span(),
+            _ => None,
+        }
+    }
+
+    /// The innermost file the error was raised in, other than the one being
+    /// run, along with its source and the error as raised there.
+    pub fn in_file(&self) -> Option<(&str, &str, &RuntimeError)> {
+        match self {
+            RuntimeError::Traced { error, .. } | RuntimeError::At { error, .. } => error.in_file(),
+            RuntimeError::InFile { file, src, error } => {
+                error.in_file().or(Some((file, src, error)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Attaches `span` to the error, unless it already has a more precise
+    /// one.
+    pub fn with_span(self, span: Span) -> Self {
+        if !span.is_known() || self.span().is_some() {
+            self
+        } else {
+            RuntimeError::At { span, error: Box::new(self) }
+        }
+    }
+
+    /// The message of the error itself, without the context that `Report`
+    /// shows as notes.
+    fn headline(&self) -> String {
+        match self.root() {
+            RuntimeError::UnboundVariable { name, .. } => format!("name '{name}' was not defined"),
+            error => error.to_string(),
+        }
+    }
+
+    /// The context of the error: the calls it was raised in and what might
+    /// fix it.
+    fn notes(&self) -> Vec<(Level, String)> {
+        let mut notes = Vec::new();
+        if let RuntimeError::UnboundVariable { call, suggestions, .. } = self.root() {
+            if let Some(call) = call {
+                notes.push((Level::Note, format!("in call position of `{call}`")));
+            }
+            if !suggestions.is_empty() {
+                let names: Vec<_> = suggestions.iter().map(|s| format!("'{s}'")).collect();
+                notes.push((Level::Help, format!("did you mean {}?", names.join(" or "))));
+            }
+        }
+        if let RuntimeError::ConstantRedefined { name, file: Some(file), .. } = self.root() {
+            notes.push((Level::Note, format!("'{name}' was defined in {file}")));
+        }
+        if let Some(trace) = self.trace().filter(|trace| !trace.is_empty()) {
+            notes.push((Level::Note, format!("in {}", shorten_trace(trace).join(" → "))));
+        }
+        notes
+    }
+
+    pub fn type_mismatch(expected: &'static str, got: impl ToString, context: impl ToString) -> Self {
+        RuntimeError::TypeMismatch {
+            expected,
+            got: got.to_string(),
+            context: context.to_string(),
+        }
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(msg: String) -> Self {
+        RuntimeError::Custom(msg)
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(msg: &str) -> Self {
+        RuntimeError::Custom(msg.to_string())
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use RuntimeError::*;
+
+        match self {
+            UnboundVariable { call, suggestions, .. } => {
+                write!(f, "{}", self.headline())?;
+                if let Some(call) = call {
+                    write!(f, " (in call position of `{call}`)")?;
+                }
+                if !suggestions.is_empty() {
+                    let names: Vec<_> = suggestions.iter().map(|s| format!("'{s}'")).collect();
+                    write!(f, "; did you mean {}?", names.join(" or "))?;
+                }
+                Ok(())
+            }
+
+            NotAFunction { value, call } => {
+                write!(f, "expected a function as the head of `{call}`, got `{value}`")
+            }
+
+            TypeMismatch { expected, got, context } => {
+                write!(f, "expected {expected} in {context}, got {got}")
+            }
+
+            ArityMismatch { expected, got, callee } => {
+                write!(f, "expected {}, but got {got} in {callee}", ast::arguments(expected))
+            }
+
+            StackUnderflow { callee: Some(callee) } => {
+                write!(f, "{callee} tried to take more arguments than it was given")
+            }
+
+            StackUnderflow { callee: None } => {
+                write!(f, "tried to pop an argument off an empty stack")
+            }
+
+            EmptyList { context } => write!(f, "expected non empty list in {context}"),
+            IntegerOverflow { operation } => write!(f, "integer overflow in {operation}"),
+            DivisionByZero => write!(f, "integer division by zero"),
+            DepthExceeded { limit } => write!(f, "maximum evaluation depth of {limit} exceeded"),
+            OutOfFuel => write!(f, "ran out of fuel"),
+            Interrupted => write!(f, "interrupted"),
+            Timeout(timeout) => write!(f, "timed out after {} ms", timeout.as_millis()),
+            ResourceLimit { size, limit } => {
+                write!(f, "value of size {size} exceeds the limit of {limit}")
+            }
+            ConstantRedefined { name, .. } => write!(f, "cannot redefine constant '{name}'"),
+            Custom(msg) => write!(f, "{msg}"),
+            Exit(status) => write!(f, "exit with status {status}"),
+            Recur => write!(f, "'recur' didn't reach its loop"),
+            Return => write!(f, "'return' didn't reach its function"),
+
+            At { error, .. } => Display::fmt(error, f),
+            InFile { file, error, .. } => write!(f, "{file}: {error}"),
+
+            Traced { trace, error } => {
+                for call in shorten_trace(trace) {
+                    write!(f, "in {call} → ")?;
+                }
+                write!(f, "error: {error}")
+            }
+        }
+    }
+}
+
+impl Debug for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
 
-pub type RuntimeError = String;
+/// The longest run of calls repeated in a trace that is shown once, as in
+/// mutual recursion.
+const MAX_TRACE_PERIOD: usize = 4;
+
+/// How many calls of a trace are shown at each end when it is too long even
+/// with repeats shown once.
+const TRACE_ENDS: usize = 10;
+
+/// The calls of `trace`, quoted, with runs of the same calls written once
+/// with how many times they repeat, and only both ends kept if there are
+/// still many. Recursion would otherwise make for thousands of them.
+fn shorten_trace(trace: &[String]) -> Vec<String> {
+    let mut calls = Vec::new();
+    let mut i = 0;
+    while i < trace.len() {
+        // The period covering the most calls from `i`, the shortest if any
+        // tie.
+        let mut best = (1, 1);
+        for period in 1..=MAX_TRACE_PERIOD.min(trace.len() - i) {
+            let run = &trace[i..i + period];
+            let repeats = 1 + trace[i + period..].chunks_exact(period).take_while(|chunk| *chunk == run).count();
+            if repeats > 1 && period * repeats > best.0 * best.1 {
+                best = (period, repeats);
+            }
+        }
+
+        let (period, repeats) = best;
+        let run: Vec<_> = trace[i..i + period].iter().map(|name| format!("'{name}'")).collect();
+        calls.push(match (period, repeats) {
+            (_, 1) => run.join(" → "),
+            (1, _) => format!("{} × {}", run[0], repeats),
+            _ => format!("[{}] × {}", run.join(" → "), repeats),
+        });
+        i += period * repeats;
+    }
+
+    if calls.len() > 2 * TRACE_ENDS {
+        let hidden = calls.len() - 2 * TRACE_ENDS;
+        calls.splice(TRACE_ENDS..calls.len() - TRACE_ENDS, [format!("... {hidden} more ...")]);
+    }
+    calls
+}
+
+/// Truncates `s` to at most `max` characters, marking the cut with "...".
+pub fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        let mut out: String = s.chars().take(max.saturating_sub(3)).collect();
+        out.push_str("...");
+        out
+    }
+}
+
+/// How far `candidate` is from `name`, if it is close enough to suggest
+/// instead: at most a third of `name` changed, or one char of a short one.
+/// Names sharing no char are never close, however short.
+pub fn near_miss(name: &str, candidate: &str) -> Option<usize> {
+    let len = name.chars().count();
+    let dist = edit_distance(name, candidate);
+    let shares = name.chars().any(|chr| candidate.contains(chr));
+    (dist <= (len / 3).max(1) && dist < len && shares).then_some(dist)
+}
+
+/// Levenshtein distance between `a` and `b`, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr.push((prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
 
 pub struct Error<'a> {
     src: &'a str,
-    char_idx: usize,
+    byte: usize,
     msg: String,
+    incomplete: bool,
+    file: Option<String>,
 }
 
 impl<'a> Error<'a> {
     pub fn new(src: &'a str, byte: usize, msg: impl ToString) -> Self {
         Error {
             src,
-            char_idx: byte,
+            byte,
             msg: msg.to_string(),
+            incomplete: false,
+            file: None,
+        }
+    }
+
+    /// Names the file the source was read from in the message.
+    pub fn in_file(self, file: impl ToString) -> Self {
+        Error { file: Some(file.to_string()), ..self }
+    }
+
+    /// An error caused by the input ending in the middle of an expression,
+    /// which more input could fix.
+    pub fn incomplete(src: &'a str, byte: usize, msg: impl ToString) -> Self {
+        Error {
+            incomplete: true,
+            ..Error::new(src, byte, msg)
+        }
+    }
+
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    pub fn byte(&self) -> usize {
+        self.byte
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl<'a> Error<'a> {
+    /// The error laid out for people to read.
+    pub fn report(&self) -> Report<'_> {
+        Report {
+            file: self.file.as_deref(),
+            location: Some((self.src, self.byte)),
+            ..Report::new(self.msg.clone())
         }
     }
 }
 
 impl<'a> Display for Error<'a> {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        let mut line = 1;
-        let mut col = 1;
-        for (i, chr) in self.src.char_indices() {
-            if i > self.char_idx { break }
-            if chr == '\n' {
-                line += 1;
-                col = 1;
-            } else {
-                col += 1;
+        Display::fmt(&self.report(), f)
+    }
+}
+
+/// Line and column, both starting at 1, of the char at byte offset `byte`.
+pub fn line_col(src: &str, byte: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut col = 1;
+    for (i, chr) in src.char_indices() {
+        if i >= byte { break }
+        if chr == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    (line, col)
+}
+
+/// What a line of a `Report` is, which gives the color of its label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Note,
+    Help,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Note => "note",
+            Level::Help => "help",
+        }
+    }
+
+    /// The ANSI escape the label is colored with.
+    fn color(self) -> &'static str {
+        match self {
+            Level::Error => "\x1b[1;31m",
+            Level::Note => "\x1b[1;36m",
+            Level::Help => "\x1b[1;32m",
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether to color reports written to a stream, which is when it is a
+/// terminal and `NO_COLOR` isn't set.
+pub fn use_color(is_terminal: bool) -> bool {
+    is_terminal && std::env::var_os("NO_COLOR").is_none_or(|val| val.is_empty())
+}
+
+/// How many chars of a line of source a report shows at most.
+const SNIPPET_WIDTH: usize = 100;
+
+/// The part of `line` a report shows, around the char at `col`, and how many
+/// chars of it come before that one. Long lines are cut to `SNIPPET_WIDTH`
+/// chars, with `...` marking the cuts.
+fn snippet(line: &str, col: usize) -> (String, usize) {
+    let len = line.chars().count();
+    if len <= SNIPPET_WIDTH {
+        return (line.to_string(), col);
+    }
+    let start = col.saturating_sub(SNIPPET_WIDTH / 2).min(len - SNIPPET_WIDTH);
+    let end = start + SNIPPET_WIDTH;
+    let mut text: String = line.chars().skip(start).take(SNIPPET_WIDTH).collect();
+    let mut caret = col - start;
+    if start > 0 {
+        text.insert_str(0, "...");
+        caret += 3;
+    }
+    if end < len {
+        text.push_str("...");
+    }
+    (text, caret)
+}
+
+/// An error laid out for people to read: the message, where it happened
+/// with the line of source and a caret under the spot, and notes giving
+/// context. Displaying it gives no trailing newline.
+///
+/// ```text
+/// error: expected a list in 'car', got int `1`
+///  --> main.yal:1:1
+///   |
+/// 1 | (car 1)
+///   | ^
+///   = note: in 'car'
+/// ```
+pub struct Report<'a> {
+    pub message: String,
+    /// The name of the file the error is in, if any.
+    pub file: Option<&'a str>,
+    /// The source the error is in, and the byte to put the caret under.
+    pub location: Option<(&'a str, usize)>,
+    pub notes: Vec<(Level, String)>,
+    color: bool,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(message: String) -> Self {
+        Report { message, file: None, location: None, notes: Vec::new(), color: false }
+    }
+
+    /// Colors the report with ANSI escapes, when `color` is true. See
+    /// `use_color`.
+    pub fn colored(self, color: bool) -> Self {
+        Report { color, ..self }
+    }
+
+    /// Writes `text` in the color `code`, if the report is colored.
+    fn paint(&self, f: &mut Formatter, code: &str, text: &dyn Display) -> Result {
+        if self.color {
+            write!(f, "{code}{text}{RESET}")
+        } else {
+            write!(f, "{text}")
+        }
+    }
+}
+
+impl<'a> Display for Report<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        self.paint(f, Level::Error.color(), &Level::Error.label())?;
+        self.paint(f, BOLD, &format_args!(": {}", self.message))?;
+
+        // The gutter is as wide as the number of the line shown.
+        let mut gutter = String::from(" ");
+        match (self.location, self.file) {
+            (Some((src, byte)), file) => {
+                let (line, col) = line_col(src, byte);
+                let number = line.to_string();
+                gutter = " ".repeat(number.len());
+
+                writeln!(f)?;
+                self.paint(f, BLUE, &format_args!("{gutter}--> "))?;
+                match file {
+                    Some(file) => write!(f, "{file}:{line}:{col}")?,
+                    None => write!(f, "{line}:{col}")?,
+                }
+                writeln!(f)?;
+                self.paint(f, BLUE, &format_args!("{gutter} |"))?;
+                writeln!(f)?;
+                let (text, caret) = snippet(src.lines().nth(line - 1).unwrap_or(""), col - 1);
+                self.paint(f, BLUE, &format_args!("{number} |"))?;
+                writeln!(f, " {text}")?;
+                self.paint(f, BLUE, &format_args!("{gutter} |"))?;
+                write!(f, " {}", " ".repeat(caret))?;
+                self.paint(f, Level::Error.color(), &"^")?;
             }
+            (None, Some(file)) => {
+                writeln!(f)?;
+                self.paint(f, BLUE, &format_args!("{gutter}--> "))?;
+                write!(f, "{file}")?;
+            }
+            (None, None) => (),
+        }
+
+        for (level, note) in &self.notes {
+            writeln!(f)?;
+            self.paint(f, BLUE, &format_args!("{gutter} = "))?;
+            self.paint(f, level.color(), &level.label())?;
+            write!(f, ": {note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The files a program was read from, when `include` spliced others into the
+/// first. Spans don't say which file they are in, so each file's are
+/// numbered from where the previous one's end, and the offset alone tells
+/// the file apart.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub src: Rc<str>,
+    /// The offset its spans start at.
+    pub start: usize,
+}
+
+impl SourceMap {
+    /// A map of a program read from `src` alone, whose spans start at 0.
+    pub fn new(name: &str, src: Rc<str>) -> SourceMap {
+        SourceMap { files: vec![SourceFile { name: name.to_string(), src, start: 0 }] }
+    }
+
+    /// Adds a file, returning the offset its spans have to start at.
+    pub fn add(&mut self, name: &str, src: Rc<str>) -> usize {
+        let last = self.files.last().unwrap();
+        // One past the end, since a span can end right after the source.
+        let start = last.start + last.src.len() + 1;
+        self.files.push(SourceFile { name: name.to_string(), src, start });
+        start
+    }
+
+    /// The file an offset is in, and the offset within it.
+    pub fn locate(&self, offset: usize) -> (&SourceFile, usize) {
+        let file = self.files.iter().rev().find(|file| file.start <= offset).unwrap();
+        (file, offset - file.start)
+    }
+
+    /// The file, line and column of an offset.
+    pub fn position(&self, offset: usize) -> (&str, usize, usize) {
+        let (file, offset) = self.locate(offset);
+        let (line, col) = line_col(&file.src, offset);
+        (&file.name, line, col)
+    }
+
+    /// `error` as raised in the file it is located in, if that isn't the
+    /// first, so that it is reported against that file.
+    pub fn attribute(&self, error: RuntimeError) -> RuntimeError {
+        let Some(span) = error.span() else { return error };
+        let (file, _) = self.locate(span.start);
+        if file.start == 0 {
+            return error;
+        }
+        RuntimeError::InFile {
+            file: file.name.clone(),
+            src: file.src.clone(),
+            error: Box::new(shift(error, file.start)),
+        }
+    }
+}
+
+/// Moves the spans `error` is located at `by` bytes back.
+fn shift(error: RuntimeError, by: usize) -> RuntimeError {
+    match error {
+        RuntimeError::At { span, error } => RuntimeError::At {
+            span: Span::new(span.start.saturating_sub(by), span.end.saturating_sub(by)),
+            error: Box::new(shift(*error, by)),
+        },
+        RuntimeError::Traced { trace, error } => RuntimeError::Traced { trace, error: Box::new(shift(*error, by)) },
+        error => error,
+    }
+}
+
+/// A runtime error rendered against the source it came from, with a caret
+/// under the offending expression when its location is known.
+pub struct Located<'a> {
+    /// The name of the file `src` was read from, if any.
+    pub file: Option<&'a str>,
+    pub src: &'a str,
+    pub error: &'a RuntimeError,
+}
+
+impl<'a> Located<'a> {
+    /// The error laid out for people to read.
+    pub fn report(&self) -> Report<'a> {
+        // Errors from loaded files are shown where they happened. Their
+        // trace already has the calls leading to the load.
+        let (file, src, error) = match self.error.in_file() {
+            Some((file, src, error)) => (Some(file), src, error),
+            None => (self.file, self.src, self.error),
+        };
+
+        let mut notes = error.notes();
+        if let RuntimeError::ConstantRedefined { name, span: Some(span), .. } = error.root() {
+            let (line, col) = line_col(src, span.start);
+            let place = match file {
+                Some(file) => format!("{file}:{line}:{col}"),
+                None => format!("{line}:{col}"),
+            };
+            notes.insert(0, (Level::Note, format!("'{name}' was defined at {place}")));
+        }
+
+        Report {
+            file,
+            location: error.span().map(|span| (src, span.start)),
+            notes,
+            ..Report::new(error.headline())
         }
-        write!(f, "{} at {}:{}", self.msg, line, col)
     }
 }
 
-impl<'a> Debug for Error<'a> {
+impl<'a> Display for Located<'a> {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        Display::fmt(self, f)
+        Display::fmt(&self.report(), f)
     }
 }
 
-impl<'a> std::error::Error for Error<'a> {
-    fn description(&self) -> &str {
-        self.msg.as_str()
+/// Why evaluating a string of source failed, with `eval_str`. Unlike a
+/// reader `Error`, it doesn't borrow the source: the report, what it
+/// displays as, is rendered when it is made. Lines and columns start at 1
+/// and are into the source that was given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The source couldn't be read.
+    Parse {
+        message: String,
+        line: usize,
+        col: usize,
+        report: String,
+    },
+    /// Evaluating the source failed, at `position` if the error says where.
+    Runtime {
+        error: Box<RuntimeError>,
+        position: Option<(usize, usize)>,
+        report: String,
+    },
+}
+
+impl EvalError {
+    pub fn parse(err: &Error) -> EvalError {
+        let (line, col) = line_col(err.src, err.byte);
+        EvalError::Parse { message: err.msg.clone(), line, col, report: err.report().to_string() }
+    }
+
+    /// `error`, raised by evaluating `src`.
+    pub fn runtime(error: RuntimeError, src: &str) -> EvalError {
+        let report = Located { file: None, src, error: &error }.to_string();
+        let position = error.span().map(|span| line_col(src, span.start));
+        EvalError::Runtime { error: Box::new(error), position, report }
+    }
+
+    /// The line and column the error is at, if known.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            EvalError::Parse { line, col, .. } => Some((*line, *col)),
+            EvalError::Runtime { position, .. } => *position,
+        }
     }
 }
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            EvalError::Parse { report, .. } | EvalError::Runtime { report, .. } => write!(f, "{}", report),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
@@ -1,137 +1,1836 @@
+//! The evaluator. An `Environment` holds the global bindings,
+//! the call stack and the settings of a run; `evaluate` and `call` run code
+//! and functions in it.
+
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::io::{ self, Write };
+use std::ops::{ Deref, DerefMut };
+use std::path::{ Path, PathBuf };
+use std::rc::{ Rc, Weak };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 
 use crate::ast::*;
+use crate::list;
+use crate::optimize;
+use crate::std_lib;
+use crate::pattern;
+use crate::error::{ self, EvalError, RuntimeError };
+use crate::printer::{ self, Written };
+use crate::reader::{ Features, Reader };
+use crate::symbol::{ self, Symbol, SymbolTable };
+use crate::coverage::Coverage;
+use crate::profile::Profiler;
+use crate::vm::{ self, Vm };
+
+/// How many characters of an offending expression to show in error messages.
+const ERROR_EXPR_LEN: usize = 60;
+
+/// How many characters of an expression or value to show when tracing.
+const TRACE_LEN: usize = 100;
+
+/// Default bound on nested calls to `evaluate`, as deep as the 8 MiB stack of
+/// a main thread goes in optimized builds. Unoptimized ones take about four
+/// times as much stack for each call, so they need a thread with a 32 MiB
+/// stack to go as deep. Running deeper takes a bigger stack still, and
+/// `Environment::set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 2500;
+
+/// How many steps go by between checks of the interrupt flag and of the
+/// timeout. Reading the clock this seldom costs nothing measurable, and even
+/// a tight loop gets through these steps in well under a millisecond.
+const INTERRUPT_INTERVAL: u64 = 1024;
+
+/// How many steps an environment sharing its fuel with others takes from
+/// the shared budget at a time.
+const FUEL_BATCH: u64 = 1024;
+
+/// A scope frame holding the bindings introduced by a single function call.
+/// Frames are small, so a vector beats a hash map here.
+type Scope = Vec<(Symbol, RefVal)>;
+
+/// When an evaluation hook is being called, relative to the expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    Before,
+    After,
+}
+
+/// What `+`, `-`, `*`, `/`, `neg` and `abs` do when an int result doesn't
+/// fit in an `i64`. Rationals that don't fit fail in every mode, and big
+/// ints, however they were made, stay exact in every mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntOverflow {
+    /// Give a big int.
+    #[default]
+    Promote,
+    /// Fail with `RuntimeError::IntegerOverflow`.
+    Checked,
+    /// Wrap around, in two's complement.
+    Wrapping,
+}
+
+impl IntOverflow {
+    /// The mode called `name`, `promote`, `checked` or `wrapping`.
+    pub fn from_name(name: &str) -> Option<IntOverflow> {
+        match name {
+            "promote" => Some(IntOverflow::Promote),
+            "checked" => Some(IntOverflow::Checked),
+            "wrapping" => Some(IntOverflow::Wrapping),
+            _ => None,
+        }
+    }
+}
+
+/// Called before and after every expression is evaluated. Returning an error
+/// aborts the evaluation with it.
+pub type EvalHook = Box<dyn FnMut(&SExpr, HookPhase) -> Result<(), RuntimeError>>;
 
 #[derive(Debug)]
 pub struct Environment {
-    variables: HashMap<String, Vec<RefVal>>,
+    globals: HashMap<Symbol, RefVal>,
+    symbols: SymbolTable,
+    /// What conditionals in code read for this environment test.
+    features: Features,
+    scopes: Vec<Scope>,
     stack: Vec<RefVal>,
+    depth: usize,
+    max_depth: usize,
+    native: Option<NativeCall>,
+    /// How many values `recur` takes in the innermost loop or function body
+    /// being run, if any.
+    recur_arity: Option<usize>,
+    /// The values `recur` was last given, on their way to its loop.
+    recur_values: Vec<RefVal>,
+    /// How many bodies of user defined functions are running.
+    functions: usize,
+    /// The value `return` was last given, on its way to its function call.
+    returned: Option<RefVal>,
+    call_stack: Vec<String>,
+    vm: Option<Vm>,
+    trace: bool,
+    /// Where the expressions being evaluated show up in traces.
+    trace_indent: usize,
+    hook: Option<Hook>,
+    fuel: Option<u64>,
+    /// The budget shared with environments on other threads, which `fuel`
+    /// is refilled from once it runs out.
+    fuel_pool: Option<Arc<AtomicU64>>,
+    steps: u64,
+    interrupt: Option<Arc<AtomicBool>>,
+    /// How long a top-level form may take, and when the one being evaluated
+    /// runs out of time.
+    timeout: Option<(Duration, Instant)>,
+    size_limit: Option<usize>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    /// The files being run, the innermost one last.
+    files: Vec<PathBuf>,
+    /// The module definitions go into, if any.
+    module: Option<Symbol>,
+    /// For each module, its definitions' names and their qualified names.
+    modules: HashMap<Symbol, HashMap<Symbol, Symbol>>,
+    /// Names imported from modules, and the qualified names they stand for.
+    imports: HashMap<Symbol, Symbol>,
+    /// Files that were loaded to find modules, by canonical path.
+    module_files: HashSet<PathBuf>,
+    /// The globals bound with `defconst`, and the file and span of the name
+    /// they were defined with, when they were defined in a file.
+    constants: HashMap<Symbol, Option<(PathBuf, Span)>>,
+    tests: Vec<Test>,
+    benches: Vec<Bench>,
+    output: Output,
+    /// The quoted expressions made so far, by `std_lib::structural_hash`,
+    /// when hash consing is enabled.
+    interned: Option<RefCell<HashMap<u64, Vec<Weak<SExpr>>>>>,
+    /// The digits after the point `print` shows of floats, all it takes to
+    /// read them back if `None`.
+    print_precision: Option<usize>,
+    /// How many levels of lists `print` shows.
+    print_depth: usize,
+    int_overflow: IntOverflow,
+    builtins: Vec<BuiltinSpec>,
+    /// What `time-now` reads the time from, instead of the system clock.
+    clock: Option<Clock>,
+    /// The state of the generator `random` draws from, seeded by
+    /// `set_random_seed`, or by the clock the first time it is drawn from.
+    random: Option<u64>,
+    /// The promises made so far, for `collect_garbage`. Those freed are
+    /// dropped from time to time.
+    promises: Vec<Weak<Promise>>,
+}
+
+/// Where printed output goes.
+struct Output(Box<dyn Write>);
+
+impl Output {
+    fn stdout() -> Output {
+        Output(Box::new(io::BufWriter::new(io::stdout())))
+    }
+}
+
+/// The milliseconds since the epoch, set with `Environment::set_clock`.
+struct Clock(Box<dyn Fn() -> u64>);
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Clock")
+    }
+}
+
+/// The nanoseconds since the epoch, to seed `random` with when neither the
+/// program nor the host did. There is no clock in the browser.
+fn system_seed() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        return 0;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64)
+}
+
+/// A buffer the output is written to by `Environment::capture_output`.
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Output")
+    }
+}
+
+/// A benchmark registered with `defbench`, to be run with `--bench`.
+#[derive(Debug, Clone)]
+pub struct Bench {
+    pub name: Symbol,
+    pub iterations: u64,
+    pub body: Rc<SExpr>,
+}
+
+/// A test registered with `deftest`, to be run by `run-tests`.
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: Symbol,
+    pub body: Rc<SExpr>,
+    /// The file the test was defined in, whose source its spans refer to.
+    pub file: Option<PathBuf>,
+}
+
+struct Hook(EvalHook);
+
+impl std::fmt::Debug for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Hook")
+    }
+}
+
+/// The lib function currently running, how many arguments it was given and
+/// where they start on the stack.
+#[derive(Debug, Clone, Copy)]
+struct NativeCall {
+    name: &'static str,
+    argc: usize,
+    floor: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
         Environment {
-            variables: HashMap::new(),
+            globals: HashMap::new(),
+            symbols: SymbolTable::new(),
+            features: Features::compiled(),
+            scopes: Vec::new(),
             stack: Vec::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            native: None,
+            recur_arity: None,
+            recur_values: Vec::new(),
+            functions: 0,
+            returned: None,
+            call_stack: Vec::new(),
+            vm: None,
+            trace: false,
+            trace_indent: 0,
+            hook: None,
+            fuel: None,
+            fuel_pool: None,
+            steps: 0,
+            interrupt: None,
+            timeout: None,
+            size_limit: None,
+            profiler: None,
+            coverage: None,
+            files: Vec::new(),
+            module: None,
+            modules: HashMap::new(),
+            imports: HashMap::new(),
+            module_files: HashSet::new(),
+            constants: HashMap::new(),
+            tests: Vec::new(),
+            benches: Vec::new(),
+            output: Output::stdout(),
+            interned: None,
+            print_precision: None,
+            print_depth: printer::DEFAULT_MAX_DEPTH,
+            int_overflow: IntOverflow::default(),
+            builtins: Vec::new(),
+            clock: None,
+            random: None,
+            promises: Vec::new(),
+        }
+    }
+
+    /// A new environment with the standard library, the one programs run by
+    /// the `yal` binary start out with.
+    pub fn with_std_lib() -> Result<Self, RuntimeError> {
+        let mut env = Environment::new();
+        std_lib::register(&mut env)?;
+        Ok(env)
+    }
+
+    /// Prints every call to stderr as it starts, and its result as it
+    /// returns, indented by how deeply nested it is.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace
+    }
+
+    /// Makes evaluating a quote give the expression of an identical quote
+    /// evaluated before, while it is alive, rather than its own. That saves
+    /// memory when the same data is quoted over and over, like by generated
+    /// code, and quotes sharing their expression are compared in no time.
+    /// Spans aren't compared, so errors and coverage in shared code point
+    /// at the copy evaluated first.
+    pub fn enable_hash_consing(&mut self) {
+        self.interned.get_or_insert_with(Default::default);
+    }
+
+    pub fn is_hash_consing(&self) -> bool {
+        self.interned.is_some()
+    }
+
+    /// How many distinct quoted expressions are shared with hash consing.
+    pub fn interned_nodes(&self) -> usize {
+        self.interned.as_ref().map_or(0, |interned| {
+            interned.borrow().values().flatten().filter(|node| node.strong_count() > 0).count()
+        })
+    }
+
+    /// The expression identical to `quoted` interned before, if hash consing
+    /// is enabled and there is one. Otherwise `quoted`, which is interned.
+    pub(crate) fn intern_quote(&self, quoted: &Rc<SExpr>) -> Rc<SExpr> {
+        let Some(interned) = &self.interned else { return quoted.clone() };
+        let mut interned = interned.borrow_mut();
+        let bucket = interned.entry(std_lib::structural_hash(quoted)).or_default();
+        bucket.retain(|node| node.strong_count() > 0);
+
+        let found = bucket
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|node| Rc::ptr_eq(node, quoted) || identical(node, quoted));
+        found.unwrap_or_else(|| {
+            bucket.push(Rc::downgrade(quoted));
+            quoted.clone()
+        })
+    }
+
+    /// Installs a hook called around the evaluation of every expression.
+    /// Instrumented code always runs on the evaluator, even with the VM on.
+    pub fn set_eval_hook(&mut self, hook: EvalHook) {
+        self.hook = Some(Hook(hook));
+    }
+
+    pub fn clear_eval_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// Limits how many more steps evaluation may take before failing with
+    /// `RuntimeError::OutOfFuel`. Entering `evaluate` and calling a lib
+    /// function take a step each. Setting it again refills the budget.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+        self.fuel_pool = None;
+    }
+
+    /// Lets evaluation run for as long as it takes.
+    pub fn clear_fuel(&mut self) {
+        self.fuel = None;
+        self.fuel_pool = None;
+    }
+
+    /// Takes steps from `pool`, shared with environments on other threads,
+    /// `FUEL_BATCH` of them at a time, so that together they take no more
+    /// than it holds.
+    pub(crate) fn share_fuel(&mut self, pool: Arc<AtomicU64>) {
+        self.fuel = Some(0);
+        self.fuel_pool = Some(pool);
+    }
+
+    /// Gives the steps taken from the shared budget but not used back to it.
+    pub(crate) fn return_fuel(&mut self) {
+        if let (Some(pool), Some(fuel)) = (&self.fuel_pool, self.fuel.replace(0)) {
+            pool.fetch_add(fuel, Ordering::Relaxed);
+        }
+    }
+
+    /// The steps left, if limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// The steps taken since the environment was created, limited or not.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Makes evaluation stop with `RuntimeError::Interrupted` soon after
+    /// `flag` is raised, from another thread or a signal handler. The flag
+    /// is lowered again when that happens.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// The flag given to `set_interrupt_flag`, if any.
+    pub fn interrupt_flag(&self) -> Option<Arc<AtomicBool>> {
+        self.interrupt.clone()
+    }
+
+    /// Forgets an interrupt that came while nothing was being evaluated.
+    pub fn clear_interrupt(&self) {
+        if let Some(flag) = &self.interrupt {
+            flag.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Makes evaluation of each top-level form fail with
+    /// `RuntimeError::Timeout` once it has taken longer than `timeout`. The
+    /// time starts over with each form `evaluate_toplevel` is given, but not
+    /// for the forms of a file loaded by one, and with `restart_timeout`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some((timeout, Instant::now() + timeout));
+    }
+
+    /// Lets top-level forms take as long as they take.
+    pub fn clear_timeout(&mut self) {
+        self.timeout = None;
+    }
+
+    /// The time each top-level form is given, if limited.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(|(timeout, _)| timeout)
+    }
+
+    /// Gives what is evaluated next the whole timeout again.
+    pub fn restart_timeout(&mut self) {
+        if let Some((timeout, deadline)) = &mut self.timeout {
+            *deadline = Instant::now() + *timeout;
+        }
+    }
+
+    /// The timeout and when the form being evaluated runs out of time, if
+    /// limited, for `pmap` to hold its threads to the same deadline.
+    pub(crate) fn deadline(&self) -> Option<(Duration, Instant)> {
+        self.timeout
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Option<(Duration, Instant)>) {
+        self.timeout = deadline;
+    }
+
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if self.steps % INTERRUPT_INTERVAL == 0 {
+            self.check_interrupt()?;
+        }
+        match &mut self.fuel {
+            Some(0) => self.refuel(),
+            Some(fuel) => {
+                *fuel -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a step out of a batch from the shared budget, if there is one
+    /// and it isn't used up.
+    fn refuel(&mut self) -> Result<(), RuntimeError> {
+        let Some(pool) = &self.fuel_pool else { return Err(RuntimeError::OutOfFuel) };
+        let left = pool
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| (left > 0).then(|| left.saturating_sub(FUEL_BATCH)))
+            .map_err(|_| RuntimeError::OutOfFuel)?;
+        self.fuel = Some(left.min(FUEL_BATCH) - 1);
+        Ok(())
+    }
+
+    /// Fails if evaluation was interrupted or ran out of time, for builtins
+    /// that wait, which take no steps while they do.
+    pub(crate) fn check_interrupt(&self) -> Result<(), RuntimeError> {
+        if let Some(flag) = &self.interrupt {
+            if flag.swap(false, Ordering::Relaxed) {
+                return Err(RuntimeError::Interrupted);
+            }
+        }
+        if let Some((timeout, deadline)) = self.timeout {
+            if Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets what arithmetic does when an int overflows, failing by default.
+    pub fn set_int_overflow(&mut self, mode: IntOverflow) {
+        self.int_overflow = mode;
+    }
+
+    pub fn int_overflow(&self) -> IntOverflow {
+        self.int_overflow
+    }
+
+    /// Limits the size of the values lib functions return and variables are
+    /// bound to, as measured by `Value::size`. Larger values fail with
+    /// `RuntimeError::ResourceLimit`.
+    pub fn set_size_limit(&mut self, limit: Option<usize>) {
+        self.size_limit = limit;
+    }
+
+    pub fn size_limit(&self) -> Option<usize> {
+        self.size_limit
+    }
+
+    pub fn check_size(&self, val: &Value) -> Result<(), RuntimeError> {
+        match self.size_limit {
+            Some(limit) if val.size() > limit => {
+                Err(RuntimeError::ResourceLimit { size: val.size(), limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts and times every function call, see `profile`.
+    pub fn set_profile(&mut self, profile: bool) {
+        match (profile, &self.profiler) {
+            (true, None) => self.profiler = Some(Profiler::new()),
+            (false, _) => self.profiler = None,
+            _ => (),
+        }
+    }
+
+    /// What was profiled so far, if profiling.
+    pub fn profile(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Counts how many times each expression of the sources given to
+    /// `add_source` is evaluated.
+    pub fn set_coverage(&mut self, coverage: bool) {
+        match (coverage, &self.coverage) {
+            (true, None) => self.coverage = Some(Coverage::new()),
+            (false, _) => self.coverage = None,
+            _ => (),
+        }
+    }
+
+    /// What was covered so far, if measuring coverage.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Tells coverage about a program about to run, parsed from `src` and
+    /// reported as `name`. The expressions must be the ones evaluated.
+    pub fn add_source(&mut self, name: &str, src: Rc<str>, exprs: Rc<[SExpr]>) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add_source(name, src, exprs);
+        }
+    }
+
+    /// Whether evaluation is being traced, hooked or covered, which only the
+    /// evaluator supports.
+    pub(crate) fn is_instrumented(&self) -> bool {
+        self.trace || self.hook.is_some() || self.coverage.is_some()
+    }
+
+    fn run_hook(&mut self, expr: &SExpr, phase: HookPhase) -> Result<(), RuntimeError> {
+        if let (Some(coverage), HookPhase::Before) = (&mut self.coverage, phase) {
+            coverage.hit(expr);
+        }
+
+        match &mut self.hook {
+            Some(Hook(hook)) => hook(expr, phase).map_err(|err| err.with_span(expr.span())),
+            None => Ok(()),
+        }
+    }
+
+    /// Marks `path` as the file whose code is being run, until `pop_file`.
+    /// Paths given to `load` are relative to its directory.
+    pub fn push_file(&mut self, path: PathBuf) {
+        self.files.push(path);
+    }
+
+    pub fn pop_file(&mut self) {
+        self.files.pop();
+    }
+
+    pub fn current_file(&self) -> Option<&Path> {
+        self.files.last().map(PathBuf::as_path)
+    }
+
+    /// The files being run, outermost first.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Drops every binding and registers the standard library again, and
+    /// makes `print` print as it does by default. Settings like the VM,
+    /// tracing, fuel and limits are kept.
+    pub fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.print_precision = None;
+        self.print_depth = printer::DEFAULT_MAX_DEPTH;
+        self.globals.clear();
+        self.scopes.clear();
+        self.stack.clear();
+        self.recur_arity = None;
+        self.recur_values.clear();
+        self.functions = 0;
+        self.returned = None;
+        self.call_stack.clear();
+        self.module = None;
+        self.modules.clear();
+        self.imports.clear();
+        self.module_files.clear();
+        self.constants.clear();
+        self.tests.clear();
+        self.benches.clear();
+        self.builtins.clear();
+        if self.vm.is_some() {
+            self.vm = Some(Vm::new());
+        }
+        std_lib::register(self)
+    }
+
+    /// Makes `print` and the other builtins that print write to `output`
+    /// instead of standard output, to capture what a program prints for
+    /// example. Output is buffered by default, so it is only written once
+    /// `flush` is called on it, by the program or the host, or when the
+    /// environment is dropped.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = Output(output);
+    }
+
+    /// Like `set_output`, giving back where the builtins printed before.
+    pub fn replace_output(&mut self, output: Box<dyn Write>) -> Box<dyn Write> {
+        std::mem::replace(&mut self.output.0, output)
+    }
+
+    /// Gives back where the builtins print, which is standard output again
+    /// afterwards.
+    pub fn take_output(&mut self) -> Box<dyn Write> {
+        std::mem::replace(&mut self.output, Output::stdout()).0
+    }
+
+    /// Runs `f` with what the builtins print going to a buffer instead, and
+    /// gives its result and what was printed. Output printed before is
+    /// flushed first, and where it went is restored afterwards.
+    pub fn capture_output<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> (R, String) {
+        let _ = self.output().flush();
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let previous = self.replace_output(Box::new(Captured(buffer.clone())));
+        let result = f(self);
+        self.set_output(previous);
+        let printed = String::from_utf8_lossy(&RefCell::borrow(&buffer)).into_owned();
+        (result, printed)
+    }
+
+    /// Makes `print` round floats to `precision` digits after the point, or
+    /// print as many as it takes to read them back, the default, if `None`.
+    /// Values written to be read back in are never rounded. Precisions past
+    /// `printer::MAX_PRECISION` are taken as that.
+    pub fn set_print_precision(&mut self, precision: Option<usize>) {
+        self.print_precision = precision.map(|digits| digits.min(printer::MAX_PRECISION));
+    }
+
+    pub fn print_precision(&self) -> Option<usize> {
+        self.print_precision
+    }
+
+    /// Makes `print` write the lists nested deeper than `depth` levels as
+    /// `...`, `printer::DEFAULT_MAX_DEPTH` by default.
+    pub fn set_print_depth(&mut self, depth: usize) {
+        self.print_depth = depth;
+    }
+
+    pub fn print_depth(&self) -> usize {
+        self.print_depth
+    }
+
+    /// Makes `time-now` give the time `clock` returns, in milliseconds since
+    /// the epoch, instead of reading the system clock, so that runs can be
+    /// reproduced with the time frozen.
+    pub fn set_clock(&mut self, clock: Box<dyn Fn() -> u64>) {
+        self.clock = Some(Clock(clock));
+    }
+
+    /// The milliseconds since the epoch by the clock set with `set_clock`,
+    /// if one was.
+    pub fn clock(&self) -> Option<u64> {
+        self.clock.as_ref().map(|clock| (clock.0)())
+    }
+
+    /// Seeds the generator `random` draws from, so that it gives the same
+    /// numbers every run.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random = Some(seed);
+    }
+
+    /// The next 64 bits of the generator `random` draws from, splitmix64,
+    /// which is fast and good enough for programs, though not for secrets.
+    /// Unless it was seeded, it is seeded by the clock set with `set_clock`,
+    /// or the system's.
+    pub(crate) fn next_random(&mut self) -> u64 {
+        let state = match self.random {
+            Some(state) => state,
+            None => self.clock().unwrap_or_else(system_seed),
+        };
+        let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.random = Some(state);
+        let bits = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        let bits = (bits ^ (bits >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        bits ^ (bits >> 31)
+    }
+
+    /// A promise of what `expr` evaluates to, which `collect_garbage` keeps
+    /// track of.
+    pub fn new_promise(&mut self, expr: Rc<SExpr>) -> Rc<Promise> {
+        if self.promises.len() == self.promises.capacity() {
+            self.promises.retain(|promise| promise.strong_count() > 0);
+        }
+        let promise = Rc::new(Promise::new(expr));
+        self.promises.push(Rc::downgrade(&promise));
+        promise
+    }
+
+    /// Frees what only reference cycles keep alive, giving how many values
+    /// that was. Cycles go through promises, so the forced promises that no
+    /// binding, argument or value on the stack leads to have their value
+    /// dropped, to be evaluated again if they are ever forced again. That
+    /// includes those only the host or a native closure holds. Meant to be
+    /// called between top-level forms.
+    pub fn collect_garbage(&mut self) -> usize {
+        let roots = self
+            .globals
+            .values()
+            .chain(self.scopes.iter().flatten().map(|(_, val)| val))
+            .chain(&self.stack)
+            .chain(&self.recur_values)
+            .chain(&self.returned);
+        // Forced values are never promises, so a promise can only be
+        // reached from a root directly.
+        let reachable: HashSet<*const Promise> = roots
+            .filter_map(|val| match &**val {
+                Value::Promise(promise) => Some(Rc::as_ptr(promise)),
+                _ => None,
+            })
+            .collect();
+
+        let mut collected = 0;
+        for promise in self.promises.iter().filter_map(Weak::upgrade) {
+            if !reachable.contains(&Rc::as_ptr(&promise)) && promise.unforce() {
+                collected += 1;
+            }
+        }
+        self.promises.retain(|promise| promise.strong_count() > 0);
+        collected
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output.0
+    }
+
+    pub fn add_test(&mut self, name: Symbol, body: Rc<SExpr>) {
+        let file = self.current_file().map(Path::to_path_buf);
+        self.tests.push(Test { name, body, file });
+    }
+
+    /// The tests registered so far, in the order they were.
+    pub fn tests(&self) -> &[Test] {
+        &self.tests
+    }
+
+    pub fn add_bench(&mut self, bench: Bench) {
+        self.benches.push(bench);
+    }
+
+    /// The benchmarks registered so far, in the order they were.
+    pub fn benches(&self) -> &[Bench] {
+        &self.benches
+    }
+
+    /// Runs `f` on its own: with an empty stack, outside of any function
+    /// call or module, and with the bindings put back the way they were
+    /// once it is done, whatever it did.
+    pub fn isolated<T>(&mut self, f: impl FnOnce(&mut Environment) -> T) -> T {
+        let globals = self.globals.clone();
+        let constants = self.constants.clone();
+        let modules = self.modules.clone();
+        let imports = self.imports.clone();
+        let module = self.module.take();
+        let scopes = std::mem::take(&mut self.scopes);
+        let stack = std::mem::take(&mut self.stack);
+        let native = self.native.take();
+        let recur_arity = self.recur_arity.take();
+        let functions = std::mem::take(&mut self.functions);
+        let call_stack = std::mem::take(&mut self.call_stack);
+
+        let retr = f(self);
+
+        self.globals = globals;
+        self.constants = constants;
+        self.modules = modules;
+        self.imports = imports;
+        self.module = module;
+        self.scopes = scopes;
+        self.stack = stack;
+        self.native = native;
+        self.recur_arity = recur_arity;
+        self.functions = functions;
+        self.call_stack = call_stack;
+        retr
+    }
+
+    /// A new environment with only the global bindings of this one named in
+    /// `allowed`, builtins included, and `nil`, `t` and `f`, for running
+    /// code that shouldn't see or change the others. Functions among them run
+    /// with the sandbox's bindings, so the names they use must be allowed
+    /// too. Values that can change are never shared: promises are copied, so
+    /// that forcing one only forces the copy, and handles, which can't be,
+    /// are left out as if they weren't allowed. Settings, like the fuel and
+    /// the output, are the sandbox's own, starting out as those of
+    /// `Environment::new`.
+    pub fn sandbox(&self, allowed: &[&str]) -> Environment {
+        let mut sandbox = Environment::new();
+        sandbox.symbols = self.symbols.clone();
+        sandbox.features = self.features.clone();
+        for (name, val) in [("nil", std_lib::nil_ref()), ("t", std_lib::true_ref()), ("f", std_lib::false_ref())] {
+            sandbox.globals.insert(self.intern(name), RefVal::reference(val));
+        }
+        for name in allowed {
+            if let Some((name, val)) = self.globals.get_key_value(*name) {
+                let Some(val) = sandbox.unshared(val) else { continue };
+                sandbox.globals.insert(name.clone(), val);
+                if let Some(place) = self.constants.get(name) {
+                    sandbox.constants.insert(name.clone(), place.clone());
+                }
+                if let Some(spec) = self.builtin(name) {
+                    sandbox.builtins.push(spec.clone());
+                }
+            }
+        }
+        sandbox
+    }
+
+    /// `val`, for a sandbox, with a copy of it if it is a promise, or `None`
+    /// if it is a handle, or a promise being forced or standing for one.
+    fn unshared(&mut self, val: &RefVal) -> Option<RefVal> {
+        match &**val {
+            Value::Promise(promise) => {
+                let (expr, forced) = match &*promise.0.borrow() {
+                    PromiseState::Delayed(expr) => (expr.clone(), None),
+                    PromiseState::Forced(forced, expr) => (expr.clone(), Some(forced.clone())),
+                    PromiseState::Forcing => return None,
+                };
+                let copy = self.new_promise(expr.clone());
+                if let Some(forced) = forced {
+                    *copy.0.borrow_mut() = PromiseState::Forced(self.unshared(&forced)?, expr);
+                }
+                Some(RefVal::owned(Value::Promise(copy)))
+            }
+            Value::Handle(_) => None,
+            _ => Some(val.clone()),
+        }
+    }
+
+    /// Reads `src` and evaluates its forms in order, giving the value of the
+    /// last one, or nil if there are none. `comptime` forms are all run
+    /// before the first form is, as the CLI does. Positions in errors are
+    /// into `src`.
+    pub fn eval_str(&mut self, src: &str) -> Result<RefVal, EvalError> {
+        let exprs = self.reader(src).parse_sexprs().map_err(|err| EvalError::parse(&err))?;
+        let exprs = exprs
+            .into_iter()
+            .map(|expr| optimize::comptime(expr, self))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| EvalError::runtime(err, src))?;
+        let mut last = RefVal::reference(std_lib::nil_ref());
+        for expr in &exprs {
+            last = evaluate_toplevel(expr, self).map_err(|err| EvalError::runtime(err, src))?;
+        }
+        Ok(last)
+    }
+
+    /// Evaluates `src` like `eval_str`, in a `sandbox` with the bindings
+    /// named in `allowed`, which is gone afterwards with whatever `src`
+    /// defined. To limit its fuel or anything else, make the sandbox first.
+    pub fn eval_str_in_sandbox(&self, allowed: &[&str], src: &str) -> Result<RefVal, EvalError> {
+        self.sandbox(allowed).eval_str(src)
+    }
+
+    /// Calls the function bound to `name` with `args`, like `(name args ...)`
+    /// would. Fails with `RuntimeError::UnboundVariable` if nothing is bound
+    /// to the name, `NotAFunction` if something else than a function is, and
+    /// `ArityMismatch` if it doesn't take that many arguments.
+    pub fn call_by_name(&mut self, name: &str, args: &[RefVal]) -> Result<RefVal, RuntimeError> {
+        let fun = self.lookup_var(name).cloned().ok_or_else(|| self.unbound(name, None))?;
+        let Value::Function(fun) = &*fun else {
+            let mut call = vec![name.to_string()];
+            call.extend(args.iter().map(|arg| Written(&**arg).to_string()));
+            let call = format!("({})", call.join(" "));
+            return Err(RuntimeError::NotAFunction {
+                value: error::truncate(&Written(&*fun).to_string(), ERROR_EXPR_LEN),
+                call: error::truncate(&call, ERROR_EXPR_LEN),
+            });
+        };
+        if !fun.takes(args.len()) {
+            return Err(RuntimeError::ArityMismatch { expected: fun.arities(), got: args.len(), callee: fun.to_string() });
+        }
+
+        self.stack.extend(args.iter().cloned());
+        call(fun, args.len(), self)
+    }
+
+    /// `call_by_name` with Rust values, converted to values and back as
+    /// `convert` describes, as in
+    /// `let area: f64 = env.call_typed("area", [2.0, 3.5])?`.
+    pub fn call_typed<A, R>(&mut self, name: &str, args: impl IntoIterator<Item = A>) -> Result<R, RuntimeError>
+    where
+        A: Into<RefVal>,
+        R: TryFrom<RefVal, Error = RuntimeError>,
+    {
+        let args: Vec<RefVal> = args.into_iter().map(Into::into).collect();
+        R::try_from(self.call_by_name(name, &args)?)
+    }
+
+    /// Runs the bodies of user defined functions on the bytecode VM instead
+    /// of walking their expressions.
+    pub fn set_use_vm(&mut self, use_vm: bool) {
+        match (use_vm, &self.vm) {
+            (true, None) => self.vm = Some(Vm::new()),
+            (false, _) => self.vm = None,
+            _ => (),
         }
     }
 
-    pub fn pop_stack(&mut self) -> RefVal {
-        self.stack.pop().unwrap()
+    pub(crate) fn vm_mut(&mut self) -> Option<&mut Vm> {
+        self.vm.as_mut()
+    }
+
+    /// Sets how deeply `evaluate` may be re-entered (through function calls,
+    /// `if`, `eval`, ...) before giving up with an error. Code read through
+    /// `reader` can be nested as deep.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The name of the lib function running, if any.
+    pub fn running_lib_fn(&self) -> Option<&'static str> {
+        self.native.map(|native| native.name)
+    }
+
+    /// How many arguments the running lib function was given, which only
+    /// variadic ones need to ask.
+    pub fn arg_count(&self) -> usize {
+        self.native.map_or(0, |native| native.argc)
+    }
+
+    /// Pops an argument of the running lib function. It is an error to pop
+    /// more values than the function's arity.
+    pub fn pop_stack(&mut self) -> Result<RefVal, RuntimeError> {
+        match self.native {
+            Some(native) if self.stack.len() <= native.floor => Err(RuntimeError::StackUnderflow {
+                callee: Some(format!("lib function '{}' with {} arguments", native.name, native.argc)),
+            }),
+
+            _ => self
+                .stack
+                .pop()
+                .ok_or(RuntimeError::StackUnderflow { callee: None }),
+        }
     }
 
     pub fn push_stack(&mut self, val: RefVal) {
         self.stack.push(val);
     }
 
+    /// Binds `name` to a function implemented in Rust, which takes its
+    /// `arity` arguments from the stack. It can be a `LibFn` or a closure,
+    /// keeping whatever it captures, a counter or a connection, for as long
+    /// as the function lives.
     pub fn register_external_fun(
         &mut self,
         name: &'static str,
         arity: usize,
-        ptr: fn(&mut Environment) -> Result<RefVal, String>,
+        ptr: impl Fn(&mut Environment) -> Result<RefVal, RuntimeError> + 'static,
+    ) {
+        self.register_builtin(BuiltinSpec::new(name, arity), ptr);
+    }
+
+    /// Like `register_external_fun`, with the documentation, purity and
+    /// category of `spec`, which `builtins` gives back.
+    pub fn register_builtin(
+        &mut self,
+        spec: BuiltinSpec,
+        ptr: impl Fn(&mut Environment) -> Result<RefVal, RuntimeError> + 'static,
     ) {
-        self.variables.insert(
-            name.to_string(),
-            vec![RefVal::owned(Value::Function(Function::Lib {
-                name,
-                arity,
-                ptr,
-            }))],
+        self.globals.insert(
+            self.symbols.intern(spec.name),
+            RefVal::owned(Value::Function(Function::Lib {
+                name: spec.name,
+                arity: spec.arity,
+                variadic: spec.variadic,
+                ptr: Rc::new(ptr),
+                doc: spec.doc,
+            })),
         );
+        match self.builtins.iter_mut().find(|registered| registered.name == spec.name) {
+            Some(registered) => *registered = spec,
+            None => self.builtins.push(spec),
+        }
     }
 
-    pub fn bind_var(&mut self, name: impl ToString, val: RefVal) {
-        let name = name.to_string();
-        if let Some(entry) = self.variables.get_mut(&name) {
-            entry.push(val);
-        } else {
-            self.variables.insert(name, vec![val]);
+    /// The builtins registered so far, in order, even those whose names
+    /// were bound to something else since.
+    pub fn builtins(&self) -> impl Iterator<Item = &BuiltinSpec> {
+        self.builtins.iter()
+    }
+
+    /// The builtin registered as `name`, if `name` is still bound to it.
+    pub fn builtin(&self, name: &str) -> Option<&BuiltinSpec> {
+        match self.lookup_var(name).map(|val| &**val) {
+            Some(Value::Function(Function::Lib { name: registered, .. })) if *registered == name => {
+                self.builtins.iter().find(|spec| spec.name == name)
+            }
+            _ => None,
         }
     }
 
-    pub fn unbind_var(&mut self, name: &str) -> Result<(), String> {
-        if let Some(entry) = self.variables.get_mut(name) {
-            let popped = entry.pop();
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::new());
+    }
+
+    /// Drops the innermost scope along with all of its bindings.
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop().expect("popped a scope that was never pushed");
+    }
+
+    /// Pushes a new scope that is popped again when the returned guard is
+    /// dropped, even if evaluation bails out with an error in between.
+    pub fn scope(&mut self) -> ScopeGuard<'_> {
+        self.push_scope();
+        ScopeGuard { env: self }
+    }
 
-            // As soon as the vector is empty, we remove the entry. Therefore it
-            // shouldn't be possible to fail this assertion.
-            assert!(popped.is_some());
+    /// The table identifiers are interned in. Code meant to run in this
+    /// environment should be read with the same table.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
 
-            if entry.len() == 0 {
-                self.variables.remove(name);
+    pub fn intern(&self, name: &str) -> Symbol {
+        self.symbols.intern(name)
+    }
+
+    /// The features `#+feature(name)` tests for in code read with `reader`,
+    /// those the interpreter was built with unless some were added.
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+
+    pub fn add_feature(&mut self, name: &str) {
+        self.features.insert(name);
+    }
+
+    /// A reader for code meant to run in this environment, with its symbol
+    /// table and its features.
+    pub fn reader<'a>(&self, src: &'a str) -> Reader<'a> {
+        Reader::with_symbols(src, self.symbols.clone())
+            .with_features(self.features.clone())
+            .with_max_depth(self.max_depth)
+    }
+
+    /// Binds `name` in the innermost scope, or globally at the top level.
+    /// Rebinding a name in the same scope replaces the old binding, only
+    /// inner scopes shadow outer ones.
+    pub fn bind_var(&mut self, name: Symbol, val: RefVal) -> Result<(), RuntimeError> {
+        self.check_size(&val)?;
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                match scope.iter_mut().find(|(bound, _)| symbol::same_symbol(bound, &name)) {
+                    Some((_, slot)) => *slot = val,
+                    None => scope.push((name, val)),
+                }
+            }
+            None => {
+                self.globals.insert(name, val);
             }
+        }
+        Ok(())
+    }
 
-            Ok(())
-        } else {
-            Err("variable not bound".to_string())
+    /// Binds `name` in the global scope, replacing any previous definition
+    /// unless it is a constant. Inside a module, the name is qualified with
+    /// the module's.
+    pub fn define_var(&mut self, name: &str, val: RefVal) -> Result<(), RuntimeError> {
+        self.define_global(name, val).map(drop)
+    }
+
+    /// Like `define_var`, but the binding can't be replaced afterwards. `span`
+    /// is where `name` is in the definition.
+    pub fn define_const(&mut self, name: &str, val: RefVal, span: Span) -> Result<(), RuntimeError> {
+        let name = self.define_global(name, val)?;
+        let place = self.current_file().map(|file| (file.to_path_buf(), span));
+        self.constants.insert(name, place);
+        Ok(())
+    }
+
+    /// Binds the global `name`, giving the name it is bound to.
+    fn define_global(&mut self, name: &str, val: RefVal) -> Result<Symbol, RuntimeError> {
+        self.check_size(&val)?;
+        let qualified = match &self.module {
+            Some(module) => self.intern(&format!("{}/{}", module, name)),
+            None => self.intern(name),
+        };
+        self.check_not_constant(&qualified)?;
+        if let Some(module) = self.module.clone() {
+            let name = self.intern(name);
+            self.modules.entry(module).or_default().insert(name, qualified.clone());
+        }
+        self.globals.insert(qualified.clone(), val);
+        Ok(qualified)
+    }
+
+    fn check_not_constant(&self, name: &Symbol) -> Result<(), RuntimeError> {
+        let Some(place) = self.constants.get(name) else { return Ok(()) };
+        // The span only means something in the file being run.
+        let (span, file) = match place {
+            Some((file, span)) if Some(file.as_path()) == self.current_file() => (Some(*span), None),
+            Some((file, _)) => (None, Some(file.display().to_string())),
+            None => (None, None),
+        };
+        Err(RuntimeError::ConstantRedefined { name: name.to_string(), span, file })
+    }
+
+    /// Makes definitions go into `module` until `leave_module` is called
+    /// with what this returns.
+    pub fn enter_module(&mut self, module: Symbol) -> Option<Symbol> {
+        self.modules.entry(module.clone()).or_default();
+        self.module.replace(module)
+    }
+
+    pub fn leave_module(&mut self, outer: Option<Symbol>) {
+        self.module = outer;
+    }
+
+    pub fn current_module(&self) -> Option<&Symbol> {
+        self.module.as_ref()
+    }
+
+    pub fn has_module(&self, module: &str) -> bool {
+        self.modules.contains_key(module)
+    }
+
+    /// Records that `path` was loaded to look for modules, returning whether
+    /// it was the first time.
+    pub fn mark_module_file(&mut self, path: PathBuf) -> bool {
+        self.module_files.insert(path)
+    }
+
+    /// Binds the names defined in `module`, or only the ones in `only`,
+    /// without qualifying them. Nothing is bound if any name would replace
+    /// an existing binding, other than one imported earlier from the same
+    /// definition.
+    pub fn import(&mut self, module: &str, only: Option<&[Symbol]>) -> Result<(), RuntimeError> {
+        let names = match self.modules.get(module) {
+            Some(names) => names,
+            None => {
+                let mut known: Vec<&str> = self.modules.keys().map(|name| &**name).collect();
+                known.sort();
+                let mut msg = format!("no module named '{}'", module);
+                if !known.is_empty() {
+                    msg += &format!(", the modules are '{}'", known.join("', '"));
+                }
+                return Err(msg.into());
+            }
+        };
+
+        let selected: Vec<(Symbol, Symbol)> = match only {
+            Some(only) => only
+                .iter()
+                .map(|name| match names.get(name) {
+                    Some(qualified) => Ok((name.clone(), qualified.clone())),
+                    None => Err(format!("module '{}' has no definition named '{}'", module, name)),
+                })
+                .collect::<Result<_, _>>()?,
+            None => names.iter().map(|(name, qualified)| (name.clone(), qualified.clone())).collect(),
+        };
+
+        for (name, qualified) in &selected {
+            match self.imports.get(name) {
+                Some(imported) if imported == qualified => (),
+                Some(imported) => {
+                    return Err(format!(
+                        "importing '{}' would replace '{}', imported as '{}'",
+                        qualified, imported, name
+                    ).into());
+                }
+                None if self.globals.contains_key(name) => {
+                    return Err(format!(
+                        "importing '{}' would replace the existing definition of '{}'",
+                        qualified, name
+                    ).into());
+                }
+                None => (),
+            }
+        }
+
+        for (name, qualified) in selected {
+            if let Some(val) = self.globals.get(&qualified).cloned() {
+                self.globals.insert(name.clone(), val);
+                self.imports.insert(name, qualified);
+            }
         }
+        Ok(())
     }
 
+    /// Looks `name` up from the innermost scope outwards, ending at globals.
     pub fn lookup_var(&self, name: &str) -> Option<&RefVal> {
-        self.variables.get(name).and_then(|vars| vars.iter().last())
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| &**bound == name)
+            .map(|(_, val)| val)
+            .or_else(|| self.lookup_global(name))
+    }
+
+    /// The value bound in the innermost scope at `slot`, in binding order.
+    pub(crate) fn scoped(&self, slot: usize) -> &RefVal {
+        &self.scopes.last().expect("no scope to load from")[slot].1
+    }
+
+    /// Like `lookup_var`, but compares the scoped names by pointer first.
+    pub fn lookup_symbol(&self, name: &Symbol) -> Option<&RefVal> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| symbol::same_symbol(bound, name))
+            .map(|(_, val)| val)
+            .or_else(|| self.lookup_global(name))
+    }
+
+    /// Inside a module its own definitions come first.
+    fn lookup_global(&self, name: &str) -> Option<&RefVal> {
+        self.module
+            .as_ref()
+            .and_then(|module| self.modules.get(module)?.get(name))
+            .and_then(|qualified| self.globals.get(qualified))
+            .or_else(|| self.globals.get(name))
+    }
+
+    /// Every name that is bound, innermost scopes first. Shadowed names show
+    /// up more than once.
+    pub fn bound_names(&self) -> impl Iterator<Item = &Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().map(|(name, _)| name))
+            .chain(self.globals.keys())
+    }
+
+    /// The global bindings, those of modules under their qualified names,
+    /// in no particular order.
+    pub fn globals(&self) -> impl Iterator<Item = (&Symbol, &RefVal)> {
+        self.globals.iter()
+    }
+
+    /// Whether the global `name` was bound with `defconst`.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains_key(name)
+    }
+
+    /// Whether the global `name` is a module's, bound under its qualified
+    /// name.
+    pub fn is_in_module(&self, name: &str) -> bool {
+        self.modules.values().any(|names| names.values().any(|qualified| &**qualified == name))
+    }
+
+    /// Every binding `lookup_var` can find, innermost scopes first, and the
+    /// names in the current module before the other globals. Names bound
+    /// more than once only show up with the value they stand for here.
+    pub fn iter_bindings(&self) -> impl Iterator<Item = (&Symbol, &RefVal)> {
+        let scoped = self.scopes.iter().rev().flat_map(|scope| scope.iter().rev().map(|(name, val)| (name, val)));
+        let module = self
+            .module
+            .as_ref()
+            .and_then(|module| self.modules.get(module))
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, qualified)| Some((name, self.globals.get(qualified)?)));
+
+        let mut seen = HashSet::new();
+        scoped
+            .chain(module)
+            .chain(&self.globals)
+            .filter(move |(name, _)| seen.insert(&***name))
+    }
+
+    /// Bound names that are a small edit away from `name`, closest first.
+    pub fn similar_names(&self, name: &str) -> Vec<String> {
+        let mut candidates: Vec<_> = self
+            .bound_names()
+            .filter_map(|key| Some((error::near_miss(name, key)?, key)))
+            .collect();
+
+        candidates.sort();
+        candidates.dedup();
+        candidates.into_iter().take(3).map(|(_, key)| key.to_string()).collect()
+    }
+
+    fn trace_line(&self, indent: usize, prefix: &str, what: &dyn std::fmt::Display) {
+        let what = error::truncate(&what.to_string(), TRACE_LEN);
+        eprintln!("{:indent$}{}{}", "", prefix, what, indent = indent * 2);
+    }
+
+    pub(crate) fn unbound(&self, name: &str, call: Option<&SExpr>) -> RuntimeError {
+        RuntimeError::UnboundVariable {
+            name: name.to_string(),
+            call: call.map(|expr| error::truncate(&Written(expr).to_string(), ERROR_EXPR_LEN)),
+            suggestions: self.similar_names(name),
+        }
+    }
+
+    /// Hands `values` to the innermost loop or function body, by unwinding
+    /// up to it with `RuntimeError::Recur`.
+    pub(crate) fn recur(&mut self, values: Vec<RefVal>) -> Result<RefVal, RuntimeError> {
+        let expected = self.recur_arity.ok_or("'recur' can only be used in a loop or a function body")?;
+        if values.len() != expected {
+            let plural = if expected == 1 { "" } else { "s" };
+            return Err(format!("'recur' expected {} value{}, got {}", expected, plural, values.len()).into());
+        }
+        self.recur_values = values;
+        Err(RuntimeError::Recur)
+    }
+
+    /// Runs `f` without losing the values of a `recur` or a `return` on
+    /// their way out, for code that runs as the stack unwinds.
+    pub(crate) fn while_unwinding<T>(&mut self, f: impl FnOnce(&mut Environment) -> T) -> T {
+        let recur_values = std::mem::take(&mut self.recur_values);
+        let returned = self.returned.take();
+        let retr = f(self);
+        self.recur_values = recur_values;
+        self.returned = returned;
+        retr
+    }
+
+    /// Ends the innermost call of a user defined function with `val`, by
+    /// unwinding up to it with `RuntimeError::Return`.
+    pub(crate) fn return_from(&mut self, val: RefVal) -> Result<RefVal, RuntimeError> {
+        if self.functions == 0 {
+            return Err("'return' can only be used in a function".into());
+        }
+        self.returned = Some(val);
+        Err(RuntimeError::Return)
     }
 }
 
-pub fn evaluate(expr: &SExpr, env: &mut Environment) -> Result<RefVal, String> {
-    match expr {
-        SExpr::Atom(atom) => match atom {
-            Atom::Ident(ident) => env
-                .lookup_var(ident)
-                .ok_or(format!("name '{ident}' was not defined"))
-                .cloned(),
+/// A list expression whose elements are being evaluated. Once every element
+/// has a value, the first one is applied to the rest.
+struct Frame<'a> {
+    expr: &'a SExpr,
+    elements: list::Iter<'a, SExpr>,
+    values: Vec<RefVal>,
+}
 
-            Atom::String(s) => Ok(RefVal::owned(Value::String(s.clone()))),
-            Atom::Number(n) => Ok(RefVal::owned(Value::Number(*n))),
-            Atom::Quote(box q) => Ok(RefVal::owned(Value::Quote(q.clone()))),
-        },
+/// Evaluates `expr`. Nested list expressions are handled with an explicit
+/// stack of frames rather than native recursion, so arbitrarily deep
+/// expressions only cost heap. Calling into a function body (directly, or
+/// through builtins like `if` and `eval`) does re-enter `evaluate`, and that
+/// nesting is bounded by `Environment::set_max_depth`.
+pub fn evaluate(expr: &SExpr, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    nested(env, |env| run(expr, env))
+}
 
-        SExpr::List(elements) => {
-            let values: Vec<_> = elements
-                .into_iter()
-                .map(|expr| evaluate(expr, env))
-                .collect::<Result<_, _>>()?;
+/// The value of the last form of `src`, evaluated in a new environment with
+/// the standard library, or nil if there are none. See
+/// `Environment::eval_str` to evaluate more in the same environment.
+pub fn eval_str(src: &str) -> Result<RefVal, EvalError> {
+    let mut env = Environment::with_std_lib().map_err(|err| EvalError::runtime(err, src))?;
+    env.eval_str(src)
+}
 
-            let fun = values
-                .get(0)
-                .ok_or("expected list to have at least one element".to_string())?
-                .clone();
+/// Evaluates a top-level form, on the VM if it is enabled.
+pub fn evaluate_toplevel(expr: &SExpr, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if env.depth == 0 {
+        env.restart_timeout();
+    }
+    if env.vm.is_some() {
+        vm::evaluate(expr, env)
+    } else {
+        evaluate(expr, env)
+    }
+}
 
-            if let Value::Function(fun) = fun.borrow() {
-                if fun.arity() != values[1..].len() {
-                    return Err(format!(
-                        "expected {} arguments, but got {} in {:?}",
-                        fun.arity(),
-                        values[1..].len(),
-                        fun
-                    ));
+/// Runs `f` one level deeper, failing if that goes past the maximum depth.
+pub(crate) fn nested(
+    env: &mut Environment,
+    f: impl FnOnce(&mut Environment) -> Result<RefVal, RuntimeError>,
+) -> Result<RefVal, RuntimeError> {
+    if env.depth >= env.max_depth {
+        return Err(RuntimeError::DepthExceeded { limit: env.max_depth });
+    }
+    env.step()?;
+
+    // Scopes are popped by their guards as errors propagate, but values may
+    // be left on the stack by the call that failed, so drop those as well.
+    let stack_len = env.stack.len();
+    env.depth += 1;
+    let retr = f(env);
+    env.depth -= 1;
+    if retr.is_err() {
+        env.stack.truncate(stack_len);
+    }
+    retr
+}
+
+fn run(expr: &SExpr, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut current = expr;
+    let trace_base = env.trace_indent;
+
+    loop {
+        env.run_hook(current, HookPhase::Before)?;
+
+        let mut value = match current {
+            SExpr::Atom(atom, span) => {
+                let value = evaluate_atom(atom, env).map_err(|err| err.with_span(*span))?;
+                env.run_hook(current, HookPhase::After)?;
+                Some(value)
+            }
+
+            // The empty list evaluates to nil, like `'()` does.
+            SExpr::List(elements, _) if elements.is_empty() => {
+                env.run_hook(current, HookPhase::After)?;
+                Some(RefVal::reference(std_lib::nil_ref()))
+            }
+
+            SExpr::List(elements, _) => {
+                let head = &elements[0];
+
+                if env.trace {
+                    env.trace_line(trace_base + frames.len(), "", current);
                 }
-                env.stack.extend(values[1..].iter().cloned());
-                call(fun, env)
-            } else {
-                Err(format!("expected a function got `{}`", fun))
+
+                let mut frame = Frame {
+                    expr: current,
+                    elements: elements.iter(),
+                    values: Vec::with_capacity(elements.len()),
+                };
+
+                // Resolve an identifier in call position here, so that the
+                // error can mention the whole call.
+                if let SExpr::Atom(Atom::Ident(ident), _) = head {
+                    env.run_hook(head, HookPhase::Before)?;
+                    let fun = env
+                        .lookup_symbol(ident)
+                        .cloned()
+                        .ok_or_else(|| env.unbound(ident, Some(current)).with_span(head.span()))?;
+                    env.run_hook(head, HookPhase::After)?;
+
+                    frame.elements.next();
+                    frame.values.push(fun);
+                }
+
+                frames.push(frame);
+                None
+            }
+        };
+
+        // Feed the value to the innermost pending list, applying every list
+        // that got complete on the way.
+        loop {
+            let frame = match frames.last_mut() {
+                Some(frame) => frame,
+                None => return Ok(value.expect("evaluation yielded no value")),
+            };
+
+            frame.values.extend(value.take());
+            if let Some(next) = frame.elements.next() {
+                current = next;
+                break;
             }
+
+            // Errors raised by the function being applied, including the
+            // ones from lib functions, point at the whole call.
+            let frame = frames.pop().unwrap();
+            let span = frame.expr.span();
+            // Code run by the function nests under the call.
+            env.trace_indent = trace_base + frames.len() + 1;
+            let result = apply(frame.values, frame.expr, env);
+            env.trace_indent = trace_base;
+
+            let result = result.map_err(|err| err.with_span(span))?;
+            env.run_hook(frame.expr, HookPhase::After)?;
+            if env.trace {
+                env.trace_line(trace_base + frames.len(), "=> ", &result);
+            }
+            value = Some(result);
+        }
+    }
+}
+
+fn evaluate_atom(atom: &Atom, env: &Environment) -> Result<RefVal, RuntimeError> {
+    let value = match atom {
+        Atom::Ident(_) if atom.as_keyword().is_some() => RefVal::owned(Value::Quote(Rc::new(SExpr::atom(atom.clone())))),
+        Atom::Ident(ident) => env
+            .lookup_symbol(ident)
+            .cloned()
+            .ok_or_else(|| env.unbound(ident, None))?,
+
+        Atom::String(s) => RefVal::owned(Value::String(s.clone())),
+        Atom::Int(n) => RefVal::owned(Value::Int(*n)),
+        Atom::BigInt(n) => RefVal::owned(Value::BigInt(n.clone())),
+        Atom::Rational(num, den) => RefVal::owned(Value::Rational(*num, *den)),
+        Atom::Float(n) => RefVal::owned(Value::Float(*n)),
+        Atom::Quote(q) => RefVal::owned(Value::Quote(env.intern_quote(q))),
+    };
+    Ok(value)
+}
+
+/// Whether two expressions are the same but for their spans, down to the
+/// bits of floats, so that one can stand for the other.
+fn identical(lhs: &SExpr, rhs: &SExpr) -> bool {
+    match (lhs, rhs) {
+        (SExpr::List(lhs, _), SExpr::List(rhs, _)) => {
+            lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| identical(lhs, rhs))
+        }
+        (SExpr::Atom(lhs, _), SExpr::Atom(rhs, _)) => match (lhs, rhs) {
+            (Atom::Int(lhs), Atom::Int(rhs)) => lhs == rhs,
+            (Atom::BigInt(..), Atom::BigInt(..)) | (Atom::Rational(..), Atom::Rational(..)) => lhs == rhs,
+            (Atom::Float(lhs), Atom::Float(rhs)) => lhs.to_bits() == rhs.to_bits(),
+            (Atom::String(lhs), Atom::String(rhs)) => lhs == rhs,
+            (Atom::Ident(lhs), Atom::Ident(rhs)) => lhs == rhs,
+            (Atom::Quote(lhs), Atom::Quote(rhs)) => Rc::ptr_eq(lhs, rhs) || identical(lhs, rhs),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Applies the first of `values` to the rest, `expr` being the call they came
+/// from.
+pub(crate) fn apply(
+    values: Vec<RefVal>,
+    expr: &SExpr,
+    env: &mut Environment,
+) -> Result<RefVal, RuntimeError> {
+    let mut values = values.into_iter();
+    let fun = values.next().unwrap();
+
+    // `recur` takes as many values as its loop binds, which no arity fits.
+    if let Value::Function(Function::Lib { name: "recur", .. }) = fun.borrow() {
+        return env.recur(values.collect());
+    }
+    let args = values.as_slice();
+
+    if let Value::Function(fun) = fun.borrow() {
+        let argc = args.len();
+        if !fun.takes(argc) {
+            return Err(RuntimeError::ArityMismatch {
+                expected: fun.arities(),
+                got: argc,
+                callee: fun.to_string(),
+            });
         }
+        env.stack.extend(values);
+
+        env.call_stack.push(describe_call(fun, expr));
+        let retr = call(fun, argc, env).map_err(|err| {
+            // Only the innermost call sees the whole stack, and `recur` and
+            // `return` are caught by their loop or function right away.
+            if err.trace().is_some() || matches!(err.root(), RuntimeError::Recur | RuntimeError::Return) {
+                err
+            } else {
+                RuntimeError::Traced {
+                    trace: env.call_stack.clone(),
+                    error: Box::new(err),
+                }
+            }
+        });
+        env.call_stack.pop();
+        retr
+    } else {
+        Err(RuntimeError::NotAFunction {
+            value: error::truncate(&Written(&*fun).to_string(), ERROR_EXPR_LEN),
+            call: error::truncate(&Written(expr).to_string(), ERROR_EXPR_LEN),
+        })
+    }
+}
+
+/// How a call shows up in stack traces.
+fn describe_call(fun: &Function, expr: &SExpr) -> String {
+    if let Some(name) = fun.name() {
+        return name.to_string();
+    }
+
+    match expr.as_list().and_then(|list| list.front()) {
+        Some(SExpr::Atom(Atom::Ident(name), _)) => name.to_string(),
+        Some(head) => error::truncate(&Written(head).to_string(), ERROR_EXPR_LEN),
+        None => fun.to_string(),
     }
 }
 
-pub fn call(func: &Function, env: &mut Environment) -> Result<RefVal, String> {
+/// Calls `func` on the `argc` values on top of the stack.
+pub fn call(func: &Function, argc: usize, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let Some(profiler) = &mut env.profiler else {
+        return call_function(func, argc, env);
+    };
+
+    profiler.enter(func.name());
+    let retr = call_function(func, argc, env);
+    if let Some(profiler) = &mut env.profiler {
+        profiler.exit();
+    }
+    retr
+}
+
+fn call_function(func: &Function, argc: usize, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if env.stack.len() < argc {
+        return Err(RuntimeError::StackUnderflow { callee: Some(func.to_string()) });
+    }
+    if !func.takes(argc) {
+        return Err(RuntimeError::ArityMismatch {
+            expected: func.arities(),
+            got: argc,
+            callee: func.to_string(),
+        });
+    }
+
     match func {
-        Function::UserDefined { arg_names, body } => {
-            let args = env.stack.split_off(env.stack.len() - func.arity());
-            for (name, val) in arg_names.iter().zip(args.into_iter()) {
-                env.bind_var(name, val);
+        Function::UserDefined { arg_names, patterns, defaults, body, module, .. } => {
+            let mut args = env.stack.split_off(env.stack.len() - argc);
+            in_module(module.as_ref(), env, |env| {
+                if !defaults.is_empty() {
+                    args = keyword_args(arg_names, defaults, args, env)?;
+                }
+                run_body(body, arg_names, patterns, args, env)
+            })
+        }
+
+        Function::MultiArity { module, .. } => {
+            let clause = func.clause(argc).expect("the function takes the arguments");
+            let mut args = env.stack.split_off(env.stack.len() - argc);
+
+            // A variadic clause takes the arguments past its own as a list.
+            if clause.variadic {
+                let rest = args
+                    .split_off(clause.arity())
+                    .iter()
+                    .map(|val| value_to_sexpr(val, env.symbols()))
+                    .collect::<Result<List<SExpr>, RuntimeError>>()?;
+                args.push(if rest.is_empty() {
+                    RefVal::reference(std_lib::nil_ref())
+                } else {
+                    RefVal::owned(Value::Quote(SExpr::list(rest).into()))
+                });
             }
+            in_module(module.as_ref(), env, |env| run_body(&clause.body, &clause.params, &clause.patterns, args, env))
+        }
 
-            let retr = evaluate(body, env)?;
+        Function::Lib { name, ptr, .. } => {
+            env.step()?;
+            let floor = env.stack.len() - argc;
+            let outer = env.native.replace(NativeCall { name, argc, floor });
+            let retr = (*ptr)(env);
+            env.native = outer;
+
+            // Don't let arguments the function didn't take leak into the
+            // caller's stack.
+            env.stack.truncate(floor);
+            let val = retr?;
+            env.check_size(&val)?;
+            Ok(val)
+        }
+    }
+}
+
+/// Runs `f` for a function defined in `module`. Functions from a module see
+/// its names, others see those of whatever module they are called from.
+fn in_module(
+    module: Option<&Symbol>,
+    env: &mut Environment,
+    f: impl FnOnce(&mut Environment) -> Result<RefVal, RuntimeError>,
+) -> Result<RefVal, RuntimeError> {
+    let outer = match module {
+        Some(module) => env.module.replace(module.clone()),
+        None => env.module.clone(),
+    };
+    let retr = f(env);
+    env.module = outer;
+    retr
+}
 
-            for name in arg_names.iter() {
-                env.unbind_var(name.as_ref())?;
+/// Runs the body of a user defined function with `args` bound to
+/// `arg_names`, and those given as `patterns` destructured.
+fn run_body(
+    body: &Rc<SExpr>,
+    arg_names: &[Symbol],
+    patterns: &[(Symbol, SExpr)],
+    args: Vec<RefVal>,
+    env: &mut Environment,
+) -> Result<RefVal, RuntimeError> {
+    env.functions += 1;
+    let retr = recur_point(env, arg_names, args, |env| {
+        for (name, pattern) in patterns {
+            let arg = env.lookup_symbol(name).cloned().expect("the arguments are bound");
+            for (name, val) in pattern::destructure(pattern, &arg, "the arguments")? {
+                env.bind_var(name, val)?;
             }
+        }
+        if env.vm.is_some() && !env.is_instrumented() {
+            vm::run_body(body, arg_names, env)
+        } else {
+            evaluate(body, env)
+        }
+    });
+    env.functions -= 1;
+
+    match retr {
+        Err(err) if matches!(err.root(), RuntimeError::Return) => {
+            Ok(env.returned.take().expect("'return' keeps its value"))
+        }
+        retr => retr,
+    }
+}
 
-            Ok(retr)
+/// The arguments of a function taking keyword arguments, in the order of
+/// `arg_names`: the positional ones, and then for each keyword one the value
+/// passed after its `:name`, or else its default. Defaults are evaluated
+/// on each call, and see the arguments before them.
+fn keyword_args(
+    arg_names: &[Symbol],
+    defaults: &[SExpr],
+    mut args: Vec<RefVal>,
+    env: &mut Environment,
+) -> Result<Vec<RefVal>, RuntimeError> {
+    let keys = &arg_names[arg_names.len() - defaults.len()..];
+    let mut passed: Vec<Option<RefVal>> = vec![None; keys.len()];
+
+    let mut pairs = args.split_off(arg_names.len() - keys.len()).into_iter();
+    while let Some(key) = pairs.next() {
+        let name = key
+            .as_quote()
+            .and_then(SExpr::as_atom)
+            .and_then(Atom::as_keyword)
+            .ok_or_else(|| std_lib::mismatch("a keyword", &key, "the keyword arguments"))?;
+        let Some(index) = keys.iter().position(|key| **key == *name) else {
+            let valid: Vec<String> = keys.iter().map(|key| format!(":{}", key)).collect();
+            return Err(format!("unknown keyword ':{}', expected one of {}", name, valid.join(" ")).into());
+        };
+        let val = pairs.next().ok_or_else(|| format!("keyword ':{}' is missing its value", name))?;
+        if passed[index].replace(val).is_some() {
+            return Err(format!("keyword ':{}' is passed twice", name).into());
         }
+    }
+
+    let mut scope = env.scope();
+    for (name, val) in arg_names.iter().zip(&args) {
+        scope.bind_var(name.clone(), val.clone())?;
+    }
+    for ((name, default), passed) in keys.iter().zip(defaults).zip(passed) {
+        let val = match passed {
+            Some(val) => val,
+            None => evaluate(default, &mut scope)?,
+        };
+        scope.bind_var(name.clone(), val.clone())?;
+        args.push(val);
+    }
+    Ok(args)
+}
+
+/// Runs `body` with `values` bound to `names` in a new scope, and runs it
+/// again for as long as it ends in `recur`, with the values given to `recur`
+/// instead. This is how loops and function bodies start over without
+/// nesting any deeper.
+pub(crate) fn recur_point(
+    env: &mut Environment,
+    names: &[Symbol],
+    mut values: Vec<RefVal>,
+    mut body: impl FnMut(&mut Environment) -> Result<RefVal, RuntimeError>,
+) -> Result<RefVal, RuntimeError> {
+    let outer = env.recur_arity.replace(names.len());
+    let retr = loop {
+        let mut scope = env.scope();
+        let retr = names
+            .iter()
+            .zip(values)
+            .try_for_each(|(name, val)| scope.bind_var(name.clone(), val))
+            .and_then(|()| body(&mut scope));
+
+        match retr {
+            Err(err) if matches!(err.root(), RuntimeError::Recur) => {
+                values = std::mem::take(&mut scope.recur_values);
+            }
+            retr => break retr,
+        }
+    };
+    env.recur_arity = outer;
+    retr
+}
+
+pub struct ScopeGuard<'a> {
+    env: &'a mut Environment,
+}
+
+impl<'a> Deref for ScopeGuard<'a> {
+    type Target = Environment;
+
+    fn deref(&self) -> &Environment {
+        self.env
+    }
+}
+
+impl<'a> DerefMut for ScopeGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Environment {
+        self.env
+    }
+}
 
-        Function::Lib { ptr, .. } => (*ptr)(env),
+impl<'a> Drop for ScopeGuard<'a> {
+    fn drop(&mut self) {
+        self.env.pop_scope();
     }
 }
@@ -0,0 +1,314 @@
+//! A C interface to the interpreter, for embedding it in C and C++
+//! programs. Built with the `ffi` feature, the declarations are in
+//! `include/yal.h`.
+//!
+//! Every function returns one of the `YAL_*` statuses, and on failure the
+//! message can be read with `yal_last_error`. Panics don't cross into C,
+//! they are caught and reported as `YAL_PANIC`.
+
+use std::ffi::{ c_char, c_int, c_void, CStr, CString };
+use std::panic::{ self, AssertUnwindSafe };
+use std::ptr;
+
+use crate::ast::*;
+use crate::error::{ Located, RuntimeError };
+use crate::evaluator::{ evaluate_toplevel, Environment };
+use crate::printer::Written;
+use crate::std_lib;
+
+pub const YAL_OK: c_int = 0;
+/// The program failed to parse or evaluate.
+pub const YAL_ERROR: c_int = 1;
+/// The result didn't fit in the buffer it was to be written to.
+pub const YAL_TRUNCATED: c_int = 2;
+/// A null pointer, or a string that isn't UTF-8.
+pub const YAL_INVALID: c_int = 3;
+/// The interpreter panicked. The environment may be left in any state, but
+/// is still safe to free.
+pub const YAL_PANIC: c_int = 4;
+
+/// What a `YalValue` holds.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YalTag {
+    Nil,
+    Int,
+    Float,
+    Bool,
+    String,
+    /// Anything else, like quoted expressions and functions, as the text
+    /// `write` would print for it. Only ever passed to natives.
+    Other,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union YalData {
+    pub int: i64,
+    pub float: f64,
+    pub boolean: bool,
+    pub string: *const c_char,
+}
+
+/// A value passed to or returned from a native.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct YalValue {
+    pub tag: YalTag,
+    pub data: YalData,
+}
+
+/// A function implemented in C. It gets the `user_data` it was registered
+/// with and its arguments, and puts its result in `out`, which starts out
+/// nil. Returning anything but `YAL_OK` fails the call, with `out` as the
+/// message if it is a string.
+///
+/// The strings in `args` only live until the native returns, and a string
+/// put in `out` only has to live until then too.
+pub type YalNative = extern "C" fn(
+    user_data: *mut c_void,
+    args: *const YalValue,
+    argc: usize,
+    out: *mut YalValue,
+) -> c_int;
+
+pub struct YalEnv {
+    env: Environment,
+    last_error: Option<CString>,
+}
+
+/// A native as it was registered. The pointers are only ever handed back
+/// to C, which is responsible for them being usable from wherever it calls
+/// the interpreter.
+#[derive(Clone, Copy)]
+struct Native {
+    name: &'static str,
+    callback: YalNative,
+    user_data: *mut c_void,
+}
+
+impl YalEnv {
+    fn fail(&mut self, status: c_int, message: impl Into<String>) -> c_int {
+        let message = message.into().replace('\0', "\\0");
+        self.last_error = Some(CString::new(message).expect("NUL bytes were replaced"));
+        status
+    }
+}
+
+/// Runs `f` on the environment behind `env`, reporting a panic as
+/// `YAL_PANIC`.
+unsafe fn with_env(env: *mut YalEnv, f: impl FnOnce(&mut YalEnv) -> c_int) -> c_int {
+    let Some(yal) = env.as_mut() else { return YAL_INVALID };
+    yal.last_error = None;
+
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut *env))) {
+        Ok(status) => status,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            (*env).fail(YAL_PANIC, format!("the interpreter panicked: {}", message))
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Makes an environment with the standard library, or returns null if that
+/// fails. It must be freed with `yal_env_free`.
+#[no_mangle]
+pub extern "C" fn yal_env_new() -> *mut YalEnv {
+    let env = panic::catch_unwind(|| {
+        Environment::with_std_lib().ok()
+    });
+
+    match env {
+        Ok(Some(env)) => Box::into_raw(Box::new(YalEnv { env, last_error: None })),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `env` must be null or come from `yal_env_new`, and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn yal_env_free(env: *mut YalEnv) {
+    if env.is_null() {
+        return;
+    }
+    // Dropping values may run arbitrary code, which must not unwind into C.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(env))));
+}
+
+/// Evaluates every expression in `src`, and writes the last value, as
+/// `write` prints it, to `out_buf` as a NUL terminated string. Gives
+/// `YAL_TRUNCATED` if the value doesn't fit in the `out_len` bytes of
+/// `out_buf`, which then holds as much of it as does. `out_buf` may be null
+/// if the result isn't needed.
+///
+/// # Safety
+///
+/// `env` must come from `yal_env_new`, `src` must be a NUL terminated
+/// string and `out_buf` must be null or point to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn yal_eval_cstr(
+    env: *mut YalEnv,
+    src: *const c_char,
+    out_buf: *mut c_char,
+    out_len: usize,
+) -> c_int {
+    with_env(env, |yal| {
+        let Some(src) = str_arg(src) else {
+            return yal.fail(YAL_INVALID, "the source isn't a UTF-8 string");
+        };
+
+        let exprs = match yal.env.reader(src).parse_sexprs() {
+            Ok(exprs) => exprs,
+            Err(err) => return yal.fail(YAL_ERROR, err.to_string()),
+        };
+
+        let mut last = Ok(String::new());
+        for expr in exprs {
+            match evaluate_toplevel(&expr, &mut yal.env) {
+                Ok(val) => last = Ok(Written(&*val).to_string()),
+                Err(err) => {
+                    last = Err(Located { file: None, src, error: &err }.to_string());
+                    break;
+                }
+            }
+        }
+        // The host's own output may come next, and Rust's standard output
+        // isn't flushed when a C program exits.
+        let _ = yal.env.output().flush();
+        let last = match last {
+            Ok(last) => last,
+            Err(message) => return yal.fail(YAL_ERROR, message),
+        };
+
+        if out_buf.is_null() || out_len == 0 {
+            return if out_buf.is_null() { YAL_OK } else { YAL_TRUNCATED };
+        }
+        let len = last.len().min(out_len - 1);
+        ptr::copy_nonoverlapping(last.as_ptr(), out_buf as *mut u8, len);
+        *out_buf.add(len) = 0;
+        if len < last.len() {
+            return yal.fail(YAL_TRUNCATED, format!("the result takes {} bytes", last.len() + 1));
+        }
+        YAL_OK
+    })
+}
+
+/// Binds `name` to a function taking `arity` arguments, implemented by
+/// `callback`. The name is kept for as long as the program runs.
+///
+/// # Safety
+///
+/// `env` must come from `yal_env_new` and `name` must be a NUL terminated
+/// string. `callback` is called with `user_data` whenever the function is.
+#[no_mangle]
+pub unsafe extern "C" fn yal_register_native(
+    env: *mut YalEnv,
+    name: *const c_char,
+    arity: usize,
+    callback: YalNative,
+    user_data: *mut c_void,
+) -> c_int {
+    with_env(env, |yal| {
+        let Some(name) = str_arg(name) else {
+            return yal.fail(YAL_INVALID, "the name isn't a UTF-8 string");
+        };
+
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let native = Native { name, callback, user_data };
+        yal.env.register_external_fun(name, arity, move |env| call_native(native, env));
+        YAL_OK
+    })
+}
+
+/// The message of the last failure in `env`, or null if the last call
+/// succeeded. It lives until the next call taking `env`.
+///
+/// # Safety
+///
+/// `env` must come from `yal_env_new`.
+#[no_mangle]
+pub unsafe extern "C" fn yal_last_error(env: *const YalEnv) -> *const c_char {
+    match env.as_ref().and_then(|yal| yal.last_error.as_ref()) {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Calls `native` with the arguments on the stack of `env`.
+fn call_native(native: Native, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let name = native.name;
+
+    let mut vals = Vec::new();
+    while let Ok(val) = env.pop_stack() {
+        vals.push(val);
+    }
+    vals.reverse();
+
+    // Keeps the strings the arguments point to alive during the call.
+    let mut strings = Vec::new();
+    let mut args = Vec::with_capacity(vals.len());
+    for val in &vals {
+        let (tag, data) = match &**val {
+            Value::Nil => (YalTag::Nil, YalData { int: 0 }),
+            Value::Int(n) => (YalTag::Int, YalData { int: *n }),
+            Value::Float(n) => (YalTag::Float, YalData { float: *n }),
+            Value::Bool(b) => (YalTag::Bool, YalData { boolean: *b }),
+            Value::String(s) => (YalTag::String, YalData { string: c_string(s, name)? }),
+            other => (YalTag::Other, YalData { string: c_string(&Written(other).to_string(), name)? }),
+        };
+        if matches!(tag, YalTag::String | YalTag::Other) {
+            // SAFETY: the pointer was just made by `CString::into_raw`.
+            strings.push(unsafe { CString::from_raw(data.string as *mut c_char) });
+        }
+        args.push(YalValue { tag, data });
+    }
+
+    let mut out = YalValue { tag: YalTag::Nil, data: YalData { int: 0 } };
+    let status = (native.callback)(native.user_data, args.as_ptr(), args.len(), &mut out);
+    drop(strings);
+
+    // SAFETY: natives promise that strings they return are NUL terminated
+    // and live until they return, and the tag says which field is set.
+    unsafe {
+        if status != YAL_OK {
+            let message = match out.tag {
+                YalTag::String if !out.data.string.is_null() => {
+                    CStr::from_ptr(out.data.string).to_string_lossy().into_owned()
+                }
+                _ => format!("the native '{}' failed with status {}", name, status),
+            };
+            return Err(message.into());
+        }
+
+        Ok(match out.tag {
+            YalTag::Nil => RefVal::reference(std_lib::nil_ref()),
+            YalTag::Int => RefVal::from(out.data.int),
+            YalTag::Float => RefVal::from(out.data.float),
+            YalTag::Bool => RefVal::from(out.data.boolean),
+            YalTag::String if !out.data.string.is_null() => {
+                RefVal::from(CStr::from_ptr(out.data.string).to_string_lossy().into_owned())
+            }
+            YalTag::String => return Err(format!("the native '{}' returned a null string", name).into()),
+            YalTag::Other => return Err(format!("the native '{}' returned an unsupported value", name).into()),
+        })
+    }
+}
+
+fn c_string(s: &str, native: &str) -> Result<*const c_char, RuntimeError> {
+    match CString::new(s) {
+        Ok(s) => Ok(s.into_raw()),
+        Err(_) => Err(format!("can't pass a string with a NUL byte to the native '{}'", native).into()),
+    }
+}
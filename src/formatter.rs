@@ -0,0 +1,136 @@
+//! The source formatter behind `yal fmt`.
+//!
+//! Top-level forms are separated by a blank line. A list is kept on one line
+//! if it fits, otherwise its head stays after the paren, followed by the
+//! arguments that fit, and the rest go on their own lines, indented two
+//! columns past the paren. Comments are kept on their own line, before the
+//! form they preceded.
+
+use std::iter::{ self, Peekable };
+use std::vec;
+
+use crate::ast::*;
+use crate::error::Error;
+use crate::printer::Written;
+use crate::reader::{ Comment, Reader };
+
+/// Lines are kept within this many columns where possible.
+const WIDTH: usize = 80;
+
+/// How much further than their list the elements on their own line go.
+const INDENT: usize = 2;
+
+/// Reformats the program in `src`, failing if it doesn't parse. Formatting
+/// the result again gives the same result.
+pub fn format_source(src: &str) -> Result<String, Error<'_>> {
+    let mut reader = Reader::new(src).keep_comments();
+    let exprs = reader.parse_sexprs()?;
+
+    let mut formatter = Formatter {
+        out: String::new(),
+        comments: reader.take_comments().into_iter().peekable(),
+    };
+
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            formatter.out.push('\n');
+        }
+        formatter.comments_before(expr.span().start, 0);
+        formatter.expr(expr);
+        formatter.out.push('\n');
+    }
+
+    if !exprs.is_empty() && formatter.comments.peek().is_some() {
+        formatter.out.push('\n');
+    }
+    formatter.comments_before(usize::MAX, 0);
+
+    Ok(formatter.out)
+}
+
+struct Formatter {
+    out: String,
+    /// The comments not written yet.
+    comments: Peekable<vec::IntoIter<Comment>>,
+}
+
+impl Formatter {
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend(iter::repeat_n(' ', indent));
+    }
+
+    fn has_comments_before(&mut self, pos: usize) -> bool {
+        self.comments.peek().is_some_and(|comment| comment.span.start < pos)
+    }
+
+    /// Writes the comments that start before `pos`, each followed by a new
+    /// line indented by `indent`.
+    fn comments_before(&mut self, pos: usize, indent: usize) {
+        while let Some(comment) = self.comments.next_if(|comment| comment.span.start < pos) {
+            self.out.push_str(&comment.text);
+            self.newline(indent);
+        }
+    }
+
+    fn expr(&mut self, expr: &SExpr) {
+        if !self.has_comments_before(expr.span().end) {
+            let flat = Written(expr).to_string();
+            if self.column() + flat.chars().count() <= WIDTH {
+                return self.out.push_str(&flat);
+            }
+        }
+
+        match expr {
+            SExpr::Atom(Atom::Quote(quoted), _) => {
+                self.out.push('\'');
+                self.expr(quoted);
+            }
+            SExpr::List(list, span) => self.list(list, *span),
+            atom => self.out.push_str(&Written(atom).to_string()),
+        }
+    }
+
+    fn list(&mut self, list: &List<SExpr>, span: Span) {
+        let indent = self.column() + INDENT;
+        self.out.push('(');
+
+        let mut same_line = true;
+        if let Some(head) = list.front() {
+            if self.has_comments_before(head.span().start) {
+                self.newline(indent);
+                self.comments_before(head.span().start, indent);
+            }
+            let start = self.out.len();
+            self.expr(head);
+            same_line = !self.out[start..].contains('\n');
+        }
+
+        for el in list.iter().skip(1) {
+            if same_line && !self.has_comments_before(el.span().end) {
+                let flat = Written(el).to_string();
+                if self.column() + 1 + flat.chars().count() <= WIDTH {
+                    self.out.push(' ');
+                    self.out.push_str(&flat);
+                    continue;
+                }
+            }
+
+            same_line = false;
+            self.newline(indent);
+            self.comments_before(el.span().start, indent);
+            self.expr(el);
+        }
+
+        if self.has_comments_before(span.end) {
+            self.newline(indent);
+            self.comments_before(span.end, indent);
+        }
+        self.out.push(')');
+    }
+}
@@ -0,0 +1,241 @@
+//! `http-get` and `http-post`, built with the `http` feature. The client is
+//! a minimal blocking one over `std::net`: plain `http://` URLs only, one
+//! request per connection, and no redirects followed.
+//!
+//! Responses are `((status 200) (headers (("content-type" "text/plain") ...))
+//! (body "..."))`, with the header names in lowercase. Statuses other than 2xx
+//! are responses like the others, only failing to get one is an error.
+
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpStream, ToSocketAddrs };
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::ast::*;
+use crate::error::RuntimeError;
+use crate::evaluator::Environment;
+use crate::std_lib;
+
+/// How long connecting, and then each read and write, may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most bytes of body a response is read up to.
+const MAX_BODY: u64 = 64 << 20;
+
+pub fn register(env: &mut Environment) {
+    let builtins: &[(&'static str, usize, &'static str, LibFn)] = &[
+        ("http-get", 1, "Fetches a URL, as ((status code) (headers ((name value) ...)) (body text)).", http_get_impl),
+        ("http-post", 3, "Posts a body with a list of (name value) headers to a URL, giving what 'http-get' does.", http_post_impl),
+    ];
+    for &(name, arity, doc, ptr) in builtins {
+        env.register_builtin(BuiltinSpec::new(name, arity).doc(doc).category("http"), ptr);
+    }
+}
+
+pub fn http_get_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let url = env.pop_stack()?;
+    let url = url.as_string().ok_or_else(|| std_lib::mismatch("a URL string", &url, "'http-get'"))?;
+    let response = request("GET", url, &[], None).map_err(|err| fetch_error(url, err))?;
+    Ok(response.to_value(env))
+}
+
+pub fn http_post_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let headers = env.pop_stack()?;
+    let body = env.pop_stack()?;
+    let url = env.pop_stack()?;
+    let url = url.as_string().ok_or_else(|| std_lib::mismatch("a URL string", &url, "'http-post'"))?;
+    let body = body.as_string().ok_or_else(|| std_lib::mismatch("a body string", &body, "'http-post'"))?;
+    let headers = header_pairs(&headers).ok_or_else(|| {
+        std_lib::mismatch("a list of (name value) strings", &headers, "'http-post'")
+    })?;
+    let response = request("POST", url, &headers, Some(body)).map_err(|err| fetch_error(url, err))?;
+    Ok(response.to_value(env))
+}
+
+fn fetch_error(url: &str, err: String) -> RuntimeError {
+    RuntimeError::Custom(format!("couldn't fetch '{}': {}", url, err))
+}
+
+/// The headers given as a list of `(name value)` strings, or nil.
+fn header_pairs(val: &RefVal) -> Option<Vec<(String, String)>> {
+    if let Value::Nil = **val {
+        return Some(Vec::new());
+    }
+    val.as_quote()?
+        .as_list()?
+        .iter()
+        .map(|pair| {
+            let pair: Vec<_> = pair.as_list()?.iter().collect();
+            match pair.as_slice() {
+                [SExpr::Atom(Atom::String(name), _), SExpr::Atom(Atom::String(value), _)] => {
+                    Some((name.to_string(), value.to_string()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn to_value(&self, env: &Environment) -> RefVal {
+        let string = |s: &str| SExpr::atom(Atom::String(s.into()));
+        let field = |name: &str, value: SExpr| {
+            SExpr::list(List::from([SExpr::atom(Atom::Ident(env.intern(name))), value]))
+        };
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| SExpr::list(List::from([string(name), string(value)])))
+            .collect();
+
+        let response = SExpr::list(List::from([
+            field("status", SExpr::atom(Atom::Int(self.status.into()))),
+            field("headers", SExpr::list(headers)),
+            field("body", string(&self.body)),
+        ]));
+        RefVal::owned(Value::Quote(Rc::new(response)))
+    }
+}
+
+/// The host, port and path of an `http://` URL.
+fn split_url(url: &str) -> Result<(&str, u16, &str), String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some(("https", _)) => return Err("https isn't supported, there is no TLS".to_string()),
+        Some((scheme, _)) => return Err(format!("unsupported scheme '{}'", scheme)),
+        None => return Err("expected a URL starting with http://".to_string()),
+    };
+
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid port '{}'", port))?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err("the URL has no host".to_string());
+    }
+    Ok((host, port, path))
+}
+
+fn request(method: &str, url: &str, headers: &[(String, String)], body: Option<&str>) -> Result<Response, String> {
+    let (host, port, path) = split_url(url)?;
+    // A line break would end the header early, and let the rest pass for
+    // headers, or a request, of its own.
+    for (name, value) in headers {
+        if name.contains(['\r', '\n', ':']) {
+            return Err(format!("invalid header name {:?}", name));
+        }
+        if value.contains(['\r', '\n']) {
+            return Err(format!("invalid value {:?} for the header '{}'", value, name));
+        }
+    }
+
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| format!("couldn't resolve '{}'", host))?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|err| err.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|err| err.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|err| err.to_string())?;
+
+    let host = if port == 80 { host.to_string() } else { format!("{}:{}", host, port) };
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: yal\r\n", method, path, host);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(|err| err.to_string())?;
+    if let Some(body) = body {
+        stream.write_all(body.as_bytes()).map_err(|err| err.to_string())?;
+    }
+
+    read_response(BufReader::new(stream)).map_err(|err| err.to_string())
+}
+
+/// Reads a response up to the end of its body, which is where the
+/// connection ends unless the headers say otherwise.
+fn read_response(mut reader: impl BufRead) -> Result<Response, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|err| err.to_string())?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| format!("invalid status line '{}'", line.trim_end()))?;
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|err| err.to_string())?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':').ok_or_else(|| format!("invalid header '{}'", header))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let header = |name: &str| headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str());
+    let mut body = Vec::new();
+    if header("transfer-encoding").is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked")) {
+        read_chunks(&mut reader, &mut body)?;
+    } else if let Some(length) = header("content-length") {
+        let length: u64 = length.parse().map_err(|_| format!("invalid content length '{}'", length))?;
+        if length > MAX_BODY {
+            return Err(too_big());
+        }
+        reader.take(length).read_to_end(&mut body).map_err(|err| err.to_string())?;
+    } else {
+        reader.take(MAX_BODY + 1).read_to_end(&mut body).map_err(|err| err.to_string())?;
+        if body.len() as u64 > MAX_BODY {
+            return Err(too_big());
+        }
+    }
+
+    Ok(Response { status, headers, body: String::from_utf8_lossy(&body).into_owned() })
+}
+
+fn too_big() -> String {
+    format!("the body is over {} bytes", MAX_BODY)
+}
+
+/// Reads a body sent in chunks, each preceded by its size in hex, up to the
+/// empty one. The sizes are the server's word, so a chunk is only kept as
+/// far as it really goes.
+fn read_chunks(reader: &mut impl BufRead, body: &mut Vec<u8>) -> Result<(), String> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|err| err.to_string())?;
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let size = u64::from_str_radix(size, 16).map_err(|_| format!("invalid chunk size '{}'", size))?;
+        if size == 0 {
+            return Ok(());
+        }
+
+        let total = (body.len() as u64).checked_add(size).filter(|&total| total <= MAX_BODY);
+        if total.is_none() {
+            return Err(too_big());
+        }
+        let read = reader.take(size).read_to_end(body).map_err(|err| err.to_string())?;
+        if read as u64 != size {
+            return Err("the body ended in the middle of a chunk".to_string());
+        }
+        // The chunk ends with a line break.
+        line.clear();
+        reader.read_line(&mut line).map_err(|err| err.to_string())?;
+    }
+}
@@ -0,0 +1,141 @@
+//! Images, files holding the global bindings of an environment, for
+//! `save-image`, `load-image` and `--image`. Loading an image is quicker than
+//! running the code that made the bindings again.
+//!
+//! An image is code: a header `(image FORMAT "VERSION" (skipped name ...))`,
+//! then a `let`, or a `defconst`, for each binding, with data written as the
+//! code that gives it back and functions as the `fn` or `define-multi` that
+//! makes them. Builtins, handles and what modules define can't be written
+//! that way, so they are left out and listed in the header. Images are only
+//! loaded by the version of yal that saved them.
+
+use std::fs;
+use std::path::Path;
+
+use crate::ast::*;
+use crate::error::RuntimeError;
+use crate::evaluator::{ evaluate_toplevel, Environment };
+use crate::parallel;
+use crate::printer::Written;
+use crate::std_lib;
+
+/// Changes whenever images are written differently.
+const FORMAT: i64 = 1;
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub fn register(env: &mut Environment) {
+    let builtins: &[(&'static str, usize, &'static str, LibFn)] = &[
+        ("save-image", 1, "Saves the global bindings to an image file, giving the names it had to leave out.", save_image_impl),
+        ("load-image", 1, "Binds what an image file holds, giving the names left out when it was saved.", load_image_impl),
+    ];
+    for &(name, arity, doc, ptr) in builtins {
+        env.register_builtin(BuiltinSpec::new(name, arity).doc(doc).category("images"), ptr);
+    }
+}
+
+pub fn save_image_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let path = env.pop_stack()?;
+    let path = path.as_string().ok_or_else(|| std_lib::mismatch("a path string", &path, "'save-image'"))?;
+    let skipped = save(env, &std_lib::resolve_path(env, path))?;
+    Ok(names(env, &skipped))
+}
+
+pub fn load_image_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let path = env.pop_stack()?;
+    let path = path.as_string().ok_or_else(|| std_lib::mismatch("a path string", &path, "'load-image'"))?;
+    let skipped = load(env, &std_lib::resolve_path(env, path))?;
+    Ok(names(env, &skipped))
+}
+
+/// The names as a quoted list, or nil.
+fn names(env: &Environment, names: &[String]) -> RefVal {
+    if names.is_empty() {
+        return RefVal::reference(std_lib::nil_ref());
+    }
+    let names = names.iter().map(|name| SExpr::atom(Atom::Ident(env.intern(name)))).collect();
+    RefVal::owned(Value::Quote(SExpr::list(names).into()))
+}
+
+/// Writes the global bindings of `env` to an image at `path`, other than
+/// those of the standard library and `*args*`, which belongs to the run.
+/// Gives the names that were left out, in order. Fails, writing nothing, if
+/// a binding wouldn't read back, nested deeper than `env` reads code.
+pub fn save(env: &Environment, path: &Path) -> Result<Vec<String>, RuntimeError> {
+    let fresh = Environment::with_std_lib()?;
+
+    let mut globals: Vec<_> = env.globals().filter(|(name, _)| &***name != "*args*").collect();
+    globals.sort_by_key(|(name, _)| *name);
+
+    let mut skipped = Vec::new();
+    let mut bindings = String::new();
+    for (name, val) in globals {
+        let code = match &**val {
+            _ if env.is_in_module(name) => None,
+            Value::Function(
+                Function::UserDefined { module: Some(_), .. } | Function::MultiArity { module: Some(_), .. },
+            ) => None,
+            Value::Function(Function::Lib { name: builtin, .. }) => match fresh.lookup_var(name).map(|val| &**val) {
+                Some(Value::Function(Function::Lib { name: fresh, .. })) if fresh == builtin => continue,
+                _ => None,
+            },
+            Value::Function(fun) => parallel::write_function(fun, env),
+            val => parallel::write_data(val, env.symbols()),
+        };
+        let Some(code) = code else {
+            skipped.push(name.to_string());
+            continue;
+        };
+        if fresh.lookup_var(name).is_some_and(|fresh| Written(&**fresh).to_string() == code) {
+            continue;
+        }
+        let define = if env.is_constant(name) { "defconst" } else { "let" };
+        let binding = format!("({} '{} {})\n", define, name, code);
+        // Values can be nested deeper than code is allowed to be.
+        if let Err(err) = env.reader(&binding).parse_sexprs() {
+            return Err(format!("couldn't save the image '{}': '{}' wouldn't read back, {}", path.display(), name, err.message()).into());
+        }
+        bindings.push_str(&binding);
+    }
+
+    let skipped_names: Vec<&str> = skipped.iter().map(String::as_str).collect();
+    let header = format!("(image {} \"{}\" (skipped {}))\n", FORMAT, VERSION, skipped_names.join(" "));
+    fs::write(path, header + &bindings)
+        .map_err(|err| format!("couldn't save the image '{}': {}", path.display(), err))?;
+    Ok(skipped)
+}
+
+/// Binds what the image at `path` holds in `env`, which should have the
+/// standard library. Gives the names that were left out of the image.
+pub fn load(env: &mut Environment, path: &Path) -> Result<Vec<String>, RuntimeError> {
+    let error = |why: &dyn std::fmt::Display| RuntimeError::Custom(format!("couldn't load the image '{}': {}", path.display(), why));
+    let src = fs::read_to_string(path).map_err(|err| error(&err))?;
+    let mut exprs = env.reader(&src).parse_sexprs().map_err(|err| error(&err.message()))?;
+
+    let header = exprs.pop_front();
+    let header: Vec<&SExpr> = header.as_ref().and_then(SExpr::as_list).map(|list| list.iter().collect()).unwrap_or_default();
+    let (format, version, skipped) = match header.as_slice() {
+        [SExpr::Atom(Atom::Ident(image), _), SExpr::Atom(Atom::Int(format), _), SExpr::Atom(Atom::String(version), _), skipped]
+            if &**image == "image" =>
+        {
+            (*format, version, skipped)
+        }
+        _ => return Err(error(&"it isn't an image")),
+    };
+    if format != FORMAT || **version != *VERSION {
+        return Err(error(&format!(
+            "it was saved by yal {} (image format {}), and this is yal {} (image format {})",
+            version, format, VERSION, FORMAT,
+        )));
+    }
+    let skipped: Vec<String> = skipped
+        .as_list()
+        .into_iter()
+        .flat_map(|list| list.iter().skip(1))
+        .filter_map(|name| Some(name.as_atom()?.as_ident()?.to_string()))
+        .collect();
+
+    for expr in &exprs {
+        evaluate_toplevel(expr, env).map_err(|err| error(&err.root()))?;
+    }
+    Ok(skipped)
+}
@@ -0,0 +1,111 @@
+//! `(include "path")`, which splices the top-level forms of another file in
+//! place of itself as the program is read, unlike `load`, which evaluates a
+//! file when it runs. The whole program is then there for the passes that
+//! look at the code before it runs.
+//!
+//! Included files can include others. Their spans are numbered after those
+//! of the files before them in a `SourceMap`, which is how errors in them
+//! are reported against the right file.
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::error::{ RuntimeError, SourceMap };
+use crate::evaluator::Environment;
+
+/// Replaces every top-level `include` of `exprs`, read from the file at
+/// `path` if any, by the forms of the file it names, read for `env`, adding
+/// the files to `sources`. Relative paths are relative to the including file, or to the
+/// working directory for code that doesn't come from a file.
+pub fn expand(
+    exprs: impl IntoIterator<Item = SExpr>,
+    path: Option<&Path>,
+    sources: &mut SourceMap,
+    env: &Environment,
+) -> Result<Vec<SExpr>, RuntimeError> {
+    let mut including: Vec<PathBuf> = path.map(canonical).into_iter().collect();
+    let mut out = Vec::new();
+    splice(exprs.into_iter().collect(), path, &mut including, sources, env, &mut out)?;
+    Ok(out)
+}
+
+/// The path `include` names, if `expr` is an include.
+fn included(expr: &SExpr) -> Option<Result<&str, RuntimeError>> {
+    let list = expr.as_list()?;
+    if list.front()?.as_atom()?.as_ident()? != "include" {
+        return None;
+    }
+    let path = match list.iter().collect::<Vec<_>>().as_slice() {
+        [_, SExpr::Atom(Atom::String(path), _)] => Ok(&**path),
+        _ => Err(RuntimeError::Custom("'include' expects a path string".to_string()).with_span(expr.span())),
+    };
+    Some(path)
+}
+
+fn splice(
+    exprs: Vec<SExpr>,
+    path: Option<&Path>,
+    including: &mut Vec<PathBuf>,
+    sources: &mut SourceMap,
+    env: &Environment,
+    out: &mut Vec<SExpr>,
+) -> Result<(), RuntimeError> {
+    for expr in exprs {
+        let target = match included(&expr) {
+            Some(target) => target?,
+            None => {
+                out.push(expr);
+                continue;
+            }
+        };
+
+        let span = expr.span();
+        let target = match path.and_then(Path::parent) {
+            Some(dir) => dir.join(target),
+            None => PathBuf::from(target),
+        };
+        let src: Rc<str> = fs::read_to_string(&target)
+            .map_err(|err| RuntimeError::Custom(format!("couldn't include '{}': {}", target.display(), err)).with_span(span))?
+            .into();
+
+        let canonical = canonical(&target);
+        if let Some(start) = including.iter().position(|file| *file == canonical) {
+            let cycle: Vec<_> = including[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(RuntimeError::Custom(format!("circular include: {}", cycle.join(" → "))).with_span(span));
+        }
+
+        let start = sources.add(&target.display().to_string(), src.clone());
+        let forms = env.reader(&src).parse_sexprs().map_err(|err| {
+            let span = Span::new(start + err.byte(), start + err.byte() + 1);
+            RuntimeError::Custom(err.message().to_string()).with_span(span)
+        })?;
+
+        including.push(canonical);
+        let forms = forms.into_iter().map(|form| shift(form, start)).collect();
+        splice(forms, Some(&target), including, sources, env, out)?;
+        including.pop();
+    }
+    Ok(())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Moves the spans of `expr` `by` bytes forward.
+fn shift(expr: SExpr, by: usize) -> SExpr {
+    let move_span = |span: Span| if span.is_known() { Span::new(span.start + by, span.end + by) } else { span };
+    match expr {
+        SExpr::List(list, span) => SExpr::List(list.into_iter().map(|el| shift(el, by)).collect(), move_span(span)),
+        SExpr::Atom(Atom::Quote(quoted), span) => {
+            SExpr::Atom(Atom::Quote(Rc::new(shift((*quoted).clone(), by))), move_span(span))
+        }
+        SExpr::Atom(atom, span) => SExpr::Atom(atom, move_span(span)),
+    }
+}
@@ -0,0 +1,275 @@
+//! Splitting source code into tokens, for the reader and for tools like
+//! syntax highlighters.
+//!
+//! The lexer never fails. Anything it can't make sense of becomes an
+//! `Error` token and it carries on after it, so it works on code that is
+//! still being written.
+
+use std::fmt::{ self, Display, Formatter };
+
+use crate::ast::Span;
+
+/// The chars besides letters an identifier may start with.
+pub const IDENT_CHARS: &str = "_+-/*=?<>&:";
+
+/// The floats that aren't written with digits, after a `+` or `-` sign, as
+/// in scheme: `+inf.0`, `-inf.0` and `+nan.0`.
+const SPECIAL_FLOATS: [&str; 2] = ["inf.0", "nan.0"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Open,
+    Close,
+    /// A string literal, quotes included.
+    String,
+    /// An int, a rational or a float, maybe signed, like `-12`, `1/3`,
+    /// `1.5e-3` or `+inf.0`.
+    Number,
+    /// A name, or a lone `.`, which marks the rest of a list in patterns.
+    Ident,
+    Quote,
+    /// From the `;` to the end of the line.
+    Comment,
+    /// `#+feature(name)` or `#-feature(name)`, which keeps the next form
+    /// only if the feature is there, or only if it isn't.
+    Conditional,
+    Error(LexError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexError {
+    /// The input ended inside of a string. The token goes up to the end.
+    UnterminatedString,
+    /// A backslash followed by `chr`, at `byte`, in a string. The token is
+    /// the whole string.
+    UnknownEscape { byte: usize, chr: char },
+    UnexpectedChar(char),
+    /// A `#` that doesn't start a conditional. The token is the `#`.
+    BadConditional,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    /// The text of the token in `src`, the source it was read from.
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.span.start..self.span.end]
+    }
+}
+
+/// An iterator over the tokens of a source, skipping whitespace.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Lexer<'a> {
+        Lexer { src, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+        self.pos += chr.len_utf8();
+        Some(chr)
+    }
+
+    fn advance_while(&mut self, pred: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&pred) {
+            self.advance();
+        }
+    }
+
+    /// Reads a string, after its opening quote.
+    fn string(&mut self) -> TokenKind {
+        let mut unknown_escape = None;
+        loop {
+            match self.advance() {
+                Some('"') => break,
+                Some('\\') => {
+                    let byte = self.pos;
+                    match self.advance() {
+                        Some(chr) if escape(chr).is_none() => {
+                            unknown_escape.get_or_insert(LexError::UnknownEscape { byte, chr });
+                        }
+                        Some(_) => (),
+                        None => return TokenKind::Error(unknown_escape.unwrap_or(LexError::UnterminatedString)),
+                    }
+                }
+                Some(_) => (),
+                None => return TokenKind::Error(unknown_escape.unwrap_or(LexError::UnterminatedString)),
+            }
+        }
+
+        match unknown_escape {
+            Some(error) => TokenKind::Error(error),
+            None => TokenKind::String,
+        }
+    }
+
+    /// Reads the rest of a number, a run of digits with at most one dot,
+    /// and maybe an exponent like `e-7`, or the digits of a fraction.
+    fn number(&mut self) {
+        let mut read_dot = false;
+        while let Some(chr) = self.peek() {
+            if chr == '.' && !read_dot {
+                read_dot = true;
+            } else if !chr.is_ascii_digit() {
+                break;
+            }
+            self.advance();
+        }
+
+        // Without digits after it, the `/` starts an identifier instead.
+        let mut fraction = self.clone();
+        if !read_dot && fraction.advance() == Some('/') && fraction.peek().is_some_and(|chr| chr.is_ascii_digit()) {
+            fraction.advance_while(|chr| chr.is_ascii_digit());
+            *self = fraction;
+            return;
+        }
+
+        // Without digits, the `e` starts an identifier instead.
+        let mut exponent = self.clone();
+        if matches!(exponent.advance(), Some('e' | 'E')) {
+            if matches!(exponent.peek(), Some('+' | '-')) {
+                exponent.advance();
+            }
+            if exponent.peek().is_some_and(|chr| chr.is_ascii_digit()) {
+                exponent.advance_while(|chr| chr.is_ascii_digit());
+                *self = exponent;
+            }
+        }
+    }
+
+    /// Reads the rest of a conditional, after its `#`.
+    fn conditional(&mut self) -> TokenKind {
+        let rest = &self.src[self.pos..];
+        let len = rest.strip_prefix(['+', '-']).and_then(|rest| rest.strip_prefix("feature(")).and_then(|rest| {
+            let name = rest.find(|chr| !is_ident_char(chr)).unwrap_or(rest.len());
+            (name > 0 && rest[name..].starts_with(')')).then_some("+feature(".len() + name + 1)
+        });
+        match len {
+            Some(len) => {
+                self.pos += len;
+                TokenKind::Conditional
+            }
+            None => TokenKind::Error(LexError::BadConditional),
+        }
+    }
+
+    /// Reads the rest of a number after its sign, if it is one. Otherwise
+    /// the sign starts an identifier.
+    fn signed(&mut self) -> bool {
+        if self.peek().is_some_and(|chr| chr.is_ascii_digit()) {
+            self.number();
+            return true;
+        }
+
+        let rest = &self.src[self.pos..];
+        let special = SPECIAL_FLOATS.iter().find(|name| rest.starts_with(*name));
+        match special {
+            Some(name) if !rest[name.len()..].starts_with(is_ident_char) => {
+                self.pos += name.len();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.advance_while(char::is_whitespace);
+
+        let start = self.pos;
+        let kind = match self.advance()? {
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '\'' => TokenKind::Quote,
+            '"' => self.string(),
+            '#' => self.conditional(),
+            ';' => {
+                self.advance_while(|chr| chr != '\n');
+                TokenKind::Comment
+            }
+            chr if chr.is_ascii_digit() => {
+                self.number();
+                TokenKind::Number
+            }
+            '+' | '-' if self.signed() => TokenKind::Number,
+            '.' if !self.peek().is_some_and(is_ident_char) => TokenKind::Ident,
+            chr if is_ident_start(chr) => {
+                self.advance_while(is_ident_char);
+                TokenKind::Ident
+            }
+            chr => TokenKind::Error(LexError::UnexpectedChar(chr)),
+        };
+
+        Some(Token { kind, span: Span::new(start, self.pos) })
+    }
+}
+
+/// Whether an identifier may start with `chr`.
+pub fn is_ident_start(chr: char) -> bool {
+    chr.is_alphabetic() || IDENT_CHARS.contains(chr)
+}
+
+/// Whether `chr` may appear in an identifier, past its first char.
+pub fn is_ident_char(chr: char) -> bool {
+    chr.is_alphanumeric() || IDENT_CHARS.contains(chr)
+}
+
+/// The char `\chr` stands for in a string, if it is a known escape.
+fn escape(chr: char) -> Option<char> {
+    match chr {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '"' => Some('"'),
+        _ => None,
+    }
+}
+
+/// The contents of the text of a `String` token, with the escapes decoded.
+pub fn string_value(text: &str) -> String {
+    let inner = text.strip_prefix('"').unwrap_or(text);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+
+    let mut s = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            s.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some(escaped) => s.push(escape(escaped).unwrap_or(escaped)),
+            None => s.push('\\'),
+        }
+    }
+    s
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LexError::UnterminatedString => write!(f, "unterminated string"),
+            LexError::UnknownEscape { chr, .. } => write!(f, "unknown escape '\\{}'", chr),
+            LexError::UnexpectedChar(chr) => write!(f, "unexpected char '{}'", chr),
+            LexError::BadConditional => write!(f, "expected '#+feature(name)' or '#-feature(name)'"),
+        }
+    }
+}
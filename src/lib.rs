@@ -0,0 +1,65 @@
+//! yal, a small Lisp. The interpreter is usable as a library, the `yal`
+//! binary being a thin command line interface over it.
+//!
+//! The quickest way to run some code is `eval_str`. To keep what it defines,
+//! make an `Environment` with `Environment::with_std_lib`, and run code in
+//! it with `Environment::eval_str`, or read it with
+//! `Environment::reader` and run each expression with `evaluate_toplevel`.
+//! Natives are added with `Environment::register_external_fun`, or with
+//! `Environment::register_builtin` to describe them with a `BuiltinSpec`,
+//! and call back into yal with `call`, and `convert` turns values into Rust
+//! ones and back. What programs print goes to standard output unless it is captured
+//! with `Environment::capture_output`, or sent elsewhere with
+//! `Environment::set_output`. What is used most of this is exported here as
+//! well as from its module.
+
+#![forbid(unstable_features)]
+
+// The embedding API: values, reading, evaluating and what can go wrong.
+pub mod error;
+pub mod symbol;
+pub mod list;
+pub mod bigint;
+pub mod ast;
+pub mod reader;
+pub mod evaluator;
+pub mod std_lib;
+pub mod convert;
+pub mod printer;
+#[cfg(feature = "serde")]
+pub mod serialize;
+
+// Tools built on it, for the `yal` binary and for other front ends.
+pub mod lexer;
+pub mod formatter;
+pub mod analysis;
+pub mod optimize;
+pub mod include;
+pub mod cache;
+pub mod image;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+
+// Interfaces for other languages.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+// Internals: the standard library's pieces and the evaluator's machinery.
+pub(crate) mod pattern;
+pub(crate) mod compiler;
+pub(crate) mod vm;
+pub(crate) mod profile;
+pub(crate) mod coverage;
+pub(crate) mod calendar;
+pub(crate) mod channel;
+pub(crate) mod parallel;
+pub(crate) mod net;
+#[cfg(feature = "http")]
+pub(crate) mod http;
+
+pub use ast::{ Atom, BuiltinSpec, Function, RefVal, SExpr, Value };
+pub use error::{ EvalError, RuntimeError };
+pub use evaluator::{ call, eval_str, evaluate, evaluate_toplevel, Environment };
+pub use reader::Reader;
@@ -0,0 +1,268 @@
+//! The lists expressions are made of, singly linked with shared tails, as
+//! in most lisps.
+//!
+//! Cloning a list, taking its tail and putting an element in front of it
+//! are all O(1), and leave the lists they were made from as they are, so
+//! `cons` and `cdr` cost the same however long the list and however many
+//! references to it there are. Indexing is O(n).
+//!
+//! Cells also cache a hash of the list from them to its end, see
+//! `cached_hash`. Dropping a list drops the lists nested in its elements
+//! too without recursing, see `Nested`, so however deep they are they don't
+//! overflow the stack.
+
+use std::cell::Cell;
+use std::fmt::{ self, Debug, Formatter };
+use std::hash::{ DefaultHasher, Hasher };
+use std::ops::Index;
+use std::rc::Rc;
+
+pub struct List<T: Nested> {
+    head: Option<Rc<Node<T>>>,
+    len: usize,
+}
+
+/// Elements that can hold lists of their own.
+pub trait Nested: Sized {
+    /// Moves the lists `self` holds, and that nothing else shares, into
+    /// `lists`, leaving empty ones in their place, for a list being dropped
+    /// to drop them in turn instead of recursively.
+    fn take_lists(&mut self, lists: &mut Vec<List<Self>>);
+}
+
+struct Node<T> {
+    value: T,
+    next: Option<Rc<Node<T>>>,
+    /// The hash of the list starting here, once computed.
+    hash: Cell<Option<u64>>,
+}
+
+/// The hash of the empty list.
+const EMPTY_HASH: u64 = 0;
+
+impl<T: Nested> List<T> {
+    pub fn new() -> Self {
+        List { head: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+
+    /// Puts `value` in front of the list. The rest of the list is shared
+    /// with its other clones, not copied.
+    pub fn push_front(&mut self, value: T) {
+        let next = self.head.take();
+        self.head = Some(Rc::new(Node { value, next, hash: Cell::new(None) }));
+        self.len += 1;
+    }
+
+    /// The list without its first element, sharing it with this one. The
+    /// tail of the empty list is empty.
+    pub fn tail(&self) -> List<T> {
+        match &self.head {
+            Some(node) => List { head: node.next.clone(), len: self.len - 1 },
+            None => List::new(),
+        }
+    }
+
+    /// A hash of the list, made of the hashes `hash` gives its elements.
+    /// It is cached in the cells of the list, where its clones and the
+    /// lists that share its tail find it, so `hash` must always be the same
+    /// function. Then equal lists hash the same if `hash` agrees with
+    /// equality on the elements.
+    pub fn cached_hash(&self, hash: impl Fn(&T) -> u64) -> u64 {
+        // The hash of a cell covers the rest of the list, so the cells
+        // without one are hashed from the last backwards.
+        let mut pending = Vec::new();
+        let mut tail_hash = EMPTY_HASH;
+        let mut next = self.head.as_deref();
+        while let Some(node) = next {
+            if let Some(hash) = node.hash.get() {
+                tail_hash = hash;
+                break;
+            }
+            pending.push(node);
+            next = node.next.as_deref();
+        }
+
+        for node in pending.into_iter().rev() {
+            let mut hasher = DefaultHasher::new();
+            hasher.write_u64(hash(&node.value));
+            hasher.write_u64(tail_hash);
+            tail_hash = hasher.finish();
+            node.hash.set(Some(tail_hash));
+        }
+        tail_hash
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: self.head.as_deref(), len: self.len }
+    }
+}
+
+impl<T: Nested + Clone> List<T> {
+    /// Takes the first element off the list. It is only cloned if another
+    /// list shares it.
+    pub fn pop_front(&mut self) -> Option<T> {
+        let node = self.head.take()?;
+        self.len -= 1;
+        match Rc::try_unwrap(node) {
+            Ok(node) => {
+                self.head = node.next;
+                Some(node.value)
+            }
+            Err(node) => {
+                self.head = node.next.clone();
+                Some(node.value.clone())
+            }
+        }
+    }
+}
+
+impl<T: Nested> Default for List<T> {
+    fn default() -> Self {
+        List::new()
+    }
+}
+
+impl<T: Nested> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List { head: self.head.clone(), len: self.len }
+    }
+}
+
+// Dropping the nodes one after the other, and the lists nested in them
+// after that, rather than recursively, so that long or deep lists don't
+// overflow the stack.
+impl<T: Nested> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut heads: Vec<Rc<Node<T>>> = self.head.take().into_iter().collect();
+        let mut nested = Vec::new();
+        while let Some(node) = heads.pop() {
+            // Nodes other lists share stay, with what they hold.
+            let Ok(mut node) = Rc::try_unwrap(node) else { continue };
+            node.value.take_lists(&mut nested);
+            heads.extend(nested.drain(..).filter_map(|mut list| list.head.take()));
+            heads.extend(node.next.take());
+        }
+    }
+}
+
+impl<T: Nested + PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &List<T>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Nested + Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Nested> Index<usize> for List<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("list index out of bounds")
+    }
+}
+
+impl<T: Nested> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let elements: Vec<T> = iter.into_iter().collect();
+        let mut list = List::new();
+        for element in elements.into_iter().rev() {
+            list.push_front(element);
+        }
+        list
+    }
+}
+
+impl<T: Nested, const N: usize> From<[T; N]> for List<T> {
+    fn from(elements: [T; N]) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+impl<T: Nested> From<Vec<T>> for List<T> {
+    fn from(elements: Vec<T>) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        self.len -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { next: self.next, len: self.len }
+    }
+}
+
+impl<'a, T: Nested> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Takes the elements out of a list, cloning only those it shares.
+pub struct IntoIter<T: Nested>(List<T>);
+
+impl<T: Nested + Clone> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T: Nested + Clone> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: Nested + Clone> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
@@ -1,75 +1,714 @@
-#![feature(pattern)]
-#![feature(box_patterns)]
-#![feature(result_cloned)]
+use std::{ fs, env, io, process, thread };
+use std::io::{ IsTerminal, Write };
+use std::collections::{ HashSet, VecDeque };
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
 
-mod error;
-mod ast;
-mod reader;
-mod evaluator;
-mod std_lib;
+use yal::{ analysis, ast, cache, error, formatter, image, include, optimize, printer, repl, std_lib };
+use yal::ast::SExpr;
+use yal::error::{ RuntimeError, SourceMap };
+use yal::evaluator::*;
 
-use std::{ fs, env };
+const USAGE: &str = "usage: yal [options] [<file>... | -] [-- <args>...]
+       yal fmt [--check] <file>...
 
-use reader::Reader;
-use evaluator::*;
+Before the program, the prelude at $YAL_PRELUDE, or else at
+~/.config/yal/prelude.yal if there is one, is evaluated in its environment.
 
-/*
-macro_rules! try_res {
-    ($($tok:tt)*) => {
-        (|| -> Result<_, _> { $($tok)* })()
+The files are evaluated in order in the same environment, stopping at the
+first one that fails. The arguments after -- are bound to *args*, as a list
+of strings.
+
+options:
+  -e, --eval <expr>      evaluate <expr> after the files, can be repeated
+  -p, --print-results    print the value of each top-level form
+  --no-prelude           don't evaluate the prelude
+  --image <path>         restore the bindings saved with save-image in <path>
+                         instead of evaluating the prelude
+  --dump-ast             print the syntax tree instead of evaluating
+  --symbols              print what the file defines instead of evaluating,
+                         as lines of tab separated <name> <line> <col>
+  --check                report syntax errors and obvious mistakes, as
+                         <file>:<line>:<col>: <message>, instead of evaluating,
+                         along with what --strict and --warn-unused report
+  --strict               refuse to run a program referring to names it never
+                         binds
+  --warn-unused          warn on stderr about variables and parameters that
+                         are never used, unless their name starts with _
+  --fold-constants       evaluate constant expressions ahead of time
+  --compile-cache        keep the parsed file in <file>.bin, and use it when
+                         the file hasn't changed
+  --vm                   run functions on the bytecode VM
+  --trace                print every call and its result to stderr
+  --profile              print how long functions took to stderr at the end
+  --bench                run the benchmarks the program defines with defbench
+  --test                 run the tests the program defines with deftest
+  --coverage             print how many times each line ran to stderr at the
+                         end
+  --fuel <steps>         stop after evaluating this many steps
+  --max-size <size>      limit the size of values
+  --max-depth <n>        how deeply calls can nest, 50000 by default
+  --int-overflow <mode>  what int arithmetic does on overflow: give a big
+                         int, with promote, the default, fail, with checked,
+                         or wrap around, with wrapping
+  --features <names>     comma separated features that #+feature(name) sees,
+                         besides those yal was built with, can be repeated
+
+fmt options:
+  --check                change nothing, but fail if a file isn't formatted
+
+The exit status is 0 on success, 1 after a runtime error and 2 when the
+program doesn't parse or the command line is wrong, unless the program calls
+exit with a status of its own. With --check, it is 1 if anything was
+reported.";
+
+/// Exit statuses, unless the program calls `exit` with its own.
+const SUCCESS: i32 = 0;
+const RUNTIME_ERROR: i32 = 1;
+/// Also used when the program doesn't parse.
+const USAGE_ERROR: i32 = 2;
+
+/// The variable naming the prelude, which otherwise is `DEFAULT_PRELUDE`.
+const PRELUDE_VAR: &str = "YAL_PRELUDE";
+/// Where the prelude is by default, relative to the home directory.
+const DEFAULT_PRELUDE: &str = ".config/yal/prelude.yal";
+
+/// The stack of the thread programs run on. Only what is used is ever
+/// allocated, so it can leave room for deep recursion.
+const STACK_SIZE: usize = 1 << 30;
+/// How deeply calls can nest unless `--max-depth` says otherwise, well within
+/// `STACK_SIZE` even for unoptimized builds.
+const DEFAULT_MAX_DEPTH: usize = 50_000;
+
+/// How programs read from standard input are called in error messages.
+const STDIN_NAME: &str = "<stdin>";
+
+/// A program to run.
+struct Source {
+    /// How the program is called in error messages.
+    name: String,
+    contents: String,
+    /// The file the program was read from, if any.
+    path: Option<PathBuf>,
+    /// Where to cache the parsed program, with `--compile-cache`.
+    cache: Option<PathBuf>,
+}
+
+impl Source {
+    fn new(name: String, contents: String) -> Source {
+        Source { name, contents, path: None, cache: None }
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    /// The files to run, in order. `-` stands for standard input.
+    files: Vec<String>,
+    /// What comes after `--`, for the program.
+    args: Vec<String>,
+    /// Expressions given with `-e`, in order.
+    exprs: Vec<String>,
+    /// Print the value of every top-level form that isn't nil.
+    print_results: bool,
+    no_prelude: bool,
+    dump_ast: bool,
+    symbols: bool,
+    check: bool,
+    strict: bool,
+    warn_unused: bool,
+    fold_constants: bool,
+    compile_cache: bool,
+    use_vm: bool,
+    trace: bool,
+    profile: bool,
+    coverage: bool,
+    test: bool,
+    bench: bool,
+    fuel: Option<u64>,
+    max_size: Option<usize>,
+    max_depth: Option<usize>,
+    int_overflow: IntOverflow,
+    /// Added with `--features`.
+    features: Vec<String>,
+    /// Restored instead of the prelude, with `--image`.
+    image: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut opts = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-e" | "--eval" => {
+                    opts.exprs.push(args.next().ok_or("-e expects an expression")?);
+                }
+                "-p" | "--print-results" => opts.print_results = true,
+                "--no-prelude" => opts.no_prelude = true,
+                "--dump-ast" => opts.dump_ast = true,
+                "--symbols" => opts.symbols = true,
+                "--check" => opts.check = true,
+                "--strict" => opts.strict = true,
+                "--warn-unused" => opts.warn_unused = true,
+                "--fold-constants" => opts.fold_constants = true,
+                "--compile-cache" => opts.compile_cache = true,
+                "--vm" => opts.use_vm = true,
+                "--trace" => opts.trace = true,
+                "--profile" => opts.profile = true,
+                "--coverage" => opts.coverage = true,
+                "--test" => opts.test = true,
+                "--bench" => opts.bench = true,
+                "--fuel" => {
+                    let steps = args.next().and_then(|steps| steps.parse::<u64>().ok());
+                    opts.fuel = Some(steps.ok_or("--fuel expects a number of steps")?);
+                }
+                "--max-size" => {
+                    let size = args.next().and_then(|size| size.parse::<usize>().ok());
+                    opts.max_size = Some(size.ok_or("--max-size expects a size")?);
+                }
+                "--max-depth" => {
+                    let depth = args.next().and_then(|depth| depth.parse::<usize>().ok());
+                    opts.max_depth = Some(depth.ok_or("--max-depth expects a number of calls")?);
+                }
+                "--image" => {
+                    opts.image = Some(PathBuf::from(args.next().ok_or("--image expects a path")?));
+                }
+                "--features" => {
+                    let names = args.next().ok_or("--features expects comma separated names")?;
+                    opts.features.extend(names.split(',').filter(|name| !name.is_empty()).map(str::to_string));
+                }
+                "--int-overflow" => {
+                    let mode = args.next().as_deref().and_then(IntOverflow::from_name);
+                    opts.int_overflow = mode.ok_or("--int-overflow expects promote, checked or wrapping")?;
+                }
+                "--" => {
+                    opts.args.extend(args);
+                    break;
+                }
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option '{}'\n{}", flag, USAGE));
+                }
+                _ => opts.files.push(arg),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+fn main() {
+    // The main thread's stack is too small for deep recursion.
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        try_main().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            RUNTIME_ERROR
+        })
+    });
+    let status = match runner.map(|runner| runner.join()) {
+        Ok(Ok(status)) => status,
+        // The panic message is printed already.
+        Ok(Err(_)) => RUNTIME_ERROR,
+        Err(err) => {
+            eprintln!("{}", err);
+            RUNTIME_ERROR
+        }
     };
+    // `process::exit` doesn't flush standard output.
+    let _ = io::stdout().flush();
+    process::exit(status);
 }
 
-macro_rules! try_opt {
-    ($($tok:tt)*) => {
-        (|| -> Option<_> { $($tok)* })()
+/// Runs the command line, returning the status to exit with. Errors are
+/// those of the interpreter itself, like failing to read standard input.
+fn try_main() -> Result<i32, Box<dyn std::error::Error>> {
+    // Ignore the program name.
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "fmt").is_some() {
+        return Ok(fmt(args));
+    }
+
+    let opts = match Options::parse(args) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return Ok(USAGE_ERROR);
+        }
     };
+
+    let mut env = Environment::with_std_lib()?;
+    env.set_use_vm(opts.use_vm);
+    env.set_trace(opts.trace);
+    env.set_profile(opts.profile);
+    env.set_coverage(opts.coverage);
+    for name in &opts.features {
+        env.add_feature(name);
+    }
+    if let Some(fuel) = opts.fuel {
+        env.set_fuel(fuel);
+    }
+    env.set_size_limit(opts.max_size);
+    env.set_max_depth(opts.max_depth.unwrap_or(DEFAULT_MAX_DEPTH));
+    env.set_int_overflow(opts.int_overflow);
+
+    // Ctrl-C stops what is being evaluated rather than the process, so that
+    // output isn't lost and the REPL survives it.
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed))?;
+    env.set_interrupt_flag(interrupt);
+
+    let args = opts.args.iter().map(|arg| SExpr::atom(ast::Atom::String(arg.as_str().into()))).collect();
+    env.define_var("*args*", ast::RefVal::owned(ast::Value::Quote(Rc::new(SExpr::list(args)))))?;
+
+    let mut sources = Vec::new();
+    for fname in &opts.files {
+        if fname == "-" {
+            sources.push(Source::new(STDIN_NAME.to_string(), io::read_to_string(io::stdin())?));
+            continue;
+        }
+
+        let read = fs::read_to_string(fname).and_then(|contents| {
+            Ok((contents, Path::new(fname).canonicalize()?))
+        });
+        let (contents, path) = match read {
+            Ok(read) => read,
+            Err(err) => {
+                eprintln!("couldn't read '{}': {}", fname, err);
+                return Ok(USAGE_ERROR);
+            }
+        };
+        sources.push(Source {
+            name: fname.clone(),
+            contents,
+            path: Some(path),
+            cache: opts.compile_cache.then(|| cache::cache_path(Path::new(fname))),
+        });
+    }
+    // Without a file, a program may still be piped in.
+    if opts.files.is_empty() && opts.exprs.is_empty() && !io::stdin().is_terminal() {
+        sources.push(Source::new(STDIN_NAME.to_string(), io::read_to_string(io::stdin())?));
+    }
+
+    for (i, expr) in opts.exprs.iter().enumerate() {
+        sources.push(Source::new(format!("<eval {}>", i + 1), expr.clone()));
+    }
+
+    if opts.check {
+        return Ok(check(&env, &sources));
+    }
+
+    // Every source is read before anything runs, the prelude included, so
+    // that a syntax error in one stops the program before the ones before it
+    // have done anything.
+    let mut programs = Vec::with_capacity(sources.len());
+    for source in &sources {
+        match read(&env, source) {
+            Ok(program) => programs.push(program),
+            Err(status) => return Ok(status),
+        }
+    }
+
+    let evaluates = !opts.dump_ast && !opts.symbols;
+    if evaluates && opts.warn_unused {
+        for program in &programs {
+            warn_unused(&program.sources, &program.exprs);
+        }
+    }
+    if let (true, Some(path)) = (evaluates, &opts.image) {
+        if let Err(err) = image::load(&mut env, path) {
+            eprintln!("{}", err);
+            return Ok(USAGE_ERROR);
+        }
+    } else if evaluates && !opts.no_prelude {
+        if let Some(status) = load_prelude(&mut env, &opts) {
+            return Ok(status);
+        }
+    }
+    if evaluates && opts.strict && !strict(&env, &sources) {
+        return Ok(RUNTIME_ERROR);
+    }
+
+    if sources.is_empty() {
+        return Ok(repl::run(&mut env, &mut repl::Terminal::new()?, &mut io::stdout())?);
+    }
+
+    let mut status = sources
+        .iter()
+        .zip(programs)
+        .find_map(|(source, program)| run(&mut env, source, program, &opts));
+    if opts.test && status.is_none() {
+        status = run_tests(&mut env);
+    }
+    if opts.bench && status.is_none() {
+        if let Err(err) = std_lib::run_benches(&mut env) {
+            let _ = env.output().flush();
+            if !matches!(err.root(), RuntimeError::Exit(_)) {
+                eprintln!("{}", err);
+            }
+            status = Some(exit_status(&err));
+        }
+    }
+
+    env.output().flush()?;
+    if let Some(profile) = env.profile() {
+        eprint!("{}", profile);
+    }
+    if let Some(coverage) = env.coverage() {
+        eprint!("{}", coverage);
+    }
+    Ok(status.unwrap_or(SUCCESS))
 }
-*/
 
+/// `yal fmt`: formats the files given in place, or with `--check` only
+/// reports those that aren't formatted. Files that don't parse are left
+/// alone. Returns the exit code.
+fn fmt(args: impl Iterator<Item = String>) -> i32 {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("unknown option '{}'\n{}", flag, USAGE);
+                return 2;
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
 
-fn main() -> Result<(), Box<dyn std::error::Error>>{
-    let mut args = env::args();
+    let mut status = 0;
+    for file in &files {
+        let src = match fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("couldn't read '{}': {}", file, err);
+                status = 1;
+                continue;
+            }
+        };
 
-    // Ignore the program name.
-    args.next();
+        let formatted = match formatter::format_source(&src) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}", err.in_file(file).report().colored(stderr_color()));
+                status = 1;
+                continue;
+            }
+        };
 
-    let fname = args.next().ok_or("Expected a file name")?;
+        if formatted == src {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", file);
+            status = 1;
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("couldn't write '{}': {}", file, err);
+            status = 1;
+        }
+    }
 
-    let contents = fs::read_to_string(fname)?;
-    let mut reader = Reader::new(&contents);
-    let s_exprs = match reader.parse_sexprs() {
+    status
+}
+
+/// Evaluates the user's prelude, if there is one. Returns the status to exit
+/// with if it failed, with the errors reported against the prelude's path.
+fn load_prelude(env: &mut Environment, opts: &Options) -> Option<i32> {
+    // Only a prelude that was asked for explicitly has to exist.
+    let (path, required) = match env::var_os(PRELUDE_VAR) {
+        Some(path) => (PathBuf::from(path), true),
+        None => (PathBuf::from(env::var_os("HOME")?).join(DEFAULT_PRELUDE), false),
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if required || err.kind() != io::ErrorKind::NotFound => {
+            eprintln!("couldn't read the prelude '{}': {}", path.display(), err);
+            return Some(USAGE_ERROR);
+        }
+        Err(_) => return None,
+    };
+
+    let source = Source {
+        name: path.display().to_string(),
+        contents,
+        path: path.canonicalize().ok(),
+        cache: None,
+    };
+    // Only the options that change how code runs apply to the prelude.
+    let prelude_opts = Options { fold_constants: opts.fold_constants, ..Options::default() };
+    match read(env, &source) {
+        Ok(program) => run(env, &source, program, &prelude_opts),
+        Err(status) => Some(status),
+    }
+}
+
+/// `--check`: reports the syntax errors and the mistakes `analysis::check`
+/// finds in every source, without evaluating anything. The reader stops at
+/// the first syntax error, so there is at most one per source. Returns the
+/// exit code.
+fn check(env: &Environment, sources: &[Source]) -> i32 {
+    // The functions the program can call without defining them, those
+    // taking any number of arguments aside.
+    let arities = env
+        .builtins()
+        .filter(|spec| !spec.variadic)
+        .map(|spec| (spec.name.to_string(), spec.arity))
+        .collect();
+
+    let read: Vec<_> = sources.iter().map(|source| read_program(env, source)).collect();
+    let known = known_names(env, read.iter().filter_map(|(_, parsed)| parsed.as_ref().ok()));
+
+    let mut status = SUCCESS;
+    for (sources, parsed) in read {
+        let report = |byte: usize, message: &str| {
+            let (name, line, col) = sources.position(byte);
+            println!("{}:{}:{}: {}", name, line, col, message);
+        };
+
+        match parsed {
+            Ok(exprs) => {
+                let mut diagnostics = analysis::check(&exprs, &arities);
+                diagnostics.extend(analysis::undefined(&exprs, &known));
+                diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+                for diagnostic in diagnostics {
+                    report(diagnostic.span.start, &diagnostic.message);
+                    status = RUNTIME_ERROR;
+                }
+                warn_unused(&sources, &exprs);
+            }
+            Err((byte, message)) => {
+                report(byte, &message);
+                status = RUNTIME_ERROR;
+            }
+        }
+    }
+    status
+}
+
+/// Parses `source` and splices in the files it includes, for the passes
+/// that look at a program without running it. The error, of the reader or
+/// of an include, comes with where it happened.
+fn read_program(env: &Environment, source: &Source) -> (SourceMap, Result<Vec<SExpr>, (usize, String)>) {
+    let mut sources = SourceMap::new(&source.name, source.contents.as_str().into());
+    let parsed = env
+        .reader(&source.contents)
+        .parse_sexprs()
+        .map_err(|err| (err.byte(), err.message().to_string()))
+        .and_then(|exprs| {
+            include::expand(exprs, source.path.as_deref(), &mut sources, env).map_err(|err| {
+                (err.span().map_or(0, |span| span.start), err.root().to_string())
+            })
+        });
+    (sources, parsed)
+}
+
+/// `--strict`: prints the references to names that aren't bound to standard
+/// error, and returns whether there are none, so that the program can run.
+/// Sources that don't parse are left to fail when they run.
+fn strict(env: &Environment, sources: &[Source]) -> bool {
+    let read: Vec<_> = sources.iter().map(|source| read_program(env, source)).collect();
+    let known = known_names(env, read.iter().filter_map(|(_, parsed)| parsed.as_ref().ok()));
+
+    let mut ok = true;
+    for (sources, parsed) in &read {
+        let Ok(exprs) = parsed else { continue };
+        for diagnostic in analysis::undefined(exprs, &known) {
+            let (name, line, col) = sources.position(diagnostic.span.start);
+            eprintln!("{}:{}:{}: {}", name, line, col, diagnostic.message);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// The names a program made of `sources` can refer to at the top level: those
+/// bound in `env` already, and those any of the sources defines, function
+/// bodies included, since they run in the same environment.
+fn known_names<'a>(env: &Environment, sources: impl Iterator<Item = &'a Vec<SExpr>>) -> HashSet<String> {
+    let mut known: HashSet<String> = env.bound_names().map(|name| name.to_string()).collect();
+    for exprs in sources {
+        known.extend(analysis::index_definitions(exprs).into_iter().map(|def| def.name));
+        known.extend(analysis::globals(exprs));
+    }
+    known
+}
+
+/// Prints what `analysis::unused` finds in `exprs`, read from `sources`, to
+/// standard error. These are only warnings, which don't change the exit
+/// status.
+fn warn_unused(sources: &SourceMap, exprs: &[SExpr]) {
+    for diagnostic in analysis::unused(exprs) {
+        let (name, line, col) = sources.position(diagnostic.span.start);
+        eprintln!("{}:{}:{}: warning: {}", name, line, col, diagnostic.message);
+    }
+}
+
+/// Whether the errors written to standard error should be colored.
+fn stderr_color() -> bool {
+    error::use_color(io::stderr().is_terminal())
+}
+
+/// Parses `contents`, or takes the expressions from `cache` if it was made
+/// from them with the same features. The cache is rewritten when it can't be
+/// used.
+fn parse<'a>(
+    env: &Environment,
+    contents: &'a str,
+    cache: Option<&Path>,
+) -> Result<VecDeque<SExpr>, error::Error<'a>> {
+    // The features decide which forms conditionals keep.
+    let features: Vec<_> = env.features().iter().collect();
+    let key = format!("{}\n{}", features.join(","), contents);
+    if let Some(exprs) = cache.and_then(|cache| cache::load(cache, &key, env.symbols())) {
+        return Ok(exprs);
+    }
+
+    let exprs = env.reader(contents).parse_sexprs()?;
+    if let Some(cache) = cache {
+        // The cache is only an optimization, the program runs all the same.
+        let _ = cache::store(cache, &key, &exprs);
+    }
+    Ok(exprs)
+}
+
+/// Runs the tests the program defined, for `--test`. Returns the status to
+/// exit with if any failed.
+fn run_tests(env: &mut Environment) -> Option<i32> {
+    let failed = std_lib::run_tests_impl(env);
+    match failed.as_deref() {
+        Ok(ast::Value::Int(0)) => None,
+        Ok(_) => Some(RUNTIME_ERROR),
+        Err(err) => Some(exit_status(err)),
+    }
+}
+
+/// The status to exit with after `err`.
+fn exit_status(err: &RuntimeError) -> i32 {
+    match err.root() {
+        RuntimeError::Exit(status) => *status,
+        // What shells use for programs killed by SIGINT.
+        RuntimeError::Interrupted => 130,
+        _ => RUNTIME_ERROR,
+    }
+}
+
+/// A source read in full, with the files it includes spliced in.
+struct Program {
+    /// The source and the files it includes, to locate errors.
+    sources: SourceMap,
+    exprs: Vec<SExpr>,
+}
+
+/// Parses `source`, expands its includes and evaluates its `comptime` forms,
+/// reporting errors against its name. Returns the status to exit with if that
+/// failed: 2 if it or a file it includes doesn't parse, or can't be included.
+fn read(env: &Environment, source: &Source) -> Result<Program, i32> {
+    let Source { name, contents, .. } = source;
+    let s_exprs = match parse(env, contents, source.cache.as_deref()) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("{}", e);
-            return Ok(());
+            eprintln!("{}", e.in_file(name).report().colored(stderr_color()));
+            return Err(USAGE_ERROR);
         },
     };
 
-    let mut env = Environment::new();
+    let mut sources = SourceMap::new(name, contents.as_str().into());
+    let s_exprs = include::expand(s_exprs, source.path.as_deref(), &mut sources, env)
+        .map_err(|err| (err, USAGE_ERROR))
+        .and_then(|s_exprs| {
+            s_exprs
+                .into_iter()
+                .map(|expr| optimize::comptime(expr, env))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| {
+                    let status = exit_status(&err);
+                    (err, status)
+                })
+        });
+    match s_exprs {
+        Ok(exprs) => Ok(Program { sources, exprs }),
+        Err((err, status)) => {
+            report(source, &sources, err);
+            Err(status)
+        }
+    }
+}
 
-    env.register_external_fun("let", 2, std_lib::let_impl);
-    env.register_external_fun("fn", 2, std_lib::fn_impl);
-    env.register_external_fun("if", 3, std_lib::if_impl);
-    env.register_external_fun("eval", 1, std_lib::eval_impl);
-    env.register_external_fun("cons", 2, std_lib::cons_impl);
-    env.register_external_fun("car", 1, std_lib::car_impl);
-    env.register_external_fun("cdr", 1, std_lib::cdr_impl);
-    env.register_external_fun("=", 2, std_lib::eq);
-    env.register_external_fun("eq", 2, std_lib::eq);
-    env.register_external_fun("+", 2, std_lib::add);
-    env.register_external_fun("-", 2, std_lib::sub);
-    env.register_external_fun("*", 2, std_lib::mul);
-    env.register_external_fun("/", 2, std_lib::div);
-    env.register_external_fun("print", 1, std_lib::print_impl);
+/// Reports `err` against the files of `source` in `sources`.
+fn report(source: &Source, sources: &SourceMap, err: RuntimeError) {
+    let err = sources.attribute(err);
+    let located = error::Located { file: Some(&source.name), src: &source.contents, error: &err };
+    eprintln!("{}", located.report().colored(stderr_color()));
+}
 
-    env.bind_var("nil", ast::RefVal::reference(std_lib::nil_ref()));
-    env.bind_var("t", ast::RefVal::reference(std_lib::true_ref()));
-    env.bind_var("f", ast::RefVal::reference(std_lib::false_ref()));
+/// Runs every top-level form of `program`, read from `source`. Returns `None`
+/// if it got to the end, or the status to exit with if it stopped because of
+/// an error or `exit`.
+fn run(env: &mut Environment, source: &Source, program: Program, opts: &Options) -> Option<i32> {
+    let Source { name, contents, .. } = source;
+    let Program { sources, exprs: s_exprs } = program;
 
-    for expr in s_exprs {
-        evaluate(&expr, &mut env)?;
+    let s_exprs: Rc<[SExpr]> = if opts.fold_constants {
+        s_exprs.into_iter().map(|expr| optimize::optimize(expr, env)).collect()
+    } else {
+        s_exprs.into_iter().collect()
+    };
+
+    if opts.dump_ast {
+        for expr in s_exprs.iter() {
+            println!("{}", printer::Dump(expr));
+        }
+        return None;
     }
 
-    Ok(())
+    if opts.symbols {
+        // Only the file's own, those it includes are listed with theirs.
+        for def in analysis::index_definitions(&s_exprs) {
+            let (file, offset) = sources.locate(def.span.start);
+            if file.start == 0 {
+                let (line, col) = error::line_col(contents, offset);
+                println!("{}\t{}\t{}", def.name, line, col);
+            }
+        }
+        return None;
+    }
+
+    env.add_source(name, contents.as_str().into(), s_exprs.clone());
+    if let Some(path) = &source.path {
+        env.push_file(path.clone());
+    }
+
+    let mut status = None;
+    for expr in s_exprs.iter() {
+        match evaluate_toplevel(expr, env) {
+            Ok(val) => {
+                // Through the program's output, to come in order with what
+                // it prints.
+                if opts.print_results && !matches!(*val, ast::Value::Nil) {
+                    let _ = writeln!(env.output(), "{}", printer::Written(&*val));
+                }
+            }
+            Err(err) => {
+                // What was printed before the error should come first.
+                let _ = env.output().flush();
+                status = Some(exit_status(&err));
+                if !matches!(err.root(), RuntimeError::Exit(_)) {
+                    report(source, &sources, err);
+                }
+                break;
+            }
+        }
+    }
+
+    if source.path.is_some() {
+        env.pop_file();
+    }
+    status
 }
@@ -0,0 +1,215 @@
+//! TCP connections and listeners, for `tcp-connect`, `tcp-listen` and the
+//! builtins that use what they return.
+//!
+//! Both are `Handle`s, which are only equal to themselves. Closing one
+//! with `tcp-close` closes it for every reference to it, and the others fail
+//! from then on. Reads on a connection time out after `DEFAULT_TIMEOUT`,
+//! unless `tcp-set-timeout` says otherwise.
+
+use std::cell::RefCell;
+use std::fmt::{ self, Display, Formatter };
+use std::io::{ self, BufRead, BufReader, ErrorKind, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::ast::*;
+use crate::error::RuntimeError;
+use crate::evaluator::Environment;
+use crate::std_lib;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Connection {
+    /// `None` once closed.
+    stream: Option<BufReader<TcpStream>>,
+    peer: SocketAddr,
+}
+
+pub struct Listener {
+    /// `None` once closed.
+    listener: Option<TcpListener>,
+    local: SocketAddr,
+}
+
+/// Written as the address of the other end.
+impl Display for Connection {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.peer, if self.stream.is_some() { "" } else { " closed" })
+    }
+}
+
+/// Written as the address listened on.
+impl Display for Listener {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.local, if self.listener.is_some() { "" } else { " closed" })
+    }
+}
+
+pub fn register(env: &mut Environment) {
+    let builtins: &[(&'static str, usize, &'static str, LibFn)] = &[
+        ("tcp-connect", 2, "Opens a TCP connection to a host and port.", tcp_connect_impl),
+        ("tcp-listen", 1, "Listens for TCP connections on a port of every interface, any free one for 0.", tcp_listen_impl),
+        ("tcp-accept", 1, "Waits for a connection to a listener and returns it.", tcp_accept_impl),
+        ("tcp-send", 2, "Sends a string over a connection.", tcp_send_impl),
+        ("tcp-recv-line", 1, "Reads a line from a connection, without its line break, or nil once it ends.", tcp_recv_line_impl),
+        ("tcp-set-timeout", 2, "Sets how many milliseconds reads on a connection wait, nil for no limit.", tcp_set_timeout_impl),
+        ("tcp-local-port", 1, "The port a listener or the local end of a connection is on.", tcp_local_port_impl),
+        ("tcp-close", 1, "Closes a connection or a listener. Closing it again does nothing.", tcp_close_impl),
+    ];
+    for &(name, arity, doc, ptr) in builtins {
+        env.register_builtin(BuiltinSpec::new(name, arity).doc(doc).category("network"), ptr);
+    }
+}
+
+fn io_error(context: &str, err: io::Error) -> RuntimeError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => RuntimeError::Custom(format!("{}: timed out", context)),
+        _ => RuntimeError::Custom(format!("{}: {}", context, err)),
+    }
+}
+
+fn closed_error(peer: SocketAddr) -> RuntimeError {
+    RuntimeError::Custom(format!("the connection to {} is closed", peer))
+}
+
+fn port(val: &RefVal, context: &str) -> Result<u16, RuntimeError> {
+    match **val {
+        Value::Int(port) => u16::try_from(port)
+            .map_err(|_| RuntimeError::Custom(format!("expected a port from 0 to 65535, got {}", port))),
+        _ => Err(std_lib::mismatch("a port", val, context)),
+    }
+}
+
+fn handle(val: &RefVal, expected: &'static str, context: &str) -> Result<Handle, RuntimeError> {
+    match &**val {
+        Value::Handle(handle) => Ok(handle.clone()),
+        _ => Err(std_lib::mismatch(expected, val, context)),
+    }
+}
+
+fn connection(val: &RefVal, context: &str) -> Result<Rc<RefCell<Connection>>, RuntimeError> {
+    match handle(val, "a connection", context)? {
+        Handle::Connection(conn) => Ok(conn),
+        _ => Err(std_lib::mismatch("a connection", val, context)),
+    }
+}
+
+fn new_connection(stream: TcpStream, peer: SocketAddr, context: &str) -> Result<RefVal, RuntimeError> {
+    stream.set_read_timeout(Some(DEFAULT_TIMEOUT)).map_err(|err| io_error(context, err))?;
+    let conn = Connection { stream: Some(BufReader::new(stream)), peer };
+    Ok(RefVal::owned(Value::Handle(Handle::Connection(Rc::new(RefCell::new(conn))))))
+}
+
+pub fn tcp_connect_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let port_val = env.pop_stack()?;
+    let host = env.pop_stack()?;
+    let host = host.as_string().ok_or_else(|| std_lib::mismatch("a host string", &host, "'tcp-connect'"))?;
+    let port = port(&port_val, "'tcp-connect'")?;
+
+    let context = format!("couldn't connect to {}:{}", host, port);
+    let stream = TcpStream::connect((host, port)).map_err(|err| io_error(&context, err))?;
+    let peer = stream.peer_addr().map_err(|err| io_error(&context, err))?;
+    new_connection(stream, peer, &context)
+}
+
+pub fn tcp_listen_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let port = port(&env.pop_stack()?, "'tcp-listen'")?;
+
+    let context = format!("couldn't listen on port {}", port);
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|err| io_error(&context, err))?;
+    let local = listener.local_addr().map_err(|err| io_error(&context, err))?;
+    let listener = Listener { listener: Some(listener), local };
+    Ok(RefVal::owned(Value::Handle(Handle::Listener(Rc::new(RefCell::new(listener))))))
+}
+
+pub fn tcp_accept_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    let Handle::Listener(listener) = handle(&val, "a listener", "'tcp-accept'")? else {
+        return Err(std_lib::mismatch("a listener", &val, "'tcp-accept'"));
+    };
+
+    let listener = listener.borrow();
+    let context = format!("couldn't accept on {}", listener.local);
+    let socket = listener.listener.as_ref().ok_or_else(|| {
+        RuntimeError::Custom(format!("the listener on {} is closed", listener.local))
+    })?;
+    let (stream, peer) = socket.accept().map_err(|err| io_error(&context, err))?;
+    new_connection(stream, peer, &context)
+}
+
+pub fn tcp_send_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let data = env.pop_stack()?;
+    let val = env.pop_stack()?;
+    let conn = connection(&val, "'tcp-send'")?;
+    let data = data.as_string().ok_or_else(|| std_lib::mismatch("a string", &data, "'tcp-send'"))?;
+
+    let mut conn = conn.borrow_mut();
+    let peer = conn.peer;
+    let context = format!("couldn't send to {}", peer);
+    let stream = conn.stream.as_mut().ok_or_else(|| closed_error(peer))?;
+    stream.get_mut().write_all(data.as_bytes()).map_err(|err| io_error(&context, err))?;
+    Ok(RefVal::reference(std_lib::nil_ref()))
+}
+
+pub fn tcp_recv_line_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    let conn = connection(&val, "'tcp-recv-line'")?;
+
+    let mut conn = conn.borrow_mut();
+    let peer = conn.peer;
+    let context = format!("couldn't receive from {}", peer);
+    let stream = conn.stream.as_mut().ok_or_else(|| closed_error(peer))?;
+    let mut line = String::new();
+    if stream.read_line(&mut line).map_err(|err| io_error(&context, err))? == 0 {
+        return Ok(RefVal::reference(std_lib::nil_ref()));
+    }
+
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(line.into())
+}
+
+pub fn tcp_set_timeout_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let millis = env.pop_stack()?;
+    let val = env.pop_stack()?;
+    let conn = connection(&val, "'tcp-set-timeout'")?;
+    let timeout = match *millis {
+        Value::Nil => None,
+        Value::Int(millis) if millis > 0 => Some(Duration::from_millis(millis as u64)),
+        _ => return Err(std_lib::mismatch("a positive number of milliseconds or nil", &millis, "'tcp-set-timeout'")),
+    };
+
+    let conn = conn.borrow();
+    let peer = conn.peer;
+    let context = format!("couldn't set the timeout of {}", peer);
+    let stream = conn.stream.as_ref().ok_or_else(|| closed_error(peer))?;
+    stream.get_ref().set_read_timeout(timeout).map_err(|err| io_error(&context, err))?;
+    Ok(RefVal::reference(std_lib::nil_ref()))
+}
+
+pub fn tcp_local_port_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    let port = match handle(&val, "a connection or a listener", "'tcp-local-port'")? {
+        Handle::Channel(_) => return Err(std_lib::mismatch("a connection or a listener", &val, "'tcp-local-port'")),
+        Handle::Listener(listener) => listener.borrow().local.port(),
+        Handle::Connection(conn) => {
+            let conn = conn.borrow();
+            let peer = conn.peer;
+            let stream = conn.stream.as_ref().ok_or_else(|| closed_error(peer))?;
+            let context = format!("couldn't find the local port of {}", peer);
+            stream.get_ref().local_addr().map_err(|err| io_error(&context, err))?.port()
+        }
+    };
+    Ok(RefVal::from(port as i64))
+}
+
+pub fn tcp_close_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    match handle(&val, "a connection or a listener", "'tcp-close'")? {
+        Handle::Connection(conn) => conn.borrow_mut().stream = None,
+        Handle::Listener(listener) => listener.borrow_mut().listener = None,
+        Handle::Channel(_) => return Err(std_lib::mismatch("a connection or a listener", &val, "'tcp-close'")),
+    }
+    Ok(RefVal::reference(std_lib::nil_ref()))
+}
@@ -0,0 +1,160 @@
+//! Optional passes that rewrite parsed code before it is evaluated.
+
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::error::RuntimeError;
+use crate::evaluator::{ evaluate, Environment, IntOverflow };
+use crate::std_lib;
+
+/// Constant folding: every application of a builtin of `env` marked pure to
+/// literal arguments is replaced by its result, innermost first, so
+/// `(* 2 (+ 1 2))` becomes `6`.
+///
+/// Quoted expressions are data and are left alone, as is any application
+/// that fails (like a division by zero), so that the error still happens at
+/// runtime. Folding overflows no int, since what that does depends on the
+/// mode the program runs in. This assumes the program doesn't rebind the
+/// builtins' names.
+pub fn optimize(expr: SExpr, env: &Environment) -> SExpr {
+    let mut pure = Environment::new();
+    pure.set_int_overflow(IntOverflow::Checked);
+    for spec in env.builtins().filter(|spec| spec.pure) {
+        let bound = env.builtin(spec.name).and(env.lookup_var(spec.name)).map(|val| &**val);
+        if let Some(Value::Function(Function::Lib { ptr, .. })) = bound {
+            let ptr = ptr.clone();
+            pure.register_builtin(spec.clone(), move |env| ptr(env));
+        }
+    }
+    fold(expr, &mut pure)
+}
+
+fn fold(expr: SExpr, env: &mut Environment) -> SExpr {
+    let (list, span) = match expr {
+        SExpr::List(list, span) => (list, span),
+        atom => return atom,
+    };
+
+    let list = list.into_iter().map(|el| fold(el, env)).collect();
+    let expr = SExpr::List(list, span);
+
+    if !is_foldable(&expr, env) {
+        return expr;
+    }
+
+    match evaluate(&expr, env).and_then(|val| value_to_sexpr(&val, env.symbols())) {
+        Ok(SExpr::Atom(atom, _)) => SExpr::Atom(atom, span),
+        _ => expr,
+    }
+}
+
+fn is_foldable(expr: &SExpr, env: &Environment) -> bool {
+    let list = match expr {
+        SExpr::List(list, _) => list,
+        _ => return false,
+    };
+
+    let is_pure = list
+        .front()
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_ident)
+        .is_some_and(|head| env.builtin(head).is_some());
+
+    is_pure && list.iter().skip(1).all(|arg| matches!(
+        arg,
+        SExpr::Atom(Atom::Int(_) | Atom::BigInt(_) | Atom::Rational(..) | Atom::Float(_) | Atom::String(_), _)
+    ))
+}
+
+/// The builtins `comptime` may use besides the pure ones, the special forms
+/// needed to write code with them.
+const COMPTIME_FORMS: &[&str] = &[
+    "let", "defconst", "fn", "define-multi", "if", "loop", "recur", "eval", "doc", "bound?", "describe",
+    "bound-names", "comptime",
+];
+
+/// Evaluates every `(comptime expr)` form ahead of time and replaces it by
+/// its result, quoted when it is code, so that the program only sees the
+/// value. Each form runs once, on its own, with only the pure builtins of
+/// `env` and the `COMPTIME_FORMS`: using another one, like `print`, is an
+/// error naming it.
+///
+/// Forms inside quotes are evaluated too, since function bodies are quoted.
+/// Errors are located at the form, or inside it when they know better.
+pub fn comptime(expr: SExpr, env: &Environment) -> Result<SExpr, RuntimeError> {
+    if !has_comptime(&expr) {
+        return Ok(expr);
+    }
+
+    match expr {
+        SExpr::List(list, span) if is_comptime(&list) => {
+            let body = comptime(list.iter().nth(1).unwrap().clone(), env)?;
+            run_comptime(&body, span, env)
+        }
+        SExpr::List(list, span) => {
+            let list = list.into_iter().map(|el| comptime(el, env)).collect::<Result<_, _>>()?;
+            Ok(SExpr::List(list, span))
+        }
+        SExpr::Atom(Atom::Quote(quoted), span) => {
+            let quoted = comptime((*quoted).clone(), env)?;
+            Ok(SExpr::Atom(Atom::Quote(Rc::new(quoted)), span))
+        }
+        atom => Ok(atom),
+    }
+}
+
+fn is_comptime(list: &List<SExpr>) -> bool {
+    list.len() == 2 && list.front().and_then(SExpr::as_atom).and_then(Atom::as_ident) == Some("comptime")
+}
+
+fn has_comptime(expr: &SExpr) -> bool {
+    match expr {
+        SExpr::List(list, _) => is_comptime(list) || list.iter().any(has_comptime),
+        SExpr::Atom(Atom::Quote(quoted), _) => has_comptime(quoted),
+        SExpr::Atom(..) => false,
+    }
+}
+
+fn run_comptime(expr: &SExpr, span: Span, env: &Environment) -> Result<SExpr, RuntimeError> {
+    let mut restricted = Environment::new();
+    for (_, val) in env.iter_bindings() {
+        if let Value::Function(Function::Lib { name, .. }) = **val {
+            if allowed_in_comptime(name, env) {
+                restricted.define_var(name, val.clone())?;
+            }
+        }
+    }
+    for (name, val) in [("nil", std_lib::nil_ref()), ("t", std_lib::true_ref()), ("f", std_lib::false_ref())] {
+        restricted.define_var(name, RefVal::reference(val))?;
+    }
+
+    let val = evaluate(expr, &mut restricted).map_err(|err| match err.root() {
+        RuntimeError::UnboundVariable { name, .. } if is_impure(name, env) => {
+            let error = RuntimeError::Custom(format!("'{}' can't be used in 'comptime', it isn't pure", name));
+            error.with_span(err.span().unwrap_or(span))
+        }
+        _ => err.with_span(span),
+    })?;
+
+    match &*val {
+        Value::Quote(quoted) => Ok(SExpr::Atom(Atom::Quote(quoted.clone()), span)),
+        Value::Function(_) | Value::Handle(_) | Value::Promise(_) => {
+            Err(std_lib::mismatch("data", &val, "'comptime'").with_span(span))
+        }
+        val => match value_to_sexpr(val, env.symbols())? {
+            SExpr::Atom(atom, _) => Ok(SExpr::Atom(atom, span)),
+            expr => Ok(expr),
+        },
+    }
+}
+
+fn allowed_in_comptime(name: &str, env: &Environment) -> bool {
+    COMPTIME_FORMS.contains(&name) || env.builtins().any(|spec| spec.name == name && spec.pure)
+}
+
+/// Whether `name` is a builtin `comptime` leaves out.
+fn is_impure(name: &str, env: &Environment) -> bool {
+    env.lookup_var(name).is_some_and(|val| {
+        matches!(**val, Value::Function(Function::Lib { name, .. }) if !allowed_in_comptime(name, env))
+    })
+}
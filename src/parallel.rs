@@ -0,0 +1,511 @@
+//! `pmap`, which calls a function on the elements of a list on several
+//! threads.
+//!
+//! Values hold `Rc`s and can't cross threads, so each thread gets its own
+//! `Environment` with the standard library, and everything else travels as
+//! source code: the function, the globals it refers to, the elements and the
+//! results. Functions defined in a module, builtins from outside the standard
+//! library and handles can't be written as code, and are an error, except for
+//! channels, which the threads share. What the function prints for each
+//! element is sent back too, and printed in the order of the elements once
+//! they are all done.
+//!
+//! The threads run under the limits of the caller, its fuel, timeout, size
+//! limit and depth limit, and stop when it is interrupted or one of them
+//! fails. They share the caller's fuel, so together they take no more steps
+//! than it had left.
+
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate::ast::*;
+use crate::channel::Channel;
+use crate::error::RuntimeError;
+use crate::evaluator::{ self, Environment, IntOverflow };
+use crate::pattern;
+use crate::printer::Written;
+use crate::reader::Reader;
+use crate::std_lib;
+use crate::symbol::{ Symbol, SymbolTable };
+
+/// The stack of each thread, as big as the main thread's usually is, so
+/// that deep recursion hits the depth limit rather than overflowing it.
+const STACK_SIZE: usize = 8 << 20;
+
+/// The stack each nested call takes at most, in unoptimized builds, for
+/// workers allowed deeper than `STACK_SIZE` goes.
+const STACK_PER_CALL: usize = 24 << 10;
+
+/// How often the thread waiting for the workers checks whether it was
+/// interrupted.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub fn register(env: &mut Environment) {
+    let spec = BuiltinSpec::new("pmap", 2)
+        .doc("Calls a function on each element of a list on several threads, giving the list of results in order.")
+        .category("parallel");
+    env.register_builtin(spec, pmap_impl);
+}
+
+pub fn pmap_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if cfg!(target_arch = "wasm32") {
+        return Err("there are no threads in the browser".into());
+    }
+
+    let list = env.pop_stack()?;
+    let fun = env.pop_stack()?;
+    let list = std_lib::quoted_list(list, "'pmap'")?;
+    let list = list.as_list().expect("'quoted_list' only returns lists");
+    if !matches!(*fun, Value::Function(_)) {
+        return Err(std_lib::mismatch("a function", &fun, "'pmap'"));
+    }
+
+    let fresh = Environment::with_std_lib()?;
+    let mut snapshot = Snapshot {
+        env,
+        fresh: &fresh,
+        defined: HashSet::new(),
+        program: String::new(),
+        channels: Vec::new(),
+    };
+    let fun = snapshot.code(&fun, "the function")?;
+    let Snapshot { program, channels, .. } = snapshot;
+
+    let jobs: Vec<(usize, String)> = list.iter().map(|elem| Written(elem).to_string()).enumerate().collect();
+    let workers = thread::available_parallelism().map_or(1, usize::from).min(jobs.len());
+    let Finished { results, failure, fuel_left } = run_workers(workers, jobs, &program, &channels, &fun, Settings::of(env));
+    if let Some(fuel) = fuel_left {
+        env.set_fuel(fuel);
+    }
+
+    let mut elements = Vec::with_capacity(results.len());
+    for result in results {
+        let Some((Ok(code), printed)) = result else { break };
+        env.output().write_all(printed.as_bytes()).map_err(std_lib::output_error)?;
+        let mut reader = env.reader(&code);
+        let elem = reader.parse_sexpr().map_err(|err| format!("couldn't read back the result '{}': {}", code, err))?;
+        elements.push(elem);
+    }
+    if let Some((index, failure)) = failure {
+        return Err(failure.into_error(index));
+    }
+    Ok(RefVal::owned(Value::Quote(SExpr::list(elements.into()).into())))
+}
+
+/// What the workers take from the environment calling `pmap`, so that
+/// running on other threads gets around none of its limits.
+struct Settings {
+    int_overflow: IntOverflow,
+    print_precision: Option<usize>,
+    print_depth: usize,
+    interrupt: Option<Arc<AtomicBool>>,
+    /// The fuel left, which the workers take their steps from.
+    fuel: Option<Arc<AtomicU64>>,
+    deadline: Option<(Duration, Instant)>,
+    size_limit: Option<usize>,
+    max_depth: usize,
+}
+
+impl Settings {
+    fn of(env: &Environment) -> Settings {
+        Settings {
+            int_overflow: env.int_overflow(),
+            print_precision: env.print_precision(),
+            print_depth: env.print_depth(),
+            interrupt: env.interrupt_flag(),
+            fuel: env.fuel().map(|fuel| Arc::new(AtomicU64::new(fuel))),
+            deadline: env.deadline(),
+            size_limit: env.size_limit(),
+            max_depth: env.max_depth(),
+        }
+    }
+
+    /// Sets up `worker` like the calling environment, sharing its fuel with
+    /// the other workers.
+    fn apply(&self, worker: &mut Environment, stop: Arc<AtomicBool>) {
+        worker.set_int_overflow(self.int_overflow);
+        worker.set_print_precision(self.print_precision);
+        worker.set_print_depth(self.print_depth);
+        worker.set_interrupt_flag(stop);
+        if let Some(fuel) = &self.fuel {
+            worker.share_fuel(fuel.clone());
+        }
+        worker.set_deadline(self.deadline);
+        worker.set_size_limit(self.size_limit);
+        worker.set_max_depth(self.max_depth);
+    }
+
+    /// The stack a worker needs to go `max_depth` calls deep, like the main
+    /// thread of the `yal` binary.
+    fn stack_size(&self) -> usize {
+        STACK_SIZE.max(self.max_depth.saturating_mul(STACK_PER_CALL))
+    }
+}
+
+/// Why calling the function on an element failed. Errors hold `Rc`s and
+/// can't cross threads, so only the limits of the calling environment are
+/// kept apart, for `pmap` to fail with the same error as the caller would.
+#[derive(Debug, Clone)]
+enum Failure {
+    OutOfFuel,
+    Interrupted,
+    Timeout(Duration),
+    DepthExceeded(usize),
+    ResourceLimit { size: usize, limit: usize },
+    Other(String),
+}
+
+impl Failure {
+    fn of(err: &RuntimeError) -> Failure {
+        match err.root() {
+            RuntimeError::OutOfFuel => Failure::OutOfFuel,
+            RuntimeError::Interrupted => Failure::Interrupted,
+            RuntimeError::Timeout(timeout) => Failure::Timeout(*timeout),
+            RuntimeError::DepthExceeded { limit } => Failure::DepthExceeded(*limit),
+            RuntimeError::ResourceLimit { size, limit } => Failure::ResourceLimit { size: *size, limit: *limit },
+            err => Failure::Other(err.to_string()),
+        }
+    }
+
+    fn into_error(self, index: usize) -> RuntimeError {
+        match self {
+            Failure::OutOfFuel => RuntimeError::OutOfFuel,
+            Failure::Interrupted => RuntimeError::Interrupted,
+            Failure::Timeout(timeout) => RuntimeError::Timeout(timeout),
+            Failure::DepthExceeded(limit) => RuntimeError::DepthExceeded { limit },
+            Failure::ResourceLimit { size, limit } => RuntimeError::ResourceLimit { size, limit },
+            Failure::Other(msg) => format!("element {} failed: {}", index, msg).into(),
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(msg: String) -> Self {
+        Failure::Other(msg)
+    }
+}
+
+/// The result of calling the function on an element, written as code, along
+/// with what the call printed.
+type Job = (Result<String, Failure>, String);
+
+/// What the workers did: the result of each element they got to, the first
+/// failure and the element it came from, and the fuel they left, if it was
+/// limited.
+struct Finished {
+    results: Vec<Option<Job>>,
+    failure: Option<(usize, Failure)>,
+    fuel_left: Option<u64>,
+}
+
+/// Runs the jobs on `workers` threads, each set up by `program` and
+/// `channels`, calling `fun` on each one. Once a job fails, or the caller is
+/// interrupted, the workers are interrupted too and no more jobs start.
+fn run_workers(
+    workers: usize,
+    jobs: Vec<(usize, String)>,
+    program: &str,
+    channels: &[(String, Arc<Channel>)],
+    fun: &str,
+    settings: Settings,
+) -> Finished {
+    let count = jobs.len();
+    let jobs = Mutex::new(jobs.into_iter());
+    let (sender, receiver) = mpsc::channel();
+    // Raising a worker's flag interrupts the job it is running, and lowers
+    // the flag again, so `halted` keeps it from starting the next one.
+    let stops: Vec<Arc<AtomicBool>> = (0..workers).map(|_| Arc::default()).collect();
+    let halted = AtomicBool::new(false);
+    let stop_all = || {
+        halted.store(true, Ordering::Relaxed);
+        stops.iter().for_each(|stop| stop.store(true, Ordering::Relaxed));
+    };
+
+    let mut results: Vec<Option<Job>> = vec![None; count];
+    let mut failure = None;
+    // This thread can't send while it waits for the workers, so its ends
+    // don't keep workers receiving from waiting.
+    let mut lent: Vec<&Arc<Channel>> = Vec::new();
+    for (_, channel) in channels {
+        if !lent.iter().any(|end| Arc::ptr_eq(end, channel)) {
+            lent.push(channel);
+        }
+    }
+    let _lent: Vec<_> = lent.iter().map(|end| end.lend()).collect();
+    thread::scope(|scope| {
+        let handles: Vec<_> = stops
+            .iter()
+            .map(|stop| {
+                let (sender, jobs, settings, halted, stop) = (sender.clone(), &jobs, &settings, &halted, stop.clone());
+                thread::Builder::new().stack_size(settings.stack_size()).spawn_scoped(scope, move || {
+                    let mut worker = Environment::with_std_lib().map_err(|err| Failure::of(&err)).and_then(|mut worker| {
+                        settings.apply(&mut worker, stop);
+                        // The worker gets its own end of each channel, once
+                        // however many names it has.
+                        let mut ends: Vec<Arc<Channel>> = Vec::new();
+                        for (name, channel) in channels {
+                            let end = match ends.iter().find(|end| end.same(channel)) {
+                                Some(end) => end.clone(),
+                                None => {
+                                    let end = Arc::new(channel.end());
+                                    ends.push(end.clone());
+                                    end
+                                }
+                            };
+                            let channel = RefVal::owned(Value::Handle(Handle::Channel(end)));
+                            worker.define_var(name, channel).map_err(|err| Failure::of(&err))?;
+                        }
+                        run(program, &mut worker)?;
+                        let fun = run(fun, &mut worker)?.ok_or_else(|| "the function is missing".to_string())?;
+                        Ok((worker, fun))
+                    });
+
+                    while !halted.load(Ordering::Relaxed) {
+                        let Some((index, elem)) = jobs.lock().unwrap().next() else { break };
+                        let result = match &mut worker {
+                            Ok((worker, fun)) => worker.capture_output(|worker| call(worker, fun, &elem)),
+                            Err(err) => (Err(err.clone()), String::new()),
+                        };
+                        if sender.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                    if let Ok((worker, _)) = &mut worker {
+                        worker.return_fuel();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok((index, result)) => {
+                    if let (Err(err), None) = (&result.0, &failure) {
+                        failure = Some((index, err.clone()));
+                        stop_all();
+                    }
+                    results[index] = Some(result);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            let interrupted = settings.interrupt.as_ref().is_some_and(|flag| flag.swap(false, Ordering::Relaxed));
+            if interrupted && failure.is_none() {
+                failure = Some((0, Failure::Interrupted));
+                stop_all();
+            }
+        }
+        for handle in handles.into_iter().flatten() {
+            let _ = handle.join();
+        }
+    });
+
+    if failure.is_none() {
+        if let Some(index) = results.iter().position(Option::is_none) {
+            failure = Some((index, Failure::Other("its thread didn't finish".to_string())));
+        }
+    }
+    let fuel_left = settings.fuel.map(|fuel| fuel.load(Ordering::Relaxed));
+    Finished { results, failure, fuel_left }
+}
+
+/// Evaluates `source`, giving the value of its last expression.
+fn run(source: &str, env: &mut Environment) -> Result<Option<RefVal>, Failure> {
+    let mut reader = env.reader(source);
+    let exprs = reader.parse_sexprs().map_err(|err| err.to_string())?;
+    let mut last = None;
+    for expr in &exprs {
+        last = Some(evaluator::evaluate_toplevel(expr, env).map_err(|err| Failure::of(&err))?);
+    }
+    Ok(last)
+}
+
+/// Calls `fun` on the element written as `elem`, giving the result written
+/// as code.
+fn call(env: &mut Environment, fun: &RefVal, elem: &str) -> Result<String, Failure> {
+    let Value::Function(fun) = &**fun else {
+        return Err(format!("expected a function, got {}", fun).into());
+    };
+    let mut reader = env.reader(elem);
+    let elem = reader.parse_sexpr().map_err(|err| err.to_string())?;
+
+    env.push_stack(RefVal::owned(sexpr_to_value(&elem)));
+    let result = evaluator::call(fun, 1, env).map_err(|err| Failure::of(&err))?;
+    let result = value_to_sexpr(&result, env.symbols()).map_err(|err| err.to_string())?;
+    Ok(Written(&result).to_string())
+}
+
+/// Writes values as code, and collects the definitions of the globals the
+/// functions among them refer to.
+struct Snapshot<'a> {
+    env: &'a Environment,
+    /// A worker's environment before anything runs in it.
+    fresh: &'a Environment,
+    defined: HashSet<String>,
+    /// The `let`s of the globals, in the order they were found.
+    program: String,
+    /// The globals that are channels, which are passed as they are.
+    channels: Vec<(String, Arc<Channel>)>,
+}
+
+impl Snapshot<'_> {
+    /// The code evaluating to `val`. `what` names it in errors.
+    fn code(&mut self, val: &Value, what: &str) -> Result<String, RuntimeError> {
+        let unsendable = |val: &dyn std::fmt::Display, why: &str| -> RuntimeError {
+            format!("can't send {} {} to another thread, {}", what, val, why).into()
+        };
+
+        match val {
+            Value::Function(
+                Function::UserDefined { module: Some(module), .. } | Function::MultiArity { module: Some(module), .. },
+            ) => {
+                Err(unsendable(val, &format!("it is defined in the module '{}'", module)))
+            }
+            Value::Function(fun @ Function::UserDefined { arg_names, patterns, defaults, body, .. }) => {
+                let mut names = Vec::new();
+                identifiers(body, &mut names);
+                defaults.iter().for_each(|default| identifiers(default, &mut names));
+                let bound = bound_names(arg_names, patterns);
+                for name in names {
+                    if !bound.contains(&&*name) {
+                        self.global(&name)?;
+                    }
+                }
+                Ok(write_function(fun, self.env).expect("user-defined functions can be written"))
+            }
+            Value::Function(fun @ Function::MultiArity { clauses, rest, .. }) => {
+                for clause in clauses.values().chain(rest) {
+                    let mut names = Vec::new();
+                    identifiers(&clause.body, &mut names);
+                    let bound = bound_names(&clause.params, &clause.patterns);
+                    for name in names {
+                        if !bound.contains(&&*name) {
+                            self.global(&name)?;
+                        }
+                    }
+                }
+                Ok(write_function(fun, self.env).expect("multi-arity functions can be written"))
+            }
+            Value::Function(Function::Lib { name, .. }) => match self.fresh.lookup_var(name).map(|val| &**val) {
+                Some(Value::Function(Function::Lib { name: fresh, .. })) if fresh == name => Ok(name.to_string()),
+                _ => Err(unsendable(val, "it isn't part of the standard library")),
+            },
+            Value::Handle(handle) => Err(unsendable(handle, "handles belong to their thread")),
+            Value::Promise(promise) => Err(unsendable(promise, "promises belong to their thread")),
+            val => Ok(write_data(val, self.env.symbols()).expect("only functions, handles and promises aren't data")),
+        }
+    }
+
+    /// Defines the global `name` in the program, unless a worker has it
+    /// already or it isn't bound.
+    fn global(&mut self, name: &str) -> Result<(), RuntimeError> {
+        if !self.defined.insert(name.to_string()) {
+            return Ok(());
+        }
+        let Some(val) = self.env.lookup_var(name) else { return Ok(()) };
+        if let Value::Handle(Handle::Channel(channel)) = &**val {
+            self.channels.push((name.to_string(), channel.clone()));
+            return Ok(());
+        }
+        let code = self.code(val, &format!("'{}',", name))?;
+        let fresh = self.fresh.lookup_var(name).map(|val| match &**val {
+            // A builtin writes as its name, which doesn't say which one it is.
+            Value::Function(Function::Lib { name, .. }) => name.to_string(),
+            val => Written(val).to_string(),
+        });
+        if fresh.as_deref() != Some(code.as_str()) {
+            self.program.push_str(&format!("(let '{} {})\n", name, code));
+        }
+        Ok(())
+    }
+}
+
+/// Writes a function defined in yal as the code that makes it again, or
+/// `None` for builtins. The code doesn't say which module the function was
+/// defined in.
+pub(crate) fn write_function(fun: &Function, env: &Environment) -> Option<String> {
+    match fun {
+        Function::UserDefined { arg_names, defaults, body, doc, .. } => {
+            let mut args: Vec<SExpr> = arg_names.iter().map(|name| SExpr::atom(Atom::Ident(name.clone()))).collect();
+            let positional = arg_names.len() - defaults.len();
+            for (arg, default) in args[positional..].iter_mut().zip(defaults) {
+                *arg = SExpr::list(List::from([arg.clone(), default.clone()]));
+            }
+            if !defaults.is_empty() {
+                args.insert(positional, SExpr::atom(Atom::Ident(env.intern("&key"))));
+            }
+            if let Some(doc) = doc {
+                args.insert(0, SExpr::atom(Atom::String(doc.clone())));
+            }
+            let args = SExpr::list(args.into());
+            Some(format!("(fn '{} '{})", Written(&args), Written(&**body)))
+        }
+        Function::MultiArity { name, clauses, rest, .. } => {
+            let mut written = Vec::new();
+            for clause in clauses.values().chain(rest) {
+                let mut params: Vec<SExpr> =
+                    clause.params.iter().map(|name| SExpr::atom(Atom::Ident(name.clone()))).collect();
+                if clause.variadic {
+                    params.insert(params.len() - 1, SExpr::atom(Atom::Ident(env.intern("&"))));
+                }
+                written.push(SExpr::list(List::from([SExpr::list(params.into()), (*clause.body).clone()])));
+            }
+
+            // `define-multi` is the only way to write one, and it binds the
+            // name where it is evaluated as well.
+            let name = name.as_deref().unwrap_or("multi");
+            Some(format!("(define-multi '{} '{})", name, Written(&SExpr::list(written.into()))))
+        }
+        Function::Lib { .. } => None,
+    }
+}
+
+/// Writes data as the code that evaluates back to it, or `None` for
+/// functions, handles and promises. `read_data` reads it back without
+/// evaluating it.
+pub(crate) fn write_data(val: &Value, symbols: &SymbolTable) -> Option<String> {
+    match val {
+        Value::Function(_) | Value::Handle(_) | Value::Promise(_) => None,
+        Value::Quote(quote) => Some(format!("'{}", Written(&**quote))),
+        val => value_to_sexpr(val, symbols).ok().map(|expr| Written(&expr).to_string()),
+    }
+}
+
+/// The value written by `write_data` as `code`.
+pub(crate) fn read_data(code: &str, env: &Environment) -> Result<RefVal, RuntimeError> {
+    let mut reader = Reader::with_symbols(code, env.symbols().clone());
+    let expr = reader.parse_sexpr().map_err(|err| format!("couldn't read back '{}': {}", code, err))?;
+    Ok(match expr {
+        SExpr::Atom(Atom::Quote(quote), _) => RefVal::owned(Value::Quote(quote)),
+        SExpr::Atom(Atom::Ident(name), _) if &*name == "t" => true.into(),
+        SExpr::Atom(Atom::Ident(name), _) if &*name == "f" => false.into(),
+        SExpr::Atom(Atom::Ident(name), _) if &*name == "nil" => RefVal::reference(std_lib::nil_ref()),
+        expr => RefVal::owned(sexpr_to_value(&expr)),
+    })
+}
+
+/// The names the arguments of a function bind, those of its patterns
+/// included.
+fn bound_names<'a>(arg_names: &'a [Symbol], patterns: &'a [(Symbol, SExpr)]) -> Vec<&'a str> {
+    let mut bound: Vec<&str> = arg_names.iter().map(|name| &**name).collect();
+    for (_, pattern) in patterns {
+        pattern::check(pattern, &mut bound).expect("the patterns were checked with the arguments");
+    }
+    bound
+}
+
+/// Every identifier in `expr`, quoted or not, since there is no telling
+/// which quoted code will run.
+fn identifiers(expr: &SExpr, names: &mut Vec<Symbol>) {
+    match expr {
+        SExpr::List(list, _) => list.iter().for_each(|expr| identifiers(expr, names)),
+        SExpr::Atom(Atom::Quote(quoted), _) => identifiers(quoted, names),
+        SExpr::Atom(Atom::Ident(name), _) => names.push(name.clone()),
+        SExpr::Atom(..) => (),
+    }
+}
@@ -0,0 +1,151 @@
+//! Patterns, which `match` tries values against, and which destructure the
+//! values bound by `let` and the arguments of `fn` and `define-multi`.
+//!
+//! A number or a string matches a value equal to it, `_` anything, and `nil`,
+//! `t`, `f` and keywords match themselves. Any other name matches anything
+//! and binds it, so a symbol is matched by quoting it, as in `'sym` or
+//! `'(a b)`, which match data equal to what is quoted. A list of patterns
+//! matches a list as long, element by element, and a dotted one like
+//! `(x y . rest)` a list at least as long as the patterns before the dot,
+//! binding `rest` to the elements left.
+
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::error::{ self, RuntimeError };
+use crate::evaluator::Environment;
+use crate::printer::Written;
+use crate::std_lib::{ self, Equality };
+use crate::symbol::Symbol;
+
+/// The value `pattern` matches when it is a literal.
+fn literal(pattern: &SExpr) -> Option<Value> {
+    match pattern {
+        SExpr::Atom(Atom::Ident(name), _) => match &**name {
+            "nil" => Some(Value::Nil),
+            "t" => Some(Value::Bool(true)),
+            "f" => Some(Value::Bool(false)),
+            _ if name.starts_with(':') => Some(sexpr_to_value(pattern)),
+            _ => None,
+        },
+        SExpr::Atom(Atom::Quote(quoted), _) => Some(sexpr_to_value(quoted)),
+        SExpr::Atom(..) => Some(sexpr_to_value(pattern)),
+        SExpr::List(..) => None,
+    }
+}
+
+/// The patterns of a list pattern, and the name after its dot, if any.
+fn parts(list: &List<SExpr>) -> Result<(Vec<&SExpr>, Option<&SExpr>), RuntimeError> {
+    let mut items: Vec<&SExpr> = list.iter().collect();
+    let Some(dot) = items.iter().position(|item| item.as_atom().and_then(Atom::as_ident) == Some(".")) else {
+        return Ok((items, None));
+    };
+    match items[dot + 1..] {
+        [rest] if dot > 0 && rest.as_atom().and_then(Atom::as_ident).is_some() && literal(rest).is_none() => {
+            items.truncate(dot);
+            Ok((items, Some(rest)))
+        }
+        _ => Err(RuntimeError::from("'.' must come after a pattern and be followed by the name of the rest of the list")
+            .with_span(items[dot].span())),
+    }
+}
+
+/// The names `pattern` binds, in order, or an error if it isn't well formed.
+pub fn bindings(pattern: &SExpr) -> Result<Vec<&SExpr>, RuntimeError> {
+    fn walk<'p>(pattern: &'p SExpr, names: &mut Vec<&'p SExpr>) -> Result<(), RuntimeError> {
+        match pattern {
+            SExpr::List(list, _) => {
+                let (items, rest) = parts(list)?;
+                items.into_iter().chain(rest).try_for_each(|item| walk(item, names))
+            }
+            SExpr::Atom(Atom::Ident(name), _) if &**name != "_" && literal(pattern).is_none() => {
+                names.push(pattern);
+                Ok(())
+            }
+            SExpr::Atom(..) => Ok(()),
+        }
+    }
+
+    let mut names = Vec::new();
+    walk(pattern, &mut names)?;
+    Ok(names)
+}
+
+/// Checks that `pattern` is well formed, adding the names it binds to
+/// `names`, none of which it can bind again.
+pub fn check<'p>(pattern: &'p SExpr, names: &mut Vec<&'p str>) -> Result<(), RuntimeError> {
+    for name in bindings(pattern)? {
+        let SExpr::Atom(Atom::Ident(ident), span) = name else { unreachable!("only names bind") };
+        if names.contains(&&**ident) {
+            return Err(RuntimeError::Custom(format!("'{}' is bound more than once", ident)).with_span(*span));
+        }
+        names.push(ident);
+    }
+    Ok(())
+}
+
+/// Whether `val` matches `pattern`, which `check` accepted, adding what it
+/// binds to `bindings`.
+pub fn matches(pattern: &SExpr, val: &RefVal, bindings: &mut Vec<(Symbol, RefVal)>) -> bool {
+    if let Some(literal) = literal(pattern) {
+        return std_lib::values_equal(&RefVal::owned(literal), val, Equality::Structural);
+    }
+    let list = match pattern {
+        SExpr::List(list, _) => list,
+        SExpr::Atom(Atom::Ident(name), _) => {
+            if &**name != "_" {
+                bindings.push((name.clone(), val.clone()));
+            }
+            return true;
+        }
+        SExpr::Atom(..) => unreachable!("atoms other than names are literals"),
+    };
+
+    let (items, rest) = parts(list).expect("the pattern was checked");
+    let mut elements = match val.deref() {
+        Value::Nil => List::new(),
+        Value::Quote(quoted) => match quoted.as_list() {
+            Some(elements) => elements.clone(),
+            None => return false,
+        },
+        _ => return false,
+    };
+    if elements.len() < items.len() || (rest.is_none() && elements.len() > items.len()) {
+        return false;
+    }
+    for item in items {
+        let elem = elements.pop_front().expect("the list is long enough");
+        if !matches(item, &RefVal::owned(sexpr_to_value(&elem)), bindings) {
+            return false;
+        }
+    }
+    if let Some(rest) = rest {
+        matches(rest, &RefVal::owned(Value::Quote(Rc::new(SExpr::list(elements)))), bindings);
+    }
+    true
+}
+
+/// What `val` binds the names of `pattern` to, or an error saying what
+/// `context` expected when it doesn't match.
+pub fn destructure(pattern: &SExpr, val: &RefVal, context: &str) -> Result<Vec<(Symbol, RefVal)>, RuntimeError> {
+    let mut bindings = Vec::new();
+    if matches(pattern, val, &mut bindings) {
+        return Ok(bindings);
+    }
+    let printed = error::truncate(&Written(&**val).to_string(), 40);
+    Err(RuntimeError::Custom(format!(
+        "expected a value shaped like `{}` in {}, got {} `{}`",
+        Written(pattern),
+        context,
+        val.get_type(),
+        printed,
+    )))
+}
+
+/// The name an argument destructured by `pattern` is bound to before that.
+/// It is written like the pattern, which no name in code can be, so the
+/// function is still written with the pattern.
+pub fn argument(pattern: &SExpr, env: &Environment) -> Symbol {
+    env.intern(&Written(pattern).to_string())
+}
@@ -0,0 +1,331 @@
+//! How values and expressions are printed. `Display` gives the form meant for
+//! people, `Rounded` the same with floats rounded, as used by `print`,
+//! `Written` the one meant to be read back in, and `Dump` shows the structure
+//! of an expression.
+//!
+//! Lists, and quotes in them, nested deeper than `DEFAULT_MAX_DEPTH` levels,
+//! or the depth `Rounded` is given, are printed as `...`, rather than running
+//! out of stack. `Written` output is
+//! read back, by `pmap` and images among others, so it is never cut short,
+//! and is written without recursion instead.
+
+use std::fmt::{ self, Debug, Display, Formatter };
+use std::ops::Deref;
+
+use crate::ast::*;
+
+/// How many levels of lists are printed unless
+/// `Environment::set_print_depth` says otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The most digits after the point floats can be rounded to, more than it
+/// takes to show any float that isn't tiny.
+pub const MAX_PRECISION: usize = 100;
+
+/// Floats are printed with `precision` digits after the point if it is set,
+/// and otherwise with as many as it takes to read back the same float. Those
+/// with no fractional part then keep a trailing `.0`, so that they can be
+/// told apart from ints when printed, and very large or small ones get an
+/// exponent. Infinities and NaN are written as the reader reads them, like
+/// in scheme, and all NaNs the same.
+fn fmt_float(n: f64, f: &mut Formatter, precision: Option<usize>) -> fmt::Result {
+    if n.is_nan() {
+        write!(f, "+nan.0")
+    } else if n.is_infinite() {
+        write!(f, "{}inf.0", if n > 0.0 { "+" } else { "-" })
+    } else if let Some(precision) = precision {
+        write!(f, "{:.*}", precision, n)
+    } else if n != 0.0 && !(1e-7..1e21).contains(&n.abs()) {
+        write!(f, "{:e}", n)
+    } else if n.fract() == 0.0 {
+        write!(f, "{:.1}", n)
+    } else {
+        Display::fmt(&n, f)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_value(self, f, false, None, DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl Display for SExpr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_sexpr(self, f, false, None, DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl Display for Atom {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_atom(self, f, false, None, DEFAULT_MAX_DEPTH)
+    }
+}
+
+/// Formats a value like `Display`, but with the floats in it rounded to the
+/// given number of digits after the point, if any, and the lists nested
+/// deeper than the given number of levels written as `...`, the way `print`
+/// does with the settings of its `Environment`.
+pub struct Rounded<'a>(pub &'a Value, pub Option<usize>, pub usize);
+
+impl Display for Rounded<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_value(self.0, f, false, self.1, self.2)
+    }
+}
+
+/// Formats an expression as an indented tree of its variants, one node per
+/// line with its span, for looking at exactly what the reader produced.
+pub struct Dump<'a>(pub &'a SExpr);
+
+impl Display for Dump<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_dump(self.0, 0, f)
+    }
+}
+
+fn fmt_dump(expr: &SExpr, depth: usize, f: &mut Formatter) -> fmt::Result {
+    write!(f, "{:1$}", "", depth * 2)?;
+    match expr {
+        SExpr::List(..) => write!(f, "List")?,
+        SExpr::Atom(Atom::String(s), _) => write!(f, "String {:?}", s)?,
+        SExpr::Atom(Atom::Int(n), _) => write!(f, "Int {}", n)?,
+        SExpr::Atom(Atom::BigInt(n), _) => write!(f, "BigInt {}", n)?,
+        SExpr::Atom(Atom::Rational(num, den), _) => write!(f, "Rational {}/{}", num, den)?,
+        SExpr::Atom(Atom::Float(n), _) => write!(f, "Float {:?}", n)?,
+        SExpr::Atom(Atom::Ident(name), _) => write!(f, "Ident {}", name)?,
+        SExpr::Atom(Atom::Quote(_), _) => write!(f, "Quote")?,
+    }
+
+    let span = expr.span();
+    if span.is_known() {
+        write!(f, " @{}..{}", span.start, span.end)?;
+    }
+
+    match expr {
+        SExpr::List(list, _) => {
+            for el in list {
+                writeln!(f)?;
+                fmt_dump(el, depth + 1, f)?;
+            }
+        }
+        SExpr::Atom(Atom::Quote(q), _) => {
+            writeln!(f)?;
+            fmt_dump(q, depth + 1, f)?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Formats a value or expression the way `write` does: like `Display`, but
+/// strings are quoted and escaped so that the output reads back as the same
+/// data. Floats are never rounded.
+pub struct Written<'a, T: ?Sized>(pub &'a T);
+
+impl Display for Written<'_, Value> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Value::Quote(q) if q.as_atom().is_some_and(|atom| atom.as_keyword().is_some()) => write_sexpr(q, f),
+            Value::Quote(q) => {
+                write!(f, "'")?;
+                write_sexpr(q, f)
+            }
+            // Nothing else has lists in it.
+            val => fmt_value(val, f, true, None, 0),
+        }
+    }
+}
+
+impl Display for Written<'_, SExpr> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_sexpr(self.0, f)
+    }
+}
+
+/// Writes `expr` for `Written`, however deeply nested, keeping what is left
+/// to write on a stack of its own.
+fn write_sexpr(expr: &SExpr, f: &mut Formatter) -> fmt::Result {
+    enum Pending<'a> {
+        Expr(&'a SExpr),
+        Text(&'static str),
+    }
+
+    let mut pending = vec![Pending::Expr(expr)];
+    while let Some(next) = pending.pop() {
+        match next {
+            Pending::Text(text) => f.write_str(text)?,
+            Pending::Expr(SExpr::List(list, _)) => {
+                f.write_str("(")?;
+                pending.push(Pending::Text(")"));
+                let elems: Vec<&SExpr> = list.iter().collect();
+                for (i, el) in elems.into_iter().enumerate().rev() {
+                    pending.push(Pending::Expr(el));
+                    if i > 0 {
+                        pending.push(Pending::Text(" "));
+                    }
+                }
+            }
+            Pending::Expr(SExpr::Atom(Atom::Quote(q), _)) => {
+                f.write_str("'")?;
+                pending.push(Pending::Expr(q));
+            }
+            Pending::Expr(SExpr::Atom(atom, _)) => fmt_atom(atom, f, true, None, 0)?,
+        }
+    }
+    Ok(())
+}
+
+/// Formats `value` with `depth` more levels of lists to go.
+fn fmt_value(value: &Value, f: &mut Formatter, readable: bool, precision: Option<usize>, depth: usize) -> fmt::Result {
+    use Value::*;
+    match value {
+        String(s) if readable => fmt_string(s, f),
+        String(s)     => Display::fmt(s, f),
+        Int(n)        => Display::fmt(n, f),
+        Value::BigInt(n) => Display::fmt(n, f),
+        Rational(num, den) => write!(f, "{}/{}", num, den),
+        Float(n)      => fmt_float(*n, f, precision),
+        Bool(true)    => write!(f, "t"),
+        Bool(false)   => write!(f, "f"),
+        Nil           => write!(f, "nil"),
+        // Quoted data is shown as it is, and keywords evaluate to
+        // themselves, so they need no quote to read back either. Quotes
+        // inside it keep theirs.
+        Quote(q) if !readable || q.as_atom().is_some_and(|atom| atom.as_keyword().is_some()) => {
+            fmt_sexpr(q, f, readable, precision, depth)
+        }
+        Quote(q)      => {
+            write!(f, "'")?;
+            fmt_sexpr(q, f, readable, precision, depth)
+        }
+        Function(fun) => Display::fmt(fun, f),
+        Handle(handle) => Display::fmt(handle, f),
+        Promise(promise) => Display::fmt(promise, f),
+    }
+}
+
+fn fmt_sexpr(expr: &SExpr, f: &mut Formatter, readable: bool, precision: Option<usize>, depth: usize) -> fmt::Result {
+    match expr {
+        SExpr::Atom(atom, _) => fmt_atom(atom, f, readable, precision, depth),
+        SExpr::List(..) if depth == 0 => write!(f, "..."),
+        SExpr::List(list, _) => {
+            write!(f, "(")?;
+            for (i, el) in list.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                fmt_sexpr(el, f, readable, precision, depth - 1)?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+fn fmt_atom(atom: &Atom, f: &mut Formatter, readable: bool, precision: Option<usize>, depth: usize) -> fmt::Result {
+    use Atom::*;
+
+    match atom {
+        String(s) if readable => fmt_string(s, f),
+        String(s) => Display::fmt(s, f),
+        Int(n)    => Display::fmt(n, f),
+        Atom::BigInt(n) => Display::fmt(n, f),
+        Rational(num, den) => write!(f, "{}/{}", num, den),
+        Float(n)  => fmt_float(*n, f, precision),
+        Quote(_) if depth == 0 => write!(f, "..."),
+        Quote(q)  => {
+            write!(f, "'")?;
+            fmt_sexpr(q, f, readable, precision, depth - 1)
+        }
+        Ident(i)  => Display::fmt(i, f),
+    }
+}
+
+fn fmt_string(s: &str, f: &mut Formatter) -> fmt::Result {
+    write!(f, "\"")?;
+    for chr in s.chars() {
+        match chr {
+            '"'  => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            '\0' => write!(f, "\\0")?,
+            chr  => write!(f, "{}", chr)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Function::*;
+
+        match self {
+            UserDefined { name, arg_names, defaults, .. } => {
+                write!(f, "#<function ")?;
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                write!(f, "(")?;
+                let positional = arg_names.len() - defaults.len();
+                for (i, arg) in arg_names.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    if i == positional {
+                        write!(f, "&key ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")>")
+            }
+
+            Lib { name, .. } => {
+                write!(f, "lib function '{}' with {}", name, self.arguments())
+            }
+
+            MultiArity { name, .. } => {
+                write!(f, "#<function ")?;
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                write!(f, "of {}>", self.arguments())
+            }
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Debug for Handle {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Handle::Connection(conn) => write!(f, "#<connection {}>", conn.borrow()),
+            Handle::Listener(listener) => write!(f, "#<listener {}>", listener.borrow()),
+            Handle::Channel(channel) => write!(f, "#<channel{}>", if channel.is_closed() { " closed" } else { "" }),
+        }
+    }
+}
+
+impl Display for Handle {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Display for Promise {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "#<promise{}>", if self.is_forced() { " forced" } else { "" })
+    }
+}
+
+impl Display for RefVal {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self.deref(), f)
+    }
+}
@@ -0,0 +1,118 @@
+//! Counting calls and timing them, for `--profile` and `(profile-report)`.
+
+use std::collections::HashMap;
+use std::fmt::{ self, Display, Formatter };
+use std::time::{ Duration, Instant };
+
+/// How functions without a name show up in reports.
+const ANONYMOUS: &str = "<anonymous>";
+
+/// Call counts and times of every function called while profiling, by name.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    functions: HashMap<String, Timings>,
+    /// The calls running, the innermost one last.
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Timings {
+    calls: u64,
+    /// Time spent in the function and the functions it called. Recursive
+    /// calls are only counted once, in the outermost one.
+    total: Duration,
+    /// Time spent in the function itself, without what it called.
+    own: Duration,
+    /// How many calls to the function are running.
+    active: usize,
+}
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    start: Instant,
+    /// Time spent in the functions this call called.
+    children: Duration,
+}
+
+/// A line of a report.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+    pub own: Duration,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn enter(&mut self, name: Option<&str>) {
+        let name = name.unwrap_or(ANONYMOUS).to_string();
+        let timings = self.functions.entry(name.clone()).or_default();
+        timings.calls += 1;
+        timings.active += 1;
+        self.frames.push(Frame { name, start: Instant::now(), children: Duration::ZERO });
+    }
+
+    pub fn exit(&mut self) {
+        let Some(frame) = self.frames.pop() else { return };
+        let elapsed = frame.start.elapsed();
+
+        let timings = self.functions.get_mut(&frame.name).expect("entered functions have timings");
+        timings.own += elapsed.saturating_sub(frame.children);
+        timings.active -= 1;
+        if timings.active == 0 {
+            timings.total += elapsed;
+        }
+
+        if let Some(caller) = self.frames.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+
+    /// Every function called so far, the one that took the longest first.
+    /// Calls still running aren't timed yet.
+    pub fn report(&self) -> Vec<Entry> {
+        let mut entries: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(name, timings)| Entry {
+                name: name.clone(),
+                calls: timings.calls,
+                total: timings.total,
+                own: timings.own,
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        entries
+    }
+}
+
+/// A table of the report, for people.
+impl Display for Profiler {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let entries = self.report();
+        let width = entries.iter().map(|entry| entry.name.len()).max().unwrap_or(0).max(8);
+
+        writeln!(f, "{:<width$} {:>10} {:>12} {:>12}", "function", "calls", "total (ms)", "self (ms)")?;
+        for entry in entries {
+            writeln!(
+                f,
+                "{:<width$} {:>10} {:>12.3} {:>12.3}",
+                entry.name,
+                entry.calls,
+                millis(entry.total),
+                millis(entry.own),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
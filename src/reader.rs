@@ -1,289 +1,296 @@
-use std::str::pattern::Pattern;
-use std::collections::VecDeque;
+//! Reading source code into `SExpr`s. Errors know where they are in the
+//! source, and whether more input could complete it.
+
+use std::collections::{ BTreeSet, VecDeque };
+use std::iter::Peekable;
+use std::rc::Rc;
 
 use crate::ast::*;
 use crate::error::*;
+use crate::evaluator::DEFAULT_MAX_DEPTH;
+use crate::lexer::{ self, LexError, Lexer, Token, TokenKind };
+use crate::symbol::SymbolTable;
 
 pub struct Reader<'a> {
     source: &'a str,
-    chars: ParenChars<'a>,
+    tokens: Peekable<Lexer<'a>>,
+    /// Where the last token read ends.
+    end: usize,
+    symbols: SymbolTable,
+    /// The comments read so far, if they are being kept.
+    comments: Option<Vec<Comment>>,
+    /// What conditionals test, or `None` to keep every form.
+    features: Option<Features>,
+    /// How many lists, quotes and conditionals the reader is in.
+    depth: usize,
+    /// How deep they can be nested. Reading is recursive, so anything deeper
+    /// could run out of stack.
+    max_depth: usize,
 }
 
-impl<'a> Reader<'a> {
-    const IDENT_CHARS: &'static str = "_+-/*=?";
+/// The names `#+feature(name)` and `#-feature(name)` test for: the optional
+/// parts the interpreter was built with, and any others a program is run
+/// with. Any other name is simply missing. Cloning is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct Features(Rc<BTreeSet<String>>);
+
+impl Features {
+    /// The cargo features the interpreter was built with.
+    pub fn compiled() -> Features {
+        let compiled = [
+            ("ffi", cfg!(feature = "ffi")),
+            ("http", cfg!(feature = "http")),
+            ("serde", cfg!(feature = "serde")),
+            ("wasm", cfg!(feature = "wasm")),
+        ];
+        let names = compiled.into_iter().filter(|&(_, on)| on).map(|(name, _)| name.to_string());
+        Features(Rc::new(names.collect()))
+    }
+
+    pub fn insert(&mut self, name: &str) {
+        Rc::make_mut(&mut self.0).insert(name.to_string());
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
 
+/// A comment, from the `;` to the end of the line.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+impl<'a> Reader<'a> {
     pub fn new(source: &'a str) -> Reader<'a> {
+        Self::with_symbols(source, SymbolTable::new())
+    }
+
+    /// Creates a reader that interns identifiers into `symbols`, usually the
+    /// table of the `Environment` the code will run in.
+    pub fn with_symbols(source: &'a str, symbols: SymbolTable) -> Reader<'a> {
         Reader {
             source,
-            chars: ParenChars::new(source),
+            tokens: Lexer::new(source).peekable(),
+            end: 0,
+            symbols,
+            comments: None,
+            features: Some(Features::compiled()),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
-    fn advance(&mut self) -> Option<char> {
-        self.chars.next()
+    /// Makes conditionals test for `features` rather than for those the
+    /// interpreter was built with.
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = Some(features);
+        self
     }
 
-    fn peek(&self) -> Option<char> {
-        self.chars.peek()
+    /// Lets lists, quotes and conditionals be nested `max_depth` levels deep,
+    /// rather than as deep as the evaluator takes by default.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
-    fn rest(&self) -> &'a str {
-        self.chars.as_str()
+    /// Makes the reader keep the comments it skips, for tools that rewrite
+    /// source code. They are taken with `take_comments`. The conditionals
+    /// are kept with them, and the forms they would drop are read like any
+    /// other.
+    pub fn keep_comments(mut self) -> Self {
+        self.comments = Some(Vec::new());
+        self.features = None;
+        self
     }
 
-    fn idx(&self) -> usize {
-        self.source[..self.source.len() - self.rest().len()].chars().count()
+    /// The comments read so far, in order.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        self.comments.as_mut().map(std::mem::take).unwrap_or_default()
     }
 
-    fn pos(&self) -> Position<'a> {
-        Position {
-            src: self.source,
-            byte: self.source.len() - self.rest().len()
+    /// The next token that isn't a comment, without reading it.
+    fn peek(&mut self) -> Option<Token> {
+        while let Some(token) = self.tokens.next_if(|token| token.kind == TokenKind::Comment) {
+            if let Some(comments) = &mut self.comments {
+                let text = token.text(self.source).trim_end().to_string();
+                comments.push(Comment { text, span: token.span });
+            }
         }
+        self.tokens.peek().copied()
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek()?;
+        self.tokens.next();
+        self.end = token.span.end;
+        Some(token)
     }
 
-    fn error(&self, msg: impl ToString) -> Error<'a> {
-        Error::new(self.source, self.idx(), msg)
+    fn error(&self, byte: usize, msg: impl ToString) -> Error<'a> {
+        Error::new(self.source, byte, msg)
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(chr) = self.peek() {
-            if !chr.is_whitespace() { return }
-            self.advance();
+    /// An error caused by the input ending too early.
+    fn eof_error(&self, msg: impl ToString) -> Error<'a> {
+        Error::incomplete(self.source, self.source.len(), msg)
+    }
+
+    fn lex_error(&self, error: LexError, span: Span) -> Error<'a> {
+        match error {
+            LexError::UnterminatedString => self.eof_error(error),
+            LexError::UnknownEscape { byte, .. } => self.error(byte, error),
+            LexError::UnexpectedChar(_) | LexError::BadConditional => self.error(span.start, error),
         }
     }
 
     pub fn parse_atom(&mut self) -> Result<Atom, Error<'a>> {
-        match self.peek().unwrap() {
-            '"' => {
-                self.advance();
-                let start = self.pos();
-                while let Some(chr) = self.peek() {
-                    if chr == '\\' {
-                        self.advance();
-                    }
-                    else if chr == '"' { break }
-                    self.advance();
-                }
-                let s = start.span_to(self.pos()).as_str().to_string();
+        let token = self.advance().ok_or_else(|| self.eof_error("unexpected end of input"))?;
+        let text = token.text(self.source);
 
-                // TODOO: Make this more efficient!
-                let s = s.replace("\\n", "\n");
-                let s = s.replace("\\r", "\0");
-                let s = s.replace("\\0", "\0");
-                self.advance();
-                Ok(Atom::String(s))
-            }
+        match token.kind {
+            TokenKind::String => Ok(Atom::String(lexer::string_value(text).into())),
 
-            '\'' => {
-                self.advance();
-                Ok(Atom::Quote(Box::new(self.parse_sexpr()?)))
-            },
-
-            chr if chr.is_digit(10) => {
-                let mut read_dot = false;
-                let start = self.pos();
-                while let Some(chr) = self.peek() {
-                    if chr == '.' && !read_dot {
-                        read_dot = true;
-                    }
-                    if !chr.is_digit(10) || (chr == '.' && read_dot) { break }
-                    self.advance();
-                }
+            TokenKind::Quote => Ok(Atom::Quote(Rc::new(self.nested(token.span.start, Self::parse_sexpr)?))),
 
-                let tok = start.span_to(self.pos()).as_str().to_string();
-                let num = tok
+            TokenKind::Number => match text.strip_prefix('+').unwrap_or(text) {
+                "inf.0" => Ok(Atom::Float(f64::INFINITY)),
+                "-inf.0" => Ok(Atom::Float(f64::NEG_INFINITY)),
+                "nan.0" | "-nan.0" => Ok(Atom::Float(f64::NAN)),
+                number if number.contains('/') => self.fraction(number, token.span.end),
+                number if number.contains(['.', 'e', 'E']) => number
                     .parse()
-                    .map_err(|_| self.error(format!("number in wrong format '{tok}'")))?;
-
-                Ok(Atom::Number(num))
-            }
+                    .map(Atom::Float)
+                    .map_err(|_| self.error(token.span.end, format!("number in wrong format '{text}'"))),
+                number => number
+                    .parse()
+                    .map(Atom::big_int)
+                    .map_err(|_| self.error(token.span.end, format!("number in wrong format '{text}'"))),
+            },
 
-            chr if chr.is_whitespace() => Err(self.error("unexpected whitespace")),
+            TokenKind::Ident => Ok(Atom::Ident(self.symbols.intern(text))),
 
-            chr if chr.is_alphabetic() || chr.is_contained_in(Self::IDENT_CHARS) => {
-                let start = self.pos();
-                while let Some(chr) = self.peek() {
-                    if !(chr.is_alphanumeric() || chr.is_contained_in(Self::IDENT_CHARS)) {
-                        break
-                    }
-                    self.advance();
-                }
-
-                Ok(Atom::Ident(start.span_to(self.pos()).as_str().to_string()))
+            TokenKind::Open | TokenKind::Close | TokenKind::Comment | TokenKind::Conditional => {
+                Err(self.error(token.span.start, format!("expected an atom, got '{text}'")))
             }
 
-            chr => Err(self.error(format!("unexpected char '{chr}'"))),
+            TokenKind::Error(error) => Err(self.lex_error(error, token.span)),
         }
     }
 
-    pub fn parse_sexpr(&mut self) -> Result<SExpr, Error<'a>> {
-        loop {
-            match self.peek() {
-                Some('(') => {
-                    self.advance();
-                    let mut sub_reader = Reader {
-                        source: self.source,
-                        chars: ParenChars::new(self.rest()),
-                    };
-                    let sexprs = sub_reader.parse_sexprs()?;
-                    self.chars.merge(sub_reader.chars);
-                    if self.peek() != Some(')') {
-                        return Err(self.error("expected a closing paren"));
-                    }
-                    self.advance();
-                    return Ok(SExpr::List(sexprs))
-                },
-
-                Some(';') => {
-                    while let Some(chr) = self.peek() {
-                        if chr == '\n' { break }
-                        self.advance();
-                    }
-                    self.skip_whitespace();
-                }
-
-                Some(_) => return Ok(SExpr::Atom(self.parse_atom()?)),
-                None => return Err(self.error("unexpected end of input")),
-            }
-        }
+    /// Reads the text of a fraction like `-2/6`, which is reduced, so an int
+    /// if it is whole. `end` is where its token ends.
+    fn fraction(&self, text: &str, end: usize) -> Result<Atom, Error<'a>> {
+        let (num, den) = text.split_once('/').unwrap_or((text, "1"));
+        let (Ok(num), Ok(den)) = (num.parse::<i64>(), den.parse::<i64>()) else {
+            return Err(self.error(end, format!("integer literal out of range '{text}'")));
+        };
+        Atom::rational(num.into(), den.into())
+            .ok_or_else(|| self.error(end, format!("division by zero in '{text}'")))
     }
 
-    pub fn parse_sexprs(&mut self) -> Result<VecDeque<SExpr>, Error<'a>> {
-        let mut s_exprs = VecDeque::new();
+    pub fn parse_sexpr(&mut self) -> Result<SExpr, Error<'a>> {
+        let token = self.peek().ok_or_else(|| self.eof_error("unexpected end of input"))?;
+        let start = token.span.start;
 
-        loop {
-            self.skip_whitespace();
-            if self.peek().is_none() {
-                return Ok(s_exprs)
+        match token.kind {
+            TokenKind::Open => {
+                self.advance();
+                let sexprs = self.nested(start, Self::parse_items)?;
+                match self.advance() {
+                    Some(_) => Ok(SExpr::List(sexprs.into(), Span::new(start, self.end))),
+                    None => Err(self.eof_error("expected a closing paren")),
+                }
             }
-            s_exprs.push_back(self.parse_sexpr()?);
-        }
-    }
-}
 
-pub struct ParenChars<'a> {
-    slice: &'a str,
-    next: Option<char>,
-    level: i32,
-    // TODO: Make this go away!
-    in_str: bool,
-    in_escape: bool,
-}
+            TokenKind::Close => Err(self.error(start, "unexpected closing paren")),
 
-impl<'a> ParenChars<'a> {
-    pub fn new(s: &'a str) -> Self {
-        Self::with_level(s, 0)
-    }
+            TokenKind::Conditional => Err(self.error(
+                start,
+                format!("'{}' can only come before an element of a list or a top-level form", token.text(self.source)),
+            )),
 
-    pub fn with_level(s: &'a str, level: i32) -> Self {
-        let next = s.chars().next();
-        ParenChars {
-            slice: s,
-            next,
-            level,
-            in_str: false,
-            in_escape: false,
+            _ => {
+                let atom = self.parse_atom()?;
+                Ok(SExpr::Atom(atom, Span::new(start, self.end)))
+            }
         }
     }
 
-    pub fn as_str(&self) -> &'a str {
-        self.slice
-    }
-
-    pub fn peek(&self) -> Option<char> {
-        if self.next == Some(')') && self.level == 0 && !self.in_str {
-            None
-        } else {
-            self.next
+    /// Runs `parse` for what the token at `start` opens, unless that is
+    /// nested too deep.
+    fn nested<T>(
+        &mut self,
+        start: usize,
+        parse: impl FnOnce(&mut Self) -> Result<T, Error<'a>>,
+    ) -> Result<T, Error<'a>> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(start, format!("nested more than {} levels deep", self.max_depth)));
         }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
     }
 
-    pub fn merge(&mut self, other: ParenChars<'a>) {
-        unsafe {
-            assert!(
-                self.slice.as_ptr() <= other.slice.as_ptr(),
-                "Slices must overlap"
-            );
-            assert!(
-                self.slice.as_ptr().add(self.slice.len()) >= other.slice.as_ptr(),
-                "Slices must overlap"
-            );
-            assert!(
-                self.slice.as_ptr().add(self.slice.len()) == other.slice.as_ptr().add(other.slice.len()),
-                "Slices must end in the same place"
-            );
+    /// Parses every expression in the source.
+    pub fn parse_sexprs(&mut self) -> Result<VecDeque<SExpr>, Error<'a>> {
+        let s_exprs = self.parse_items()?;
+        if let Some(token) = self.peek() {
+            return Err(self.error(token.span.start, "unexpected closing paren"));
         }
-        self.level += other.level;
-        self.slice = other.slice;
-        self.next = other.next;
+        Ok(s_exprs.into())
     }
-}
-
-impl<'a> Iterator for ParenChars<'a> {
-    type Item = char;
-
-    fn next(&mut self) -> Option<char> {
-        let prev = self.next?;
-        self.slice = &self.slice[prev.len_utf8()..];
-
-        // Let's hope that this `.nth(0)` is not terribly inefficient.
-        self.next = self.slice.chars().nth(0);
 
-        let was_escape = self.in_escape;
+    /// Parses expressions up to the end of the input or of the enclosing
+    /// list.
+    fn parse_items(&mut self) -> Result<Vec<SExpr>, Error<'a>> {
+        let mut s_exprs = Vec::new();
 
-        if prev == '"' && !self.in_escape {
-            self.in_str = !self.in_str;
-        } else if prev == '\\' {
-            self.in_escape = true;
-        }
-
-        if was_escape {
-            self.in_escape = false;
-        }
-
-        if self.in_str {
-            return Some(prev);
+        while self.peek().is_some_and(|token| token.kind != TokenKind::Close) {
+            s_exprs.extend(self.parse_item()?);
         }
+        Ok(s_exprs)
+    }
 
-        if prev == ')' {
-            self.level -= 1;
-            if self.level < 0 {
-                return None;
+    /// Parses an element of a list or a top-level form, `None` if a
+    /// conditional drops it. Dropped forms are parsed all the same, so they
+    /// still have to be valid.
+    fn parse_item(&mut self) -> Result<Option<SExpr>, Error<'a>> {
+        let Some(token) = self.peek().filter(|token| token.kind == TokenKind::Conditional) else {
+            return self.parse_sexpr().map(Some);
+        };
+        self.advance();
+        let text = token.text(self.source);
+        match self.peek() {
+            None => return Err(self.eof_error(format!("expected a form after '{text}'"))),
+            Some(next) if next.kind == TokenKind::Close => {
+                return Err(self.error(next.span.start, format!("expected a form after '{text}'")));
             }
-        } else if prev == '(' {
-            self.level += 1;
+            Some(_) => (),
         }
-        Some(prev)
-    }
-}
-
-#[derive(Clone, Copy)]
-pub struct Span<'a> {
-    src: &'a str,
-    start: usize,
-    end: usize
-}
 
-impl<'a> Span<'a> {
-    fn as_str(&self) -> &'a str {
-        &self.src[self.start..self.end]
-    }
-}
-
-#[derive(Clone, Copy)]
-pub struct Position<'a> {
-    src: &'a str,
-    byte: usize,
-}
-
-impl<'a> Position<'a> {
-    fn span_to(&self,end: Position<'a>) -> Span<'a> {
-        Span {
-            src: self.src,
-            start: self.byte,
-            end: end.byte,
-        }
+        let features = match &self.features {
+            Some(features) => features.clone(),
+            None => {
+                if let Some(comments) = &mut self.comments {
+                    // The comments up to the next token are in already.
+                    let at = comments.partition_point(|comment| comment.span.start < token.span.start);
+                    comments.insert(at, Comment { text: text.to_string(), span: token.span });
+                }
+                return self.nested(token.span.start, Self::parse_item);
+            }
+        };
+        let item = self.nested(token.span.start, Self::parse_item)?;
+        let name = &text["#+feature(".len()..text.len() - 1];
+        let keep = features.contains(name) == text.starts_with("#+");
+        Ok(item.filter(|_| keep))
     }
 }
-
@@ -0,0 +1,674 @@
+//! The interactive read–eval–print loop, used when no file is given.
+
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::{ env, fs };
+use std::fs::File;
+use std::io::{ self, BufRead, IsTerminal, Write };
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+
+use rustyline::completion::{ Completer, Pair };
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ Config, Context, Editor, Helper };
+
+use crate::ast::{ Function, RefVal, Value };
+use crate::printer::Written;
+use crate::error::{ self, RuntimeError };
+use crate::evaluator::{ evaluate_toplevel, Environment };
+use crate::lexer::{ LexError, Lexer, Token, TokenKind };
+use crate::optimize;
+
+const PROMPT: &str = "yal> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+const DEBUG_PROMPT: &str = "debug> ";
+
+/// Where history is kept, relative to the data directory, see
+/// `history_path`.
+const HISTORY_FILE: &str = "yal/history";
+/// How many lines of history are kept, the oldest being dropped first.
+pub const MAX_HISTORY: usize = 1000;
+
+pub enum Line {
+    Text(String),
+    /// The input typed so far was dropped, with Ctrl-C.
+    Interrupted,
+    Eof,
+}
+
+/// Where the REPL reads its input from.
+pub trait LineSource {
+    /// Reads a line, without its line break. `env` is the environment the
+    /// line is going to be evaluated in.
+    fn read_line(&mut self, prompt: &str, env: &Environment) -> io::Result<Line>;
+
+    /// Whether errors should be colored.
+    fn color(&self) -> bool {
+        false
+    }
+}
+
+/// Reads from a terminal, with line editing, history and completion of the
+/// names bound in the environment.
+pub struct Terminal {
+    editor: Editor<Completion, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl Terminal {
+    /// A terminal with the history of the previous sessions. If it can't be
+    /// read, or there is nowhere to write it, the history of this session is
+    /// only kept in memory, with a warning.
+    pub fn new() -> io::Result<Self> {
+        let config = Config::builder()
+            .max_history_size(MAX_HISTORY)
+            .and_then(|config| config.history_ignore_dups(true))
+            .map_err(io::Error::other)?
+            .build();
+        let mut editor = Editor::with_config(config).map_err(io::Error::other)?;
+        editor.set_helper(Some(Completion::default()));
+
+        let history = history_path().and_then(|path| match load_history(&mut editor, &path) {
+            Ok(()) => Some(path),
+            Err(err) => {
+                eprintln!("warning: the history won't be saved, '{}' can't be used: {}", path.display(), err);
+                None
+            }
+        });
+        Ok(Terminal { editor, history })
+    }
+
+    /// Adds a line to the history, unless it is blank or the same as the
+    /// last one. The history is saved when the terminal is dropped.
+    pub fn add_history(&mut self, line: &str) {
+        if !line.trim().is_empty() {
+            let _ = self.editor.add_history_entry(line);
+        }
+    }
+
+    /// The lines in the history, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.editor.history().iter().map(String::as_str)
+    }
+}
+
+/// `$XDG_DATA_HOME/yal/history`, or `~/.local/share/yal/history`.
+fn history_path() -> Option<PathBuf> {
+    let data = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join(HISTORY_FILE))
+}
+
+/// Reads the history at `path` into `editor`, making sure it can be written
+/// back later. A missing file is an empty history.
+fn load_history(editor: &mut Editor<Completion, DefaultHistory>, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match editor.load_history(path) {
+        Ok(()) => Ok(()),
+        Err(ReadlineError::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(ReadlineError::Io(err)) => Err(err),
+        Err(err) => Err(io::Error::other(err)),
+    }
+}
+
+impl LineSource for Terminal {
+    fn read_line(&mut self, prompt: &str, env: &Environment) -> io::Result<Line> {
+        if let Some(completion) = self.editor.helper_mut() {
+            *completion = Completion::of(env);
+        }
+
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                self.add_history(&line);
+                Ok(Line::Text(line))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Line::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Line::Eof),
+            Err(ReadlineError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn color(&self) -> bool {
+        error::use_color(io::stdout().is_terminal())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if let Some(history) = &self.history {
+            if let Err(err) = self.editor.save_history(history) {
+                eprintln!("warning: couldn't save the history to '{}': {}", history.display(), err);
+            }
+        }
+    }
+}
+
+/// Reads lines as they come, without prompting.
+pub struct Script<R>(pub R);
+
+impl<R: BufRead> LineSource for Script<R> {
+    fn read_line(&mut self, _prompt: &str, _env: &Environment) -> io::Result<Line> {
+        let mut line = String::new();
+        if self.0.read_line(&mut line)? == 0 {
+            return Ok(Line::Eof);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        Ok(Line::Text(line))
+    }
+}
+
+/// A way of completing what is before the cursor: `replacement` takes the
+/// place of the line from `start` up to the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub start: usize,
+    pub display: String,
+    pub replacement: String,
+}
+
+/// The candidates for completing `line` at the byte `cursor`, with the
+/// names bound in `env`, all with the same `start`. What they are depends on
+/// where the cursor is: right after a `(`, only functions; in the string a
+/// `load` or an `include` starts with, paths; in any other string or in a
+/// comment, nothing; in a call to a function taking keyword arguments, those
+/// it wasn't passed yet and then the names; elsewhere, the names.
+pub fn complete(line: &str, cursor: usize, env: &Environment) -> Vec<Candidate> {
+    Completion::of(env).candidates(line, cursor)
+}
+
+/// What completion needs to know of an environment, taken when a line starts
+/// being edited, since the editor doesn't see the environment.
+#[derive(Default)]
+struct Completion {
+    /// The names bound, in order.
+    names: Vec<String>,
+    functions: HashSet<String>,
+    /// The keyword arguments of the functions taking some, with their `:`.
+    keywords: HashMap<String, Vec<String>>,
+}
+
+/// A list the cursor is in, with what it has before the cursor.
+struct Enclosing<'a> {
+    /// Whether the list is in a quoted one, or quoted itself, so not a call.
+    quoted: bool,
+    /// The items, with `()` for those that are lists.
+    items: Vec<&'a str>,
+}
+
+impl Completion {
+    fn of(env: &Environment) -> Completion {
+        let mut completion = Completion::default();
+        for name in env.bound_names() {
+            let name = name.to_string();
+            if let Some(Value::Function(fun)) = env.lookup_var(&name).map(|val| &**val) {
+                if let Function::UserDefined { arg_names, defaults, .. } = fun {
+                    if !defaults.is_empty() {
+                        let keys = &arg_names[arg_names.len() - defaults.len()..];
+                        completion.keywords.insert(name.clone(), keys.iter().map(|key| format!(":{}", key)).collect());
+                    }
+                }
+                completion.functions.insert(name.clone());
+            }
+            completion.names.push(name);
+        }
+        completion.names.sort();
+        completion.names.dedup();
+        completion
+    }
+
+    fn candidates(&self, line: &str, cursor: usize) -> Vec<Candidate> {
+        let before = &line[..cursor];
+        let mut tokens: Vec<_> = Lexer::new(before).collect();
+        let last = tokens.last().filter(|token| token.span.end == cursor).map(|token| token.kind);
+
+        let (start, prefix) = match last {
+            Some(TokenKind::Ident) => {
+                let token = tokens.pop().unwrap();
+                (token.span.start, token.text(before))
+            }
+            Some(TokenKind::Error(LexError::UnterminatedString)) => {
+                let token = tokens.pop().unwrap();
+                return match enclosing(&tokens, before) {
+                    Some(Enclosing { quoted: false, items }) if matches!(items.as_slice(), ["load" | "include"]) => {
+                        paths(&before[token.span.start + 1..], token.span.start + 1)
+                    }
+                    _ => Vec::new(),
+                };
+            }
+            // In a comment, or in a string with a bad escape, which may not be
+            // over.
+            Some(TokenKind::Comment | TokenKind::Error(LexError::UnknownEscape { .. })) => return Vec::new(),
+            _ => (cursor, ""),
+        };
+
+        let names = self.names.iter().filter(|name| name.starts_with(prefix));
+        // The items of the call the cursor is in, if it is in one.
+        let call = match enclosing(&tokens, before) {
+            Some(Enclosing { quoted: false, items }) => Some(items),
+            _ => None,
+        };
+        let candidates: Vec<&String> = match call.as_deref() {
+            None => names.collect(),
+            Some([]) => names.filter(|name| self.functions.contains(*name)).collect(),
+            Some([head, args @ ..]) => match self.keywords.get(*head) {
+                // After a keyword comes its value.
+                Some(keys) if args.last().is_some_and(|arg| keys.iter().any(|key| key == arg)) => names.collect(),
+                Some(keys) => {
+                    let keys = keys.iter().filter(|key| key.starts_with(prefix) && !args.contains(&key.as_str()));
+                    keys.chain(names.filter(|_| !prefix.starts_with(':'))).collect()
+                }
+                None => names.collect(),
+            },
+        };
+
+        let mut candidates: Vec<_> = candidates
+            .into_iter()
+            .map(|name| Candidate { start, display: name.clone(), replacement: name.clone() })
+            .collect();
+        // A unique match is complete, so move on to the next argument.
+        if let [candidate] = candidates.as_mut_slice() {
+            candidate.replacement.push(' ');
+        }
+        candidates
+    }
+}
+
+/// The innermost list the tokens of `src` leave open, if any.
+fn enclosing<'a>(tokens: &[Token], src: &'a str) -> Option<Enclosing<'a>> {
+    let mut lists: Vec<Enclosing> = Vec::new();
+    let mut after_quote = false;
+    for token in tokens {
+        match token.kind {
+            TokenKind::Open => {
+                let quoted = after_quote || lists.last().is_some_and(|list| list.quoted);
+                lists.push(Enclosing { quoted, items: Vec::new() });
+            }
+            TokenKind::Close => {
+                lists.pop();
+                if let Some(list) = lists.last_mut() {
+                    list.items.push("()");
+                }
+            }
+            TokenKind::Quote | TokenKind::Comment | TokenKind::Conditional => {}
+            _ => {
+                if let Some(list) = lists.last_mut() {
+                    list.items.push(token.text(src));
+                }
+            }
+        }
+        after_quote = token.kind == TokenKind::Quote || (after_quote && token.kind == TokenKind::Comment);
+    }
+    lists.pop()
+}
+
+/// The files and directories whose path starts with `partial`, which is in
+/// the line from `start`. Hidden ones are left out unless asked for.
+fn paths(partial: &str, start: usize) -> Vec<Candidate> {
+    let (dir, file) = match partial.rfind('/') {
+        Some(slash) => partial.split_at(slash + 1),
+        None => ("", partial),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let mut name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            Some(Candidate { start: start + dir.len(), display: name.clone(), replacement: name })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+
+    // A unique file is complete, so close the string.
+    if let [candidate] = candidates.as_mut_slice() {
+        if !candidate.replacement.ends_with('/') {
+            candidate.replacement.push('"');
+        }
+    }
+    candidates
+}
+
+impl Completer for Completion {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let candidates = self.candidates(line, pos);
+        let start = candidates.first().map_or(pos, |candidate| candidate.start);
+        let pairs = candidates
+            .into_iter()
+            .map(|Candidate { display, replacement, .. }| Pair { display, replacement })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+/// Commands that aren't code, with a description of each.
+const COMMANDS: &[(&str, &str)] = &[
+    (":env", "list the names bound and the types of their values"),
+    (":gc", "free the values only reference cycles keep alive"),
+    (":load <path>", "evaluate a file in this session"),
+    (":reset", "start over with only the standard library"),
+    (":transcript <path>", "copy what is typed and printed from now on to a file, until :transcript off"),
+    (":type <expr>", "evaluate an expression and show its type"),
+    (":quit", "leave the REPL"),
+];
+
+/// Runs a colon command, returning the status to end the session with, if
+/// it should end.
+fn command(line: &str, env: &mut Environment, output: &mut impl Write, color: bool) -> io::Result<Option<i32>> {
+    let line = line.trim();
+    let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+
+    match name {
+        ":env" => {
+            let mut names: Vec<_> = env.bound_names().cloned().collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                if let Some(val) = env.lookup_symbol(&name) {
+                    writeln!(output, "{} : {}", name, val.get_type())?;
+                }
+            }
+        }
+
+        ":gc" => {
+            let collected = env.collect_garbage();
+            let plural = if collected == 1 { "" } else { "s" };
+            writeln!(output, "freed {} value{}", collected, plural)?;
+        }
+
+        ":load" if !arg.is_empty() => match fs::read_to_string(arg) {
+            Ok(src) => return eval_source(&src, env, output, color, |_, _| Ok(())),
+            Err(err) => writeln!(output, "couldn't read '{}': {}", arg, err)?,
+        },
+
+        ":reset" => {
+            if let Err(err) = env.reset() {
+                writeln!(output, "{}", err)?;
+            }
+        }
+
+        ":type" if !arg.is_empty() => {
+            return eval_source(arg, env, output, color, |output, val| writeln!(output, "{}", val.get_type()));
+        }
+
+        ":quit" => return Ok(Some(0)),
+
+        _ => {
+            writeln!(output, "unknown command '{}', the commands are:", line)?;
+            for (usage, description) in COMMANDS {
+                writeln!(output, "  {:<14} {}", usage, description)?;
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Evaluates every expression in `src`, handing each value to `on_value`.
+/// Errors are written to `output`, colored if `color` is set, and stop the
+/// evaluation. Returns the
+/// status to end the session with if `exit` was called.
+fn eval_source<W: Write>(
+    src: &str,
+    env: &mut Environment,
+    output: &mut W,
+    color: bool,
+    mut on_value: impl FnMut(&mut W, &RefVal) -> io::Result<()>,
+) -> io::Result<Option<i32>> {
+    let mut reader = env.reader(src);
+    let exprs = match reader.parse_sexprs() {
+        Ok(exprs) => exprs,
+        Err(err) => {
+            writeln!(output, "{}", err.report().colored(color))?;
+            return Ok(None);
+        }
+    };
+
+    // Ctrl-C pressed before now was meant for something else.
+    env.clear_interrupt();
+    for expr in exprs {
+        let retr = optimize::comptime(expr, env).and_then(|expr| evaluate_toplevel(&expr, env));
+        // What the program printed goes before its value.
+        env.output().flush()?;
+        match retr {
+            Ok(val) => on_value(output, &val)?,
+            Err(err) => {
+                if let RuntimeError::Exit(status) = err.root() {
+                    return Ok(Some(*status));
+                }
+                let located = error::Located { file: None, src, error: &err };
+                writeln!(output, "{}", located.report().colored(color))?;
+                break;
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads expressions from `input` until it ends, evaluating each one in `env`
+/// and writing its value to `output`. Lines are accumulated until they make
+/// up complete expressions, and errors are reported without ending the
+/// session. Lines starting with a colon are commands, see `COMMANDS`.
+/// Returns the status the session ended with, which `exit` sets.
+pub fn run(env: &mut Environment, input: &mut impl LineSource, output: &mut impl Write) -> io::Result<i32> {
+    let transcript = Transcript::default();
+    let mut input = Recorded { source: input, transcript: transcript.clone() };
+    let mut output = Tee { inner: output, transcript: transcript.clone() };
+    let printed = env.replace_output(Box::new(io::sink()));
+    env.set_output(Box::new(Tee { inner: printed, transcript: transcript.clone() }));
+
+    let end = session(env, &mut input, &mut output, PROMPT, |line, env, output, color| {
+        match line.trim().strip_prefix(":transcript") {
+            Some(arg) if arg.is_empty() || arg.starts_with(char::is_whitespace) => {
+                transcript.set(arg.trim(), output)?;
+                Ok(None)
+            }
+            _ => command(line, env, output, color),
+        }
+    }, |status| status)?;
+    Ok(end.unwrap_or(0))
+}
+
+/// The file `:transcript` copies the session to, if any, shared by the input
+/// and the outputs that copy to it.
+#[derive(Clone, Default)]
+struct Transcript(Rc<RefCell<Option<File>>>);
+
+impl Transcript {
+    fn is_on(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Starts copying to a new file at `arg`, or stops with `off`.
+    fn set(&self, arg: &str, output: &mut impl Write) -> io::Result<()> {
+        match arg {
+            "" => writeln!(output, "usage: :transcript <path>, or :transcript off"),
+            "off" => {
+                self.0.borrow_mut().take();
+                Ok(())
+            }
+            path => match File::create(path) {
+                Ok(file) => {
+                    *self.0.borrow_mut() = Some(file);
+                    Ok(())
+                }
+                Err(err) => writeln!(output, "couldn't start a transcript in '{}': {}", path, err),
+            },
+        }
+    }
+
+    /// Copies `bytes` to the transcript, if there is one. The session goes
+    /// on if it can't be written to, without it.
+    fn record(&self, bytes: &[u8]) {
+        let mut file = self.0.borrow_mut();
+        if let Some(Err(err)) = file.as_mut().map(|file| file.write_all(bytes)) {
+            eprintln!("warning: stopped the transcript: {}", err);
+            *file = None;
+        }
+    }
+}
+
+/// Writes to `inner`, copying what it writes to the transcript.
+struct Tee<W> {
+    inner: W,
+    transcript: Transcript,
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.transcript.record(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads from `source`, copying each line to the transcript after its
+/// prompt.
+struct Recorded<'a, S> {
+    source: &'a mut S,
+    transcript: Transcript,
+}
+
+impl<S: LineSource> LineSource for Recorded<'_, S> {
+    fn read_line(&mut self, prompt: &str, env: &Environment) -> io::Result<Line> {
+        let line = self.source.read_line(prompt, env)?;
+        if let Line::Text(text) = &line {
+            self.transcript.record(format!("{}{}\n", prompt, text).as_bytes());
+        }
+        Ok(line)
+    }
+
+    /// Transcripts are meant to be read as text, without colors.
+    fn color(&self) -> bool {
+        self.source.color() && !self.transcript.is_on()
+    }
+}
+
+/// How a debugger session started by `break` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Abort,
+    /// `exit` was called with this status.
+    Exit(i32),
+}
+
+/// Commands of the debugger, with a description of each.
+const DEBUG_COMMANDS: &[(&str, &str)] = &[
+    (":continue", "resume the program"),
+    (":abort", "stop the program with an error"),
+    (":env", "list the names bound here and the types of their values"),
+    (":type <expr>", "evaluate an expression and show its type"),
+];
+
+/// The debugger `break` enters: a REPL like `run`'s in the environment as it
+/// is where `break` was called, so the arguments of the enclosing functions
+/// are visible. It ends with `:continue`, or when `input` does, or `:abort`.
+pub fn debug(env: &mut Environment, input: &mut impl LineSource, output: &mut impl Write) -> io::Result<Resume> {
+    writeln!(output, "stopped at a breakpoint, :continue to resume")?;
+    let end = session(env, input, output, DEBUG_PROMPT, |line, env, output, color| {
+        let line = line.trim();
+        let name = line.split_whitespace().next().unwrap_or(line);
+        match name {
+            ":continue" => Ok(Some(Resume::Continue)),
+            ":abort" => Ok(Some(Resume::Abort)),
+            ":env" | ":type" => Ok(command(line, env, output, color)?.map(Resume::Exit)),
+            _ => {
+                writeln!(output, "unknown command '{}', the commands are:", line)?;
+                for (usage, description) in DEBUG_COMMANDS {
+                    writeln!(output, "  {:<14} {}", usage, description)?;
+                }
+                Ok(None)
+            }
+        }
+    }, Resume::Exit)?;
+
+    // Ctrl-C while in the debugger wasn't meant for the program.
+    env.clear_interrupt();
+    Ok(end.unwrap_or(Resume::Continue))
+}
+
+/// Reads and evaluates expressions, as described on `run`, handing the
+/// lines starting with a colon to `on_command`. Ends when `on_command`
+/// returns a value, which is returned, or with `on_exit` of the status when
+/// `exit` is called, or with `None` when `input` ends.
+fn session<W: Write, T>(
+    env: &mut Environment,
+    input: &mut impl LineSource,
+    output: &mut W,
+    prompt: &str,
+    mut on_command: impl FnMut(&str, &mut Environment, &mut W, bool) -> io::Result<Option<T>>,
+    on_exit: fn(i32) -> T,
+) -> io::Result<Option<T>> {
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() { prompt } else { CONTINUATION_PROMPT };
+
+        match input.read_line(prompt, env)? {
+            Line::Text(line) if buffer.is_empty() && line.trim_start().starts_with(':') => {
+                let end = on_command(&line, env, output, input.color())?;
+                output.flush()?;
+                if end.is_some() {
+                    return Ok(end);
+                }
+                continue;
+            }
+            Line::Text(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+            Line::Interrupted => {
+                buffer.clear();
+                continue;
+            }
+            Line::Eof => return Ok(None),
+        }
+
+        let mut reader = env.reader(&buffer);
+        if reader.parse_sexprs().is_err_and(|err| err.is_incomplete()) {
+            continue;
+        }
+
+        let status = eval_source(&buffer, env, output, input.color(), |output, val| writeln!(output, "{}", Written(&**val)))?;
+        output.flush()?;
+        if let Some(status) = status {
+            return Ok(Some(on_exit(status)));
+        }
+        buffer.clear();
+    }
+}
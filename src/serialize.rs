@@ -0,0 +1,185 @@
+//! `serde` support for expressions and values, behind the `serde` feature.
+//!
+//! The representation maps naturally to JSON:
+//!
+//! | yal                  | JSON                              |
+//! |----------------------|-----------------------------------|
+//! | `(a 1)`              | `[{"ident": "a"}, {"int": 1}]`    |
+//! | `1`                  | `{"int": 1}`                      |
+//! | `1.5`                | `{"float": 1.5}`                  |
+//! | `"hi"`               | `{"string": "hi"}`                |
+//! | `foo`                | `{"ident": "foo"}`                |
+//! | `'x`                 | `{"quote": {"ident": "x"}}`       |
+//!
+//! Values use the same tags, plus `{"bool": true}` for booleans and `"nil"`
+//! for nil. Functions can't be serialized, trying to is an error.
+//!
+//! Spans aren't kept, so deserialized expressions don't point anywhere in
+//! the source, and their identifiers aren't interned into any table.
+
+use std::rc::Rc;
+
+use serde::de::{ self, Deserialize, Deserializer };
+use serde::ser::{ self, Serialize, Serializer };
+
+use crate::ast::*;
+use crate::symbol::Symbol;
+
+#[derive(serde::Serialize)]
+#[serde(rename = "Atom", rename_all = "lowercase")]
+enum AtomRef<'a> {
+    String(&'a str),
+    Int(i64),
+    /// An int past an `i64`, in decimal.
+    BigInt(String),
+    Rational(i64, i64),
+    Float(f64),
+    Quote(&'a SExpr),
+    Ident(&'a str),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename = "Atom", rename_all = "lowercase")]
+enum AtomRepr {
+    String(String),
+    Int(i64),
+    /// An int past an `i64`, in decimal.
+    BigInt(String),
+    Rational(i64, i64),
+    Float(f64),
+    Quote(Rc<SExpr>),
+    Ident(Symbol),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SExprRepr {
+    List(Vec<SExpr>),
+    Atom(Atom),
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename = "Value", rename_all = "lowercase")]
+enum ValueRef<'a> {
+    String(&'a str),
+    Int(i64),
+    /// An int past an `i64`, in decimal.
+    BigInt(String),
+    Rational(i64, i64),
+    Float(f64),
+    Bool(bool),
+    Nil,
+    Quote(&'a SExpr),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename = "Value", rename_all = "lowercase")]
+enum ValueRepr {
+    String(String),
+    Int(i64),
+    /// An int past an `i64`, in decimal.
+    BigInt(String),
+    Rational(i64, i64),
+    Float(f64),
+    Bool(bool),
+    Nil,
+    Quote(Rc<SExpr>),
+}
+
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let atom = match self {
+            Atom::String(s) => AtomRef::String(s),
+            Atom::Int(n)    => AtomRef::Int(*n),
+            Atom::BigInt(n) => AtomRef::BigInt(n.to_string()),
+            Atom::Rational(num, den) => AtomRef::Rational(*num, *den),
+            Atom::Float(n)  => AtomRef::Float(*n),
+            Atom::Quote(q)  => AtomRef::Quote(q),
+            Atom::Ident(s)  => AtomRef::Ident(s),
+        };
+        atom.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Atom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match AtomRepr::deserialize(deserializer)? {
+            AtomRepr::String(s) => Atom::String(s.into()),
+            AtomRepr::Int(n)    => Atom::Int(n),
+            AtomRepr::BigInt(n) => Atom::big_int(n.parse().map_err(|_| de::Error::custom(format!("invalid int {}", n)))?),
+            AtomRepr::Rational(num, den) => Atom::rational(num.into(), den.into())
+                .ok_or_else(|| de::Error::custom(format!("invalid rational {}/{}", num, den)))?,
+            AtomRepr::Float(n)  => Atom::Float(n),
+            AtomRepr::Quote(q)  => Atom::Quote(q),
+            AtomRepr::Ident(s)  => Atom::Ident(s),
+        })
+    }
+}
+
+impl Serialize for SExpr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SExpr::List(list, _) => serializer.collect_seq(list),
+            SExpr::Atom(atom, _) => atom.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SExpr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expr = SExprRepr::deserialize(deserializer)
+            .map_err(|_| de::Error::custom("expected a list or an atom"))?;
+
+        Ok(match expr {
+            SExprRepr::List(list) => SExpr::List(list.into(), Span::default()),
+            SExprRepr::Atom(atom) => SExpr::Atom(atom, Span::default()),
+        })
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let val = match self {
+            Value::String(s)   => ValueRef::String(s),
+            Value::Int(n)      => ValueRef::Int(*n),
+            Value::BigInt(n)   => ValueRef::BigInt(n.to_string()),
+            Value::Rational(num, den) => ValueRef::Rational(*num, *den),
+            Value::Float(n)    => ValueRef::Float(*n),
+            Value::Bool(b)     => ValueRef::Bool(*b),
+            Value::Nil         => ValueRef::Nil,
+            Value::Quote(q)    => ValueRef::Quote(q),
+            Value::Function(f) => {
+                return Err(ser::Error::custom(format!("can't serialize the function {}", f)));
+            }
+            Value::Handle(h) => {
+                return Err(ser::Error::custom(format!("can't serialize {}", h)));
+            }
+            Value::Promise(p) => {
+                return Err(ser::Error::custom(format!("can't serialize {}", p)));
+            }
+        };
+        val.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match ValueRepr::deserialize(deserializer)? {
+            ValueRepr::String(s) => Value::String(s.into()),
+            ValueRepr::Int(n)    => Value::Int(n),
+            ValueRepr::BigInt(n) => Value::big_int(n.parse().map_err(|_| de::Error::custom(format!("invalid int {}", n)))?),
+            ValueRepr::Rational(num, den) => Value::rational(num.into(), den.into())
+                .ok_or_else(|| de::Error::custom(format!("invalid rational {}/{}", num, den)))?,
+            ValueRepr::Float(n)  => Value::Float(n),
+            ValueRepr::Bool(b)   => Value::Bool(b),
+            ValueRepr::Nil       => Value::Nil,
+            ValueRepr::Quote(q)  => Value::Quote(q),
+        })
+    }
+}
+
+impl Serialize for RefVal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
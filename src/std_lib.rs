@@ -1,265 +1,1693 @@
-use std::ops::Deref;
+//! The builtins every environment made with `Environment::with_std_lib`
+//! has, and the helpers natives written in Rust share with them.
 
-use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{ DefaultHasher, Hash, Hasher };
+use std::io::{ self, IsTerminal };
+use std::ops::Deref;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 
 use crate::ast::*;
-use crate::error::RuntimeError;
+use crate::bigint::BigInt;
+use crate::calendar::DateTime;
+use crate::error::{ self, RuntimeError, SourceMap };
+use crate::include;
+use crate::optimize;
+use crate::pattern;
+use crate::printer::{ self, Rounded, Written };
 use crate::evaluator::*;
+use crate::profile::{ self, Profiler };
+use crate::symbol::Symbol;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::repl::{ self, Resume };
 
-lazy_static! {
-    static ref TRUE: Value = Value::Quote(SExpr::Atom(Atom::Ident("t".to_string())));
-    static ref FALSE: Value = Value::Quote(SExpr::Atom(Atom::Ident("f".to_string())));
-    static ref NIL: Value = Value::Quote(SExpr::Atom(Atom::Ident("nil".to_string())));
+// Values may hold `Rc`s, so they can't be shared across threads. Instead each
+// thread leaks its own copy of the singletons, which is a few bytes per thread.
+thread_local! {
+    static TRUE: &'static Value = Box::leak(Box::new(Value::Bool(true)));
+    static FALSE: &'static Value = Box::leak(Box::new(Value::Bool(false)));
+    static NIL: &'static Value = Box::leak(Box::new(Value::Nil));
 }
 
 pub fn true_ref() -> &'static Value {
-    TRUE.deref()
+    TRUE.with(|v| *v)
 }
 
 pub fn false_ref() -> &'static Value {
-    FALSE.deref()
+    FALSE.with(|v| *v)
 }
 
 pub fn nil_ref() -> &'static Value {
-    NIL.deref()
+    NIL.with(|v| *v)
 }
 
-fn symbol(s: impl ToString) -> RefVal {
-    RefVal::owned(Value::Quote(SExpr::Atom(Atom::Ident(s.to_string()))))
-}
+/// Builtins, by name, arity and documentation.
+type Builtins = [(&'static str, usize, &'static str, LibFn)];
+
+/// The builtins that take any number of arguments past their arity.
+const VARIADIC: &[&str] = &["+", "*"];
 
-impl Into<RefVal> for bool {
-    fn into(self) -> RefVal {
-        match self {
-            true => RefVal::reference(true_ref()),
-            false => RefVal::reference(false_ref()),
+/// Binds the builtins and constants every program starts out with.
+pub fn register(env: &mut Environment) -> Result<(), RuntimeError> {
+    // By category, and whether they are pure.
+    let builtins: &[(&'static str, bool, &Builtins)] = &[
+        ("control", false, &[
+            ("let", 2, "Binds a quoted name to a value, and returns the value.", let_impl),
+            ("defconst", 2, "Binds a quoted name to a value that can't be bound again.", defconst_impl),
+            ("fn", 2, "Makes a function from a quoted argument list and a quoted body.", fn_impl),
+            ("define-multi", 2, "Binds a quoted name to a function with a clause for each number of arguments, from a quoted list of ((args ...) body).", define_multi_impl),
+            ("if", 3, "Evaluates the second argument if the first is true, the third otherwise.", if_impl),
+            ("match", 2, "Evaluates the result of the first quoted (pattern result) clause whose pattern matches a value, with what it binds, or gives nil.", match_impl),
+            ("loop", 2, "Evaluates a quoted body with quoted ((name value) ...) bindings, again with new values each time it ends in 'recur'.", loop_impl),
+            ("recur", 0, "Starts the innermost loop or function body over with the given values, from its tail position.", recur_impl),
+            ("return", 1, "Ends the innermost call of a function defined in yal, which gives the value.", return_impl),
+            ("unwind-protect", 2, "Evaluates a quoted body, then a quoted cleanup however the body ended, giving what the body gave.", unwind_protect_impl),
+            ("delay", 1, "Makes a promise of the value of a quoted expression, which is evaluated when the promise is first forced.", delay_impl),
+            ("force", 1, "The value of a promise, evaluating its expression the first time only. Anything else is its own value.", force_impl),
+            ("eval", 1, "Evaluates quoted code.", eval_impl),
+            ("comptime", 1, "Evaluates an expression with pure builtins only, once, before the program runs.", comptime_impl),
+            ("exit", 1, "Stops the program with the given exit status.", exit_impl),
+        ]),
+        ("modules", false, &[
+            ("load", 1, "Evaluates the file at the given path.", load_impl),
+            ("include", 1, "Splices the forms of the file at the given path in place, as the including file is read.", include_impl),
+            ("module", 2, "Evaluates quoted definitions inside a module.", module_impl),
+            ("import", 1, "Binds every definition of a module to its unqualified name.", import_impl),
+            ("import-only", 2, "Binds the given definitions of a module to their unqualified names.", import_only_impl),
+        ]),
+        ("lists", true, &[
+            ("cons", 2, "A list with the first argument in front of the second.", cons_impl),
+            ("car", 1, "The first element of a list.", car_impl),
+            ("cdr", 1, "A list without its first element.", cdr_impl),
+        ]),
+        ("arithmetic", true, &[
+            ("=", 2, "Whether two values are equal, with NaN unequal to everything.", eq),
+            ("eq", 2, "The same as '='.", eq),
+            ("equal?", 2, "Whether two values have the same structure.", equal_impl),
+            ("+", 2, "The sum of two or more numbers.", add),
+            ("-", 2, "The difference of two numbers.", sub),
+            ("*", 2, "The product of two or more numbers.", mul),
+            ("/", 2, "The quotient of two numbers, exact unless one of them is a float.", div),
+            ("neg", 1, "A number with its sign flipped.", neg_impl),
+            ("abs", 1, "A number without its sign.", abs_impl),
+            ("exact->inexact", 1, "The float nearest a number.", exact_to_inexact_impl),
+        ]),
+        ("time", false, &[
+            ("time-now", 0, "The current date and time in UTC, as (year month day hour minute second).", time_now_impl),
+            ("time-format", 2, "Formats a date, or milliseconds since the epoch, with %Y %m %d %H %M %S and %%.", time_format_impl),
+            ("time-parse", 2, "Reads a date written with a format, as milliseconds since the epoch, or f if it doesn't match.", time_parse_impl),
+            ("sleep", 1, "Waits the given number of milliseconds, and gives nil.", sleep_impl),
+        ]),
+        ("random", false, &[
+            ("random", 1, "A random int from 0 up to a positive int, or float from 0 up to a positive float, excluded.", random_impl),
+            ("random-seed", 1, "Seeds 'random' with an int, so that it gives the same numbers every run.", random_seed_impl),
+        ]),
+        ("output", false, &[
+            ("print", 1, "Prints a value.", print_impl),
+            ("flush", 0, "Writes out what was printed, which is otherwise buffered.", flush_impl),
+        ]),
+        ("settings", false, &[
+            ("set-int-overflow-mode", 1, "Makes arithmetic on ints that overflows give a big int, with 'promote, fail, with 'checked, or wrap around, with 'wrapping.", set_int_overflow_mode_impl),
+            ("set-print-precision", 1, "Makes 'print' round floats to the given number of digits after the point, or not if nil.", set_print_precision_impl),
+            ("set-print-depth", 1, "Makes 'print' write lists nested deeper than the given number of levels as '...', or as deep as by default if nil.", set_print_depth_impl),
+        ]),
+        ("introspection", false, &[
+            ("doc", 1, "The documentation of a function, or nil.", doc_impl),
+            ("help", 0, "Prints every bound name, with the arity and documentation of functions and the category of builtins.", help_impl),
+            ("bound-names", 0, "Every name bound where it is called, sorted.", bound_names_impl),
+            ("bound?", 1, "Whether a quoted name is bound where it is called.", is_bound_impl),
+            ("describe", 1, "What a quoted name is bound to, as ((type name) (arity n) (doc text)), with the arity and doc of functions only.", describe_impl),
+        ]),
+        ("debugging", false, &[
+            ("trace", 1, "Evaluates quoted code, printing every call and its result.", trace_impl),
+            ("profile-report", 0, "The calls made so far while profiling, as (name calls total-ms self-ms).", profile_report_impl),
+            ("break", 0, "Stops in the debugger when run from a terminal, and does nothing otherwise.", break_impl),
+        ]),
+        ("testing", false, &[
+            ("deftest", 2, "Registers quoted code as a test named by a quoted name.", deftest_impl),
+            ("run-tests", 0, "Runs the tests and returns how many failed.", run_tests_impl),
+            ("bench", 2, "Times calls to a function, as ((total ms) (mean ms) (min ms) (max ms)).", bench_impl),
+            ("defbench", 3, "Registers quoted code as a benchmark run the given number of times.", defbench_impl),
+        ]),
+    ];
+    for &(category, pure, builtins) in builtins {
+        for &(name, arity, doc, ptr) in builtins {
+            let spec = BuiltinSpec::new(name, arity).doc(doc).category(category);
+            let spec = if VARIADIC.contains(&name) { spec.variadic() } else { spec };
+            env.register_builtin(if pure { spec.pure() } else { spec }, ptr);
         }
     }
-}
+    crate::net::register(env);
+    crate::parallel::register(env);
+    crate::channel::register(env);
+    crate::image::register(env);
+    #[cfg(feature = "http")]
+    crate::http::register(env);
 
-impl Into<RefVal> for String {
-    fn into(self) -> RefVal {
-        RefVal::owned(Value::String(self))
-    }
-}
-
-impl Into<RefVal> for f64 {
-    fn into(self) -> RefVal {
-        RefVal::owned(Value::Number(self))
-    }
+    env.define_var("nil", RefVal::reference(nil_ref()))?;
+    env.define_var("t", RefVal::reference(true_ref()))?;
+    env.define_var("f", RefVal::reference(false_ref()))?;
+    Ok(())
 }
 
 impl From<SExpr> for Atom {
     fn from(expr: SExpr) -> Atom {
-        Atom::Quote(Box::new(expr))
+        Atom::Quote(Rc::new(expr))
     }
 }
 
 impl From<Atom> for SExpr {
     fn from(atom: Atom) -> SExpr {
-        SExpr::Atom(atom)
+        SExpr::atom(atom)
     }
 }
 
-// TODOOO: This should be scoped, somehow
+/// A type mismatch for `got`, showing both its type and (the start of) its
+/// printed form.
+pub(crate) fn mismatch(expected: &'static str, got: &RefVal, context: &str) -> RuntimeError {
+    let printed = error::truncate(&Written(&**got).to_string(), 40);
+    RuntimeError::type_mismatch(expected, format!("{} `{}`", got.get_type(), printed), context)
+}
+
+/// Binds a quoted name, or the names of a quoted list pattern, as globals.
+/// Even in a function body the binding outlives the call, since only the
+/// arguments of a call, the bindings of a `loop` and the names a `match`
+/// clause binds are scoped.
 pub fn let_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let val = env.pop_stack();
-    let name = env.pop_stack();
+    let mut val = env.pop_stack()?;
+    let name = env.pop_stack()?;
+
+    // A quoted list is a pattern destructuring the value, as in
+    // `(let '(x y . rest) '(1 2 3 4))`.
+    if let Some(pattern) = name.deref().as_quote().filter(|quoted| quoted.as_list().is_some()) {
+        pattern::check(pattern, &mut Vec::new())?;
+        for (name, val) in pattern::destructure(pattern, &val, "'let'")? {
+            env.define_var(&name, val)?;
+        }
+        return Ok(val);
+    }
 
     let name = name
         .deref()
         .as_quote()
         .and_then(SExpr::as_atom)
-        .and_then(Atom::as_ident)
-        .ok_or(format!("expected a symbol, got {:?}", name))?;
+        .and_then(Atom::as_symbol)
+        .ok_or_else(|| mismatch("a quoted symbol", &name, "'let'"))?;
+
+    name_function(&mut val, name);
+    env.define_var(name, val.clone())?;
+    Ok(val)
+}
+
+/// Binds a quoted name to a value like `let`, for good: binding the name
+/// again is an error. Functions can still take arguments named like it.
+pub fn defconst_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut val = env.pop_stack()?;
+    let name = env.pop_stack()?;
+
+    let quoted = name.deref().as_quote();
+    let symbol = quoted
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_symbol)
+        .ok_or_else(|| mismatch("a quoted symbol", &name, "'defconst'"))?;
+    let span = quoted.map(SExpr::span).unwrap_or_default();
 
-    env.bind_var(name, val.clone());
+    name_function(&mut val, symbol);
+    env.define_const(symbol, val.clone(), span)?;
     Ok(val)
 }
 
+/// A function created just to be bound takes the name it is bound to, which
+/// then shows up when printing it and in error messages.
+fn name_function(val: &mut RefVal, name: &Symbol) {
+    if let Some(Value::Function(Function::UserDefined { name: fun_name @ None, .. })) = val.get_mut() {
+        *fun_name = Some(name.clone());
+    }
+}
+
+/// Makes a function from a quoted argument list and a quoted body. A string
+/// leading the argument list documents the function, as in
+/// `(fn '("Adds one." x) '(+ x 1))`. The arguments after `&key` are passed
+/// by keyword after the others, and are either a name, nil unless passed, or
+/// `(name default)`: `(fn '(title &key (width 80)) 'width)` can be called
+/// as `(f "hi")` or `(f "hi" :width 100)`. The others can be list patterns,
+/// which destructure their argument, as in `(fn '((x y)) '(+ x y))`.
 pub fn fn_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let body = env.pop_stack();
-    let args = env.pop_stack();
+    let body = env.pop_stack()?;
+    let args = env.pop_stack()?;
 
     let args = args
         .deref()
         .as_quote()
         .and_then(SExpr::as_list)
-        .ok_or(format!("expected arguments, got {:?}", args))?;
+        .ok_or_else(|| mismatch("a quoted argument list", &args, "'fn'"))?;
+
+    let doc = match args.front().and_then(SExpr::as_atom) {
+        Some(Atom::String(doc)) => Some(doc.clone()),
+        _ => None,
+    };
 
     let mut arg_names = Vec::new();
-    for arg in args {
+    let mut patterns = Vec::new();
+    let mut defaults = Vec::new();
+    let mut keys = false;
+    for arg in args.iter().skip(doc.is_some() as usize) {
+        if !keys && arg.as_atom().and_then(Atom::as_ident) == Some("&key") {
+            keys = true;
+            continue;
+        }
+        if !keys && arg.as_list().is_some() {
+            arg_names.push(pattern_argument(arg, &arg_names, &mut patterns, env, "'fn'")?);
+            continue;
+        }
+
+        let (arg, default) = match (keys, arg.as_list()) {
+            (true, Some(list)) => match list.iter().collect::<Vec<_>>()[..] {
+                [name, default] => (name, Some(default.clone())),
+                _ => return Err(RuntimeError::type_mismatch("a (name default) keyword argument", arg, "'fn'")),
+            },
+            (true, None) => (arg, Some(SExpr::atom(Atom::Ident(env.intern("nil"))))),
+            (false, _) => (arg, None),
+        };
         let arg = arg
             .as_atom()
-            .and_then(Atom::as_ident)
-            .ok_or(format!("expected argument, got {:?}", arg))?;
+            .and_then(Atom::as_symbol)
+            .ok_or_else(|| RuntimeError::type_mismatch("an argument name", arg, "'fn'"))?;
 
-        arg_names.push(arg.clone())
+        arg_names.push(arg.clone());
+        defaults.extend(default);
     }
 
     let body = body
-        .deref()
-        .as_quote()
-        .ok_or(format!(
-            "expected function body to be a list of expressions, got {:?}",
-            body
-        ))?
-        .clone();
+        .into_quote()
+        .map_err(|body| mismatch("a quoted function body", &body, "'fn'"))?;
+    check_recur(&body, true)?;
+    check_patterns(&arg_names, &patterns)?;
 
     Ok(RefVal::owned(Value::Function(Function::UserDefined {
+        name: None,
+        module: env.current_module().cloned(),
         arg_names,
+        patterns,
+        defaults,
         body,
+        doc,
     })))
 }
 
-pub fn if_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let else_branch = env.pop_stack();
-    let then_branch = env.pop_stack();
-    let cond = env.pop_stack();
-
-    let then_branch = then_branch.deref().as_quote().ok_or(format!(
-        "expected then branch to be quoted, got {:?}",
-        then_branch
-    ))?;
-
-    let else_branch = else_branch.deref().as_quote().ok_or(format!(
-        "expected else branch to be quoted, got {:?}",
-        else_branch
-    ))?;
-
-    if let RefVal::Borrowed(b) = cond {
-        let ptr = b as *const Value;
-        if ptr == false_ref() as *const Value || ptr == nil_ref() as *const Value {
-            return evaluate(else_branch, env);
+/// The name an argument given as the list pattern `arg` is bound to, adding
+/// the pattern to `patterns`. Two arguments can't have the same pattern,
+/// since they would get the same name.
+fn pattern_argument(
+    arg: &SExpr,
+    arg_names: &[Symbol],
+    patterns: &mut Vec<(Symbol, SExpr)>,
+    env: &Environment,
+    context: &str,
+) -> Result<Symbol, RuntimeError> {
+    let name = pattern::argument(arg, env);
+    if arg_names.contains(&name) {
+        let message = format!("the pattern `{}` is given for more than one argument of {}", name, context);
+        return Err(RuntimeError::Custom(message).with_span(arg.span()));
+    }
+    patterns.push((name.clone(), arg.clone()));
+    Ok(name)
+}
+
+/// Checks the patterns of some arguments, which can't bind the names of the
+/// others, nor those another pattern binds.
+fn check_patterns(arg_names: &[Symbol], patterns: &[(Symbol, SExpr)]) -> Result<(), RuntimeError> {
+    let mut names: Vec<&str> = arg_names.iter().map(|name| &**name).collect();
+    for (_, pattern) in patterns {
+        pattern::check(pattern, &mut names)?;
+    }
+    Ok(())
+}
+
+/// Binds a quoted name to a function choosing one of a quoted list of
+/// `((args ...) body)` clauses by the number of arguments it is called with,
+/// as in `(define-multi 'greet '(((name) (+ "hi " name)) ((hi name) (+ hi name))))`.
+/// A clause whose arguments end in `& rest` takes any number of arguments
+/// past the others, bound to `rest` as a list, when no other clause takes
+/// them. Arguments can be list patterns, as with `fn`.
+pub fn define_multi_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let clauses = env.pop_stack()?;
+    let name = env.pop_stack()?;
+
+    let name = name
+        .deref()
+        .as_quote()
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_symbol)
+        .ok_or_else(|| mismatch("a quoted symbol", &name, "'define-multi'"))?;
+
+    let list = clauses
+        .deref()
+        .as_quote()
+        .and_then(SExpr::as_list)
+        .filter(|list| !list.is_empty())
+        .ok_or_else(|| mismatch("a quoted list of clauses", &clauses, "'define-multi'"))?;
+
+    let mut fixed = BTreeMap::new();
+    let mut rest = None;
+    for expr in list.iter() {
+        let clause = multi_clause(expr, env)?;
+        check_recur(&clause.body, true)?;
+
+        let arity = clause.arity();
+        let clash = if clause.variadic {
+            rest.replace(clause).map(|_| format!("'{}' has more than one clause taking the rest of its arguments", name))
+        } else {
+            fixed.insert(arity, clause).map(|_| format!("'{}' has more than one clause taking {}", name, arguments(&arity.to_string())))
+        };
+        if let Some(message) = clash {
+            return Err(RuntimeError::Custom(message).with_span(expr.span()));
+        }
+    }
+
+    let val = RefVal::owned(Value::Function(Function::MultiArity {
+        name: Some(name.clone()),
+        module: env.current_module().cloned(),
+        clauses: fixed,
+        rest,
+    }));
+    env.define_var(name, val.clone())?;
+    Ok(val)
+}
+
+/// A `((args ...) body)` clause of `define-multi`.
+fn multi_clause(expr: &SExpr, env: &Environment) -> Result<Clause, RuntimeError> {
+    let parts: Vec<&SExpr> = expr.as_list().into_iter().flatten().collect();
+    let [args, body] = parts[..] else {
+        return Err(RuntimeError::type_mismatch("a ((args ...) body) clause", expr, "'define-multi'"));
+    };
+    let args = args
+        .as_list()
+        .ok_or_else(|| RuntimeError::type_mismatch("an argument list", args, "'define-multi'"))?;
+
+    let arg_name = |arg: &SExpr| {
+        arg.as_atom()
+            .and_then(Atom::as_symbol)
+            .cloned()
+            .ok_or_else(|| RuntimeError::type_mismatch("an argument name", arg, "'define-multi'"))
+    };
+
+    let mut params = Vec::new();
+    let mut patterns = Vec::new();
+    let mut variadic = false;
+    let mut args = args.iter();
+    while let Some(mut arg) = args.next() {
+        if arg.as_atom().and_then(Atom::as_ident) == Some("&") {
+            match (args.next(), args.next()) {
+                (Some(rest), None) => (arg, variadic) = (rest, true),
+                _ => {
+                    return Err(RuntimeError::from("'&' must be followed by the last argument").with_span(arg.span()))
+                }
+            }
         }
+        let name = match arg.as_list() {
+            Some(_) => pattern_argument(arg, &params, &mut patterns, env, "'define-multi'")?,
+            None => arg_name(arg)?,
+        };
+        params.push(name);
     }
-    evaluate(then_branch, env)
+    check_patterns(&params, &patterns)?;
+    Ok(Clause { params, patterns, variadic, body: Rc::new(body.clone()) })
 }
 
-pub fn eval_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let expr = env.pop_stack();
+pub fn if_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let else_branch = env.pop_stack()?;
+    let then_branch = env.pop_stack()?;
+    let cond = env.pop_stack()?;
 
-    let expr = expr
+    let then_branch = then_branch
         .deref()
         .as_quote()
-        .ok_or(format!("expected an expression, got {:?}", expr))?;
+        .ok_or_else(|| mismatch("a quoted then branch", &then_branch, "'if'"))?;
 
-    evaluate(expr, env)
+    let else_branch = else_branch
+        .deref()
+        .as_quote()
+        .ok_or_else(|| mismatch("a quoted else branch", &else_branch, "'if'"))?;
+
+    if cond.is_truthy() {
+        evaluate(then_branch, env)
+    } else {
+        evaluate(else_branch, env)
+    }
 }
 
-pub fn cons_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let tail = env.pop_stack();
-    let head = env.pop_stack();
+/// Evaluates the result of the first quoted `(pattern result)` clause whose
+/// pattern matches the value, with the names the pattern binds in scope, as
+/// in `(match xs '((() 'empty) ((x) x) ((x _ . rest) rest)))`. Gives nil if
+/// no pattern matches. See `pattern` for what patterns match.
+pub fn match_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let clauses = env.pop_stack()?;
+    let val = env.pop_stack()?;
 
-    let head = head
+    let clauses = clauses
         .deref()
         .as_quote()
-        .ok_or(format!("expected a quoted expression, got {:?}", head))?;
+        .and_then(SExpr::as_list)
+        .ok_or_else(|| mismatch("a quoted list of clauses", &clauses, "'match'"))?;
+
+    let mut parsed = Vec::with_capacity(clauses.len());
+    for clause in clauses.iter() {
+        let parts: Vec<&SExpr> = clause.as_list().into_iter().flatten().collect();
+        let [pattern, result] = parts[..] else {
+            return Err(RuntimeError::type_mismatch("a (pattern result) clause", clause, "'match'"));
+        };
+        pattern::check(pattern, &mut Vec::new())?;
+        parsed.push((pattern, result));
+    }
 
-    let mut tail = tail
+    for (pattern, result) in parsed {
+        let mut bindings = Vec::new();
+        if !pattern::matches(pattern, &val, &mut bindings) {
+            continue;
+        }
+        let mut scope = env.scope();
+        for (name, val) in bindings {
+            scope.bind_var(name, val)?;
+        }
+        return evaluate(result, &mut scope);
+    }
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// Evaluates a quoted body with the quoted bindings `((name value) ...)`,
+/// each value seeing the names bound before it. A `recur` in tail position
+/// of the body runs it again with the names bound to new values, as in
+/// `(loop '((i 0) (sum 0)) '(if (= i 10) 'sum '(recur (+ i 1) (+ sum i))))`.
+pub fn loop_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let body = env.pop_stack()?;
+    let bindings = env.pop_stack()?;
+
+    let bindings = bindings
         .deref()
         .as_quote()
         .and_then(SExpr::as_list)
-        .ok_or(format!("expected a quoted expression, got {:?}", tail))?
+        .ok_or_else(|| mismatch("a quoted list of bindings", &bindings, "'loop'"))?;
+
+    let body = body
+        .into_quote()
+        .map_err(|body| mismatch("a quoted loop body", &body, "'loop'"))?;
+    check_recur(&body, true)?;
+
+    let mut names = Vec::new();
+    let mut values = Vec::new();
+    let mut scope = env.scope();
+    for binding in bindings.iter() {
+        let pair: Vec<&SExpr> = binding.as_list().into_iter().flatten().collect();
+        let [name, expr] = pair[..] else {
+            return Err(RuntimeError::type_mismatch("a (name value) binding", binding, "'loop'"));
+        };
+        let name = name
+            .as_atom()
+            .and_then(Atom::as_symbol)
+            .ok_or_else(|| RuntimeError::type_mismatch("a binding name", name, "'loop'"))?;
+
+        let val = evaluate(expr, &mut scope)?;
+        scope.bind_var(name.clone(), val.clone())?;
+        names.push(name.clone());
+        values.push(val);
+    }
+    drop(scope);
+
+    recur_point(env, &names, values, |env| evaluate(&body, env))
+}
+
+/// Calls to `recur` are handled by `evaluator::apply`, since it takes any
+/// number of values. This is only reached when another builtin calls it,
+/// with none.
+pub fn recur_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    env.recur(Vec::new())
+}
+
+pub fn return_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    env.return_from(val)
+}
+
+/// The body's value or error is the outcome, unless the cleanup fails. Then
+/// its error is, and it says which error of the body it replaced, if any.
+pub fn unwind_protect_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let cleanup = env.pop_stack()?;
+    let body = env.pop_stack()?;
+    let body = body.into_quote().map_err(|body| mismatch("a quoted body", &body, "'unwind-protect'"))?;
+    let cleanup = cleanup.into_quote().map_err(|cleanup| mismatch("a quoted cleanup", &cleanup, "'unwind-protect'"))?;
+
+    let outcome = evaluate(&body, env);
+    let cleaned = env.while_unwinding(|env| evaluate(&cleanup, env));
+    match (outcome, cleaned) {
+        (outcome, Ok(_)) => outcome,
+        (Err(err), Err(cleanup_err)) if !is_unwinding(&err) && !is_unwinding(&cleanup_err) => {
+            let error = RuntimeError::Custom(format!(
+                "{} (in the cleanup, which suppressed the earlier error: {})",
+                cleanup_err.root(),
+                err.root(),
+            ));
+            Err(match cleanup_err.span() {
+                Some(span) => error.with_span(span),
+                None => error,
+            })
+        }
+        (_, Err(cleanup_err)) => Err(cleanup_err),
+    }
+}
+
+pub fn delay_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let expr = env.pop_stack()?;
+    let expr = expr.into_quote().map_err(|expr| mismatch("a quoted expression", &expr, "'delay'"))?;
+    Ok(RefVal::owned(Value::Promise(env.new_promise(expr))))
+}
+
+/// When a promise gives another promise, that one is forced too, and both
+/// keep the value it gives, so promises never stand for promises. If the
+/// expression fails, the promises are left to be forced again.
+pub fn force_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut val = env.pop_stack()?;
+    let mut forcing = Vec::new();
+    while let Value::Promise(promise) = &*val {
+        let promise = promise.clone();
+        let state = std::mem::replace(&mut *promise.0.borrow_mut(), PromiseState::Forcing);
+        let outcome = match state {
+            PromiseState::Forced(forced, expr) => {
+                *promise.0.borrow_mut() = PromiseState::Forced(forced.clone(), expr);
+                Ok(forced)
+            }
+            PromiseState::Forcing => Err("promise forced during its own evaluation".into()),
+            PromiseState::Delayed(expr) => {
+                let outcome = evaluate(&expr, env);
+                forcing.push((promise, expr));
+                outcome
+            }
+        };
+        match outcome {
+            Ok(next) => val = next,
+            Err(err) => {
+                for (promise, expr) in forcing {
+                    *promise.0.borrow_mut() = PromiseState::Delayed(expr);
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    for (promise, expr) in forcing {
+        *promise.0.borrow_mut() = PromiseState::Forced(val.clone(), expr);
+    }
+    Ok(val)
+}
+
+/// Whether `err` stops evaluation without anything being wrong with the
+/// code.
+fn is_unwinding(err: &RuntimeError) -> bool {
+    use RuntimeError::*;
+
+    matches!(err.root(), Exit(_) | Recur | Return | Interrupted | OutOfFuel | Timeout(_))
+}
+
+/// Makes sure `recur` is only called in tail position of `expr`, the body of
+/// a loop or function: as the body itself, or as a branch of an `if` or the
+/// result of a `match` clause in tail position. The loops and functions
+/// inside have bodies of their own.
+fn check_recur(expr: &SExpr, tail: bool) -> Result<(), RuntimeError> {
+    let list = match expr {
+        SExpr::List(list, _) => list,
+        SExpr::Atom(Atom::Quote(quoted), _) => return check_recur(quoted, false),
+        SExpr::Atom(..) => return Ok(()),
+    };
+
+    match (list.front().and_then(SExpr::as_atom).and_then(Atom::as_ident), list.len()) {
+        (Some("recur"), _) if !tail => {
+            Err(RuntimeError::from("'recur' can only be used in tail position").with_span(expr.span()))
+        }
+        (Some("if"), 4) => {
+            check_recur(&list[1], false)?;
+            for branch in [&list[2], &list[3]] {
+                match branch {
+                    SExpr::Atom(Atom::Quote(quoted), _) => check_recur(quoted, tail)?,
+                    branch => check_recur(branch, false)?,
+                }
+            }
+            Ok(())
+        }
+        (Some("match"), 3) => {
+            check_recur(&list[1], false)?;
+            let clauses = match &list[2] {
+                SExpr::Atom(Atom::Quote(quoted), _) => quoted.as_list(),
+                _ => None,
+            };
+            let Some(clauses) = clauses else { return check_recur(&list[2], false) };
+            for clause in clauses.iter() {
+                match clause.as_list() {
+                    Some(clause) if clause.len() == 2 => check_recur(&clause[1], tail)?,
+                    _ => check_recur(clause, false)?,
+                }
+            }
+            Ok(())
+        }
+        (Some("fn" | "loop"), 3) => check_recur(&list[1], false),
+        _ => list.iter().try_for_each(|expr| check_recur(expr, false)),
+    }
+}
+
+/// Evaluates quoted code. Other values are already evaluated, so they are
+/// returned as they are.
+pub fn eval_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let expr = env.pop_stack()?;
+
+    match expr.deref() {
+        Value::Quote(expr) => evaluate(expr, env),
+        _ => Ok(expr),
+    }
+}
+
+/// `(comptime expr)` forms are replaced by their value before the code runs,
+/// see `optimize::comptime`. Those that weren't, in code made at runtime, give
+/// the value of their argument.
+pub fn comptime_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    env.pop_stack()
+}
+
+/// Evaluates quoted code like `eval`, tracing it while it runs.
+pub fn trace_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let was_tracing = env.is_tracing();
+    env.set_trace(true);
+    let retr = eval_impl(env);
+    env.set_trace(was_tracing);
+    retr
+}
+
+/// The functions called so far while profiling, as a list of
+/// `(name calls total-ms self-ms)`, the one that took the longest first.
+/// Without profiling, the list is empty.
+pub fn profile_report_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let entries = env.profile().map(Profiler::report).unwrap_or_default();
+
+    let report = entries
+        .into_iter()
+        .map(|entry| {
+            SExpr::list(List::from([
+                SExpr::atom(Atom::String(entry.name.into())),
+                SExpr::atom(Atom::Int(entry.calls as i64)),
+                SExpr::atom(Atom::Float(profile::millis(entry.total))),
+                SExpr::atom(Atom::Float(profile::millis(entry.own))),
+            ]))
+        })
+        .collect();
+    Ok(RefVal::owned(Value::Quote(Rc::new(SExpr::list(report)))))
+}
+
+/// Registers quoted code as a test, for `run-tests` to run later.
+pub fn deftest_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let body = env.pop_stack()?;
+    let name = quoted_symbol(&env.pop_stack()?, "'deftest'")?;
+
+    let body = body.into_quote().map_err(|body| mismatch("quoted code", &body, "'deftest'"))?;
+    env.add_test(name, body);
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// Runs every test registered with `deftest`, each on its own so that
+/// bindings it makes don't outlive it, and with the fuel there was when the
+/// tests started, so that one running out doesn't fail those after it. A
+/// test fails if it raises an error or evaluates to false. Prints how each
+/// went and returns how many failed.
+pub fn run_tests_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let tests = env.tests().to_vec();
+    let fuel = env.fuel();
+    let mut failed = 0;
+
+    for test in &tests {
+        if let Some(fuel) = fuel {
+            env.set_fuel(fuel);
+        }
+        let retr = env.isolated(|env| evaluate(&test.body, env));
+        if let Err(err) = &retr {
+            // Stopping the program stops the tests too.
+            if let RuntimeError::Exit(_) | RuntimeError::Interrupted | RuntimeError::Timeout(_) = err.root() {
+                return retr;
+            }
+        }
+
+        let out = env.output();
+        match retr {
+            Ok(val) if val.is_truthy() => writeln!(out, "test {} ... ok", test.name),
+            Ok(val) => {
+                failed += 1;
+                writeln!(out, "test {} ... FAILED", test.name)
+                    .and_then(|_| writeln!(out, "  evaluated to {}", val))
+            }
+            Err(err) => {
+                failed += 1;
+                writeln!(out, "test {} ... FAILED", test.name).and_then(|_| {
+                    let src = test.file.as_ref().and_then(|file| fs::read_to_string(file).ok());
+                    match (&test.file, src) {
+                        (Some(file), Some(src)) => {
+                            let file = file.display().to_string();
+                            writeln!(out, "{}", error::Located { file: Some(&file), src: &src, error: &err })
+                        }
+                        _ => writeln!(out, "{}", err),
+                    }
+                })
+            }
+        }
+        .map_err(output_error)?;
+    }
+
+    writeln!(env.output(), "{} passed, {} failed", tests.len() - failed, failed).map_err(output_error)?;
+    Ok(RefVal::from(failed as i64))
+}
+
+/// Iterations run before measuring, so that the first measured ones don't
+/// pay for warming up caches.
+const BENCH_WARMUP: u64 = 3;
+
+/// How long the measured iterations of a benchmark took.
+pub struct BenchTimes {
+    pub iterations: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl BenchTimes {
+    pub fn mean(&self) -> Duration {
+        self.total.div_f64(self.iterations as f64)
+    }
+}
+
+/// Runs `f` `iterations` times after warming up, timing each run.
+fn measure(
+    env: &mut Environment,
+    iterations: u64,
+    mut f: impl FnMut(&mut Environment) -> Result<RefVal, RuntimeError>,
+) -> Result<BenchTimes, RuntimeError> {
+    for _ in 0..BENCH_WARMUP.min(iterations) {
+        f(env)?;
+    }
+
+    let mut times = BenchTimes { iterations, total: Duration::ZERO, min: Duration::MAX, max: Duration::ZERO };
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f(env)?;
+        let elapsed = start.elapsed();
+
+        times.total += elapsed;
+        times.min = times.min.min(elapsed);
+        times.max = times.max.max(elapsed);
+    }
+    Ok(times)
+}
+
+fn bench_iterations(val: &RefVal, context: &str) -> Result<u64, RuntimeError> {
+    match **val {
+        Value::Int(n) if n > 0 => Ok(n as u64),
+        _ => Err(mismatch("a positive number of iterations", val, context)),
+    }
+}
+
+/// Calls a function taking no arguments the given number of times, and
+/// returns how long that took in milliseconds, as
+/// `((total ms) (mean ms) (min ms) (max ms))`.
+pub fn bench_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let thunk = env.pop_stack()?;
+    let iterations = bench_iterations(&env.pop_stack()?, "'bench'")?;
+
+    let fun = match &*thunk {
+        Value::Function(fun) if fun.takes(0) => fun.clone(),
+        _ => return Err(mismatch("a function taking no arguments", &thunk, "'bench'")),
+    };
+
+    let times = measure(env, iterations, |env| call(&fun, 0, env))?;
+
+    let field = |name: &str, duration: Duration| {
+        SExpr::list(List::from([
+            SExpr::atom(Atom::Ident(env.intern(name))),
+            SExpr::atom(Atom::Float(profile::millis(duration))),
+        ]))
+    };
+    let result = SExpr::list(List::from([
+        field("total", times.total),
+        field("mean", times.mean()),
+        field("min", times.min),
+        field("max", times.max),
+    ]));
+    Ok(RefVal::owned(Value::Quote(Rc::new(result))))
+}
+
+/// Registers quoted code as a benchmark run the given number of times, for
+/// `--bench`.
+pub fn defbench_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let body = env.pop_stack()?;
+    let iterations = bench_iterations(&env.pop_stack()?, "'defbench'")?;
+    let name = quoted_symbol(&env.pop_stack()?, "'defbench'")?;
+
+    let body = body.into_quote().map_err(|body| mismatch("quoted code", &body, "'defbench'"))?;
+    env.add_bench(Bench { name, iterations, body });
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// Runs every benchmark registered with `defbench` and prints a table of
+/// how long they took.
+pub fn run_benches(env: &mut Environment) -> Result<(), RuntimeError> {
+    let benches = env.benches().to_vec();
+    let width = benches.iter().map(|bench| bench.name.len()).max().unwrap_or(0).max(9);
+
+    writeln!(
+        env.output(),
+        "{:<width$} {:>10} {:>12} {:>10} {:>10} {:>10}",
+        "benchmark", "iterations", "total (ms)", "mean (ms)", "min (ms)", "max (ms)",
+    )
+    .map_err(output_error)?;
+
+    for bench in &benches {
+        let times = measure(env, bench.iterations, |env| evaluate(&bench.body, env))?;
+        writeln!(
+            env.output(),
+            "{:<width$} {:>10} {:>12.3} {:>10.3} {:>10.3} {:>10.3}",
+            bench.name,
+            times.iterations,
+            profile::millis(times.total),
+            profile::millis(times.mean()),
+            profile::millis(times.min),
+            profile::millis(times.max),
+        )
+        .map_err(output_error)?;
+    }
+    Ok(())
+}
+
+/// Stops the program with the given exit status.
+pub fn exit_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let status = env.pop_stack()?;
+    match *status {
+        Value::Int(n) => match i32::try_from(n) {
+            Ok(n) => Err(RuntimeError::Exit(n)),
+            Err(_) => Err(format!("exit status {} is out of range", n).into()),
+        },
+        _ => Err(mismatch("an exit status", &status, "'exit'")),
+    }
+}
+
+/// Evaluates the file at the given path, relative to the file doing the
+/// loading, in the current environment. Returns the value of the file's last
+/// expression.
+pub fn load_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let path = env.pop_stack()?;
+    let path = match path.deref() {
+        Value::String(path) => path.clone(),
+        _ => return Err(mismatch("a path string", &path, "'load'")),
+    };
+
+    load_file(&resolve_path(env, &path), env)
+}
+
+/// `(include "path")` forms at the top level of a file are replaced by the
+/// included forms as it is read, see `include::expand`. Any other one is
+/// evaluated, too late to include anything.
+pub fn include_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    env.pop_stack()?;
+    Err("'include' only works at the top level of a file, use 'load' to evaluate a file at runtime".into())
+}
+
+/// Makes `path` relative to the file being run.
+pub(crate) fn resolve_path(env: &Environment, path: &str) -> PathBuf {
+    match env.current_file().and_then(Path::parent) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+fn load_file(path: &Path, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if cfg!(target_arch = "wasm32") {
+        return Err(format!("couldn't load '{}': there is no filesystem in the browser", path.display()).into());
+    }
+
+    let src: Rc<str> = fs::read_to_string(path)
+        .map_err(|err| format!("couldn't load '{}': {}", path.display(), err))?
+        .into();
+
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if let Some(start) = env.files().iter().position(|file| *file == canonical) {
+        let cycle: Vec<_> = env.files()[start..]
+            .iter()
+            .chain([&canonical])
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(format!("circular load: {}", cycle.join(" → ")).into());
+    }
+
+    let in_file = |error| RuntimeError::InFile {
+        file: path.display().to_string(),
+        src: src.clone(),
+        error: Box::new(error),
+    };
+
+    let exprs = env.reader(&src)
+        .parse_sexprs()
+        .map_err(|err| {
+            let span = Span::new(err.byte(), err.byte() + 1);
+            in_file(RuntimeError::Custom(err.message().to_string()).with_span(span))
+        })?;
+
+    let mut sources = SourceMap::new(&path.display().to_string(), src.clone());
+    let exprs: Rc<[SExpr]> = include::expand(exprs, Some(path), &mut sources, env)
+        .and_then(|exprs| exprs.into_iter().map(|expr| optimize::comptime(expr, env)).collect())
+        .map_err(|err| in_file(sources.attribute(err)))?;
+    env.add_source(&path.display().to_string(), src.clone(), exprs.clone());
+
+    env.push_file(canonical);
+    let mut last = Ok(RefVal::reference(nil_ref()));
+    for expr in exprs.iter() {
+        last = evaluate_toplevel(expr, env);
+        if last.is_err() {
+            break;
+        }
+    }
+    env.pop_file();
+    last.map_err(|err| in_file(sources.attribute(err)))
+}
+
+/// Evaluates quoted definitions inside the given module, so that they are
+/// bound to names qualified with the module's, like `math/square`.
+pub fn module_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let forms = env.pop_stack()?;
+    let name = env.pop_stack()?;
+
+    let module = quoted_symbol(&name, "'module'")?;
+    let forms = forms
+        .deref()
+        .as_quote()
+        .and_then(SExpr::as_list)
+        .ok_or_else(|| mismatch("a quoted list of definitions", &forms, "'module'"))?
         .clone();
 
-    tail.push_front(head.clone());
-    Ok(RefVal::owned(Value::Quote(SExpr::List(tail))))
+    let outer = env.enter_module(module);
+    let retr = forms.iter().try_for_each(|form| evaluate(form, env).map(drop));
+    env.leave_module(outer);
+    retr.map(|()| name)
 }
 
-pub fn car_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let list = env.pop_stack();
+/// Binds every definition of a module to its unqualified name. A module that
+/// isn't defined yet is looked for in a file named after it.
+pub fn import_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let name = env.pop_stack()?;
+    import(env, &name, None)
+}
 
-    let list = list
+/// Like `import`, binding only the names in the given list.
+pub fn import_only_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let names = env.pop_stack()?;
+    let name = env.pop_stack()?;
+
+    let names = names
         .deref()
         .as_quote()
         .and_then(SExpr::as_list)
-        .ok_or(format!("car expected a list, got {}", list))?;
+        .ok_or_else(|| mismatch("a quoted list of names", &names, "'import-only'"))?
+        .iter()
+        .map(|name| {
+            name.as_atom()
+                .and_then(Atom::as_symbol)
+                .cloned()
+                .ok_or_else(|| RuntimeError::type_mismatch("a name", name, "'import-only'"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(RefVal::owned(Value::Quote(
-        list.get(0)
-            .ok_or(format!("expected non empty list"))?
-            .clone(),
-    )))
+    import(env, &name, Some(&names))
 }
 
-pub fn cdr_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    let list = env.pop_stack();
+fn import(env: &mut Environment, name: &RefVal, only: Option<&[Symbol]>) -> Result<RefVal, RuntimeError> {
+    let module = quoted_symbol(name, "'import'")?;
+
+    if !env.has_module(&module) {
+        let path = resolve_path(env, &format!("{}.yal", module));
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if path.exists() && env.mark_module_file(canonical) {
+            load_file(&path, env)?;
+        }
+    }
+
+    env.import(&module, only)?;
+    Ok(name.clone())
+}
+
+fn quoted_symbol(val: &RefVal, context: &str) -> Result<Symbol, RuntimeError> {
+    val.deref()
+        .as_quote()
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_symbol)
+        .cloned()
+        .ok_or_else(|| mismatch("a quoted name", val, context))
+}
+
+/// Quoted lists share their storage, so the list is only copied when someone
+/// else still holds on to it.
+pub(crate) fn quoted_list(val: RefVal, context: &str) -> Result<Rc<SExpr>, RuntimeError> {
+    if let Value::Nil = *val {
+        return Ok(Rc::new(SExpr::list(Default::default())));
+    }
+
+    match val.into_quote() {
+        Ok(quote) if quote.as_list().is_some() => Ok(quote),
+        Ok(quote) => Err(mismatch("a list", &RefVal::owned(Value::Quote(quote)), context)),
+        Err(val) => Err(mismatch("a list", &val, context)),
+    }
+}
+
+pub fn cons_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let tail = env.pop_stack()?;
+    let head = env.pop_stack()?;
+
+    let head = value_to_sexpr(&head, env.symbols())?;
+
+    let mut tail = quoted_list(tail, "'cons'")?;
+    if let SExpr::List(list, _) = Rc::make_mut(&mut tail) {
+        list.push_front(head);
+    }
+    Ok(RefVal::owned(Value::Quote(tail)))
+}
+
+pub fn car_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let list = env.pop_stack()?;
 
     let list = list
         .deref()
         .as_quote()
         .and_then(SExpr::as_list)
-        .ok_or(format!("cdr expected a list, got {}", list))?;
+        .ok_or_else(|| mismatch("a list", &list, "'car'"))?;
 
-    if list.len() == 0 {
-        return Err(format!("expected non empty list"));
+    let head = list
+        .front()
+        .ok_or_else(|| RuntimeError::EmptyList { context: "'car'".to_string() })?;
+
+    Ok(RefVal::owned(sexpr_to_value(head)))
+}
+
+pub fn cdr_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let list = quoted_list(env.pop_stack()?, "'cdr'")?;
+    let list = list.as_list().expect("'quoted_list' only returns lists");
+    if list.is_empty() {
+        return Err(RuntimeError::EmptyList { context: "'cdr'".to_string() });
     }
 
-    Ok(RefVal::owned(Value::Quote(SExpr::List(
-        list.iter()
-            .skip(1)
-            .cloned()
-            .collect(),
-    ))))
+    // The tail is shared with the list, neither is copied.
+    Ok(RefVal::owned(Value::Quote(Rc::new(SExpr::list(list.tail())))))
 }
 
-pub fn eq(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    use Value::*;
+/// How floats are compared. `=` follows IEEE 754: NaN is unequal to
+/// everything, itself included, and `0.0` equals `-0.0`. `equal?` compares
+/// structure instead, so a NaN equals any other NaN and data containing NaNs
+/// is still equal to itself. Zeros of either sign are equal in both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Equality {
+    Ieee,
+    Structural,
+}
+
+fn floats_equal(lhs: f64, rhs: f64, mode: Equality) -> bool {
+    lhs == rhs || (mode == Equality::Structural && lhs.is_nan() && rhs.is_nan())
+}
 
-    let rhs = env.pop_stack();
-    let lhs = env.pop_stack();
+fn atoms_equal(lhs: &Atom, rhs: &Atom, mode: Equality) -> bool {
+    use Atom::*;
 
-    let res = match (lhs.deref(), rhs.deref()) {
-        (String(lhs), String(rhs)) if lhs == rhs => true,
-        (Number(lhs), Number(rhs)) if lhs == rhs => true,
-        (Quote(lhs), Quote(rhs)) if lhs == rhs => true,
-        (Function(_), Function(_)) if &lhs.as_ptr() == &rhs.as_ptr() => true,
+    match (lhs, rhs) {
+        (Int(lhs), Int(rhs)) => lhs == rhs,
+        (Float(lhs), Float(rhs)) => floats_equal(*lhs, *rhs, mode),
+        (Int(i), Float(x)) | (Float(x), Int(i)) => int_eq_float(*i, *x),
+        (BigInt(n), Float(x)) | (Float(x), BigInt(n)) => n.to_exact_f64() == Some(*x),
+        (Rational(num, den), Float(x)) | (Float(x), Rational(num, den)) => rational_eq_float(*num, *den, *x),
+        (Quote(lhs), Quote(rhs)) => sexprs_equal(lhs, rhs, mode),
+        (lhs, rhs) => lhs == rhs,
+    }
+}
+
+fn sexprs_equal(lhs: &SExpr, rhs: &SExpr, mode: Equality) -> bool {
+    match (lhs, rhs) {
+        (SExpr::Atom(lhs, _), SExpr::Atom(rhs, _)) => atoms_equal(lhs, rhs, mode),
+        (SExpr::List(lhs, _), SExpr::List(rhs, _)) => {
+            lhs.len() == rhs.len()
+                && lhs.iter().zip(rhs).all(|(lhs, rhs)| sexprs_equal(lhs, rhs, mode))
+        }
         _ => false,
+    }
+}
+
+/// A hash of `expr` that agrees with both kinds of equality: expressions
+/// that are equal, with `=` or with `equal?`, hash the same. So an int and
+/// the float equal to it do, and a rational and the float equal to it, as
+/// do all NaNs, and spans are left out.
+pub fn structural_hash(expr: &SExpr) -> u64 {
+    let atom = match expr {
+        SExpr::List(list, _) => return list.cached_hash(structural_hash),
+        SExpr::Atom(atom, _) => atom,
     };
 
-    Ok(res.into())
+    let mut hasher = DefaultHasher::new();
+    match atom {
+        Atom::Int(n) => (0u8, *n).hash(&mut hasher),
+        Atom::Float(x) if int_eq_float(*x as i64, *x) => (0u8, *x as i64).hash(&mut hasher),
+        Atom::Float(x) if x.is_nan() => 1u8.hash(&mut hasher),
+        Atom::Float(x) => (2u8, x.to_bits()).hash(&mut hasher),
+        // Only those with a power of two below and at most 53 bits above
+        // equal a float, and converting them is exact.
+        Atom::Rational(num, den) if (*den as u64).is_power_of_two() && num.unsigned_abs() <= 1 << 53 => {
+            (2u8, (*num as f64 / *den as f64).to_bits()).hash(&mut hasher)
+        }
+        Atom::Rational(num, den) => (6u8, num, den).hash(&mut hasher),
+        // Those a float equals hash like it, the rest can't be anything else.
+        Atom::BigInt(n) => match n.to_exact_f64() {
+            Some(x) => (2u8, x.to_bits()).hash(&mut hasher),
+            None => (7u8, n).hash(&mut hasher),
+        },
+        Atom::String(s) => (3u8, s).hash(&mut hasher),
+        Atom::Ident(name) => (4u8, &**name).hash(&mut hasher),
+        Atom::Quote(quoted) => (5u8, structural_hash(quoted)).hash(&mut hasher),
+    }
+    hasher.finish()
 }
 
-macro_rules! impl_bin_op {
-    () => {};
+pub(crate) fn values_equal(lhs: &RefVal, rhs: &RefVal, mode: Equality) -> bool {
+    use Value::*;
 
-    (@once pub fn $name:ident => $op:tt) => {
-        #[allow(dead_code)]
-        pub fn $name(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-            use Value::*;
+    match (lhs.deref(), rhs.deref()) {
+        (String(lhs), String(rhs)) => lhs == rhs,
+        (Int(lhs), Int(rhs)) => lhs == rhs,
+        (Float(lhs), Float(rhs)) => floats_equal(*lhs, *rhs, mode),
+        (Int(i), Float(x)) | (Float(x), Int(i)) => int_eq_float(*i, *x),
+        (BigInt(lhs), BigInt(rhs)) => lhs == rhs,
+        (BigInt(n), Float(x)) | (Float(x), BigInt(n)) => n.to_exact_f64() == Some(*x),
+        (Rational(lhs_num, lhs_den), Rational(rhs_num, rhs_den)) => (lhs_num, lhs_den) == (rhs_num, rhs_den),
+        (Rational(num, den), Float(x)) | (Float(x), Rational(num, den)) => rational_eq_float(*num, *den, *x),
+        (Bool(lhs), Bool(rhs)) => lhs == rhs,
+        // `nil` and the empty list are the same thing, as in most lisps.
+        (Nil, Nil) => true,
+        (Nil, Quote(q)) | (Quote(q), Nil) => q.as_list().is_some_and(|l| l.is_empty()),
+        // The hashes are cached, so comparing the same lists again only
+        // walks them when they are equal. A NaN inside isn't `=` to itself,
+        // so only `equal?` can tell shared expressions equal right away.
+        (Quote(lhs), Quote(rhs)) => {
+            (mode == Equality::Structural && Rc::ptr_eq(lhs, rhs))
+                || (structural_hash(lhs) == structural_hash(rhs) && sexprs_equal(lhs, rhs, mode))
+        }
+        (Function(_), Function(_)) => lhs.as_ptr() == rhs.as_ptr(),
+        (Handle(lhs), Handle(rhs)) => lhs.ptr_eq(rhs),
+        (Promise(lhs), Promise(rhs)) => Rc::ptr_eq(lhs, rhs),
+        _ => false,
+    }
+}
 
-            let rhs = env.pop_stack();
-            let lhs = env.pop_stack();
+/// `=`, numeric equality following IEEE 754 for floats.
+pub fn eq(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let rhs = env.pop_stack()?;
+    let lhs = env.pop_stack()?;
+    Ok(values_equal(&lhs, &rhs, Equality::Ieee).into())
+}
 
-            match (lhs.deref(), rhs.deref()) {
-                (Number(lhs), Number(rhs)) => Ok((lhs $op rhs).into()),
-                _ => {
-                    Err(format!(
-                        "expected two numbers in operation '{}', got {} and {}",
-                        stringify!($op),
-                        lhs.get_type(),
-                        rhs.get_type()
-                    ))
-                }
+/// `equal?`, structural equality under which NaN equals itself.
+pub fn equal_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let rhs = env.pop_stack()?;
+    let lhs = env.pop_stack()?;
+    Ok(values_equal(&lhs, &rhs, Equality::Structural).into())
+}
+
+/// Compares an int and a float by their exact numeric value, so that big ints
+/// don't compare equal to a float they merely round to.
+fn int_eq_float(i: i64, x: f64) -> bool {
+    // 2^63 is exactly representable, and every float in the range is integral
+    // past 2^53, so this check and cast is exact.
+    x.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&x) && x as i64 == i
+}
+
+/// Compares a rational and a float by their exact numeric value. Floats are
+/// fractions with a power of two below, so only those rationals can be equal
+/// to one, and then scaling the float by it is exact.
+fn rational_eq_float(num: i64, den: i64, x: f64) -> bool {
+    (den as u64).is_power_of_two() && int_eq_float(num, x * den as f64)
+}
+
+/// An exact number as `(num, den)`, with `den > 0`. The parts of sums and
+/// products of two fractions of `i64`s fit.
+type Fraction = (i128, i128);
+
+fn fraction(val: &Value) -> Option<Fraction> {
+    match *val {
+        Value::Int(n) => Some((n.into(), 1)),
+        Value::Rational(num, den) => Some((num.into(), den.into())),
+        _ => None,
+    }
+}
+
+/// An int as a big int, for arithmetic with big ints.
+fn big_int(val: &Value) -> Option<BigInt> {
+    match val {
+        Value::Int(n) => Some(BigInt::from(*n)),
+        Value::BigInt(n) => Some((**n).clone()),
+        _ => None,
+    }
+}
+
+/// The float nearest a number, or nearly for rationals and big ints.
+fn inexact(val: &Value) -> Option<f64> {
+    match *val {
+        Value::Int(n) => Some(n as f64),
+        Value::BigInt(ref n) => Some(n.to_f64()),
+        Value::Rational(num, den) => Some(num as f64 / den as f64),
+        Value::Float(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// The int `n`, which doesn't fit in an `i64`, as `overflow` has it.
+fn overflowed(n: i128, overflow: IntOverflow, operation: impl FnOnce() -> String) -> Result<RefVal, RuntimeError> {
+    match overflow {
+        IntOverflow::Promote => Ok(RefVal::owned(Value::big_int(BigInt::from(n)))),
+        IntOverflow::Wrapping => Ok((n as i64).into()),
+        IntOverflow::Checked => Err(RuntimeError::IntegerOverflow { operation: operation() }),
+    }
+}
+
+/// Applies an arithmetic operation to two numbers: `exact` if they are both
+/// ints or rationals, giving a rational, which is an int when it is whole,
+/// `big` if either is a big int and the other an int, and `float` if either
+/// is a float, which the other is converted to. So ints stay ints unless
+/// divided, and floats are contagious. An int result that overflows an
+/// `i64` is handled as `overflow` says, a rational one is an error.
+fn arithmetic(
+    lhs: &RefVal,
+    rhs: &RefVal,
+    op: &str,
+    overflow: IntOverflow,
+    exact: fn(Fraction, Fraction) -> Fraction,
+    big: Option<fn(&BigInt, &BigInt) -> BigInt>,
+    float: fn(f64, f64) -> f64,
+) -> Result<RefVal, RuntimeError> {
+    let operation = || format!("{} {} {}", lhs, op, rhs);
+    if let (Some(lhs_exact), Some(rhs_exact)) = (fraction(lhs), fraction(rhs)) {
+        let (num, den) = exact(lhs_exact, rhs_exact);
+        return match Value::rational(num, den) {
+            Some(val) => Ok(RefVal::owned(val)),
+            None if num % den == 0 => overflowed(num / den, overflow, operation),
+            None => Err(RuntimeError::IntegerOverflow { operation: operation() }),
+        };
+    }
+
+    if matches!(**lhs, Value::BigInt(_)) || matches!(**rhs, Value::BigInt(_)) {
+        match (big_int(lhs), big_int(rhs), big) {
+            (Some(lhs), Some(rhs), Some(big)) => return Ok(RefVal::owned(Value::big_int(big(&lhs, &rhs)))),
+            // Neither side is a float, so the result would be a rational
+            // with a part past an `i64`.
+            _ if fraction(lhs).is_some() || fraction(rhs).is_some() => {
+                return Err(RuntimeError::IntegerOverflow { operation: operation() })
+            }
+            _ => (),
+        }
+    }
+
+    match (inexact(lhs), inexact(rhs)) {
+        (Some(lhs), Some(rhs)) => Ok(float(lhs, rhs).into()),
+        _ => Err(RuntimeError::type_mismatch(
+            "two numbers",
+            format!("{} and {}", lhs.get_type(), rhs.get_type()),
+            format!("'{}'", op),
+        )),
+    }
+}
+
+/// Applies `+` or `*` to all the arguments it was given, from left to right.
+fn arithmetic_fold(
+    env: &mut Environment,
+    op: &str,
+    exact: fn(Fraction, Fraction) -> Fraction,
+    big: fn(&BigInt, &BigInt) -> BigInt,
+    float: fn(f64, f64) -> f64,
+) -> Result<RefVal, RuntimeError> {
+    let mut args = Vec::with_capacity(env.arg_count());
+    for _ in 0..env.arg_count() {
+        args.push(env.pop_stack()?);
+    }
+    let mut args = args.into_iter().rev();
+    let first = args.next().ok_or(RuntimeError::StackUnderflow { callee: Some(format!("'{}'", op)) })?;
+    let overflow = env.int_overflow();
+    args.try_fold(first, |acc, arg| arithmetic(&acc, &arg, op, overflow, exact, Some(big), float))
+}
+
+pub fn sub(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let rhs = env.pop_stack()?;
+    let lhs = env.pop_stack()?;
+    arithmetic(&lhs, &rhs, "-", env.int_overflow(), |(a, b), (c, d)| (a * d - c * b, b * d), Some(|lhs, rhs| lhs - rhs), |lhs, rhs| lhs - rhs)
+}
+
+pub fn add(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    arithmetic_fold(env, "+", |(a, b), (c, d)| (a * d + c * b, b * d), |lhs, rhs| lhs + rhs, |lhs, rhs| lhs + rhs)
+}
+
+pub fn mul(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    arithmetic_fold(env, "*", |(a, b), (c, d)| (a * c, b * d), |lhs, rhs| lhs * rhs, |lhs, rhs| lhs * rhs)
+}
+
+/// Division of ints and rationals is exact, giving a rational unless it
+/// comes out whole, and gives a float if either is a float. Big ints can
+/// only be divided by floats, or divide them.
+pub fn div(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let rhs = env.pop_stack()?;
+    let lhs = env.pop_stack()?;
+
+    if fraction(&lhs).is_some() && matches!(*rhs, Value::Int(0)) {
+        return Err(RuntimeError::DivisionByZero);
+    }
+    arithmetic(&lhs, &rhs, "/", env.int_overflow(), |(a, b), (c, d)| (a * d, b * c), None, |lhs, rhs| lhs / rhs)
+}
+
+/// Applies `neg` or `abs` to a number, exactly for ints, big ints and
+/// rationals, where only `i64::MIN` overflows, as `arithmetic` does.
+fn sign_op(
+    val: &RefVal,
+    op: &str,
+    overflow: IntOverflow,
+    exact: fn(i128) -> i128,
+    big: fn(&BigInt) -> BigInt,
+    float: fn(f64) -> f64,
+) -> Result<RefVal, RuntimeError> {
+    let operation = || format!("{} {}", op, val);
+    if let Some((num, den)) = fraction(val) {
+        let num = exact(num);
+        return match Value::rational(num, den) {
+            Some(val) => Ok(RefVal::owned(val)),
+            None if den == 1 => overflowed(num, overflow, operation),
+            None => Err(RuntimeError::IntegerOverflow { operation: operation() }),
+        };
+    }
+
+    match **val {
+        Value::BigInt(ref n) => Ok(RefVal::owned(Value::big_int(big(n)))),
+        Value::Float(x) => Ok(float(x).into()),
+        _ => Err(mismatch("a number", val, &format!("'{}'", op))),
+    }
+}
+
+pub fn neg_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    sign_op(&val, "neg", env.int_overflow(), |n| -n, |n| -n, |x| -x)
+}
+
+pub fn abs_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    sign_op(&val, "abs", env.int_overflow(), i128::abs, BigInt::abs, f64::abs)
+}
+
+pub fn set_int_overflow_mode_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mode = env.pop_stack()?;
+    let found = mode.as_quote().and_then(SExpr::as_atom).and_then(Atom::as_ident).and_then(IntOverflow::from_name);
+    match found {
+        Some(found) => env.set_int_overflow(found),
+        None => return Err(mismatch("'promote, 'checked or 'wrapping", &mode, "'set-int-overflow-mode'")),
+    }
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// `exact->inexact`, the float nearest an int or rational.
+pub fn exact_to_inexact_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    match inexact(&val) {
+        Some(x) => Ok(x.into()),
+        None => Err(mismatch("a number", &val, "'exact->inexact'")),
+    }
+}
+
+pub fn print_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let val = env.pop_stack()?;
+    let (precision, depth) = (env.print_precision(), env.print_depth());
+    write!(env.output(), "{}", Rounded(&val, precision, depth)).map_err(output_error)?;
+    Ok(RefVal::reference(nil_ref()))
+}
+
+pub fn set_print_precision_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let precision = env.pop_stack()?;
+    let precision = match *precision {
+        Value::Int(digits) if (0..=printer::MAX_PRECISION as i64).contains(&digits) => Some(digits as usize),
+        Value::Nil => None,
+        _ => return Err(mismatch("a number of digits up to 100, or nil", &precision, "'set-print-precision'")),
+    };
+    env.set_print_precision(precision);
+    Ok(RefVal::reference(nil_ref()))
+}
+
+pub fn set_print_depth_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let depth = env.pop_stack()?;
+    let depth = match *depth {
+        Value::Int(levels) if levels > 0 => levels as usize,
+        Value::Nil => printer::DEFAULT_MAX_DEPTH,
+        _ => return Err(mismatch("a positive number of levels or nil", &depth, "'set-print-depth'")),
+    };
+    env.set_print_depth(depth);
+    Ok(RefVal::reference(nil_ref()))
+}
+
+pub fn flush_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    env.output().flush().map_err(output_error)?;
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// The documentation of a function, or nil if it has none.
+pub fn doc_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let fun = env.pop_stack()?;
+    let Value::Function(fun) = &*fun else {
+        return Err(mismatch("a function", &fun, "'doc'"));
+    };
+
+    Ok(match fun.doc() {
+        Some(doc) => RefVal::from(doc.to_string()),
+        None => RefVal::reference(nil_ref()),
+    })
+}
+
+/// The names bound where it is called, sorted, as a quoted list.
+pub fn bound_names_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut names: Vec<&Symbol> = env.iter_bindings().map(|(name, _)| name).collect();
+    names.sort();
+    let names = names.into_iter().map(|name| SExpr::atom(Atom::Ident(name.clone()))).collect();
+    Ok(RefVal::owned(Value::Quote(SExpr::list(names).into())))
+}
+
+/// The symbol a builtin taking a quoted name was given.
+fn quoted_name(val: &RefVal, context: &str) -> Result<Symbol, RuntimeError> {
+    val.as_quote()
+        .and_then(SExpr::as_atom)
+        .and_then(Atom::as_symbol)
+        .cloned()
+        .ok_or_else(|| mismatch("a quoted symbol", val, context))
+}
+
+pub fn is_bound_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let name = quoted_name(&env.pop_stack()?, "'bound?'")?;
+    Ok(env.lookup_symbol(&name).is_some().into())
+}
+
+/// What a quoted name is bound to, as `((type function) (arity 2) (doc
+/// "..."))`. The arity is a string for functions taking different numbers of
+/// arguments, and only functions have an arity and a doc, if they have one.
+pub fn describe_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let name = quoted_name(&env.pop_stack()?, "'describe'")?;
+    let val = env.lookup_symbol(&name).ok_or_else(|| env.unbound(&name, None))?;
+
+    let field = |field: &str, value: Atom| {
+        SExpr::list(List::from([SExpr::atom(Atom::Ident(env.intern(field))), SExpr::atom(value)]))
+    };
+    let mut fields = vec![field("type", Atom::Ident(env.intern(val.get_type())))];
+    if let Value::Function(fun) = &**val {
+        let arities = fun.arities();
+        fields.push(match arities.parse() {
+            Ok(arity) => field("arity", Atom::Int(arity)),
+            Err(_) => field("arity", Atom::String(arities.into())),
+        });
+        if let Some(doc) = fun.doc() {
+            fields.push(field("doc", Atom::String(doc.into())));
+        }
+    }
+    Ok(RefVal::owned(Value::Quote(SExpr::list(fields.into()).into())))
+}
+
+/// Prints every bound name in order, with the arity and the first line of
+/// the documentation of functions, and the category of builtins.
+pub fn help_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut names: Vec<Symbol> = env.bound_names().cloned().collect();
+    names.sort();
+    names.dedup();
+
+    let mut lines = Vec::new();
+    for name in names {
+        let line = match env.lookup_symbol(&name).map(Deref::deref) {
+            Some(Value::Function(fun)) => {
+                let doc = fun.doc().and_then(|doc| doc.lines().next()).unwrap_or("");
+                let category = env.builtin(&name).map_or("", |spec| spec.category);
+                format!("{:<16} {:>2}  {:<13} {}", name, fun.arities(), category, doc)
+            }
+            Some(val) => format!("{:<16} {:>2}  {:<13} {}", name, "-", "", val.get_type()),
+            None => continue,
+        };
+        lines.push(line);
+    }
+
+    let out = env.output();
+    for line in lines {
+        writeln!(out, "{}", line.trim_end()).map_err(output_error)?;
+    }
+    Ok(RefVal::reference(nil_ref()))
+}
+
+/// A date as the list `(year month day hour minute second)`.
+fn date_to_list(date: &DateTime) -> RefVal {
+    let components = [date.year, date.month.into(), date.day.into(), date.hour.into(), date.minute.into(), date.second.into()];
+    let list = components.into_iter().map(|n| SExpr::atom(Atom::Int(n))).collect();
+    RefVal::owned(Value::Quote(Rc::new(SExpr::list(list))))
+}
+
+/// A date given as milliseconds since the epoch or as a list of components,
+/// like `date_to_list` makes.
+fn date_from_value(val: &RefVal, context: &str) -> Result<DateTime, RuntimeError> {
+    if let Value::Int(millis) = **val {
+        return Ok(DateTime::from_millis(millis));
+    }
+
+    let components: Option<Vec<i64>> = val
+        .as_quote()
+        .and_then(SExpr::as_list)
+        .map(|list| list.iter().map(|el| el.as_atom().and_then(Atom::as_int)).collect())
+        .unwrap_or_default();
+    let date = match components.as_deref() {
+        Some(&[year, month, day, hour, minute, second]) => {
+            let component = |n: i64| u32::try_from(n).unwrap_or(u32::MAX);
+            DateTime {
+                year,
+                month: component(month),
+                day: component(day),
+                hour: component(hour),
+                minute: component(minute),
+                second: component(second),
             }
         }
+        _ => return Err(mismatch("a date or milliseconds since the epoch", val, context)),
+    };
+    if !date.is_valid() {
+        return Err(format!("invalid date {} in {}", val, context).into());
+    }
+    Ok(date)
+}
+
+pub fn time_now_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if let Some(millis) = env.clock() {
+        return Ok(date_to_list(&DateTime::from_millis(millis as i64)));
+    }
+    if cfg!(target_arch = "wasm32") {
+        return Err("there is no clock in the browser".into());
+    }
+
+    let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
     };
+    Ok(date_to_list(&DateTime::from_millis(millis)))
+}
 
-    (pub fn $name:ident => $op:tt; $($tail:tt)*) => {
-        impl_bin_op! { @once pub fn $name => $op }
-        impl_bin_op! { $($tail)* }
+pub fn random_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let limit = env.pop_stack()?;
+    match *limit {
+        // The high bits of the product, which are evenly spread below `n`.
+        Value::Int(n) if n > 0 => Ok((((env.next_random() as u128 * n as u128) >> 64) as i64).into()),
+        Value::Float(x) if x > 0.0 && x.is_finite() => {
+            let unit = (env.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+            Ok((unit * x).into())
+        }
+        _ => Err(mismatch("a positive number", &limit, "'random'")),
+    }
+}
+
+pub fn random_seed_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let seed = env.pop_stack()?;
+    match *seed {
+        Value::Int(seed) => env.set_random_seed(seed as u64),
+        _ => return Err(mismatch("an int", &seed, "'random-seed'")),
+    }
+    Ok(RefVal::reference(nil_ref()))
+}
+
+pub fn time_format_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let format = env.pop_stack()?;
+    let date = date_from_value(&env.pop_stack()?, "'time-format'")?;
+    let format = format.as_string().ok_or_else(|| mismatch("a format string", &format, "'time-format'"))?;
+    let formatted = date.format(format).map_err(|err| format!("{} in 'time-format'", err))?;
+    Ok(formatted.into())
+}
+
+pub fn time_parse_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let format = env.pop_stack()?;
+    let text = env.pop_stack()?;
+    let text = text.as_string().ok_or_else(|| mismatch("a string", &text, "'time-parse'"))?;
+    let format = format.as_string().ok_or_else(|| mismatch("a format string", &format, "'time-parse'"))?;
+    match DateTime::parse(text, format).and_then(|date| date.to_millis()) {
+        Some(millis) => Ok(millis.into()),
+        None => Ok(false.into()),
+    }
+}
+
+/// How long `sleep` waits at a time before checking whether it was
+/// interrupted or ran out of time.
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+pub fn sleep_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let millis = env.pop_stack()?;
+    let millis = match *millis {
+        Value::Int(millis) if millis >= 0 => millis as u64,
+        _ => return Err(mismatch("a number of milliseconds", &millis, "'sleep'")),
     };
+    if cfg!(target_arch = "wasm32") {
+        return Err("there is no sleeping in the browser".into());
+    }
+
+    let until = Instant::now() + Duration::from_millis(millis);
+    loop {
+        env.check_interrupt()?;
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(RefVal::reference(nil_ref()));
+        }
+        std::thread::sleep(left.min(SLEEP_SLICE));
+    }
 }
 
-impl_bin_op! {
-    pub fn sub => -;
-    pub fn add => +;
-    pub fn mul => *;
-    pub fn div => /;
+/// Enters the debugger, see `repl::debug`, if standard input is a terminal
+/// to read commands from.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn break_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if !io::stdin().is_terminal() {
+        return Ok(RefVal::reference(nil_ref()));
+    }
+
+    // What the program printed so far should show before the prompt.
+    env.output().flush().map_err(output_error)?;
+    let debugger_error = |err: io::Error| RuntimeError::Custom(format!("the debugger failed: {}", err));
+    let mut terminal = repl::Terminal::new().map_err(debugger_error)?;
+    match repl::debug(env, &mut terminal, &mut io::stdout()).map_err(debugger_error)? {
+        Resume::Continue => Ok(RefVal::reference(nil_ref())),
+        Resume::Abort => Err("aborted from the debugger".into()),
+        Resume::Exit(status) => Err(RuntimeError::Exit(status)),
+    }
 }
 
-pub fn print_impl(env: &mut Environment) -> Result<RefVal, RuntimeError> {
-    print!("{}", env.pop_stack());
+/// There is no terminal in the browser.
+#[cfg(target_arch = "wasm32")]
+pub fn break_impl(_env: &mut Environment) -> Result<RefVal, RuntimeError> {
     Ok(RefVal::reference(nil_ref()))
 }
+
+pub(crate) fn output_error(err: io::Error) -> RuntimeError {
+    RuntimeError::Custom(format!("couldn't write the output: {}", err))
+}
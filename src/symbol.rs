@@ -0,0 +1,37 @@
+//! Interned identifiers, so that names are compared and hashed by pointer.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// An interned identifier. Symbols coming from the same `SymbolTable` share
+/// their allocation, so comparing them is usually a pointer comparison.
+pub type Symbol = Rc<str>;
+
+/// A set of interned symbols. Cloning the table is cheap and the clones share
+/// the same storage, so a `Reader` and an `Environment` can intern into one
+/// table.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable(Rc<RefCell<HashSet<Symbol>>>);
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, name: &str) -> Symbol {
+        let mut table = self.0.borrow_mut();
+        if let Some(sym) = table.get(name) {
+            return sym.clone();
+        }
+        let sym: Symbol = Rc::from(name);
+        table.insert(sym.clone());
+        sym
+    }
+}
+
+/// Compares two symbols, skipping the string comparison when both were
+/// interned in the same table.
+pub fn same_symbol(a: &Symbol, b: &Symbol) -> bool {
+    Rc::ptr_eq(a, b) || a == b
+}
@@ -0,0 +1,130 @@
+//! A stack machine running compiled chunks. Function calls go through the
+//! same path as in the tree-walking evaluator, so builtins evaluating quoted
+//! code (`eval`, or an `if` the compiler couldn't turn into jumps) still use
+//! the evaluator, while user defined functions run their compiled bodies.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::compiler::{ self, Chunk, Op };
+use crate::error::RuntimeError;
+use crate::evaluator::{ self, apply, nested, Environment };
+use crate::symbol::Symbol;
+
+/// Function bodies compiled so far, by the address of their expression.
+#[derive(Debug, Default)]
+pub struct Vm {
+    chunks: HashMap<*const SExpr, Compiled>,
+}
+
+#[derive(Debug)]
+struct Compiled {
+    // Keeps the address from being reused by another expression.
+    body: Rc<SExpr>,
+    arg_names: Vec<Symbol>,
+    chunk: Rc<Chunk>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::default()
+    }
+
+    fn chunk_for(&mut self, body: &Rc<SExpr>, arg_names: &[Symbol]) -> Rc<Chunk> {
+        let compiled = self.chunks.entry(Rc::as_ptr(body)).or_insert_with(|| Compiled {
+            body: body.clone(),
+            arg_names: arg_names.to_vec(),
+            chunk: Rc::new(compiler::compile_body(body, arg_names)),
+        });
+
+        // The same body may be shared by functions with different arguments.
+        if compiled.arg_names == arg_names {
+            debug_assert!(Rc::ptr_eq(&compiled.body, body));
+            compiled.chunk.clone()
+        } else {
+            Rc::new(compiler::compile_body(body, arg_names))
+        }
+    }
+}
+
+/// Compiles and runs a top-level expression.
+pub fn evaluate(expr: &SExpr, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    if env.is_instrumented() {
+        return evaluator::evaluate(expr, env);
+    }
+    execute(&compiler::compile(expr), env)
+}
+
+/// Runs the body of a user defined function whose arguments are bound in the
+/// innermost scope.
+pub fn run_body(
+    body: &Rc<SExpr>,
+    arg_names: &[Symbol],
+    env: &mut Environment,
+) -> Result<RefVal, RuntimeError> {
+    let chunk = match env.vm_mut() {
+        Some(vm) => vm.chunk_for(body, arg_names),
+        None => Rc::new(compiler::compile_body(body, arg_names)),
+    };
+    execute(&chunk, env)
+}
+
+pub fn execute(chunk: &Chunk, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    nested(env, |env| run(chunk, env))
+}
+
+fn run(chunk: &Chunk, env: &mut Environment) -> Result<RefVal, RuntimeError> {
+    let mut stack: Vec<RefVal> = Vec::new();
+    let mut pc = 0;
+
+    while let Some(op) = chunk.code.get(pc) {
+        pc += 1;
+
+        match op {
+            Op::Const(idx) => {
+                let val = match &*chunk.consts[*idx] {
+                    Value::Quote(quoted) if env.is_hash_consing() => RefVal::owned(Value::Quote(env.intern_quote(quoted))),
+                    _ => chunk.consts[*idx].clone(),
+                };
+                stack.push(val);
+            }
+
+            Op::Load { name, span, call } => {
+                let val = env
+                    .lookup_symbol(name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        env.unbound(name, call.map(|call| &chunk.exprs[call])).with_span(*span)
+                    })?;
+                stack.push(val);
+            }
+
+            Op::LoadArg(slot) => stack.push(env.scoped(*slot).clone()),
+
+            Op::Call { argc, expr } => {
+                let expr = &chunk.exprs[*expr];
+                let values = stack.split_off(stack.len() - argc - 1);
+                let val = apply(values, expr, env).map_err(|err| err.with_span(expr.span()))?;
+                stack.push(val);
+            }
+
+            Op::Jump(target) => pc = *target,
+
+            Op::JumpIfFalse(target) => {
+                let cond = stack.pop().expect("VM stack underflow");
+                if !cond.is_truthy() {
+                    pc = *target;
+                }
+            }
+
+            Op::JumpUnlessBuiltin { name, target } => {
+                if env.builtin(name).is_none() {
+                    pc = *target;
+                }
+            }
+        }
+    }
+
+    Ok(stack.pop().expect("chunk left no value"))
+}
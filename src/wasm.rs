@@ -0,0 +1,73 @@
+//! A JavaScript interface to the interpreter, for running yal in the
+//! browser. Built with the `wasm` feature, for `wasm32-unknown-unknown`.
+//!
+//! ```js
+//! const yal = new YalInterpreter();
+//! yal.setPrintCallback(text => console.log(text));
+//! yal.eval("(print (+ 1 2))");
+//! ```
+
+use std::io::{ self, Write };
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::Located;
+use crate::evaluator::{ evaluate_toplevel, Environment };
+use crate::printer::Written;
+
+#[wasm_bindgen]
+pub struct YalInterpreter {
+    env: Environment,
+}
+
+#[wasm_bindgen]
+impl YalInterpreter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<YalInterpreter, String> {
+        let mut env = Environment::with_std_lib().map_err(|err| err.to_string())?;
+        // There is no standard output to print to until a callback is set.
+        env.set_output(Box::new(io::sink()));
+        Ok(YalInterpreter { env })
+    }
+
+    /// Calls `callback` with the text of everything the program prints.
+    #[wasm_bindgen(js_name = setPrintCallback)]
+    pub fn set_print_callback(&mut self, callback: js_sys::Function) {
+        self.env.set_output(Box::new(Callback(callback)));
+    }
+
+    /// Evaluates every expression in `source`, in the bindings made by the
+    /// previous calls. Returns the last value as `write` prints it, or the
+    /// message of the error that stopped the evaluation.
+    pub fn eval(&mut self, source: &str) -> Result<String, String> {
+        let exprs = self.env.reader(source)
+            .parse_sexprs()
+            .map_err(|err| err.to_string())?;
+
+        let mut last = String::new();
+        for expr in exprs {
+            match evaluate_toplevel(&expr, &mut self.env) {
+                Ok(val) => last = Written(&*val).to_string(),
+                Err(err) => return Err(Located { file: None, src: source, error: &err }.to_string()),
+            }
+        }
+        Ok(last)
+    }
+}
+
+/// Hands what is written to a JavaScript function, as a string.
+struct Callback(js_sys::Function);
+
+impl Write for Callback {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0
+            .call1(&JsValue::NULL, &JsValue::from_str(&text))
+            .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! What `Environment::builtins` says about every builtin of the standard
+//! library.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn every_builtin_has_a_category_and_docs() {
+    let env = env();
+    assert!(env.builtins().count() > 0);
+    for spec in env.builtins() {
+        assert!(!spec.category.is_empty(), "'{}' has no category", spec.name);
+        assert_ne!(spec.category, "native", "'{}' is left in the embedders' category", spec.name);
+        assert!(spec.doc.is_some_and(|doc| !doc.is_empty()), "'{}' has no docs", spec.name);
+    }
+}
+
+#[test]
+fn every_builtin_is_bound_to_its_name() {
+    let env = env();
+    for spec in env.builtins() {
+        assert!(env.lookup_var(spec.name).is_some(), "'{}' isn't bound", spec.name);
+    }
+}
+
+#[test]
+fn pure_builtins_have_no_side_effects() {
+    let pure: Vec<_> = env().builtins().filter(|spec| spec.pure).map(|spec| (spec.name, spec.arity)).collect();
+    assert!(pure.iter().any(|&(name, _)| name == "+"));
+
+    for (name, arity) in pure {
+        for arg in ["1", "'(1 2)", "\"s\""] {
+            let src = format!("({} {})", name, vec![arg; arity].join(" "));
+            let mut env = env();
+            let names = env.bound_names().count();
+            let (first, printed) = env.capture_output(|env| env.eval_str(&src).map(|val| val.to_string()).map_err(|err| err.to_string()));
+            assert_eq!(printed, "", "{src} printed");
+            assert_eq!(env.bound_names().count(), names, "{src} bound a name");
+            // Nothing it did changes what it gives the next time.
+            let second = env.eval_str(&src).map(|val| val.to_string()).map_err(|err| err.to_string());
+            assert_eq!(first, second, "{src}");
+        }
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::path::{ Path, PathBuf };
+
+use common::*;
+use yal::cache;
+use yal::symbol::SymbolTable;
+use yal::Reader;
+
+const PROGRAM: &str = "\
+; Output, a runtime error and every kind of atom, to compare runs with.
+(let 'square (fn '(x) '(* x x)))
+(print (square 12))
+(print \" \")
+(print '(\"s\\n\" 2.5 -7 1/3 'q ()))
+(print \" \")
+(print (car (square 2)))
+";
+
+/// A directory of its own for each test, as the cache goes next to the
+/// program.
+fn program_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yal-cache-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("program.yal"), PROGRAM).unwrap();
+    dir
+}
+
+fn run(path: &Path, cached: bool) -> (i32, String, String) {
+    let path = path.to_str().unwrap();
+    if cached {
+        yal(&["--compile-cache", path])
+    } else {
+        yal(&[path])
+    }
+}
+
+#[test]
+fn a_cache_reads_back_what_was_parsed() {
+    let dir = program_dir("library");
+    let path = cache::cache_path(&dir.join("program.yal"));
+    let exprs = Reader::new(PROGRAM).parse_sexprs().map_err(|err| err.to_string()).unwrap();
+    cache::store(&path, PROGRAM, &exprs).unwrap();
+
+    let loaded = cache::load(&path, PROGRAM, &SymbolTable::default()).expect("the cache is fresh");
+    assert_eq!(loaded, exprs);
+    let spans = |exprs: &std::collections::VecDeque<yal::SExpr>| exprs.iter().map(|expr| expr.span()).collect::<Vec<_>>();
+    assert_eq!(spans(&loaded), spans(&exprs));
+
+    assert!(cache::load(&path, "(print 1)", &SymbolTable::default()).is_none(), "the cache is stale");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn corrupt_caches_are_ignored() {
+    let dir = program_dir("corrupt");
+    let path = cache::cache_path(&dir.join("program.yal"));
+    let exprs = Reader::new(PROGRAM).parse_sexprs().map_err(|err| err.to_string()).unwrap();
+    cache::store(&path, PROGRAM, &exprs).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    for i in 0..bytes.len() {
+        let mut flipped = bytes.clone();
+        flipped[i] ^= 0x5a;
+        std::fs::write(&path, &flipped).unwrap();
+        assert!(cache::load(&path, PROGRAM, &SymbolTable::default()).is_none(), "byte {} flipped", i);
+    }
+    for len in 0..bytes.len() {
+        std::fs::write(&path, &bytes[..len]).unwrap();
+        assert!(cache::load(&path, PROGRAM, &SymbolTable::default()).is_none(), "cut to {} bytes", len);
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_cached_program_runs_like_the_parsed_one() {
+    let dir = program_dir("cli");
+    let program = dir.join("program.yal");
+    let cache = cache::cache_path(&program);
+
+    let fresh = run(&program, false);
+    assert_eq!(fresh.0, 1, "the program ends with an error: {:?}", fresh);
+    assert!(!cache.exists());
+
+    assert_eq!(run(&program, true), fresh);
+    let written = std::fs::metadata(&cache).unwrap().modified().unwrap();
+    // A usable cache is read rather than written again.
+    assert_eq!(run(&program, true), fresh);
+    assert_eq!(std::fs::metadata(&cache).unwrap().modified().unwrap(), written);
+
+    std::fs::write(&cache, b"not a cache").unwrap();
+    assert_eq!(run(&program, true), fresh);
+    // And replaced by a good one.
+    assert_ne!(std::fs::read(&cache).unwrap(), b"not a cache");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,110 @@
+//! Channels, sent to and received from on one thread, across the threads
+//! of `pmap`, and waited on until something gives.
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+
+use common::*;
+use yal::RuntimeError;
+
+#[test]
+fn values_come_out_in_the_order_they_went_in() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    eval_in(&mut env, "(channel-send ch 1) (channel-send ch \"two\") (channel-send ch '(3 (4)))");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "1");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "two");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "(3 (4))");
+}
+
+#[test]
+fn trying_to_receive_doesnt_wait() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "f");
+    eval_in(&mut env, "(channel-send ch 5)");
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "5");
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "f");
+}
+
+#[test]
+fn a_closed_channel_gives_what_is_left_then_f() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel)) (channel-send ch 1) (channel-close ch)");
+    assert_eq!(eval_in(&mut env, "ch"), "#<channel closed>");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "1");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "f");
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "f");
+    assert!(eval_err_in(&mut env, "(channel-send ch 2)").starts_with("error: the channel is closed"));
+}
+
+#[test]
+fn receiving_from_an_empty_channel_no_one_else_has_gives_f() {
+    let start = Instant::now();
+    assert_eq!(eval("(channel-recv (make-channel))"), "f");
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel)) (channel-send ch 1)");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "1");
+    assert_eq!(eval_in(&mut env, "(channel-recv ch)"), "f");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn workers_stop_waiting_once_the_others_are_done() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    let src = "(pmap (fn '(x) '(if (= x 0) '(channel-send ch 'hi) '(channel-recv ch))) '(0 1 2))";
+    let received = eval_in(&mut env, src);
+    assert!(["(nil hi f)", "(nil f hi)"].contains(&received.as_str()), "{received}");
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "f");
+}
+
+#[test]
+fn only_data_goes_through() {
+    let err = eval_err("(channel-send (make-channel) (fn '(x) 'x))");
+    assert!(err.starts_with("error: expected data in 'channel-send'"), "{err}");
+}
+
+#[test]
+fn pmap_workers_send_to_the_caller() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    eval_in(&mut env, "(pmap (fn '(x) '(channel-send ch (* x 10))) '(1 2 3))");
+    let mut received: Vec<String> = (0..3).map(|_| eval_in(&mut env, "(channel-recv ch)")).collect();
+    received.sort();
+    assert_eq!(received, ["10", "20", "30"]);
+    assert_eq!(eval_in(&mut env, "(channel-try-recv ch)"), "f");
+}
+
+/// Receives from `ch` while another worker, if there is one, holds it open.
+/// With a single worker the sleep is what gets stopped instead.
+const WAIT: &str = "(pmap (fn '(x) '(if (= x 0) '(sleep 10000) '(channel-recv ch))) '(0 1))";
+
+#[test]
+fn waiting_can_be_interrupted() {
+    let mut env = env();
+    let flag = Arc::new(AtomicBool::new(false));
+    env.set_interrupt_flag(flag.clone());
+    let raise = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        flag.store(true, Ordering::Relaxed);
+    });
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    let start = Instant::now();
+    assert_eq!(root_error_in(&mut env, WAIT), RuntimeError::Interrupted);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    raise.join().unwrap();
+}
+
+#[test]
+fn waiting_runs_out_of_time() {
+    let mut env = env();
+    env.set_timeout(Duration::from_millis(100));
+    eval_in(&mut env, "(let 'ch (make-channel))");
+    let start = Instant::now();
+    assert_eq!(root_error_in(&mut env, WAIT), RuntimeError::Timeout(Duration::from_millis(100)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,163 @@
+//! What the integration tests share: running yal code in a fresh
+//! environment with the standard library, and looking at what came out.
+
+#![allow(dead_code)]
+
+use std::rc::Rc;
+
+use yal::{ Environment, EvalError, RuntimeError, SExpr, Value };
+use yal::repl::{ Line, LineSource };
+
+/// An environment with the standard library, printing to nowhere.
+pub fn env() -> Environment {
+    let mut env = Environment::with_std_lib().expect("the standard library registers");
+    env.set_output(Box::new(std::io::sink()));
+    env
+}
+
+/// The value of the last form of `src`, as `print` shows it, failing the
+/// test if evaluating it fails.
+pub fn eval(src: &str) -> String {
+    eval_in(&mut env(), src)
+}
+
+pub fn eval_in(env: &mut Environment, src: &str) -> String {
+    match env.eval_str(src) {
+        Ok(val) => val.to_string(),
+        Err(err) => panic!("evaluating {:?} failed:\n{}", src, err),
+    }
+}
+
+/// The report of the error evaluating `src` fails with, failing the test if
+/// it doesn't.
+pub fn eval_err(src: &str) -> String {
+    eval_err_in(&mut env(), src)
+}
+
+pub fn eval_err_in(env: &mut Environment, src: &str) -> String {
+    match env.eval_str(src) {
+        Ok(val) => panic!("evaluating {:?} gave {} instead of failing", src, val),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// The error evaluating `src` fails with, without where it happened.
+pub fn root_error(src: &str) -> RuntimeError {
+    root_error_in(&mut env(), src)
+}
+
+pub fn root_error_in(env: &mut Environment, src: &str) -> RuntimeError {
+    match env.eval_str(src) {
+        Err(EvalError::Runtime { error, .. }) => error.root().clone(),
+        Err(err) => panic!("evaluating {:?} failed to parse: {}", src, err),
+        Ok(val) => panic!("evaluating {:?} gave {} instead of failing", src, val),
+    }
+}
+
+/// The expression the quote `src` evaluates to in `env`.
+pub fn quoted(env: &mut Environment, src: &str) -> Rc<SExpr> {
+    match &*env.eval_str(src).unwrap() {
+        Value::Quote(quoted) => quoted.clone(),
+        other => panic!("{:?} gave {} instead of a quote", src, other),
+    }
+}
+
+/// What evaluating `src` printed, failing the test if evaluating it fails.
+pub fn output(src: &str) -> String {
+    let (result, printed) = env().capture_output(|env| env.eval_str(src).map(|_| ()));
+    if let Err(err) = result {
+        panic!("evaluating {:?} failed:\n{}", src, err);
+    }
+    printed
+}
+
+/// What the `yal` binary run with `args` exits with, and prints to standard
+/// output and to standard error.
+pub fn yal(args: &[&str]) -> (i32, String, String) {
+    yal_with_stdin(args, "")
+}
+
+/// Like `yal`, with `stdin` as its standard input.
+pub fn yal_with_stdin(args: &[&str], stdin: &str) -> (i32, String, String) {
+    use std::io::Write;
+    use std::process::{ Command, Stdio };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_yal"))
+        .arg("--no-prelude")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the yal binary runs");
+    // Dropping standard input closes it.
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let out = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+    (out.status.code().unwrap_or(-1), stdout, stderr)
+}
+
+/// Lines typed into the REPL, one after the other.
+pub struct Typed(pub Vec<&'static str>);
+
+impl LineSource for Typed {
+    fn read_line(&mut self, _: &str, _: &Environment) -> std::io::Result<Line> {
+        if self.0.is_empty() {
+            return Ok(Line::Eof);
+        }
+        Ok(Line::Text(self.0.remove(0).to_string()))
+    }
+}
+
+/// Types `lines` into a REPL running in `env`, giving the status it ends
+/// with and everything it wrote.
+pub fn repl(env: &mut Environment, lines: &[&'static str]) -> (i32, String) {
+    let mut output = Vec::new();
+    let status = yal::repl::run(env, &mut Typed(lines.to_vec()), &mut output).unwrap();
+    (status, String::from_utf8(output).unwrap())
+}
+
+/// The stack `evaluator::DEFAULT_MAX_DEPTH` is sized for: a main thread's,
+/// or four times that in unoptimized builds.
+pub const DEFAULT_DEPTH_STACK: usize = if cfg!(debug_assertions) { 32 << 20 } else { 8 << 20 };
+
+/// Runs `f` on a thread with a stack of `size` bytes, as deep recursion
+/// takes more than the test threads have.
+pub fn with_stack<R: Send + 'static>(size: usize, f: impl FnOnce() -> R + Send + 'static) -> R {
+    std::thread::Builder::new().stack_size(size).spawn(f).unwrap().join().unwrap()
+}
+
+/// The `; expect: <line>:<col>: <message>` comments of the fixture at
+/// `path`, which are what running it should report.
+pub fn expected_diagnostics(path: &std::path::Path) -> Vec<String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    src.lines().filter_map(|line| line.strip_prefix("; expect: ")).map(str::to_string).collect()
+}
+
+/// The fixtures in `tests/fixtures/<dir>`, in order.
+pub fn fixtures(dir: &str) -> Vec<std::path::PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(format!("tests/fixtures/{}", dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yal"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// A xorshift generator, so that failures can be replayed.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
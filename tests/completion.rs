@@ -0,0 +1,74 @@
+//! What the REPL offers to complete, depending on where the cursor is.
+
+mod common;
+
+use common::*;
+use yal::repl::{ complete, Candidate };
+
+/// What completing `line` at its end offers, by what they'd be shown as.
+fn offered(env: &yal::Environment, line: &str) -> Vec<String> {
+    complete(line, line.len(), env).into_iter().map(|candidate| candidate.display).collect()
+}
+
+fn env_with_definitions() -> yal::Environment {
+    let mut env = env();
+    eval_in(&mut env, "(let 'prefix-value 1) (let 'prefix-fn (fn '(x) 'x))");
+    eval_in(&mut env, "(let 'greet (fn '(name &key (greeting \"hi\") (punct \"!\")) 'name))");
+    env
+}
+
+#[test]
+fn after_a_paren_only_functions_are_offered() {
+    let env = env_with_definitions();
+    assert_eq!(offered(&env, "(prefix-"), ["prefix-fn"]);
+    assert_eq!(offered(&env, "(print prefix-"), ["prefix-fn", "prefix-value"]);
+    assert!(offered(&env, "(").iter().all(|name| name != "prefix-value" && name != "nil"));
+    assert!(offered(&env, "(").contains(&"print".to_string()));
+}
+
+#[test]
+fn a_unique_name_is_completed_with_a_space() {
+    let env = env_with_definitions();
+    let line = "(prefix-f";
+    assert_eq!(complete(line, line.len(), &env), [Candidate {
+        start: 1,
+        display: "prefix-fn".to_string(),
+        replacement: "prefix-fn ".to_string(),
+    }]);
+}
+
+#[test]
+fn a_load_string_offers_paths() {
+    let env = env();
+    assert_eq!(offered(&env, "(load \"tests/fixtures/ex"), ["exit/"]);
+    assert_eq!(offered(&env, "(include \"tests/fixtures/exit/su"), ["success.yal"]);
+    let line = "(load \"tests/fixtures/exit/su";
+    assert_eq!(complete(line, line.len(), &env)[0].replacement, "success.yal\"");
+    assert_eq!(complete(line, line.len(), &env)[0].start, "(load \"tests/fixtures/exit/".len());
+}
+
+#[test]
+fn strings_and_comments_offer_nothing() {
+    let env = env_with_definitions();
+    assert!(offered(&env, "(print \"prefix-").is_empty());
+    assert!(offered(&env, "(print 1) ; prefix-").is_empty());
+    assert!(offered(&env, "(print '(\"tests/").is_empty());
+}
+
+#[test]
+fn keyword_arguments_not_yet_passed_are_offered() {
+    let env = env_with_definitions();
+    assert_eq!(offered(&env, "(greet \"me\" :"), [":greeting", ":punct"]);
+    assert_eq!(offered(&env, "(greet \"me\" :greeting \"yo\" :"), [":punct"]);
+    // Then come the names, and after a keyword only the names.
+    let offered_after_args = offered(&env, "(greet \"me\" ");
+    assert_eq!(&offered_after_args[..2], [":greeting", ":punct"]);
+    assert!(offered_after_args.contains(&"prefix-value".to_string()));
+    assert!(!offered(&env, "(greet \"me\" :punct ").iter().any(|name| name.starts_with(':')));
+}
+
+#[test]
+fn quoted_lists_are_not_calls() {
+    let env = env_with_definitions();
+    assert_eq!(offered(&env, "'(prefix-"), ["prefix-fn", "prefix-value"]);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use common::*;
+use yal::evaluator::HookPhase;
+
+#[test]
+fn comptime_forms_are_folded_by_eval_str() {
+    assert_eq!(eval("(comptime (* 6 7))"), "42");
+    assert_eq!(eval("(let 'f (fn '() '(comptime (+ 1 2)))) (f)"), "3");
+}
+
+#[test]
+fn impure_builtins_are_refused_in_comptime() {
+    let (result, printed) = env().capture_output(|env| env.eval_str("(comptime (print 1))").map(|_| ()));
+    let err = result.expect_err("print isn't pure").to_string();
+    assert!(err.contains("'print' can't be used in 'comptime'"), "{}", err);
+    assert_eq!(printed, "");
+}
+
+#[test]
+fn comptime_errors_abort_before_anything_runs() {
+    let (result, printed) = env().capture_output(|env| env.eval_str("(print 1) (comptime (car 1))").map(|_| ()));
+    let err = result.expect_err("'car' of an int fails").to_string();
+    assert!(err.contains("1:21"), "{}", err);
+    assert_eq!(printed, "");
+}
+
+#[test]
+fn folded_forms_are_not_evaluated_again() {
+    let mut env = env();
+    let seen = Rc::new(Cell::new(0));
+    let counter = seen.clone();
+    env.set_eval_hook(Box::new(move |expr, phase| {
+        if phase == HookPhase::Before && expr.to_string() == "(* 6 7)" {
+            counter.set(counter.get() + 1);
+        }
+        Ok(())
+    }));
+    assert_eq!(eval_in(&mut env, "(+ (comptime (* 6 7)) 1)"), "43");
+    assert_eq!(seen.get(), 0);
+
+    // The hook does see the same expression outside 'comptime'.
+    assert_eq!(eval_in(&mut env, "(+ (* 6 7) 1)"), "43");
+    assert_eq!(seen.get(), 1);
+}
@@ -0,0 +1,50 @@
+//! Globals bound with `defconst`, which can't be bound again but can still
+//! be shadowed by parameters.
+
+mod common;
+
+use common::*;
+use yal::{ EvalError, RuntimeError };
+
+#[test]
+fn constants_are_bound_like_globals() {
+    assert_eq!(eval("(defconst 'pi 3)"), "3");
+    assert_eq!(eval("(defconst 'pi 3) (+ pi 1)"), "4");
+}
+
+#[test]
+fn binding_a_constant_again_fails() {
+    for rebind in ["(let 'pi 4)", "(defconst 'pi 4)", "((fn '() '(let 'pi 5)))"] {
+        let mut env = env();
+        eval_in(&mut env, "(defconst 'pi 3)");
+        let err = env.eval_str(rebind).unwrap_err();
+        assert!(err.to_string().starts_with("error: cannot redefine constant 'pi'\n"), "{err}");
+        let EvalError::Runtime { error, .. } = err else { panic!("{rebind} failed to parse") };
+        assert!(matches!(error.root(), RuntimeError::ConstantRedefined { name, .. } if name == "pi"));
+        assert_eq!(eval_in(&mut env, "pi"), "3");
+    }
+}
+
+#[test]
+fn errors_point_at_the_definition() {
+    let path = "tests/fixtures/runtime/constant.yal";
+    let (status, _, stderr) = yal(&[path]);
+    assert_eq!(status, 1);
+    assert!(stderr.contains(&format!("= note: 'pi' was defined at {path}:4:12\n")), "{stderr}");
+}
+
+#[test]
+fn parameters_may_shadow_constants() {
+    let mut env = env();
+    eval_in(&mut env, "(defconst 'pi 3) (let 'f (fn '(pi) '(+ pi 1)))");
+    assert_eq!(eval_in(&mut env, "(f 10)"), "11");
+    assert_eq!(eval_in(&mut env, "pi"), "3");
+}
+
+#[test]
+fn binding_a_shadowed_constant_still_fails() {
+    let mut env = env();
+    eval_in(&mut env, "(defconst 'pi 3) (let 'f (fn '(pi) '(let 'pi 5)))");
+    assert!(eval_err_in(&mut env, "(f 1)").starts_with("error: cannot redefine constant 'pi'"));
+    assert_eq!(eval_in(&mut env, "pi"), "3");
+}
@@ -0,0 +1,38 @@
+//! Converting between values and Rust types, as code embedding the
+//! interpreter does.
+
+mod common;
+
+use common::*;
+use yal::{ RefVal, RuntimeError };
+
+#[test]
+fn nested_vecs_round_trip_through_quoted_lists() {
+    let rows = vec![vec![1.5, 2.0], vec![], vec![-3.25]];
+    let val = RefVal::from(rows.clone());
+    assert_eq!(val.to_string(), "((1.5 2.0) () (-3.25))");
+    assert_eq!(Vec::<Vec<f64>>::try_from(val).unwrap(), rows);
+
+    // And through yal code, which can hand back ints for floats.
+    let mut env = env();
+    let val = env.eval_str("(let 'rows '((1 2.0) () (1/4))) rows").unwrap();
+    assert_eq!(Vec::<Vec<f64>>::try_from(val).unwrap(), vec![vec![1.0, 2.0], vec![], vec![0.25]]);
+}
+
+#[test]
+fn scalars_round_trip() {
+    assert_eq!(i64::try_from(RefVal::from(-7i64)).unwrap(), -7);
+    assert_eq!(String::try_from(RefVal::from("hi")).unwrap(), "hi");
+    assert!(bool::try_from(RefVal::from(true)).unwrap());
+    assert_eq!(Vec::<bool>::try_from(env().eval_str("'(t f)").unwrap()).unwrap(), [true, false]);
+}
+
+#[test]
+fn mismatches_say_what_was_expected_and_found() {
+    let err = i64::try_from(RefVal::from(1.5)).unwrap_err();
+    assert!(matches!(err, RuntimeError::TypeMismatch { .. }), "{err}");
+    assert_eq!(err.to_string(), "expected an int in the conversion to i64, got float `1.5`");
+
+    let err = Vec::<Vec<f64>>::try_from(env().eval_str("'((1) (a))").unwrap()).unwrap_err().to_string();
+    assert_eq!(err, "expected a number in the conversion to f64, got quote `'a`");
+}
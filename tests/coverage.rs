@@ -0,0 +1,46 @@
+mod common;
+
+use common::*;
+
+/// The counts `--coverage` reports for each file run by `path`, in order,
+/// as the file's name and its lines with their counts.
+fn coverage(path: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let (status, _, stderr) = yal(&["--coverage", path]);
+    assert_eq!(status, 0, "{stderr}");
+    let mut files: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for line in stderr.lines() {
+        if let Some(name) = line.strip_prefix("coverage of ").and_then(|rest| rest.strip_suffix(':')) {
+            files.push((name.to_string(), Vec::new()));
+            continue;
+        }
+        let (count, text) = line.split_once(" | ").unwrap_or_else(|| (line.trim_end_matches(" |"), ""));
+        files.last_mut().unwrap().1.push((count.trim().to_string(), text.to_string()));
+    }
+    files
+}
+
+#[test]
+fn a_branch_never_taken_shows_as_not_run() {
+    let files = coverage("tests/fixtures/coverage/branch.yal");
+    let (name, lines) = &files[0];
+    assert_eq!(name, "tests/fixtures/coverage/branch.yal");
+    let counts: Vec<&str> = lines.iter().map(|(count, _)| count.as_str()).collect();
+    assert_eq!(counts, ["-", "1", "2", "2", "#####", "-", "1", "1", "1", "1"]);
+    assert_eq!(lines[4].1, "     '(car n))))");
+}
+
+#[test]
+fn loaded_files_are_reported_too() {
+    let files = coverage("tests/fixtures/coverage/branch.yal");
+    assert_eq!(files.len(), 2);
+    let (name, lines) = &files[1];
+    assert!(name.ends_with("tests/fixtures/coverage/helper.yal"), "{name}");
+    let counts: Vec<&str> = lines.iter().map(|(count, _)| count.as_str()).collect();
+    assert_eq!(counts, ["1", "1", "#####"]);
+}
+
+#[test]
+fn coverage_doesnt_change_what_the_program_prints() {
+    let path = "tests/fixtures/coverage/branch.yal";
+    assert_eq!(yal(&["--coverage", path]).1, yal(&[path]).1);
+}
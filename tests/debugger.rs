@@ -0,0 +1,82 @@
+//! The debugger `break` enters, driven by a script of commands instead of a
+//! terminal.
+
+mod common;
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use common::*;
+use yal::repl::{ self, Resume, Script };
+use yal::{ BuiltinSpec, RefVal, RuntimeError };
+
+/// Makes `break` in `env` run the debugger on `commands`, as it does on a
+/// terminal, giving what the debugger wrote.
+fn break_with(env: &mut yal::Environment, commands: &'static str) -> Rc<RefCell<Vec<u8>>> {
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let output = written.clone();
+    let input = RefCell::new(Script(Cursor::new(commands)));
+    env.register_builtin(BuiltinSpec::new("break", 0), move |env| {
+        let resume = repl::debug(env, &mut *input.borrow_mut(), &mut *output.borrow_mut());
+        match resume.map_err(|err| RuntimeError::Custom(err.to_string()))? {
+            Resume::Continue => Ok(RefVal::from(0i64)),
+            Resume::Abort => Err("aborted from the debugger".into()),
+            Resume::Exit(status) => Err(RuntimeError::Exit(status)),
+        }
+    });
+    written
+}
+
+fn text(written: &Rc<RefCell<Vec<u8>>>) -> String {
+    String::from_utf8(written.borrow().clone()).unwrap()
+}
+
+#[test]
+fn break_inspect_and_continue() {
+    let mut env = env();
+    let written = break_with(&mut env, "(* n 10)\n:type n\n:continue\n");
+    eval_in(&mut env, "(let 'f (fn '(n) '(+ n (break))))");
+    assert_eq!(eval_in(&mut env, "(f 4)"), "4");
+
+    let written = text(&written);
+    assert!(written.starts_with("stopped at a breakpoint, :continue to resume\n"), "{written}");
+    assert!(written.contains("40\n"), "{written}");
+    assert!(written.contains("int\n"), "{written}");
+    // The arguments are gone once the call is over.
+    assert!(eval_err_in(&mut env, "n").starts_with("error: name 'n' was not defined"));
+}
+
+#[test]
+fn changes_made_in_the_debugger_stay() {
+    let mut env = env();
+    break_with(&mut env, "(let 'seen 'yes)\n:continue\n");
+    eval_in(&mut env, "(break)");
+    assert_eq!(eval_in(&mut env, "seen"), "yes");
+}
+
+#[test]
+fn abort_stops_the_program() {
+    let mut env = env();
+    let (result, printed) = env.capture_output(|env| {
+        break_with(env, ":abort\n");
+        env.eval_str("(print 1) (break) (print 2)").map(|_| ())
+    });
+    let err = result.unwrap_err().to_string();
+    assert!(err.starts_with("error: aborted from the debugger"), "{err}");
+    assert_eq!(printed, "1");
+}
+
+#[test]
+fn the_end_of_the_commands_continues() {
+    let mut env = env();
+    let written = break_with(&mut env, ":bogus\n");
+    assert_eq!(eval_in(&mut env, "(+ 1 (break))"), "1");
+    assert!(text(&written).contains("unknown command ':bogus', the commands are:\n  :continue"), "{}", text(&written));
+}
+
+#[test]
+fn without_a_terminal_break_does_nothing() {
+    let (status, stdout, stderr) = yal_with_stdin(&["-e", "(print (break))"], ":abort\n");
+    assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "nil", ""));
+}
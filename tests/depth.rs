@@ -0,0 +1,60 @@
+mod common;
+
+use common::*;
+use yal::evaluator::DEFAULT_MAX_DEPTH;
+
+const COUNTER: &str = "(let 'cnt (fn '(n) '(if (= n 0) '0 '(+ 1 (cnt (- n 1))))))";
+
+#[test]
+fn the_cli_runs_a_recursive_counter_3000_calls_deep() {
+    let src = format!("{COUNTER} (print (cnt 3000))");
+    let (status, stdout, stderr) = yal(&["-e", &src]);
+    assert_eq!((status, stdout.trim()), (0, "3000"), "{stderr}");
+}
+
+#[test]
+fn the_cli_evaluates_an_expression_nested_50000_levels_deep() {
+    let n = 50_000;
+    let src = format!("{}0{}", "(+ 1 ".repeat(n), ")".repeat(n));
+    let (status, stdout, stderr) = yal_with_stdin(&["-p", "-"], &src);
+    assert_eq!((status, stdout.trim()), (0, "50000"), "{stderr}");
+}
+
+#[test]
+fn the_cli_takes_the_max_depth_from_the_command_line() {
+    let src = format!("{COUNTER} (print (cnt 100))");
+    let (status, _, stderr) = yal(&["--max-depth", "50", "-e", &src]);
+    assert_eq!(status, 1);
+    assert!(stderr.contains("maximum evaluation depth of 50 exceeded"), "{stderr}");
+
+    let (status, stdout, stderr) = yal(&["--max-depth", "500", "-e", &src]);
+    assert_eq!((status, stdout.trim()), (0, "100"), "{stderr}");
+}
+
+#[test]
+fn the_cli_rejects_a_max_depth_that_is_not_a_number() {
+    let (status, _, stderr) = yal(&["--max-depth", "deep", "-e", "1"]);
+    assert_eq!(status, 2);
+    assert!(stderr.contains("--max-depth expects a number"), "{stderr}");
+}
+
+#[test]
+fn the_default_depth_fits_the_stack_it_is_sized_for() {
+    let err = with_stack(DEFAULT_DEPTH_STACK, || {
+        eval_err(&format!("{COUNTER} (cnt {})", DEFAULT_MAX_DEPTH))
+    });
+    assert!(err.contains(&format!("maximum evaluation depth of {} exceeded", DEFAULT_MAX_DEPTH)), "{err}");
+
+    let depth = with_stack(DEFAULT_DEPTH_STACK, || eval(&format!("{COUNTER} (cnt {})", DEFAULT_MAX_DEPTH / 2 - 10)));
+    assert_eq!(depth, (DEFAULT_MAX_DEPTH / 2 - 10).to_string());
+}
+
+#[test]
+fn the_max_depth_can_be_raised_on_a_bigger_stack() {
+    let depth = with_stack(256 << 20, || {
+        let mut env = env();
+        env.set_max_depth(20_000);
+        eval_in(&mut env, &format!("{COUNTER} (cnt 5000)"))
+    });
+    assert_eq!(depth, "5000");
+}
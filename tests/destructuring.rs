@@ -0,0 +1,45 @@
+//! List patterns in the place of a name, in `let` and in the arguments of
+//! `fn` and `define-multi`, binding the pieces of the value they get.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn let_binds_the_pieces_of_a_list() {
+    let mut env = env();
+    eval_in(&mut env, "(let '(a (b c) . rest) '(1 (2 3) 4 5))");
+    assert_eq!(eval_in(&mut env, "(cons a (cons b (cons c (cons rest '()))))"), "(1 2 3 (4 5))");
+}
+
+#[test]
+fn function_arguments_bind_the_pieces_of_lists() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'dist (fn '((x1 y1) (x2 y2)) '(+ (- x2 x1) (- y2 y1))))");
+    assert_eq!(eval_in(&mut env, "(dist '(1 2) '(4 6))"), "7");
+    assert!(env.lookup_var("x1").is_none());
+}
+
+#[test]
+fn patterns_compose_with_the_rest_of_the_arguments() {
+    let mut env = env();
+    eval_in(&mut env, "(define-multi 'f '((((x . tail) & more) (cons x (cons tail (cons more '()))))))");
+    assert_eq!(eval_in(&mut env, "(f '(1 2 3) 4 5)"), "(1 (2 3) (4 5))");
+}
+
+#[test]
+fn a_mismatch_shows_the_shape_and_the_value() {
+    let err = eval_err("(let '(a b) '(1))");
+    assert!(err.starts_with("error: expected a value shaped like `(a b)` in 'let', got quote `'(1)`"), "{err}");
+
+    let mut env = env();
+    eval_in(&mut env, "(let 'f (fn '((x y)) '(+ x y)))");
+    let err = eval_err_in(&mut env, "(f 1)");
+    assert!(err.starts_with("error: expected a value shaped like `(x y)` in the arguments, got int `1`"), "{err}");
+}
+
+#[test]
+fn a_name_bound_twice_is_an_error() {
+    let err = eval_err("(fn '((x y) (x z)) 'x)");
+    assert!(err.starts_with("error: 'x' is bound more than once"), "{err}");
+}
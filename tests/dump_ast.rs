@@ -0,0 +1,34 @@
+//! `--dump-ast`, whose output is compared with the `.ast` file next to each
+//! fixture in `tests/fixtures/ast`.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn the_trees_of_the_fixtures_are_as_expected() {
+    for path in fixtures("ast") {
+        let expected = std::fs::read_to_string(path.with_extension("ast")).unwrap();
+        let src = std::fs::read_to_string(&path).unwrap();
+        let name = path.display();
+
+        assert_eq!(yal(&["--dump-ast", path.to_str().unwrap()]), (0, expected.clone(), String::new()), "{name}");
+        assert_eq!(yal(&["--dump-ast", "-e", &src]), (0, expected.clone(), String::new()), "{name} with -e");
+        assert_eq!(yal_with_stdin(&["--dump-ast", "-"], &src), (0, expected, String::new()), "{name} from stdin");
+    }
+}
+
+#[test]
+fn nothing_is_evaluated() {
+    let (status, stdout, _) = yal(&["--dump-ast", "-e", "(print 1) (exit 3)"]);
+    assert_eq!(status, 0);
+    assert!(stdout.starts_with("List @0..9\n"), "{stdout}");
+    assert!(stdout.contains("Ident exit"), "{stdout}");
+}
+
+#[test]
+fn parse_errors_are_reported() {
+    let (status, stdout, stderr) = yal(&["--dump-ast", "-e", "(+ 1"]);
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.contains("expected a closing paren"), "{stderr}");
+}
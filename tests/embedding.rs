@@ -0,0 +1,103 @@
+mod common;
+
+use common::*;
+use yal::{ RefVal, RuntimeError };
+
+#[test]
+fn call_typed_calls_a_script_function_with_floats() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'area (fn '(w h) '(* w h)))");
+    let area: f64 = env.call_typed("area", [2.0, 3.5]).unwrap();
+    assert_eq!(area, 7.0);
+}
+
+#[test]
+fn call_by_name_passes_values_as_they_are() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'pair (fn '(a b) '(cons a (cons b '()))))");
+    let args = [RefVal::from(1i64), RefVal::from("two")];
+    assert_eq!(env.call_by_name("pair", &args).unwrap().to_string(), "(1 two)");
+}
+
+#[test]
+fn calling_an_unknown_name_is_an_unbound_variable() {
+    let err = env().call_by_name("nowhere", &[]).unwrap_err();
+    assert!(matches!(err, RuntimeError::UnboundVariable { ref name, .. } if name == "nowhere"), "{err}");
+}
+
+#[test]
+fn calling_a_value_that_isnt_a_function_says_how_it_was_called() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 1)");
+    let err = env.call_by_name("x", &[]).unwrap_err();
+    assert_eq!(err, RuntimeError::NotAFunction { value: "1".to_string(), call: "(x)".to_string() });
+
+    let err = env.call_by_name("x", &[RefVal::from(2i64), RefVal::from("s")]).unwrap_err();
+    assert_eq!(err, RuntimeError::NotAFunction { value: "1".to_string(), call: "(x 2 \"s\")".to_string() });
+}
+
+#[test]
+fn calling_with_the_wrong_number_of_arguments_is_an_arity_mismatch() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'area (fn '(w h) '(* w h)))");
+    let err = env.call_typed::<f64, f64>("area", [1.0]).unwrap_err();
+    assert!(matches!(err, RuntimeError::ArityMismatch { got: 1, .. }), "{err}");
+}
+
+#[test]
+fn calling_a_native_with_the_wrong_number_of_arguments_is_an_arity_mismatch() {
+    let mut env = env();
+    env.register_builtin(yal::BuiltinSpec::new("double", 1), |env| {
+        let n = env.pop_stack()?;
+        match *n {
+            yal::Value::Int(n) => Ok(RefVal::from(n * 2)),
+            _ => Err("expected an int".into()),
+        }
+    });
+    assert_eq!(eval_in(&mut env, "(double 21)"), "42");
+
+    for src in ["(double)", "(double 1 2)"] {
+        let err = eval_err_in(&mut env, src);
+        assert!(err.starts_with("error: expected 1 argument"), "{src}: {err}");
+        assert!(err.contains("'double'"), "{src}: {err}");
+    }
+    let err = env.call_by_name("double", &[]).unwrap_err();
+    assert!(matches!(err, RuntimeError::ArityMismatch { got: 0, .. }), "{err}");
+}
+
+#[test]
+fn a_native_taking_more_than_its_arity_fails_cleanly() {
+    let mut env = env();
+    // Registered as taking one argument, but takes two.
+    env.register_builtin(yal::BuiltinSpec::new("mis-declared", 1), |env| {
+        let rhs = env.pop_stack()?;
+        let lhs = env.pop_stack()?;
+        Ok(RefVal::from(format!("{} {}", lhs, rhs).as_str()))
+    });
+    let err = eval_err_in(&mut env, "(mis-declared 1)");
+    assert!(err.starts_with("error: lib function 'mis-declared'"), "{err}");
+    assert!(err.contains("tried to take more arguments than it was given"), "{err}");
+}
+
+#[test]
+fn a_closure_keeps_the_state_it_captured() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut env = env();
+    let counter = Rc::new(RefCell::new(0i32));
+    let captured = counter.clone();
+    env.register_external_fun("tick", 0, move |_| {
+        *captured.borrow_mut() += 1;
+        Ok(RefVal::from(*captured.borrow() as i64))
+    });
+    eval_in(&mut env, "(loop '((i 0)) '(if (= i 9) 'i '(recur (tick))))");
+    assert_eq!(eval_in(&mut env, "(tick)"), "10");
+    assert_eq!(*counter.borrow(), 10);
+
+    assert_eq!(eval_in(&mut env, "tick"), "lib function 'tick' with 0 arguments");
+    assert_eq!(eval_in(&mut env, "(eq tick tick)"), "t");
+    eval_in(&mut env, "(let 'old-tick tick)");
+    env.register_external_fun("tick", 0, |_| Ok(RefVal::from(0i64)));
+    assert_eq!(eval_in(&mut env, "(eq tick old-tick)"), "f");
+}
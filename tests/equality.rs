@@ -0,0 +1,78 @@
+mod common;
+
+use common::*;
+use yal::std_lib::structural_hash;
+
+const NAN: &str = "(/ 0.0 0.0)";
+
+#[test]
+fn eq_follows_ieee() {
+    assert_eq!(eval(&format!("(= {NAN} {NAN})")), "f");
+    assert_eq!(eval(&format!("(eq {NAN} {NAN})")), "f");
+    assert_eq!(eval("(= 0.0 -0.0)"), "t");
+    assert_eq!(eval("(= (/ 1.0 0.0) (/ 1.0 0.0))"), "t");
+    assert_eq!(eval(&format!("(= (cons {NAN} '()) (cons {NAN} '()))")), "f");
+}
+
+#[test]
+fn equal_treats_nan_as_itself() {
+    assert_eq!(eval(&format!("(equal? {NAN} {NAN})")), "t");
+    assert_eq!(eval(&format!("(equal? (cons {NAN} '()) (cons {NAN} '()))")), "t");
+    assert_eq!(eval(&format!("(equal? {NAN} 1.0)")), "f");
+    assert_eq!(eval("(equal? 0.0 -0.0)"), "t");
+}
+
+#[test]
+fn numbers_compare_by_value_across_types() {
+    assert_eq!(eval("(= 1 1.0)"), "t");
+    assert_eq!(eval("(equal? 1 1.0)"), "t");
+    assert_eq!(eval("(= 1 2)"), "f");
+}
+
+#[test]
+fn lists_compare_by_structure() {
+    assert_eq!(eval("(= '(1 (2 x)) '(1 (2 x)))"), "t");
+    assert_eq!(eval("(equal? '(1 (2 x)) '(1 (2 y)))"), "f");
+}
+
+#[test]
+fn hashes_are_stable_across_clones() {
+    let mut env = env();
+    let list = quoted(&mut env, "'(1 (2.5 \"three\") four (5 (6)))");
+    let hash = structural_hash(&list);
+    assert_eq!(structural_hash(&list), hash);
+    assert_eq!(structural_hash(&(*list).clone()), hash);
+    assert_eq!(structural_hash(&quoted(&mut env, "'(1 (2.5 \"three\") four (5 (6)))")), hash);
+    assert_ne!(structural_hash(&quoted(&mut env, "'(1 (2.5 \"three\") four (5 (7)))")), hash);
+}
+
+#[test]
+fn lists_built_differently_are_equal_and_hash_the_same() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'literal '(1 2 (3 x)))");
+    eval_in(&mut env, "(let 'consed (cons 1 (cons 2 (cons '(3 x) '()))))");
+    eval_in(&mut env, "(let 'tail (cdr '(0 1 2 (3 x))))");
+    eval_in(&mut env, "(let 'floats '(1.0 2 (3.0 x)))");
+    let literal = structural_hash(&quoted(&mut env, "literal"));
+    for other in ["consed", "tail", "floats"] {
+        assert_eq!(eval_in(&mut env, &format!("(= literal {other})")), "t", "{other}");
+        assert_eq!(eval_in(&mut env, &format!("(equal? {other} literal)")), "t", "{other}");
+        assert_eq!(structural_hash(&quoted(&mut env, other)), literal, "{other}");
+    }
+}
+
+#[test]
+fn comparing_large_lists_again_gives_the_same_answer() {
+    let mut env = env();
+    eval_in(&mut env, "
+        (let 'build (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons n acc)))))
+        (let 'xs (build 10000 '()))
+        (let 'ys (build 10000 '()))
+        (let 'zs (build 10000 '(0)))");
+    for _ in 0..3 {
+        assert_eq!(eval_in(&mut env, "(= xs ys)"), "t");
+        assert_eq!(eval_in(&mut env, "(= xs zs)"), "f");
+        assert_eq!(eval_in(&mut env, "(= (cdr xs) (cdr ys))"), "t");
+        assert_eq!(eval_in(&mut env, "(= (cons 0 xs) ys)"), "f");
+    }
+}
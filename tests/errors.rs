@@ -0,0 +1,87 @@
+mod common;
+
+use common::*;
+use yal::{ EvalError, RuntimeError };
+
+/// The suggestions of the unbound variable error evaluating `src` fails with.
+fn suggestions(src: &str) -> Vec<String> {
+    let err = env().eval_str(src).unwrap_err();
+    match &err {
+        EvalError::Runtime { error, .. } => match error.root() {
+            RuntimeError::UnboundVariable { suggestions, .. } => suggestions.clone(),
+            _ => panic!("evaluating {:?} didn't fail with an unbound variable: {}", src, err),
+        },
+        _ => panic!("evaluating {:?} didn't fail with an unbound variable: {}", src, err),
+    }
+}
+
+#[test]
+fn a_head_that_isnt_a_function_is_shown_with_its_list() {
+    let err = eval_err("(1 2 3)");
+    assert!(err.contains("expected a function as the head of `(1 2 3)`, got `1`"), "{err}");
+
+    let err = eval_err(r#"("s" 1)"#);
+    assert!(err.contains(r#"expected a function as the head of `("s" 1)`, got `"s"`"#), "{err}");
+}
+
+#[test]
+fn a_long_list_is_cut_short_in_the_message() {
+    let src = format!("({})", (1..=100).map(|n| n.to_string()).collect::<Vec<_>>().join(" "));
+    let err = eval_err(&src);
+    let message = err.lines().next().unwrap();
+    assert!(message.contains("of `(1 2 3 4 5"), "{message}");
+    assert!(message.contains("...`, got `1`"), "{message}");
+    assert!(!message.contains("100"), "{message}");
+}
+
+#[test]
+fn an_unbound_head_says_it_is_in_call_position() {
+    let err = eval_err("(undefined-name 1)");
+    assert!(err.contains("name 'undefined-name' was not defined"), "{err}");
+    assert!(err.contains("in call position of `(undefined-name 1)`"), "{err}");
+
+    let err = eval_err("(+ 1 undefined-name)");
+    assert!(!err.contains("call position"), "{err}");
+}
+
+#[test]
+fn near_misses_are_suggested() {
+    let err = eval_err("(prnt 1)");
+    assert!(err.contains("did you mean 'print'?"), "{err}");
+    assert_eq!(suggestions("(let 'counter 1) (+ 1 countr)"), ["counter"]);
+}
+
+#[test]
+fn short_names_only_suggest_names_sharing_a_char() {
+    assert!(suggestions("(let 'xy 1) (print x)").is_empty());
+    assert!(suggestions("(print q)").is_empty());
+    assert!(suggestions("(let 'xy 1) (print uv)").is_empty());
+    assert_eq!(suggestions("(let 'ab 1) (print ac)"), ["ab"]);
+}
+
+#[test]
+fn failures_are_told_apart_by_their_variant() {
+    assert!(matches!(
+        root_error("(+ 1 nowhere)"),
+        RuntimeError::UnboundVariable { name, call: None, .. } if name == "nowhere"
+    ));
+    assert!(matches!(
+        root_error("(car 1)"),
+        RuntimeError::TypeMismatch { expected: "a list", got, context } if got == "int `1`" && context == "'car'"
+    ));
+    assert!(matches!(
+        root_error("((fn '(x) 'x))"),
+        RuntimeError::ArityMismatch { expected, got: 0, .. } if expected == "1"
+    ));
+    assert!(matches!(root_error("(1 2)"), RuntimeError::NotAFunction { value, .. } if value == "1"));
+    assert!(matches!(root_error("(car '())"), RuntimeError::EmptyList { context } if context == "'car'"));
+    assert!(matches!(root_error("(/ 1 0)"), RuntimeError::DivisionByZero));
+    assert!(matches!(root_error("(+ 9223372036854775807 1/2)"), RuntimeError::IntegerOverflow { .. }));
+}
+
+#[test]
+fn variants_display_as_the_messages_did() {
+    assert_eq!(root_error("(car '())").to_string(), "expected non empty list in 'car'");
+    assert_eq!(root_error("(/ 1 0)").to_string(), "integer division by zero");
+    assert_eq!(RuntimeError::from("anything".to_string()).to_string(), "anything");
+}
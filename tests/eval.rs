@@ -0,0 +1,66 @@
+mod common;
+
+use common::*;
+use yal::Value;
+
+#[test]
+fn the_empty_list_evaluates_to_nil() {
+    assert!(matches!(&*env().eval_str("()").unwrap(), Value::Nil));
+    assert_eq!(eval("(= () nil)"), "t");
+}
+
+#[test]
+fn empty_results_flow_through_eval() {
+    assert_eq!(eval("(= (eval (cdr '(x))) nil)"), "t");
+    assert_eq!(eval("(= (eval '()) nil)"), "t");
+    assert_eq!(eval("(= (cdr (cdr '(1 2))) '())"), "t");
+}
+
+/// A random arithmetic expression, nested at most `depth` levels, as its
+/// source and as yal code building the same expression out of evaluated
+/// pieces with `cons`.
+fn random_code(rng: &mut Rng, depth: usize) -> (String, String) {
+    match rng.below(if depth == 0 { 3 } else { 6 }) {
+        0 => {
+            let n = rng.below(100).to_string();
+            (n.clone(), n)
+        }
+        1 => {
+            let n = format!("{}.5", rng.below(10));
+            (n.clone(), n)
+        }
+        2 => ("(car '(7 x))".to_string(), "(cons 'car (cons ''(7 x) '()))".to_string()),
+        _ => {
+            let op = ["+", "-", "*"][rng.below(3)];
+            let (lhs, build_lhs) = random_code(rng, depth - 1);
+            let (rhs, build_rhs) = random_code(rng, depth - 1);
+            let src = format!("({} {} {})", op, lhs, rhs);
+            let build = format!("(cons '{} (cons {} (cons {} '())))", op, build_lhs, build_rhs);
+            (src, build)
+        }
+    }
+}
+
+#[test]
+fn constructed_code_evaluates_as_if_it_was_read() {
+    let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+    for _ in 0..2_000 {
+        let (src, build) = random_code(&mut rng, 4);
+        let mut env = env();
+        assert_eq!(eval_in(&mut env, &build), src, "built by {}", build);
+        assert_eq!(eval_in(&mut env, &format!("(eval {})", build)), eval_in(&mut env, &src), "built by {}", build);
+    }
+}
+
+#[test]
+fn taking_code_apart_gives_values_back() {
+    assert_eq!(eval("(+ (car (cdr '(x 2))) 1)"), "3");
+    assert_eq!(eval("(= (car '(\"s\")) \"s\")"), "t");
+    assert_eq!(eval("(eval (cons '+ (cons (car '(1)) (cdr '(x 2)))))"), "3");
+}
+
+#[test]
+fn functions_have_no_code_to_become() {
+    let err = eval_err("(eval (cons (fn '(x) 'x) '(3)))");
+    assert!(err.contains("expected data in conversion to code"), "{err}");
+}
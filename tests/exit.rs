@@ -0,0 +1,62 @@
+//! The exit status and standard error of the binary: 0 on success, 1 after
+//! a runtime error, 2 for parse and usage errors, or what `exit` was given.
+
+mod common;
+
+use common::*;
+
+fn run(fixture: &str) -> (i32, String, String) {
+    yal(&[&format!("tests/fixtures/exit/{}.yal", fixture)])
+}
+
+#[test]
+fn success_exits_with_0_and_says_nothing() {
+    assert_eq!(run("success"), (0, "fine".to_string(), String::new()));
+}
+
+#[test]
+fn runtime_errors_exit_with_1_on_stderr() {
+    let (status, stdout, stderr) = run("runtime_error");
+    assert_eq!((status, stdout.as_str()), (1, "before"));
+    assert!(stderr.starts_with("error: expected a list in 'car', got int `1`\n"), "{stderr}");
+    assert!(stderr.contains(" --> tests/fixtures/exit/runtime_error.yal:3:8\n"), "{stderr}");
+}
+
+#[test]
+fn parse_errors_exit_with_2_on_stderr_before_anything_runs() {
+    let (status, stdout, stderr) = run("parse_error");
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.starts_with("error: expected a closing paren\n"), "{stderr}");
+    assert!(stderr.contains(" --> tests/fixtures/exit/parse_error.yal:"), "{stderr}");
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let (status, stdout, stderr) = yal(&["--no-such-option"]);
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.starts_with("unknown option '--no-such-option'\n"), "{stderr}");
+
+    let (status, _, stderr) = yal(&["tests/fixtures/exit/missing.yal"]);
+    assert_eq!(status, 2);
+    assert!(stderr.starts_with("couldn't read 'tests/fixtures/exit/missing.yal'"), "{stderr}");
+}
+
+#[test]
+fn exit_overrides_the_status() {
+    assert_eq!(run("exit"), (3, "leaving".to_string(), String::new()));
+    assert_eq!(yal(&["-e", "(exit 0)"]).0, 0);
+    assert_eq!(yal(&["-e", "(exit 42)"]).0, 42);
+}
+
+#[test]
+fn exit_statuses_past_32_bits_are_errors() {
+    let (status, _, stderr) = yal(&["-e", "(exit 4294967338)"]);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error: exit status 4294967338 is out of range"), "{stderr}");
+}
+
+#[test]
+fn a_failing_file_stops_the_ones_after_it() {
+    let (status, stdout, _) = yal(&["tests/fixtures/exit/runtime_error.yal", "tests/fixtures/exit/success.yal"]);
+    assert_eq!((status, stdout.as_str()), (1, "before"));
+}
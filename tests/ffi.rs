@@ -0,0 +1,54 @@
+//! Builds the C interface as a shared library and runs the C program in
+//! `tests/ffi` against it. Needs the `ffi` feature and a C compiler, `$CC`
+//! or `cc`, and is skipped without one.
+
+#![cfg(feature = "ffi")]
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn the_c_test_program_passes() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+
+    // A target directory of its own, as this one is in use by the tests.
+    let status = Command::new(env!("CARGO"))
+        .args(["rustc", "--lib", "--features", "ffi", "--crate-type", "cdylib", "--manifest-path"])
+        .arg(root.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", &target)
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "building the shared library failed");
+
+    let lib_dir = target.join("debug");
+    let program = target.join("test");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let compiled = Command::new(&cc)
+        .arg(root.join("tests/ffi/test.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-lyal", "-Wall", "-Werror", "-o"])
+        .arg(&program)
+        .status();
+    match compiled {
+        Ok(status) => assert!(status.success(), "compiling the C test program failed"),
+        Err(err) => {
+            eprintln!("skipped, there is no C compiler '{}': {}", cc, err);
+            return;
+        }
+    }
+
+    // Cargo points the loader at its own directories, which may have an
+    // older build of the library.
+    let out = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .expect("the C test program runs");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(out.status.success(), "the C test program failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "ok\n");
+}
@@ -0,0 +1,90 @@
+/* Exercises the C interface in `include/yal.h`. Built and run by
+ * `tests/ffi.rs`; prints what failed and exits with 1 on the first failed
+ * check. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "yal.h"
+
+#define CHECK(cond)                                                       \
+    do {                                                                  \
+        if (!(cond)) {                                                    \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,        \
+                    __LINE__, #cond);                                     \
+            exit(1);                                                      \
+        }                                                                 \
+    } while (0)
+
+/* Adds its two int arguments, counting its calls in `user_data`. */
+static int add(void *user_data, const YalValue *args, size_t argc, YalValue *out) {
+    (*(int *)user_data)++;
+    if (argc != 2 || args[0].tag != YAL_INT || args[1].tag != YAL_INT) {
+        out->tag = YAL_STRING;
+        out->data.string = "add takes two ints";
+        return YAL_ERROR;
+    }
+    out->tag = YAL_INT;
+    out->data.int_ = args[0].data.int_ + args[1].data.int_;
+    return YAL_OK;
+}
+
+/* Gives the length of its string argument, and the tag of anything else. */
+static int measure(void *user_data, const YalValue *args, size_t argc, YalValue *out) {
+    (void)user_data;
+    (void)argc;
+    out->tag = YAL_INT;
+    out->data.int_ = args[0].tag == YAL_STRING ? (int64_t)strlen(args[0].data.string) : -(int64_t)args[0].tag;
+    return YAL_OK;
+}
+
+int main(void) {
+    char buf[64];
+    int calls = 0;
+
+    YalEnv *env = yal_env_new();
+    CHECK(env != NULL);
+
+    CHECK(yal_eval_cstr(env, "(+ 1 2)", buf, sizeof buf) == YAL_OK);
+    CHECK(strcmp(buf, "3") == 0);
+    CHECK(yal_last_error(env) == NULL);
+
+    /* Bindings stay between calls, and strings come out as `write` prints them. */
+    CHECK(yal_eval_cstr(env, "(let 'greeting \"hi\")", NULL, 0) == YAL_OK);
+    CHECK(yal_eval_cstr(env, "greeting", buf, sizeof buf) == YAL_OK);
+    CHECK(strcmp(buf, "\"hi\"") == 0);
+
+    /* What doesn't fit is cut, and still NUL terminated. */
+    CHECK(yal_eval_cstr(env, "12345678", buf, 4) == YAL_TRUNCATED);
+    CHECK(strcmp(buf, "123") == 0);
+    CHECK(strstr(yal_last_error(env), "9 bytes") != NULL);
+
+    CHECK(yal_eval_cstr(env, "(car 1)", buf, sizeof buf) == YAL_ERROR);
+    CHECK(yal_last_error(env) != NULL);
+    CHECK(yal_eval_cstr(env, "(+ 1", buf, sizeof buf) == YAL_ERROR);
+    CHECK(yal_last_error(env) != NULL);
+    CHECK(yal_eval_cstr(env, NULL, buf, sizeof buf) == YAL_INVALID);
+    CHECK(yal_eval_cstr(NULL, "1", buf, sizeof buf) == YAL_INVALID);
+
+    CHECK(yal_register_native(env, "add", 2, add, &calls) == YAL_OK);
+    CHECK(yal_eval_cstr(env, "(add 40 2)", buf, sizeof buf) == YAL_OK);
+    CHECK(strcmp(buf, "42") == 0);
+    CHECK(calls == 1);
+
+    /* A failing native fails the call with its message. */
+    CHECK(yal_eval_cstr(env, "(add 1 \"two\")", buf, sizeof buf) == YAL_ERROR);
+    CHECK(strstr(yal_last_error(env), "add takes two ints") != NULL);
+    CHECK(calls == 2);
+
+    CHECK(yal_register_native(env, "measure", 1, measure, NULL) == YAL_OK);
+    CHECK(yal_eval_cstr(env, "(measure \"four\")", buf, sizeof buf) == YAL_OK);
+    CHECK(strcmp(buf, "4") == 0);
+    CHECK(yal_eval_cstr(env, "(measure '(1 2))", buf, sizeof buf) == YAL_OK);
+    CHECK(strcmp(buf, "-5") == 0);
+
+    yal_env_free(env);
+    yal_env_free(NULL);
+    puts("ok");
+    return 0;
+}
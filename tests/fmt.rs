@@ -0,0 +1,118 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::*;
+use yal::formatter::format_source;
+use yal::Reader;
+
+/// What `yal fmt` run with `args` exits with, and prints to standard error.
+fn fmt(args: &[&str]) -> (i32, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_yal")).arg("fmt").args(args).output().unwrap();
+    (out.status.code().unwrap_or(-1), String::from_utf8_lossy(&out.stderr).into_owned())
+}
+
+/// A file in the temporary directory holding `src`.
+fn temp_file(name: &str, src: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("yal-fmt-{}-{}", std::process::id(), name));
+    std::fs::write(&path, src).unwrap();
+    path
+}
+
+fn formatted(src: &str) -> String {
+    format_source(src).unwrap_or_else(|err| panic!("couldn't format {:?}: {}", src, err))
+}
+
+#[test]
+fn top_level_forms_are_separated_by_a_blank_line() {
+    assert_eq!(formatted("(let 'x 1) (let 'y 2)\n\n\n(print x)"), "(let 'x 1)\n\n(let 'y 2)\n\n(print x)\n");
+}
+
+#[test]
+fn long_lists_are_broken_and_indented() {
+    let src = "(let 'long-function-name (fn '(alpha beta gamma) '(+ alpha (* beta (- gamma alpha beta gamma alpha beta gamma)))))";
+    assert_eq!(formatted(src), "\
+(let 'long-function-name
+  (fn '(alpha beta gamma)
+    '(+ alpha (* beta (- gamma alpha beta gamma alpha beta gamma)))))
+");
+}
+
+#[test]
+fn comments_stay_before_their_form() {
+    assert_eq!(formatted("; header\n(let 'x 1)\n   ; about y\n(let 'y 2)"), "; header\n(let 'x 1)\n\n; about y\n(let 'y 2)\n");
+}
+
+/// Random programs, made of lists long and short, quotes and comments.
+fn random_program(rng: &mut Rng) -> String {
+    fn expr(rng: &mut Rng, depth: usize, out: &mut String) {
+        match rng.below(if depth == 0 { 2 } else { 5 }) {
+            0 => out.push_str(["x", "1", "2.5", "\"s\"", "long-name-for-a-value", "nil"][rng.below(6)]),
+            1 => {
+                out.push('\'');
+                expr(rng, depth.saturating_sub(1), out);
+            }
+            _ => {
+                out.push('(');
+                for i in 0..rng.below(7) {
+                    if i > 0 {
+                        out.push_str([" ", "\n", "  "][rng.below(3)]);
+                    }
+                    if rng.below(10) == 0 {
+                        out.push_str("; a comment\n");
+                    }
+                    expr(rng, depth - 1, out);
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    let mut src = String::new();
+    for _ in 0..rng.below(4) + 1 {
+        if rng.below(3) == 0 {
+            src.push_str(";; before\n");
+        }
+        expr(rng, 4, &mut src);
+        src.push_str(["\n", " ", "\n\n\n"][rng.below(3)]);
+    }
+    src
+}
+
+#[test]
+fn formatting_is_idempotent_and_keeps_the_program() {
+    let mut rng = Rng(0x8cb9_2ba7_2f3d_8dd7);
+    for _ in 0..2_000 {
+        let src = random_program(&mut rng);
+        let once = formatted(&src);
+        assert_eq!(formatted(&once), once, "formatting {:?}", src);
+        let read = |src: &str| Reader::new(src).parse_sexprs().map_err(|err| err.to_string()).unwrap();
+        assert_eq!(read(&once), read(&src), "formatting {:?}", src);
+    }
+}
+
+#[test]
+fn files_that_dont_parse_are_left_alone() {
+    let path = temp_file("broken.yal", "(let 'x\n  (+ 1 2)");
+    let (status, stderr) = fmt(&[path.to_str().unwrap()]);
+    let after = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(status, 1);
+    assert!(stderr.contains("expected a closing paren"), "{stderr}");
+    assert_eq!(after, "(let 'x\n  (+ 1 2)");
+}
+
+#[test]
+fn check_writes_nothing_and_fails_on_changes() {
+    let path = temp_file("unformatted.yal", "(let 'x 1)   (let 'y 2)");
+    let (status, stderr) = fmt(&["--check", path.to_str().unwrap()]);
+    assert_eq!(status, 1);
+    assert!(stderr.contains("is not formatted"), "{stderr}");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "(let 'x 1)   (let 'y 2)");
+
+    assert_eq!(fmt(&[path.to_str().unwrap()]).0, 0);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "(let 'x 1)\n\n(let 'y 2)\n");
+    assert_eq!(fmt(&["--check", path.to_str().unwrap()]), (0, String::new()));
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+use yal::optimize::optimize;
+use yal::printer::Written;
+use yal::Reader;
+
+/// `src` folded with the builtins of a standard environment, written back.
+fn folded(src: &str) -> String {
+    let expr = Reader::new(src).parse_sexpr().map_err(|err| err.to_string()).unwrap();
+    Written(&optimize(expr, &env())).to_string()
+}
+
+#[test]
+fn literal_arithmetic_is_folded_innermost_first() {
+    assert_eq!(folded("(* 2 (+ 1 2))"), "6");
+    assert_eq!(folded("(/ 1 4)"), "1/4");
+    assert_eq!(folded("(= 1 1)"), "t");
+    assert_eq!(folded("(print (+ 1 2) x)"), "(print 3 x)");
+    assert_eq!(folded("(+ x (* 2 3))"), "(+ x 6)");
+}
+
+#[test]
+fn quoted_data_and_impure_calls_are_left_alone() {
+    assert_eq!(folded("'(+ 1 2)"), "'(+ 1 2)");
+    assert_eq!(folded("(fn '(x) '(+ 1 2))"), "(fn '(x) '(+ 1 2))");
+    assert_eq!(folded("(print 1)"), "(print 1)");
+}
+
+#[test]
+fn failing_applications_are_left_for_runtime() {
+    assert_eq!(folded("(/ 1 0)"), "(/ 1 0)");
+    assert_eq!(folded("(+ 1 \"a\")"), "(+ 1 \"a\")");
+    assert_eq!(folded("(* 9223372036854775807 2)"), "(* 9223372036854775807 2)");
+}
+
+#[test]
+fn programs_behave_the_same_folded() {
+    let programs = [
+        "(print (* 2 (+ 1 2))) (print \" \") (print (/ 1 3))",
+        "(let 'f (fn '(x) '(+ x (* 2 3)))) (print (f 1))",
+        "(print (= 1 1.0)) (print (car '((+ 1 2))))",
+        "(print 1) (print (/ 1 0))",
+    ];
+    for src in programs {
+        let plain = yal(&["-e", src]);
+        let folded = yal(&["--fold-constants", "-e", src]);
+        assert_eq!(plain, folded, "running {:?}", src);
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::rc::{ Rc, Weak };
+
+use common::*;
+use yal::ast::{ Function, Promise };
+use yal::{ Environment, RefVal, Value };
+
+/// Registers `hold`, which gives a native closure holding on to its
+/// argument: the way values can refer back to themselves.
+fn with_hold(env: &mut Environment) {
+    env.register_external_fun("hold", 1, |env| {
+        let held = env.pop_stack()?;
+        let ptr = Rc::new(move |_: &mut Environment| Ok(held.clone()));
+        Ok(RefVal::owned(Value::Function(Function::Lib { name: "held", ptr, arity: 0, variadic: false, doc: None })))
+    });
+}
+
+/// The promise bound to `name`.
+fn promise(env: &Environment, name: &str) -> Weak<Promise> {
+    match &**env.lookup_var(name).unwrap() {
+        Value::Promise(promise) => Rc::downgrade(promise),
+        val => panic!("'{}' is {}, not a promise", name, val),
+    }
+}
+
+#[test]
+fn a_promise_holding_itself_is_freed() {
+    let mut env = env();
+    with_hold(&mut env);
+    eval_in(&mut env, "(let 'p (delay '(hold p))) (force p)");
+    let weak = promise(&env, "p");
+    eval_in(&mut env, "(let 'p nil)");
+    assert!(weak.upgrade().is_some(), "the cycle keeps the promise alive");
+
+    assert_eq!(env.collect_garbage(), 1);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(env.collect_garbage(), 0);
+}
+
+#[test]
+fn promises_still_bound_keep_their_value() {
+    let mut env = env();
+    with_hold(&mut env);
+    eval_in(&mut env, "(let 'p (delay '(hold p))) (force p) (let 'q (delay '(+ 1 2))) (force q)");
+    assert_eq!(env.collect_garbage(), 0);
+    assert_eq!(eval_in(&mut env, "p"), "#<promise forced>");
+    assert_eq!(eval_in(&mut env, "q"), "#<promise forced>");
+}
+
+#[test]
+fn a_promise_the_host_holds_is_forced_again() {
+    let mut env = env();
+    let val = env.eval_str("(let 'n 0) (delay '(let 'n (+ n 1)))").unwrap();
+    env.define_var("p", val).unwrap();
+    eval_in(&mut env, "(force p)");
+    let held = env.lookup_var("p").unwrap().clone();
+    eval_in(&mut env, "(let 'p nil)");
+
+    assert_eq!(env.collect_garbage(), 1);
+    env.define_var("p", held).unwrap();
+    assert_eq!(eval_in(&mut env, "(force p)"), "2");
+    assert_eq!(eval_in(&mut env, "n"), "2");
+}
+
+#[test]
+fn the_repl_collects_with_gc() {
+    let mut env = env();
+    with_hold(&mut env);
+    let (_, output) = repl(&mut env, &["(let 'p (delay '(hold p)))", "(force p)", "(let 'p nil)", ":gc", ":gc"]);
+    assert!(output.contains("freed 1 value\n"), "{output}");
+    assert!(output.contains("freed 0 values\n"), "{output}");
+}
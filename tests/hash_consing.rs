@@ -0,0 +1,65 @@
+//! Sharing identical quoted expressions with `Environment::enable_hash_consing`.
+
+mod common;
+
+use std::rc::Rc;
+
+use common::*;
+
+#[test]
+fn copies_parsed_separately_share_storage() {
+    let mut env = env();
+    env.enable_hash_consing();
+    let a = quoted(&mut env, "(let 'a '(1 (2 \"x\") y))");
+    let b = quoted(&mut env, "(let 'b '(1 (2 \"x\") y))");
+    assert!(Rc::ptr_eq(&a, &b));
+    assert_eq!(env.interned_nodes(), 1);
+
+    let c = quoted(&mut env, "'(1 (2 \"x\") z)");
+    assert!(!Rc::ptr_eq(&a, &c));
+    assert_eq!(env.interned_nodes(), 2);
+}
+
+#[test]
+fn quotes_made_in_compiled_functions_are_shared_too() {
+    let mut env = env();
+    env.set_use_vm(true);
+    env.enable_hash_consing();
+    eval_in(&mut env, "(let 'f (fn '() ''(1 2 3))) (let 'g (fn '() ''(1 2 3)))");
+    let a = quoted(&mut env, "(f)");
+    let b = quoted(&mut env, "(g)");
+    assert!(Rc::ptr_eq(&a, &b));
+}
+
+#[test]
+fn nothing_is_shared_unless_enabled() {
+    let mut env = env();
+    assert!(!env.is_hash_consing());
+    let a = quoted(&mut env, "'(1 2 3)");
+    let b = quoted(&mut env, "'(1 2 3)");
+    assert!(!Rc::ptr_eq(&a, &b));
+    assert_eq!(env.interned_nodes(), 0);
+}
+
+#[test]
+fn shared_quotes_still_behave_as_values() {
+    let mut env = env();
+    env.enable_hash_consing();
+    eval_in(&mut env, "(let 'a '(1 2 3)) (let 'b '(1 2 3))");
+    assert_eq!(eval_in(&mut env, "(eq a b)"), "t");
+    assert_eq!(eval_in(&mut env, "(let 'a (cons 0 a))"), "(0 1 2 3)");
+    assert_eq!(eval_in(&mut env, "b"), "(1 2 3)");
+    assert_eq!(eval_in(&mut env, "(cdr a)"), "(1 2 3)");
+    assert_eq!(eval_in(&mut env, "(= (cdr a) b)"), "t");
+    assert_eq!(eval_in(&mut env, "(= a b)"), "f");
+}
+
+#[test]
+fn quotes_nothing_holds_are_forgotten() {
+    let mut env = env();
+    env.enable_hash_consing();
+    eval_in(&mut env, "(let 'a '(1 2 3))");
+    assert_eq!(env.interned_nodes(), 1);
+    eval_in(&mut env, "(let 'a nil)");
+    assert_eq!(env.interned_nodes(), 0);
+}
@@ -0,0 +1,58 @@
+//! The history of the REPL's terminal, kept across sessions in the data
+//! directory. On its own, since it points `XDG_DATA_HOME` somewhere else.
+
+use std::path::PathBuf;
+
+use yal::repl::{ Terminal, MAX_HISTORY };
+
+/// A data directory of its own, empty.
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yal-history-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&dir);
+    dir
+}
+
+#[test]
+fn history_is_kept_across_sessions() {
+    let data = data_dir("round-trip");
+    std::env::set_var("XDG_DATA_HOME", &data);
+
+    let mut terminal = Terminal::new().unwrap();
+    for line in ["(let 'x 1)", "(print x)", "(print x)", "  ", "(+ x 1)", "(print x)"] {
+        terminal.add_history(line);
+    }
+    drop(terminal);
+    assert!(data.join("yal/history").is_file());
+
+    // Repeated lines are kept once, unless something came between them.
+    let terminal = Terminal::new().unwrap();
+    assert_eq!(terminal.history().collect::<Vec<_>>(), ["(let 'x 1)", "(print x)", "(+ x 1)", "(print x)"]);
+    drop(terminal);
+
+    // Only the latest lines are kept.
+    let mut terminal = Terminal::new().unwrap();
+    for i in 0..MAX_HISTORY + 10 {
+        terminal.add_history(&format!("(+ {} 1)", i));
+    }
+    drop(terminal);
+    let terminal = Terminal::new().unwrap();
+    let history: Vec<_> = terminal.history().collect();
+    assert_eq!(history.len(), MAX_HISTORY);
+    assert_eq!(history[0], "(+ 10 1)");
+    assert_eq!(history[MAX_HISTORY - 1], format!("(+ {} 1)", MAX_HISTORY + 9));
+    drop(terminal);
+
+    // Without anywhere to write it, the history is only kept in memory.
+    let unusable = data_dir("unusable");
+    std::fs::write(&unusable, "not a directory").unwrap();
+    std::env::set_var("XDG_DATA_HOME", &unusable);
+    let mut terminal = Terminal::new().unwrap();
+    terminal.add_history("(print 1)");
+    assert_eq!(terminal.history().collect::<Vec<_>>(), ["(print 1)"]);
+    drop(terminal);
+    assert_eq!(std::fs::read_to_string(&unusable).unwrap(), "not a directory");
+
+    let _ = std::fs::remove_dir_all(&data);
+    let _ = std::fs::remove_file(&unusable);
+}
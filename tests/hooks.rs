@@ -0,0 +1,86 @@
+//! The evaluation hook embedders install, called before and after every
+//! expression.
+
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::*;
+use yal::evaluator::HookPhase;
+use yal::RuntimeError;
+
+/// Installs a hook in `env` recording every call, as `>` or `<` and the
+/// expression.
+fn record(env: &mut yal::Environment) -> Rc<RefCell<Vec<String>>> {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let log = calls.clone();
+    env.set_eval_hook(Box::new(move |expr, phase| {
+        let arrow = if phase == HookPhase::Before { ">" } else { "<" };
+        log.borrow_mut().push(format!("{} {}", arrow, expr));
+        Ok(())
+    }));
+    calls
+}
+
+#[test]
+fn the_hook_is_called_once_before_and_once_after_each_expression() {
+    let mut env = env();
+    let calls = record(&mut env);
+    assert_eq!(eval_in(&mut env, "(+ 1 (* 2 3))"), "7");
+    assert_eq!(*calls.borrow(), [
+        "> (+ 1 (* 2 3))",
+        "> +",
+        "< +",
+        "> 1",
+        "< 1",
+        "> (* 2 3)",
+        "> *",
+        "< *",
+        "> 2",
+        "< 2",
+        "> 3",
+        "< 3",
+        "< (* 2 3)",
+        "< (+ 1 (* 2 3))",
+    ]);
+}
+
+#[test]
+fn the_hook_counts_the_same_for_the_same_program() {
+    let count = || {
+        let mut env = env();
+        eval_in(&mut env, "(let 'fact (fn '(n) '(if (= n 0) '1 '(* n (fact (- n 1))))))");
+        let calls = record(&mut env);
+        assert_eq!(eval_in(&mut env, "(fact 5)"), "120");
+        let calls = calls.borrow();
+        let before = calls.iter().filter(|call| call.starts_with('>')).count();
+        assert_eq!(before * 2, calls.len());
+        before
+    };
+    let first = count();
+    // The 3 expressions of `(fact 5)`, then the body of each call: the 8 of
+    // the `if` with its condition and quoted branches, and the branch taken,
+    // which is the 9 of `(* n (fact (- n 1)))` but for the last call's `1`.
+    assert_eq!(first, 3 + 5 * (8 + 9) + (8 + 1));
+    assert_eq!(count(), first);
+}
+
+#[test]
+fn an_error_from_the_hook_stops_evaluation() {
+    let mut env = env();
+    let (result, printed) = env.capture_output(|env| {
+        env.set_eval_hook(Box::new(|expr, _| match expr.to_string().as_str() {
+            "(print 2)" => Err(RuntimeError::from("not allowed")),
+            _ => Ok(()),
+        }));
+        env.eval_str("(print 1) (print 2) (print 3)").map(|_| ())
+    });
+    let err = result.unwrap_err().to_string();
+    assert!(err.starts_with("error: not allowed\n"), "{err}");
+    assert_eq!(printed, "1");
+
+    // The environment still works after, without the hook.
+    env.clear_eval_hook();
+    assert_eq!(eval_in(&mut env, "(+ 1 2)"), "3");
+}
@@ -0,0 +1,124 @@
+//! `http-get` and `http-post` against a server in the test, so no real
+//! network is needed.
+
+#![cfg(feature = "http")]
+
+mod common;
+
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+
+use common::*;
+
+/// Serves `response` to one request on a port of its own, giving the URL
+/// to reach it and the request it got.
+fn serve(response: &'static str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        request.push_str(&String::from_utf8(body).unwrap());
+        reader.into_inner().write_all(response.as_bytes()).unwrap();
+        request
+    });
+    (url, server)
+}
+
+#[test]
+fn get_parses_the_status_headers_and_body() {
+    let (url, server) = serve("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello, and more");
+    let response = eval(&format!("(http-get \"{url}/greeting?lang=en\")"));
+    assert_eq!(response, "((status 200) (headers ((content-type text/plain) (content-length 5))) (body hello))");
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /greeting?lang=en HTTP/1.1\r\n"), "{request}");
+    assert!(request.contains(&format!("Host: {}\r\n", url.trim_start_matches("http://"))), "{request}");
+}
+
+#[test]
+fn chunked_bodies_are_put_together() {
+    let (url, server) = serve("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n7;ext\r\n, world\r\n0\r\n\r\n");
+    let response = eval(&format!("(car (cdr (car (cdr (cdr (http-get \"{url}\"))))))"));
+    assert_eq!(response, "hello, world");
+    server.join().unwrap();
+}
+
+#[test]
+fn error_statuses_are_responses() {
+    let (url, server) = serve("HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\nno such page");
+    let response = eval(&format!("(http-get \"{url}/missing\")"));
+    assert_eq!(response, "((status 404) (headers ((connection close))) (body no such page))");
+    server.join().unwrap();
+}
+
+#[test]
+fn post_sends_the_body_and_headers() {
+    let (url, server) = serve("HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n");
+    let response = eval(&format!("(http-post \"{url}/items\" \"{{\\\"a\\\": 1}}\" '((\"Content-Type\" \"application/json\")))"));
+    assert_eq!(response, "((status 201) (headers ((content-length 0))) (body ))");
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /items HTTP/1.1\r\n"), "{request}");
+    assert!(request.contains("Content-Type: application/json\r\n"), "{request}");
+    assert!(request.contains("Content-Length: 8\r\n"), "{request}");
+    assert!(request.ends_with("\r\n\r\n{\"a\": 1}"), "{request}");
+}
+
+#[test]
+fn failures_are_errors_naming_the_url() {
+    // Nothing listens on a port that was just freed.
+    let url = format!("http://{}/", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+    let err = eval_err(&format!("(http-get \"{url}\")"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': ")), "{err}");
+
+    let err = eval_err("(http-get \"https://example.com\")");
+    assert!(err.starts_with("error: couldn't fetch 'https://example.com': https isn't supported"), "{err}");
+    let err = eval_err("(http-get \"example.com\")");
+    assert!(err.starts_with("error: couldn't fetch 'example.com': expected a URL starting with http://"), "{err}");
+
+    let (url, server) = serve("nonsense\r\n\r\n");
+    let err = eval_err(&format!("(http-get \"{url}\")"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': invalid status line 'nonsense'")), "{err}");
+    server.join().unwrap();
+}
+
+#[test]
+fn headers_cant_break_lines() {
+    let url = "http://127.0.0.1:1/";
+    let err = eval_err(&format!("(http-post \"{url}\" \"\" '((\"X-A\" \"1\\r\\nX-B: 2\")))"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': invalid value \"1\\r\\nX-B: 2\" for the header 'X-A'")), "{err}");
+    let err = eval_err(&format!("(http-post \"{url}\" \"\" '((\"X-A: 1\" \"2\")))"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': invalid header name \"X-A: 1\"")), "{err}");
+    let err = eval_err(&format!("(http-post \"{url}\" \"\" '((\"X-A\\n\" \"2\")))"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': invalid header name \"X-A\\n\"")), "{err}");
+}
+
+#[test]
+fn chunks_are_only_taken_as_far_as_they_go() {
+    let (url, server) = serve("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nhello\r\n0\r\n\r\n");
+    let err = eval_err(&format!("(http-get \"{url}\")"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': the body is over 67108864 bytes")), "{err}");
+    server.join().unwrap();
+
+    let (url, server) = serve("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4000\r\nhello");
+    let err = eval_err(&format!("(http-get \"{url}\")"));
+    assert!(err.starts_with(&format!("error: couldn't fetch '{url}': the body ended in the middle of a chunk")), "{err}");
+    server.join().unwrap();
+}
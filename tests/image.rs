@@ -0,0 +1,74 @@
+//! Saving the bindings of an environment with `save-image`, and loading them
+//! again in another.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::*;
+
+/// Where a test keeps its image.
+fn image_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("yal-image-{}-{}.yal", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn save(env: &mut yal::Environment, path: &std::path::Path) -> String {
+    eval_in(env, &format!("(save-image {:?})", path.to_str().unwrap()))
+}
+
+fn load(env: &mut yal::Environment, path: &std::path::Path) -> String {
+    eval_in(env, &format!("(load-image {:?})", path.to_str().unwrap()))
+}
+
+#[test]
+fn bindings_come_back_as_they_were() {
+    let path = image_path("round-trip");
+    let mut env = env();
+    eval_in(&mut env, "(let 'xs '(1 \"two\\n\" 3.5 1/3 (nested 'q)))");
+    eval_in(&mut env, "(let 'square (fn '(x) '(* x x)))");
+    eval_in(&mut env, "(defconst 'limit 10)");
+    eval_in(&mut env, "(let 'conn (make-channel))");
+    assert_eq!(save(&mut env, &path), "(conn)");
+
+    let mut loaded = self::env();
+    assert_eq!(load(&mut loaded, &path), "(conn)");
+    assert_eq!(eval_in(&mut loaded, "xs"), eval_in(&mut env, "xs"));
+    assert_eq!(eval_in(&mut loaded, "(equal? xs '(1 \"two\\n\" 3.5 1/3 (nested 'q)))"), "t");
+    assert_eq!(eval_in(&mut loaded, "(square limit)"), "100");
+    assert!(eval_err_in(&mut loaded, "(let 'limit 11)").starts_with("error: cannot redefine constant 'limit'"));
+}
+
+#[test]
+fn deep_values_read_back() {
+    let path = image_path("deep");
+    let saved = path.clone();
+    let nested = with_stack(1 << 30, move || {
+        let mut env = env();
+        env.set_max_depth(50_000);
+        eval_in(&mut env, "(let 'nest (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons acc '())))))");
+        eval_in(&mut env, "(let 'deep (nest 1200 '(bottom)))");
+        save(&mut env, &saved);
+
+        let mut loaded = self::env();
+        loaded.set_max_depth(50_000);
+        load(&mut loaded, &saved);
+        eval_in(&mut loaded, "(let 'dig (fn '(xs n) '(if (= n 0) 'xs '(recur (car xs) (- n 1)))))");
+        eval_in(&mut loaded, "(dig deep 1200)")
+    });
+    assert_eq!(nested, "(bottom)");
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("..."));
+}
+
+#[test]
+fn what_wouldnt_read_back_isnt_saved() {
+    let path = image_path("too-deep");
+    let mut env = env();
+    env.set_max_depth(100);
+    eval_in(&mut env, "(let 'nest (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons acc '())))))");
+    eval_in(&mut env, "(let 'deep (nest 200 '()))");
+    let err = eval_err_in(&mut env, &format!("(save-image {:?})", path.to_str().unwrap()));
+    assert!(err.contains("'deep' wouldn't read back, nested more than 100 levels deep"), "{err}");
+    assert!(!path.exists());
+}
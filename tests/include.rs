@@ -0,0 +1,47 @@
+//! `include`, which splices other files into the program before any of it
+//! runs, and how the binary fails when they can't be.
+
+mod common;
+
+use common::*;
+
+fn run(fixtures: &[&str]) -> (i32, String, String) {
+    let paths: Vec<_> = fixtures.iter().map(|name| format!("tests/fixtures/include/{}.yal", name)).collect();
+    yal(&paths.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+#[test]
+fn included_files_include_others_relative_to_themselves() {
+    assert_eq!(run(&["main"]), (0, "20".to_string(), String::new()));
+}
+
+#[test]
+fn a_cycle_exits_with_2_before_anything_runs() {
+    let (status, stdout, stderr) = run(&["cycle_a"]);
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.starts_with("error: circular include: "), "{stderr}");
+    assert!(stderr.contains("cycle_a.yal → "), "{stderr}");
+    assert!(stderr.contains("cycle_b.yal:1:1\n"), "{stderr}");
+}
+
+#[test]
+fn a_syntax_error_in_an_included_file_exits_with_2_against_it() {
+    let (status, stdout, stderr) = run(&["broken"]);
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.starts_with("error: expected a closing paren\n"), "{stderr}");
+    assert!(stderr.contains("lib/unclosed.yal:2:1\n"), "{stderr}");
+}
+
+#[test]
+fn a_missing_include_exits_with_2() {
+    let (status, _, stderr) = yal(&["-e", "(include \"tests/fixtures/include/missing.yal\")"]);
+    assert_eq!(status, 2);
+    assert!(stderr.starts_with("error: couldn't include 'tests/fixtures/include/missing.yal'"), "{stderr}");
+}
+
+#[test]
+fn every_file_is_read_before_the_first_runs() {
+    let (status, stdout, stderr) = run(&["main", "unclosed"]);
+    assert_eq!((status, stdout.as_str()), (2, ""));
+    assert!(stderr.contains(" --> tests/fixtures/include/unclosed.yal:3:1\n"), "{stderr}");
+}
@@ -0,0 +1,56 @@
+//! `bound-names`, `bound?` and `describe`, which let a program look at the
+//! names it can use, and `Environment::iter_bindings` under them.
+
+mod common;
+
+use common::*;
+use yal::RefVal;
+
+#[test]
+fn bound_names_are_sorted_and_listed_once() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'zz 1) (let 'names (fn '(car zz) '(bound-names)))");
+    let names = eval_in(&mut env, "(names 1 2)");
+    let names: Vec<&str> = names.trim_matches(['(', ')']).split(' ').collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(names, sorted);
+    for name in ["car", "zz", "names", "+"] {
+        assert!(names.contains(&name), "{name} in {names:?}");
+    }
+}
+
+#[test]
+fn bound_tells_whether_a_name_is_there() {
+    let mut env = env();
+    assert_eq!(eval_in(&mut env, "(bound? 'car)"), "t");
+    assert_eq!(eval_in(&mut env, "(bound? 'x)"), "f");
+    eval_in(&mut env, "(let 'f (fn '(x) '(bound? 'x)))");
+    assert_eq!(eval_in(&mut env, "(f 1)"), "t");
+    assert_eq!(eval_in(&mut env, "(bound? 'x)"), "f");
+}
+
+#[test]
+fn describe_gives_the_type_arity_and_docs() {
+    let mut env = env();
+    assert_eq!(eval_in(&mut env, "(describe 'car)"), "((type function) (arity 1) (doc The first element of a list.))");
+    eval_in(&mut env, "(let 'f (fn '(\"Adds one.\" x) '(+ x 1)))");
+    assert_eq!(eval_in(&mut env, "(describe 'f)"), "((type function) (arity 1) (doc Adds one.))");
+    assert_eq!(eval_in(&mut env, "(describe '+)"), "((type function) (arity at least 2) (doc The sum of two or more numbers.))");
+    assert!(eval_err_in(&mut env, "(describe 'nope)").starts_with("error: name 'nope' was not defined"));
+}
+
+#[test]
+fn the_innermost_binding_wins() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'f (fn '(car) '(describe 'car)))");
+    assert_eq!(eval_in(&mut env, "(f 1)"), "((type int))");
+
+    let car = env.intern("car");
+    let mut scope = env.scope();
+    scope.bind_var(car.clone(), RefVal::from(1i64)).unwrap();
+    let bound: Vec<_> = scope.iter_bindings().filter(|(name, _)| **name == car).collect();
+    assert_eq!(bound.len(), 1);
+    assert_eq!(bound[0].1.to_string(), "1");
+}
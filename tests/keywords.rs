@@ -0,0 +1,60 @@
+//! Keyword arguments: those after `&key` in a function's argument list,
+//! passed by name after the positional ones, or left to their defaults.
+
+mod common;
+
+use common::*;
+
+const WINDOW: &str = "(let 'window (fn '(name &key (width 80) (title \"untitled\")) \
+                                      '(cons name (cons width (cons title '())))))";
+
+fn window(call: &str) -> String {
+    let mut env = env();
+    eval_in(&mut env, WINDOW);
+    eval_in(&mut env, call)
+}
+
+fn window_err(call: &str) -> String {
+    let mut env = env();
+    eval_in(&mut env, WINDOW);
+    eval_err_in(&mut env, call)
+}
+
+#[test]
+fn missing_keywords_get_their_defaults() {
+    assert_eq!(window("(window 'main)"), "(main 80 untitled)");
+    assert_eq!(window("(window 'main :title \"hi\")"), "(main 80 hi)");
+}
+
+#[test]
+fn keywords_can_come_in_any_order_after_the_positionals() {
+    assert_eq!(window("(window 'main :width 100 :title \"hi\")"), "(main 100 hi)");
+    assert_eq!(window("(window 'main :title \"hi\" :width 100)"), "(main 100 hi)");
+}
+
+#[test]
+fn defaults_are_evaluated_for_each_call() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'w 1) (let 'width (fn '(&key (width w)) 'width))");
+    assert_eq!(eval_in(&mut env, "(width)"), "1");
+    eval_in(&mut env, "(let 'w 2)");
+    assert_eq!(eval_in(&mut env, "(width)"), "2");
+}
+
+#[test]
+fn an_unknown_keyword_names_the_valid_ones() {
+    let err = window_err("(window 'main :height 1)");
+    assert!(err.starts_with("error: unknown keyword ':height', expected one of :width :title"), "{err}");
+}
+
+#[test]
+fn a_keyword_missing_its_value_is_an_error() {
+    let err = window_err("(window 'main :width)");
+    assert!(err.starts_with("error: keyword ':width' is missing its value"), "{err}");
+}
+
+#[test]
+fn the_positionals_are_still_required() {
+    let err = window_err("(window)");
+    assert!(err.starts_with("error: expected 1 plus keyword arguments, but got 0"), "{err}");
+}
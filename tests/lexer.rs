@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+use yal::lexer::{ LexError, Lexer, TokenKind };
+
+/// The kinds and texts of the tokens of `src`.
+fn tokens(src: &str) -> Vec<(TokenKind, &str)> {
+    Lexer::new(src).map(|token| (token.kind, token.text(src))).collect()
+}
+
+#[test]
+fn a_form_is_split_into_its_tokens() {
+    use TokenKind::*;
+    assert_eq!(tokens("(let 'x (+ 1 -2.5)) ; done"), [
+        (Open, "("),
+        (Ident, "let"),
+        (Quote, "'"),
+        (Ident, "x"),
+        (Open, "("),
+        (Ident, "+"),
+        (Number, "1"),
+        (Number, "-2.5"),
+        (Close, ")"),
+        (Close, ")"),
+        (Comment, "; done"),
+    ]);
+}
+
+#[test]
+fn numbers_and_strings_are_single_tokens() {
+    use TokenKind::*;
+    assert_eq!(tokens("1/3 1.5e-3 +inf.0 \"a \\\"b\\\" c\" -"), [
+        (Number, "1/3"),
+        (Number, "1.5e-3"),
+        (Number, "+inf.0"),
+        (String, "\"a \\\"b\\\" c\""),
+        (Ident, "-"),
+    ]);
+}
+
+#[test]
+fn a_lone_dot_is_a_name() {
+    use TokenKind::*;
+    assert_eq!(tokens("(x . rest) .5"), [
+        (Open, "("),
+        (Ident, "x"),
+        (Ident, "."),
+        (Ident, "rest"),
+        (Close, ")"),
+        (Error(LexError::UnexpectedChar('.')), "."),
+        (Number, "5"),
+    ]);
+}
+
+#[test]
+fn an_unterminated_string_goes_to_the_end() {
+    use TokenKind::*;
+    assert_eq!(tokens("(print \"unfinished\n(more)"), [
+        (Open, "("),
+        (Ident, "print"),
+        (Error(LexError::UnterminatedString), "\"unfinished\n(more)"),
+    ]);
+}
+
+#[test]
+fn lexing_carries_on_after_errors() {
+    use TokenKind::*;
+    assert_eq!(tokens("(a # b"), [
+        (Open, "("),
+        (Ident, "a"),
+        (Error(LexError::BadConditional), "#"),
+        (Ident, "b"),
+    ]);
+    assert_eq!(tokens("#+feature(ffi) x"), [(Conditional, "#+feature(ffi)"), (Ident, "x")]);
+}
+
+#[test]
+fn tokens_cover_the_source_but_whitespace() {
+    let mut rng = Rng(0x6a09_e667_f3bc_c908);
+    let pieces = ["(", ")", "'", "\"", "\\", " ", "\n", ";", "a", "1", "#", "é", "1/", ".5", "+"];
+    for _ in 0..5_000 {
+        let src: String = (0..rng.below(30)).map(|_| pieces[rng.below(pieces.len())]).collect();
+        let mut end = 0;
+        for token in Lexer::new(&src) {
+            assert!(src[end..token.span.start].trim().is_empty(), "{:?} skips text before {:?}", src, token);
+            assert!(token.span.end > token.span.start, "{:?} gives the empty {:?}", src, token);
+            end = token.span.end;
+        }
+        assert!(src[end..].trim().is_empty(), "{:?} leaves text after the last token", src);
+    }
+}
@@ -0,0 +1,49 @@
+//! Embedding the interpreter through what the crate root exports, and
+//! nothing else.
+
+use yal::{ call, evaluate, Environment, Reader, RefVal, RuntimeError, Value };
+
+#[test]
+fn a_program_is_read_and_evaluated_form_by_form() {
+    let mut env = Environment::with_std_lib().unwrap();
+    env.set_output(Box::new(std::io::sink()));
+    let src = "(let 'square (fn '(x) '(* x x))) (square 12)";
+    let mut last = None;
+    for expr in Reader::new(src).parse_sexprs().map_err(|err| err.to_string()).unwrap() {
+        last = Some(evaluate(&expr, &mut env).unwrap());
+    }
+    assert_eq!(last.unwrap().to_string(), "144");
+}
+
+#[test]
+fn script_functions_are_called_from_rust() {
+    let mut env = Environment::with_std_lib().unwrap();
+    env.eval_str("(let 'add (fn '(a b) '(+ a b)))").unwrap();
+    let add = match &**env.lookup_var("add").unwrap() {
+        Value::Function(add) => add.clone(),
+        val => panic!("'add' is {}", val),
+    };
+    env.push_stack(RefVal::from(2i64));
+    env.push_stack(RefVal::from(40i64));
+    assert_eq!(call(&add, 2, &mut env).unwrap().to_string(), "42");
+}
+
+#[test]
+fn natives_are_called_from_scripts() {
+    let mut env = Environment::with_std_lib().unwrap();
+    env.register_external_fun("greet", 1, |env| {
+        let name = env.pop_stack()?;
+        if name.to_string().is_empty() {
+            return Err(RuntimeError::Custom("'greet' needs a name".to_string()));
+        }
+        Ok(RefVal::from(format!("hello, {}", name).as_str()))
+    });
+    assert_eq!(env.eval_str("(greet 'yal)").unwrap().to_string(), "hello, yal");
+    assert!(env.eval_str("(greet \"\")").is_err());
+}
+
+#[test]
+fn eval_str_needs_no_environment() {
+    assert_eq!(yal::eval_str("(+ 1 2)").unwrap().to_string(), "3");
+    assert!(matches!(yal::eval_str("(+ 1"), Err(yal::EvalError::Parse { .. })));
+}
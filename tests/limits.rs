@@ -0,0 +1,129 @@
+mod common;
+
+use std::time::{ Duration, Instant };
+
+use common::*;
+use yal::RuntimeError;
+
+#[test]
+fn an_infinite_loop_runs_out_of_fuel_promptly() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'forever (fn '() '(recur)))");
+    env.set_fuel(100_000);
+    let start = Instant::now();
+    assert_eq!(root_error_in(&mut env, "(forever)"), RuntimeError::OutOfFuel);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(env.fuel(), Some(0));
+}
+
+#[test]
+fn fuel_use_is_deterministic() {
+    let used = || {
+        let mut env = env();
+        eval_in(&mut env, "(let 'fib (fn '(n) '(if (= n 0) '0 '(if (= n 1) '1 '(+ (fib (- n 1)) (fib (- n 2)))))))");
+        env.set_fuel(1_000_000);
+        eval_in(&mut env, "(fib 12)");
+        1_000_000 - env.fuel().unwrap()
+    };
+    let first = used();
+    assert!(first > 0);
+    assert_eq!(used(), first);
+}
+
+#[test]
+fn fuel_can_be_refilled_between_forms() {
+    let mut env = env();
+    env.set_fuel(50);
+    eval_in(&mut env, "(+ 1 2)");
+    let left = env.fuel().unwrap();
+    assert!(left < 50);
+    env.set_fuel(50);
+    eval_in(&mut env, "(+ 1 2)");
+    assert_eq!(env.fuel(), Some(left));
+
+    env.clear_fuel();
+    eval_in(&mut env, "(+ 1 2)");
+    assert_eq!(env.fuel(), None);
+}
+
+#[test]
+fn steps_are_counted_without_a_limit() {
+    let mut env = env();
+    let before = env.steps();
+    eval_in(&mut env, "(+ 1 (* 2 3))");
+    assert!(env.steps() > before);
+}
+
+const DOUBLING: &str = "
+    (let 'double (fn '(xs) '(cons xs xs)))
+    (let 'grow (fn '(xs n) '(if (= n 0) 'xs '(grow (double xs) (- n 1)))))";
+
+#[test]
+fn growth_past_the_size_limit_is_a_clean_error() {
+    let mut env = env();
+    eval_in(&mut env, DOUBLING);
+    env.set_size_limit(Some(1_000));
+    assert!(matches!(
+        root_error_in(&mut env, "(grow '(1) 30)"),
+        RuntimeError::ResourceLimit { size, limit: 1_000 } if size > 1_000
+    ));
+    // What fits is still fine.
+    eval_in(&mut env, "(let 'small (grow '(1) 3))");
+    assert!(env.lookup_var("small").unwrap().size() < 1_000);
+}
+
+#[test]
+fn binding_a_value_past_the_limit_fails() {
+    let mut env = env();
+    eval_in(&mut env, DOUBLING);
+    eval_in(&mut env, "(let 'big (grow '(1) 8))");
+    env.set_size_limit(Some(100));
+    assert!(matches!(root_error_in(&mut env, "(let 'copy big)"), RuntimeError::ResourceLimit { .. }));
+    assert!(env.lookup_var("copy").is_none());
+}
+
+#[test]
+fn sizes_are_unlimited_by_default() {
+    let mut env = env();
+    eval_in(&mut env, DOUBLING);
+    eval_in(&mut env, "(let 'big (grow '(1) 16))");
+    assert!(env.lookup_var("big").unwrap().size() > 65_000);
+}
+
+#[test]
+fn each_test_gets_the_fuel_the_tests_started_with() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'forever (fn '() '(recur)))");
+    eval_in(&mut env, "(deftest 'spins '(forever)) (deftest 'adds '(= (+ 1 2) 3)) (deftest 'also-adds '(= (* 2 2) 4))");
+    env.set_fuel(10_000);
+    let (failed, printed) = env.capture_output(|env| env.eval_str("(run-tests)").map(|failed| failed.to_string()));
+    assert_eq!(failed.unwrap(), "1");
+    assert!(printed.contains("test spins ... FAILED\n"), "{printed}");
+    assert!(printed.contains("ran out of fuel"), "{printed}");
+    assert!(printed.contains("test adds ... ok\ntest also-adds ... ok\n"), "{printed}");
+}
+
+#[test]
+fn an_infinite_loop_stops_at_a_50ms_timeout() {
+    for forever in ["(let 'forever (fn '() '(recur)))", "(let 'forever (fn '() '(if (= (+ 1 2) 3) '(recur) 'nil)))"] {
+        let mut env = env();
+        eval_in(&mut env, forever);
+        env.set_timeout(Duration::from_millis(50));
+        let start = Instant::now();
+        assert_eq!(root_error_in(&mut env, "(forever)"), RuntimeError::Timeout(Duration::from_millis(50)));
+        let took = start.elapsed();
+        assert!(took >= Duration::from_millis(50), "{took:?}");
+        assert!(took < Duration::from_secs(1), "{took:?}");
+    }
+}
+
+#[test]
+fn the_timeout_starts_over_with_each_top_level_form() {
+    let mut env = env();
+    env.set_timeout(Duration::from_millis(300));
+    eval_in(&mut env, "(sleep 200) (sleep 200)");
+    assert_eq!(root_error_in(&mut env, "(sleep 400)"), RuntimeError::Timeout(Duration::from_millis(300)));
+
+    env.clear_timeout();
+    eval_in(&mut env, "(sleep 400)");
+}
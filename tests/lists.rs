@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn dropping_a_deeply_nested_list_does_not_overflow() {
+    let src = "
+        (let 'nest (fn '(n a) '(if (= n 0) 'a '(recur (- n 1) (cons a '())))))
+        (let 'big (nest 100000 '()))
+        t";
+    assert_eq!(eval(src), "t");
+}
+
+/// Defines `build`, which conses the numbers from 1 to `n` in front of `acc`,
+/// and `sum`, which adds up a list of numbers walking it with `car` and
+/// `cdr`.
+const HELPERS: &str = "
+    (let 'build (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons n acc)))))
+    (let 'sum (fn '(xs acc) '(if (= xs '()) 'acc '(recur (cdr xs) (+ acc (car xs))))))";
+
+#[test]
+fn cons_builds_the_same_lists_as_literals() {
+    let mut env = env();
+    eval_in(&mut env, HELPERS);
+    assert_eq!(eval_in(&mut env, "(build 5 '())"), "(1 2 3 4 5)");
+    assert_eq!(eval_in(&mut env, "(equal? (build 3 '(4 5)) '(1 2 3 4 5))"), "t");
+    assert_eq!(eval_in(&mut env, "(= (build 3 '()) '(1 2 3))"), "t");
+    assert_eq!(eval_in(&mut env, "(cons '(1) '(2))"), "((1) 2)");
+    assert_eq!(eval_in(&mut env, "(cons 1 '())"), "(1)");
+}
+
+#[test]
+fn consing_onto_a_list_leaves_it_as_it_was() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'xs '(2 3)) (let 'a (cons 1 xs)) (let 'b (cons 0 xs))");
+    assert_eq!(eval_in(&mut env, "xs"), "(2 3)");
+    assert_eq!(eval_in(&mut env, "a"), "(1 2 3)");
+    assert_eq!(eval_in(&mut env, "b"), "(0 2 3)");
+    assert_eq!(eval_in(&mut env, "(= (cdr a) (cdr b))"), "t");
+}
+
+#[test]
+fn car_and_cdr_leave_their_list_as_it_was() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'xs '((1 2) 3 4))");
+    assert_eq!(eval_in(&mut env, "(car xs)"), "(1 2)");
+    assert_eq!(eval_in(&mut env, "(cdr xs)"), "(3 4)");
+    assert_eq!(eval_in(&mut env, "(cdr (cdr (cdr xs)))"), "()");
+    assert_eq!(eval_in(&mut env, "(cons 0 (cdr xs))"), "(0 3 4)");
+    assert_eq!(eval_in(&mut env, "xs"), "((1 2) 3 4)");
+    assert!(eval_err_in(&mut env, "(cdr '())").starts_with("error: expected non empty list in 'cdr'"));
+}
+
+#[test]
+fn long_lists_are_built_and_walked() {
+    let mut env = env();
+    eval_in(&mut env, HELPERS);
+    eval_in(&mut env, "(let 'big (build 20000 '()))");
+    assert_eq!(eval_in(&mut env, "(car big)"), "1");
+    assert_eq!(eval_in(&mut env, "(= big (build 20000 '()))"), "t");
+    assert_eq!(eval_in(&mut env, "(= (cons 0 big) (build 20000 '()))"), "f");
+    assert_eq!(eval_in(&mut env, "(sum big 0)"), "200010000");
+    assert_eq!(eval_in(&mut env, "(sum (cdr big) 0)"), "200009999");
+    assert_eq!(eval_in(&mut env, "(car big)"), "1");
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::*;
+
+/// Where running `path` failed and why, as `L:C: message`.
+fn runtime_error(path: &str) -> String {
+    let (status, _, stderr) = yal(&[path]);
+    assert_eq!(status, 1, "running {} didn't fail:\n{}", path, stderr);
+    let mut lines = stderr.lines();
+    let message = lines.next().unwrap().strip_prefix("error: ").unwrap();
+    let location = lines.next().unwrap().strip_prefix(&format!(" --> {}:", path)).unwrap();
+    format!("{}: {}", location, message)
+}
+
+#[test]
+fn runtime_errors_are_located_where_the_fixtures_expect() {
+    for path in fixtures("runtime") {
+        let expected = expected_diagnostics(&path);
+        assert_eq!(vec![runtime_error(path.to_str().unwrap())], expected, "in {}", path.display());
+    }
+}
+
+#[test]
+fn eval_str_errors_point_into_the_source() {
+    let err = eval_err("(let 'x 1)\n\n  (+ x y)");
+    assert!(err.contains(" --> 3:8"), "{err}");
+    assert!(err.contains("3 |   (+ x y)"), "{err}");
+}
@@ -0,0 +1,43 @@
+//! `loop` and `recur`, which iterate without growing the stack, and the
+//! places `recur` can't be used.
+
+mod common;
+
+use common::*;
+
+/// Small enough that a million frames, or anything near it, wouldn't fit.
+const STACK: usize = 512 * 1024;
+
+#[test]
+fn a_loop_runs_a_million_times_in_constant_stack() {
+    let sum = with_stack(STACK, || {
+        eval("(loop '((i 0) (sum 0)) '(if (= i 1000000) 'sum '(recur (+ i 1) (+ sum i))))")
+    });
+    assert_eq!(sum, "499999500000");
+}
+
+#[test]
+fn a_function_recurs_a_million_times_in_constant_stack() {
+    let n = with_stack(STACK, || {
+        eval("(let 'count-up (fn '(i) '(if (= i 1000000) 'i '(recur (+ i 1))))) (count-up 0)")
+    });
+    assert_eq!(n, "1000000");
+}
+
+#[test]
+fn recur_outside_a_loop_is_an_error() {
+    let err = eval_err("(recur 1)");
+    assert!(err.starts_with("error: 'recur' can only be used in a loop or a function body"), "{err}");
+}
+
+#[test]
+fn recur_with_the_wrong_number_of_values_is_an_error() {
+    let err = eval_err("(loop '((i 0)) '(recur 1 2))");
+    assert!(err.starts_with("error: 'recur' expected 1 value, got 2"), "{err}");
+}
+
+#[test]
+fn recur_out_of_tail_position_is_an_error() {
+    let err = eval_err("(loop '((i 0)) '(+ 1 (recur 1)))");
+    assert!(err.starts_with("error: 'recur' can only be used in tail position"), "{err}");
+}
@@ -0,0 +1,77 @@
+//! `match`, which picks the first clause whose pattern a value matches, and
+//! binds what the pattern names while its result is evaluated.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn literals_match_values_equal_to_them() {
+    let describe = "(let 'describe (fn '(x) '(match x '((0 'zero) (\"s\" 'string) (nil 'empty) (t 'true) (:key 'key) (_ 'other)))))";
+    let mut env = env();
+    eval_in(&mut env, describe);
+    for (val, expected) in [("0", "zero"), ("\"s\"", "string"), ("'()", "empty"), ("t", "true"), (":key", "key"), ("1", "other")] {
+        assert_eq!(eval_in(&mut env, &format!("(describe {val})")), expected, "{val}");
+    }
+}
+
+#[test]
+fn names_bind_and_quoted_symbols_are_literals() {
+    // A bare name binds whatever is there, so it matches a symbol too.
+    assert_eq!(eval("(match 'other '((sym (cons sym '()))))"), "(other)");
+    // Quoted, it only matches that symbol.
+    assert_eq!(eval("(match 'other '(('sym 'literal) (_ 'no)))"), "no");
+    assert_eq!(eval("(match 'sym '(('sym 'literal) (_ 'no)))"), "literal");
+    assert_eq!(eval("(match '(a 1) '(('(a 1) 'quoted-list) (_ 'no)))"), "quoted-list");
+    assert_eq!(eval("(match '(a 1) '((('a n) n)))"), "1");
+}
+
+#[test]
+fn bindings_go_away_after_the_clause() {
+    let mut env = env();
+    assert_eq!(eval_in(&mut env, "(match 5 '((x (* x 2))))"), "10");
+    assert!(eval_err_in(&mut env, "x").starts_with("error: name 'x' was not defined"));
+}
+
+#[test]
+fn the_first_matching_clause_wins() {
+    assert_eq!(eval("(match '(1 2) '(((x) 'one) ((x y) 'two) (_ 'many)))"), "two");
+    assert_eq!(eval("(match 3 '((x 'first) (3 'second)))"), "first");
+    assert_eq!(eval("(match 3 '((4 'four)))"), "nil");
+}
+
+#[test]
+fn nested_patterns_destructure_nested_lists() {
+    assert_eq!(eval("(match '(1 (2 (3 4))) '(((a (b (c d))) (+ a (* 10 d)))))"), "41");
+    assert_eq!(eval("(match '(1 (2 3)) '(((a (b)) 'short) ((a (b c)) (+ a b c))))"), "6");
+    assert_eq!(eval("(match '(1 2) '(((a (b)) 'nested) (_ 'flat)))"), "flat");
+}
+
+#[test]
+fn a_dot_binds_the_rest_of_the_list() {
+    assert_eq!(eval("(match '(1 2 3 4) '(((x y . rest) rest)))"), "(3 4)");
+    assert_eq!(eval("(match '(1 2) '(((x y . rest) rest)))"), "()");
+    assert_eq!(eval("(match '(1) '(((x y . rest) rest) (_ 'too-short)))"), "too-short");
+    assert_eq!(eval("(match '((1 2 3) 4) '((((x . xs) . _) xs)))"), "(2 3)");
+    assert_eq!(eval("(let '(head . tail) '(1 2 3)) tail"), "(2 3)");
+}
+
+#[test]
+fn malformed_patterns_are_errors() {
+    for (src, expected) in [
+        ("(match '(1) '(((. r) r)))", "error: '.' must come after a pattern and be followed by the name"),
+        ("(match '(1) '(((x . 5) x)))", "error: '.' must come after a pattern and be followed by the name"),
+        ("(match '(1) '(((x . r s) x)))", "error: '.' must come after a pattern and be followed by the name"),
+        ("(match '(1 1) '(((x x) x)))", "error: 'x' is bound more than once"),
+        ("(match 1 '((x)))", "error: expected a (pattern result) clause in 'match'"),
+    ] {
+        let err = eval_err(src);
+        assert!(err.starts_with(expected), "{src}: {err}");
+    }
+}
+
+#[test]
+fn dotted_patterns_print_and_read_back() {
+    assert_eq!(eval("'(x . rest)"), "(x . rest)");
+    assert_eq!(eval("(equal? '(x . rest) (cons 'x '(. rest)))"), "t");
+}
@@ -0,0 +1,59 @@
+//! `define-multi`, which makes a function with a clause for each number of
+//! arguments it takes.
+
+mod common;
+
+use common::*;
+
+const GREET: &str = "(define-multi 'greet '(((name) (cons 'hi (cons name '()))) \
+                                          ((greeting name) (cons greeting (cons name '()))) \
+                                          ((a b c & rest) rest)))";
+
+#[test]
+fn the_clause_taking_as_many_arguments_is_called() {
+    let mut env = env();
+    eval_in(&mut env, GREET);
+    assert_eq!(eval_in(&mut env, "(greet 'ann)"), "(hi ann)");
+    assert_eq!(eval_in(&mut env, "(greet 'hello 'ann)"), "(hello ann)");
+}
+
+#[test]
+fn the_variadic_clause_takes_the_counts_past_its_own() {
+    let mut env = env();
+    eval_in(&mut env, GREET);
+    assert_eq!(eval_in(&mut env, "(greet 1 2 3)"), "nil");
+    assert_eq!(eval_in(&mut env, "(greet 1 2 3 4 5)"), "(4 5)");
+}
+
+#[test]
+fn calling_with_no_matching_clause_lists_the_arities() {
+    let mut env = env();
+    eval_in(&mut env, GREET);
+    let err = eval_err_in(&mut env, "(greet)");
+    assert!(err.starts_with("error: expected 1, 2 or at least 3 arguments, but got 0 in "), "{err}");
+    eval_in(&mut env, "(define-multi 'one '(((a) a)))");
+    let err = eval_err_in(&mut env, "(one 1 2)");
+    assert!(err.starts_with("error: expected 1 argument, but got 2 in #<function one of 1 argument>"), "{err}");
+}
+
+#[test]
+fn two_clauses_of_the_same_arity_are_an_error() {
+    let err = eval_err("(define-multi 'g '(((a) a) ((b) b)))");
+    assert!(err.starts_with("error: 'g' has more than one clause taking 1 argument\n"), "{err}");
+    let err = eval_err("(define-multi 'g '(((a b) a) ((c d) b)))");
+    assert!(err.starts_with("error: 'g' has more than one clause taking 2 arguments\n"), "{err}");
+}
+
+#[test]
+fn it_shows_its_arities() {
+    let mut env = env();
+    eval_in(&mut env, GREET);
+    assert_eq!(eval_in(&mut env, "greet"), "#<function greet of 1, 2 or at least 3 arguments>");
+}
+
+#[test]
+fn natives_taking_one_argument_say_so() {
+    assert_eq!(eval("car"), "lib function 'car' with 1 argument");
+    let err = eval_err("(car 1 2)");
+    assert!(err.starts_with("error: expected 1 argument, but got 2 in lib function 'car' with 1 argument\n"), "{err}");
+}
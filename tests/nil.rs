@@ -0,0 +1,38 @@
+mod common;
+
+use common::*;
+use yal::Value;
+
+#[test]
+fn nil_is_bound_from_the_start() {
+    assert!(matches!(&*env().eval_str("nil").unwrap(), Value::Nil));
+    assert_eq!(eval("(describe 'nil)"), "((type nil))");
+}
+
+#[test]
+fn nil_prints_as_nil() {
+    assert_eq!(output("(print nil)"), "nil");
+    assert_eq!(eval("nil"), "nil");
+}
+
+#[test]
+fn nil_is_what_side_effects_give() {
+    assert_eq!(eval("(= (print 1) nil)"), "t");
+    assert_eq!(eval("(= (match 1 '((2 'two))) nil)"), "t");
+}
+
+#[test]
+fn nil_is_equal_to_the_empty_list() {
+    assert_eq!(eval("(= nil '())"), "t");
+    assert_eq!(eval("(= '() nil)"), "t");
+    assert_eq!(eval("(equal? nil '())"), "t");
+    assert_eq!(eval("(= nil (cdr '(1)))"), "t");
+    assert_eq!(eval("(= nil '(nil))"), "f");
+    assert_eq!(eval("(= nil 0)"), "f");
+}
+
+#[test]
+fn nil_is_false() {
+    assert!(!Value::Nil.is_truthy());
+    assert_eq!(eval("(if nil ''yes ''no)"), "no");
+}
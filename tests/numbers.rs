@@ -0,0 +1,84 @@
+//! Numbers read back as what was written: printing a number and reading
+//! the output gives the same number.
+
+mod common;
+
+use common::*;
+use yal::{ Atom, Reader, RefVal, SExpr, Value };
+
+/// The number `text` reads as.
+fn read(text: &str) -> Atom {
+    let mut exprs = Reader::new(text).parse_sexprs().unwrap_or_else(|err| panic!("{:?}: {}", text, err));
+    match exprs.pop_front() {
+        Some(SExpr::Atom(atom @ (Atom::Int(_) | Atom::Float(_)), _)) if exprs.is_empty() => atom,
+        other => panic!("{:?} read as {:?}", text, other),
+    }
+}
+
+/// Whether `x` prints as something that reads back as `x`. All NaNs read
+/// back as NaN, which is the only one that isn't itself.
+fn round_trips(x: f64) -> bool {
+    let written = RefVal::owned(Value::Float(x)).to_string();
+    match read(&written) {
+        Atom::Float(y) if x.is_nan() => y.is_nan(),
+        Atom::Float(y) => y.to_bits() == x.to_bits(),
+        _ => false,
+    }
+}
+
+#[test]
+fn special_floats_round_trip() {
+    let specials = [
+        0.0,
+        -0.0,
+        1.0,
+        -1.0,
+        0.1,
+        1e21,
+        1e-7,
+        123456789012345680000.0,
+        f64::MAX,
+        f64::MIN,
+        f64::MIN_POSITIVE,
+        f64::EPSILON,
+        5e-324,
+        -5e-324,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NAN,
+        -f64::NAN,
+    ];
+    for x in specials {
+        assert!(round_trips(x), "{:?} printed as {}", x, RefVal::owned(Value::Float(x)));
+    }
+}
+
+#[test]
+fn random_floats_round_trip() {
+    let mut rng = Rng(0x1234_5678_9abc_def0);
+    for _ in 0..5000 {
+        // Random bits cover every exponent, subnormals and NaNs included.
+        let x = f64::from_bits(rng.next_u64());
+        assert!(round_trips(x), "{:?} printed as {}", x, RefVal::owned(Value::Float(x)));
+    }
+}
+
+#[test]
+fn ints_round_trip() {
+    let mut rng = Rng(42);
+    let randoms = (0..1000).map(|_| rng.next_u64() as i64);
+    for n in [0, 1, -1, i64::MAX, i64::MIN].into_iter().chain(randoms) {
+        let written = RefVal::owned(Value::Int(n)).to_string();
+        assert_eq!(read(&written), Atom::Int(n), "{} printed as {}", n, written);
+    }
+}
+
+#[test]
+fn special_floats_are_written_as_the_reader_reads_them() {
+    assert_eq!(eval("(/ 1.0 0.0)"), "+inf.0");
+    assert_eq!(eval("(/ -1.0 0.0)"), "-inf.0");
+    assert_eq!(eval("(/ 0.0 0.0)"), "+nan.0");
+    assert_eq!(eval("1e300"), "1e300");
+    assert_eq!(eval("(= +inf.0 (/ 1.0 0.0))"), "t");
+    assert_eq!(eval("(= -2.5e-8 (* -2.5 1e-8))"), "t");
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{ self, Write };
+use std::rc::Rc;
+
+use common::*;
+
+/// A writer keeping what is written, for the test to look at.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Shared {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn printing_goes_to_the_writer_set() {
+    let shared = Shared::default();
+    let mut env = env();
+    env.set_output(Box::new(shared.clone()));
+    eval_in(&mut env, r#"(print "one ") (print 2) (flush)"#);
+    assert_eq!(shared.text(), "one 2");
+}
+
+#[test]
+fn replacing_the_writer_gives_back_the_old_one() {
+    let first = Shared::default();
+    let second = Shared::default();
+    let mut env = env();
+    env.set_output(Box::new(first.clone()));
+    eval_in(&mut env, "(print 1)");
+    let mut previous = env.replace_output(Box::new(second.clone()));
+    eval_in(&mut env, "(print 2)");
+    previous.write_all(b"!").unwrap();
+    assert_eq!((first.text(), second.text()), ("1!".to_string(), "2".to_string()));
+}
+
+#[test]
+fn capture_output_restores_the_writer() {
+    let shared = Shared::default();
+    let mut env = env();
+    env.set_output(Box::new(shared.clone()));
+    let (_, printed) = env.capture_output(|env| env.eval_str("(print 1)").map(|_| ()));
+    eval_in(&mut env, "(print 2)");
+    assert_eq!((printed.as_str(), shared.text().as_str()), ("1", "2"));
+}
+
+#[test]
+fn flush_gives_nil() {
+    assert_eq!(eval("(flush)"), "nil");
+}
+
+#[test]
+fn the_cli_prints_results_in_order_with_the_output() {
+    let (status, stdout, stderr) = yal(&["-p", "-e", "(print 1)", "-e", "\"s\"", "-e", "(+ 1 2)"]);
+    assert_eq!((status, stdout.as_str()), (0, "1\"s\"\n3\n"), "{stderr}");
+}
+
+#[test]
+fn the_cli_flushes_what_was_printed_before_an_error() {
+    let (status, stdout, stderr) = yal(&["-e", "(print 1) (car 1)"]);
+    assert_eq!((status, stdout.as_str()), (1, "1"));
+    assert!(stderr.contains("error"), "{stderr}");
+}
+
+#[test]
+fn capture_output_gets_what_print_and_help_print() {
+    let (_, printed) = env().capture_output(|env| env.eval_str(r#"(print "a") (print 1.5) (help)"#).map(|_| ()));
+    assert!(printed.starts_with("a1.5"), "{printed}");
+    assert!(printed.contains("pmap"), "{printed}");
+}
+
+#[test]
+fn capture_output_gets_what_pmap_workers_print() {
+    let (result, printed) = env().capture_output(|env| env.eval_str("(pmap (fn '(x) '(print x)) '(1 2 3))").map(|_| ()));
+    assert!(result.is_ok());
+    assert_eq!(printed, "123");
+}
+
+#[test]
+fn take_output_gives_back_the_writer() {
+    let shared = Shared::default();
+    let mut env = env();
+    env.set_output(Box::new(shared.clone()));
+    eval_in(&mut env, "(print 1)");
+    let mut taken = env.take_output();
+    taken.write_all(b"2").unwrap();
+    assert_eq!(shared.text(), "12");
+}
+
+/// Set for the copy of the test binary that prints while capturing.
+const CAPTURING_CHILD: &str = "YAL_TEST_CAPTURING_CHILD";
+
+#[test]
+fn captured_output_stays_off_stdout() {
+    let marker = "printed-while-captured";
+    if std::env::var_os(CAPTURING_CHILD).is_some() {
+        let mut env = yal::Environment::with_std_lib().unwrap();
+        let (_, printed) = env.capture_output(|env| env.eval_str(&format!("(print \"{marker}\")")).map(|_| ()));
+        assert_eq!(printed, marker);
+        return;
+    }
+
+    // The test harness captures what Rust prints, not what is written to
+    // standard output directly, so the test runs again in a process of its
+    // own.
+    let out = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "captured_output_stays_off_stdout", "--nocapture", "--test-threads", "1"])
+        .env(CAPTURING_CHILD, "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{stdout}");
+    assert!(stdout.contains("1 passed"), "{stdout}");
+    assert!(!stdout.contains(marker), "{stdout}");
+}
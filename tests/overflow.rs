@@ -0,0 +1,132 @@
+//! What ints do at the ends of `i64`: become big ints in the default
+//! 'promote mode, fail in 'checked, and wrap around in 'wrapping.
+
+mod common;
+
+use common::*;
+
+const MAX: &str = "9223372036854775807";
+const MIN: &str = "-9223372036854775808";
+
+/// What `src` gives with ints overflowing as `mode` says.
+fn in_mode(mode: &str, src: &str) -> String {
+    let mut env = env();
+    eval_in(&mut env, &format!("(set-int-overflow-mode '{mode})"));
+    eval_in(&mut env, src)
+}
+
+/// The error `src` fails with, with ints overflowing as `mode` says.
+fn in_mode_err(mode: &str, src: &str) -> String {
+    let mut env = env();
+    eval_in(&mut env, &format!("(set-int-overflow-mode '{mode})"));
+    eval_err_in(&mut env, src)
+}
+
+/// What `src` gives with ints wrapping.
+fn wrapping(src: &str) -> String {
+    in_mode("wrapping", src)
+}
+
+/// The error `src` fails with, with ints wrapping.
+fn wrapping_err(src: &str) -> String {
+    in_mode_err("wrapping", src)
+}
+
+#[test]
+fn promoted_overflow_gives_big_ints() {
+    assert_eq!(eval(&format!("(+ {MAX} 1)")), "9223372036854775808");
+    assert_eq!(eval(&format!("(- {MIN} 1)")), "-9223372036854775809");
+    assert_eq!(eval(&format!("(* {MAX} 2)")), "18446744073709551614");
+    assert_eq!(eval(&format!("(* {MIN} -1)")), "9223372036854775808");
+    assert_eq!(eval(&format!("(neg {MIN})")), "9223372036854775808");
+    assert_eq!(eval(&format!("(abs {MIN})")), "9223372036854775808");
+    assert_eq!(eval(&format!("(* {MAX} {MAX} {MAX})")), "784637716923335095224261902710254454442933591094742482943");
+}
+
+#[test]
+fn big_ints_that_fit_again_are_ints() {
+    assert_eq!(eval(&format!("(- (+ {MAX} 1) 1)")), MAX);
+    assert_eq!(eval(&format!("(+ (- {MIN} 1) 1)")), MIN);
+    assert_eq!(eval(&format!("(neg (abs {MIN}))")), MIN);
+    assert_eq!(eval(&format!("(- (- (* {MAX} 2) {MAX}) {MAX})")), "0");
+}
+
+#[test]
+fn big_ints_are_read_compared_and_typed_as_ints() {
+    assert_eq!(eval("-100000000000000000000"), "-100000000000000000000");
+    assert_eq!(eval("(= 100000000000000000000 (* 10000000000 10000000000))"), "t");
+    assert_eq!(eval("(= 18446744073709551616 18446744073709551616.0)"), "t");
+    assert_eq!(eval("(= 18446744073709551617 18446744073709551616.0)"), "f");
+    assert_eq!(eval("(+ 18446744073709551616 0.5)"), "18446744073709551616.0");
+    assert_eq!(eval("(let 'big 18446744073709551616) (describe 'big)"), "((type int))");
+}
+
+#[test]
+fn big_ints_stay_exact_in_every_mode() {
+    for mode in ["checked", "wrapping"] {
+        assert_eq!(in_mode(mode, "(+ 18446744073709551616 1)"), "18446744073709551617", "{mode}");
+    }
+    let err = eval_err("(/ 18446744073709551616 3)");
+    assert!(err.starts_with("error: integer overflow in 18446744073709551616 / 3\n"), "{err}");
+}
+
+#[test]
+fn checked_overflow_is_an_error() {
+    for (src, operation) in [
+        (format!("(+ {MAX} 1)"), format!("{MAX} + 1")),
+        (format!("(- {MIN} 1)"), format!("{MIN} - 1")),
+        (format!("(* {MAX} 2)"), format!("{MAX} * 2")),
+        (format!("(* {MIN} -1)"), format!("{MIN} * -1")),
+        (format!("(neg {MIN})"), format!("neg {MIN}")),
+        (format!("(abs {MIN})"), format!("abs {MIN}")),
+    ] {
+        let err = in_mode_err("checked", &src);
+        assert!(err.starts_with(&format!("error: integer overflow in {operation}\n")), "{src}: {err}");
+    }
+}
+
+#[test]
+fn checked_stops_right_at_the_ends() {
+    assert_eq!(in_mode("checked", "(+ 9223372036854775806 1)"), MAX);
+    assert_eq!(in_mode("checked", "(- -9223372036854775807 1)"), MIN);
+    assert_eq!(in_mode("checked", &format!("(neg {MAX})")), "-9223372036854775807");
+    assert_eq!(in_mode("checked", &format!("(abs (+ {MIN} 1))")), MAX);
+}
+
+#[test]
+fn wrapping_overflow_wraps() {
+    assert_eq!(wrapping(&format!("(+ {MAX} 1)")), MIN);
+    assert_eq!(wrapping(&format!("(- {MIN} 1)")), MAX);
+    assert_eq!(wrapping(&format!("(* {MAX} 2)")), "-2");
+    assert_eq!(wrapping(&format!("(* {MIN} -1)")), MIN);
+    assert_eq!(wrapping(&format!("(neg {MIN})")), MIN);
+    assert_eq!(wrapping(&format!("(abs {MIN})")), MIN);
+}
+
+#[test]
+fn rationals_overflow_even_when_wrapping() {
+    let err = wrapping_err(&format!("(+ {MAX} 1/2)"));
+    assert!(err.starts_with("error: integer overflow in "), "{err}");
+}
+
+#[test]
+fn the_mode_can_be_set_from_the_command_line() {
+    let (status, stdout, _) = yal(&["--int-overflow", "wrapping", "-e", &format!("(print (+ {MAX} 1))")]);
+    assert_eq!((status, stdout.as_str()), (0, MIN));
+    let (status, _, stderr) = yal(&["--int-overflow", "checked", "-e", &format!("(print (+ {MAX} 1))")]);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error: integer overflow"), "{stderr}");
+    let (status, stdout, _) = yal(&["-e", &format!("(print (+ {MAX} 1))")]);
+    assert_eq!((status, stdout.as_str()), (0, "9223372036854775808"));
+}
+
+#[test]
+fn neg_and_abs_take_rationals_and_floats() {
+    assert_eq!(eval("(neg 5)"), "-5");
+    assert_eq!(eval("(abs -5)"), "5");
+    assert_eq!(eval("(neg 1/2)"), "-1/2");
+    assert_eq!(eval("(abs -2/3)"), "2/3");
+    assert_eq!(eval("(abs -2.5)"), "2.5");
+    assert_eq!(eval("(neg 0.5)"), "-0.5");
+    assert!(eval_err("(abs 'a)").starts_with("error: expected a number in 'abs'"));
+}
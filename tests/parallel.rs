@@ -0,0 +1,135 @@
+//! `pmap`, which runs on other threads but under the limits of the
+//! environment calling it.
+
+mod common;
+
+use std::time::{ Duration, Instant };
+
+use common::*;
+use yal::RuntimeError;
+
+const SPIN: &str = "(let 'spin (fn '(n) '(if (= n 0) 'n '(recur (- n 1)))))";
+
+#[test]
+fn results_come_back_in_order() {
+    assert_eq!(eval("(pmap (fn '(x) '(* x x)) '(1 2 3 4))"), "(1 4 9 16)");
+    assert_eq!(eval("(let 'k 10) (pmap (fn '(x) '(+ x k)) '(1 2))"), "(11 12)");
+    assert_eq!(eval("(pmap (fn '(x) 'x) '())"), "()");
+}
+
+#[test]
+fn elements_sleep_side_by_side() {
+    let delay = 200;
+    let start = Instant::now();
+    let src = format!("(pmap (fn '(x) '(if (sleep {delay}) 'x 'x)) '(1 2 3 4))");
+    assert_eq!(eval(&src), "(1 2 3 4)");
+    let took = start.elapsed();
+    assert!(took >= Duration::from_millis(delay), "{took:?}");
+    if std::thread::available_parallelism().map_or(1, usize::from) >= 2 {
+        assert!(took < Duration::from_millis(4 * delay), "{took:?}");
+    }
+}
+
+#[test]
+fn workers_run_on_the_callers_fuel() {
+    let mut env = env();
+    eval_in(&mut env, SPIN);
+    env.set_fuel(1_000);
+    let start = Instant::now();
+    assert_eq!(root_error_in(&mut env, "(pmap spin '(3000000))"), RuntimeError::OutOfFuel);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // What they use up is gone for the caller too.
+    env.set_fuel(100_000);
+    eval_in(&mut env, "(pmap spin '(1000))");
+    assert!(env.fuel().unwrap() < 100_000 - 1_000, "{:?}", env.fuel());
+}
+
+#[test]
+fn workers_share_the_callers_fuel() {
+    let mut env = env();
+    eval_in(&mut env, SPIN);
+    env.set_fuel(1_000_000);
+    eval_in(&mut env, "(spin 5000)");
+    let each = 1_000_000 - env.fuel().unwrap();
+
+    // Any one element fits in what's left, but not all four of them.
+    env.set_fuel(3 * each);
+    assert_eq!(root_error_in(&mut env, "(pmap spin '(5000 5000 5000 5000))"), RuntimeError::OutOfFuel);
+    assert!(env.fuel().unwrap() < each, "{:?}", env.fuel());
+
+    env.set_fuel(8 * each);
+    eval_in(&mut env, "(pmap spin '(5000 5000 5000 5000))");
+    assert!(env.fuel().unwrap() <= 4 * each, "{:?}", env.fuel());
+}
+
+#[test]
+fn fuel_runs_out_through_the_binary_too() {
+    let src = format!("{SPIN} (print (pmap spin '(3000000)))");
+    let (status, _, stderr) = yal(&["--fuel", "1000", "-e", &src]);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error: ran out of fuel\n"), "{stderr}");
+}
+
+#[test]
+fn workers_stop_at_the_callers_timeout() {
+    let mut env = env();
+    eval_in(&mut env, SPIN);
+    env.set_timeout(Duration::from_millis(100));
+    let start = Instant::now();
+    assert_eq!(
+        root_error_in(&mut env, "(pmap spin '(2 3000000000 3000000000))"),
+        RuntimeError::Timeout(Duration::from_millis(100))
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn workers_go_as_deep_as_the_caller() {
+    let result = with_stack(1 << 30, || {
+        let mut env = env();
+        env.set_max_depth(50_000);
+        eval_in(&mut env, "(let 'nest (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons acc '())))))");
+        eval_in(&mut env, "(let 'depth (fn '(xs) '(if (= xs '()) '0 '(+ 1 (depth (car xs))))))");
+        eval_in(&mut env, "(pmap depth (cons (nest 1200 '()) '()))")
+    });
+    assert_eq!(result, "(1200)");
+
+    let mut env = env();
+    env.set_max_depth(100);
+    eval_in(&mut env, "(let 'down (fn '(n) '(if (= n 0) '0 '(+ 1 (down (- n 1))))))");
+    assert_eq!(root_error_in(&mut env, "(pmap down '(1000))"), RuntimeError::DepthExceeded { limit: 100 });
+}
+
+#[test]
+fn workers_are_held_to_the_size_limit() {
+    let mut env = env();
+    env.set_size_limit(Some(50));
+    eval_in(&mut env, "(let 'grow (fn '(xs n) '(if (= n 0) 'xs '(grow (cons xs xs) (- n 1)))))");
+    assert!(matches!(
+        root_error_in(&mut env, "(pmap (fn '(n) '(grow '(1) n)) '(20))"),
+        RuntimeError::ResourceLimit { limit: 50, .. }
+    ));
+}
+
+#[test]
+fn other_failures_name_the_element() {
+    let err = eval_err("(pmap (fn '(x) '(car x)) '((1) 2))");
+    assert!(err.starts_with("error: element 1 failed: "), "{err}");
+}
+
+#[test]
+fn interrupting_the_caller_stops_the_workers() {
+    let mut env = env();
+    eval_in(&mut env, SPIN);
+    let flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    env.set_interrupt_flag(flag.clone());
+    let raise = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        flag.store(true, std::sync::atomic::Ordering::Relaxed);
+    });
+    let start = Instant::now();
+    assert_eq!(root_error_in(&mut env, "(pmap spin '(3000000000 3000000000))"), RuntimeError::Interrupted);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    raise.join().unwrap();
+}
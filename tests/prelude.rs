@@ -0,0 +1,59 @@
+//! The prelude the binary evaluates before the program, named by
+//! `YAL_PRELUDE`, and what skips or replaces it.
+
+use std::process::Command;
+
+const HELPERS: &str = "tests/fixtures/prelude/helpers.yal";
+
+/// What the `yal` binary run with `args` and `YAL_PRELUDE` set to `prelude`
+/// exits with, and prints to standard output and standard error.
+fn yal_with_prelude(prelude: &str, args: &[&str]) -> (i32, String, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_yal"))
+        .env("YAL_PRELUDE", prelude)
+        .args(args)
+        .output()
+        .expect("the yal binary runs");
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+    (out.status.code().unwrap_or(-1), stdout, stderr)
+}
+
+#[test]
+fn the_prelude_is_there_for_the_program() {
+    assert_eq!(yal_with_prelude(HELPERS, &["-e", "(print (double 21))"]), (0, "42".to_string(), String::new()));
+}
+
+#[test]
+fn no_prelude_skips_it() {
+    let (status, _, stderr) = yal_with_prelude(HELPERS, &["--no-prelude", "-e", "(print (double 21))"]);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error: name 'double' was not defined"), "{stderr}");
+}
+
+#[test]
+fn an_image_replaces_the_prelude() {
+    let image = std::env::temp_dir().join(format!("yal-prelude-image-{}.yal", std::process::id()));
+    let image = image.to_str().unwrap();
+    let save = format!("(let 'triple (fn '(x) '(* x 3))) (save-image {:?})", image);
+    assert_eq!(yal_with_prelude(HELPERS, &["--no-prelude", "-e", &save]).0, 0);
+
+    let (status, stdout, stderr) =
+        yal_with_prelude(HELPERS, &["--image", image, "-e", "(print (cons (triple 2) (cons (bound? 'double) '())))"]);
+    assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "(6 f)", ""));
+    let _ = std::fs::remove_file(image);
+}
+
+#[test]
+fn errors_in_the_prelude_stop_before_the_program_and_name_it() {
+    let (status, stdout, stderr) = yal_with_prelude("tests/fixtures/prelude/broken.yal", &["-e", "(print 1)"]);
+    assert_eq!((status, stdout.as_str()), (1, ""));
+    assert!(stderr.starts_with("error: expected a list in 'car', got int `1`\n"), "{stderr}");
+    assert!(stderr.contains(" --> tests/fixtures/prelude/broken.yal:3:1\n"), "{stderr}");
+}
+
+#[test]
+fn a_missing_prelude_that_was_asked_for_is_an_error() {
+    let (status, _, stderr) = yal_with_prelude("tests/fixtures/prelude/missing.yal", &["-e", "(print 1)"]);
+    assert_eq!(status, 2);
+    assert!(stderr.starts_with("couldn't read the prelude 'tests/fixtures/prelude/missing.yal'"), "{stderr}");
+}
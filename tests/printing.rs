@@ -0,0 +1,121 @@
+mod common;
+
+use common::*;
+use yal::printer::Written;
+use yal::{ Reader, SExpr };
+
+#[test]
+fn print_precision_rounds_floats() {
+    assert_eq!(output("(set-print-precision 2) (print 1.0) (print \" \") (print 2.5)"), "1.00 2.50");
+}
+
+#[test]
+fn print_precision_is_bounded() {
+    let err = eval_err("(set-print-precision 1000000)");
+    assert!(err.contains("expected a number of digits up to 100, or nil"), "{}", err);
+    assert_eq!(output("(set-print-precision 100) (print 1.5)"), format!("1.5{}", "0".repeat(99)));
+
+    let mut env = env();
+    env.set_print_precision(Some(1_000_000));
+    assert_eq!(env.print_precision(), Some(yal::printer::MAX_PRECISION));
+}
+
+const NEST: &str = "(let 'nest (fn '(n acc) '(if (= n 0) 'acc '(recur (- n 1) (cons acc '())))))";
+
+#[test]
+fn deep_lists_print_down_to_the_default_depth() {
+    let printed = output(&format!("{NEST} (print (nest 1500 '(x)))"));
+    let depth = yal::printer::DEFAULT_MAX_DEPTH;
+    assert_eq!(printed, format!("{}...{}", "(".repeat(depth), ")".repeat(depth)));
+    let printed = output(&format!("{NEST} (print (nest 5 '(x)))"));
+    assert_eq!(printed, "((((((x))))))");
+}
+
+#[test]
+fn print_depth_cuts_lists_short() {
+    assert_eq!(output(&format!("{NEST} (set-print-depth 3) (print (nest 5 '(x)))")), "(((...)))");
+    assert_eq!(output(&format!("{NEST} (set-print-depth 3) (set-print-depth nil) (print (nest 5 '(x)))")), "((((((x))))))");
+    assert!(eval_err("(set-print-depth 0)").contains("expected a positive number of levels or nil"));
+}
+
+#[test]
+fn print_depth_belongs_to_its_environment() {
+    let mut shallow = env();
+    eval_in(&mut shallow, &format!("{NEST} (set-print-depth 2)"));
+    assert_eq!(shallow.print_depth(), 2);
+    assert_eq!(output(&format!("{NEST} (print (nest 3 '(x)))")), "((((x))))");
+
+    let (_, printed) = shallow.capture_output(|env| env.eval_str("(pmap (fn '(x) '(print x)) '(((((1))))))").map(|_| ()));
+    assert_eq!(printed, "((...))");
+
+    shallow.reset().unwrap();
+    assert_eq!(shallow.print_depth(), yal::printer::DEFAULT_MAX_DEPTH);
+}
+
+#[test]
+fn lists_print_without_a_leading_quote() {
+    assert_eq!(output("(print '(1 2 3))"), "(1 2 3)");
+    assert_eq!(output("(print '())"), "()");
+    assert_eq!(output("(print 'foo)"), "foo");
+    assert_eq!(eval("'(1 (2 3))"), "(1 (2 3))");
+}
+
+#[test]
+fn quotes_inside_lists_keep_theirs() {
+    assert_eq!(output("(print '(a 'b '(c d)))"), "(a 'b '(c d))");
+}
+
+#[test]
+fn strings_print_as_they_are_and_are_written_escaped() {
+    assert_eq!(output(r#"(print "a \"b\"\n")"#), "a \"b\"\n");
+    let val = env().eval_str(r#"'("a \"b\"\n" c)"#).unwrap();
+    assert_eq!(Written(&*val).to_string(), r#"'("a \"b\"\n" c)"#);
+    assert_eq!(val.to_string(), "(a \"b\"\n c)");
+}
+
+#[test]
+fn written_values_keep_their_quote() {
+    let val = env().eval_str("'(1 2)").unwrap();
+    assert_eq!(Written(&*val).to_string(), "'(1 2)");
+    let val = env().eval_str(":key").unwrap();
+    assert_eq!(Written(&*val).to_string(), ":key");
+}
+
+/// Pieces of data random expressions are made of.
+const ATOMS: &[&str] = &[
+    "1", "-7", "1/3", "2.5", "-0.0", "1e300", "+inf.0", "+nan.0", "\"\"", "\"a b\"", "\"\\\"\\n\\t\\\\\"", "\"é\"",
+    "x", "foo-bar", ":key", "t", "nil", "+",
+];
+
+/// The source of a random expression, nested at most `depth` levels.
+fn random_expr(rng: &mut Rng, depth: usize) -> String {
+    match rng.below(if depth == 0 { 1 } else { 4 }) {
+        0 => ATOMS[rng.below(ATOMS.len())].to_string(),
+        1 => format!("'{}", random_expr(rng, depth - 1)),
+        _ => {
+            let len = rng.below(5);
+            let elements: Vec<String> = (0..len).map(|_| random_expr(rng, depth - 1)).collect();
+            format!("({})", elements.join(" "))
+        }
+    }
+}
+
+fn read(src: &str) -> SExpr {
+    Reader::new(src).parse_sexpr().unwrap_or_else(|err| panic!("couldn't read {:?}: {}", src, err))
+}
+
+#[test]
+fn written_expressions_read_back_the_same() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..5_000 {
+        let src = random_expr(&mut rng, 5);
+        let expr = read(&src);
+        let written = Written(&expr).to_string();
+        let reread = read(&written);
+        // NaN is never equal to itself, so the text is compared too.
+        assert_eq!(Written(&reread).to_string(), written, "from {:?}", src);
+        if !written.contains("nan") {
+            assert_eq!(reread, expr, "from {:?}", src);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! `delay` and `force`: promises, whose expression is evaluated the first
+//! time they are forced and never again.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn delaying_doesnt_evaluate() {
+    assert_eq!(output("(let 'p (delay '(print 'ran))) 'done"), "");
+    assert_eq!(output("(force (delay '(print 'ran)))"), "ran");
+}
+
+#[test]
+fn a_promise_is_evaluated_at_most_once() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'n 0) (let 'p (delay '(let 'n (+ n 1))))");
+    assert_eq!(eval_in(&mut env, "(force p)"), "1");
+    assert_eq!(eval_in(&mut env, "(force p)"), "1");
+    // Forced from somewhere else, it still gives what it gave.
+    eval_in(&mut env, "(let 'again (fn '(q) '(force q)))");
+    assert_eq!(eval_in(&mut env, "(again p)"), "1");
+    assert_eq!(eval_in(&mut env, "n"), "1");
+}
+
+#[test]
+fn forcing_anything_else_gives_it_back() {
+    assert_eq!(eval("(force 5)"), "5");
+    assert_eq!(eval("(force '(1 2))"), "(1 2)");
+}
+
+#[test]
+fn a_promise_forcing_itself_is_an_error() {
+    let err = eval_err("(let 'p (delay '(force p))) (force p)");
+    assert!(err.starts_with("error: promise forced during its own evaluation"), "{err}");
+}
+
+#[test]
+fn a_failed_promise_can_be_forced_again() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'xs 1) (let 'p (delay '(car xs)))");
+    eval_err_in(&mut env, "(force p)");
+    eval_in(&mut env, "(let 'xs '(7))");
+    assert_eq!(eval_in(&mut env, "(force p)"), "7");
+}
@@ -0,0 +1,74 @@
+//! Exact rationals: what dividing ints gives, how they mix with ints and
+//! floats, and how they are read and printed.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn thirds_add_up_to_one() {
+    assert_eq!(eval("(= (+ 1/3 1/3 1/3) 1)"), "t");
+    assert_eq!(eval("(+ 1/3 1/3 1/3)"), "1");
+    assert_eq!(eval("(+ 1/3 1/3)"), "2/3");
+}
+
+#[test]
+fn rationals_are_normalized() {
+    assert_eq!(eval("2/4"), "1/2");
+    assert_eq!(eval("-2/4"), "-1/2");
+    assert_eq!(eval("(/ 6 4)"), "3/2");
+    assert_eq!(eval("(/ 6 -4)"), "-3/2");
+    assert_eq!(eval("(/ -6 -4)"), "3/2");
+    assert_eq!(eval("4/2"), "2");
+    assert_eq!(eval("(- 1/2 1/2)"), "0");
+}
+
+#[test]
+fn whole_results_are_ints() {
+    assert_eq!(eval("(/ 6 3)"), "2");
+    assert_eq!(eval("(* 2/3 3)"), "2");
+    assert_eq!(eval("(= (* 2/3 3) 2)"), "t");
+}
+
+#[test]
+fn ints_stay_exact_with_rationals() {
+    assert_eq!(eval("(+ 1 1/2)"), "3/2");
+    assert_eq!(eval("(- 1 1/3)"), "2/3");
+    assert_eq!(eval("(* 3 1/6)"), "1/2");
+    assert_eq!(eval("(/ 1/2 2)"), "1/4");
+    assert_eq!(eval("(= 1/2 (/ 1 2))"), "t");
+}
+
+#[test]
+fn floats_are_contagious() {
+    assert_eq!(eval("(+ 1/2 0.25)"), "0.75");
+    assert_eq!(eval("(* 1/3 1.5)"), "0.5");
+    assert_eq!(eval("(/ 1/2 0.5)"), "1.0");
+    assert_eq!(eval("(+ 1 2 1/2 0.5)"), "4.0");
+    assert_eq!(eval("(exact->inexact 1/4)"), "0.25");
+    assert_eq!(eval("(exact->inexact 3)"), "3.0");
+}
+
+#[test]
+fn rationals_equal_floats_only_exactly() {
+    assert_eq!(eval("(= 1/2 0.5)"), "t");
+    assert_eq!(eval("(= 1/3 (exact->inexact 1/3))"), "f");
+}
+
+#[test]
+fn dividing_by_zero_fails_unless_a_float_is_involved() {
+    for src in ["(/ 1 0)", "(/ 1/2 0)"] {
+        assert!(eval_err(src).starts_with("error: integer division by zero"), "{src}");
+    }
+    assert!(eval_err("1/0").starts_with("error: division by zero in '1/0'"));
+    assert_eq!(eval("(/ 1/2 0.0)"), "+inf.0");
+    assert_eq!(eval("(/ 1.0 0)"), "+inf.0");
+}
+
+#[test]
+fn plus_and_times_take_any_number_of_arguments_past_two() {
+    assert_eq!(eval("(+ 1 2 3 4)"), "10");
+    assert_eq!(eval("(* 1 2 3 4)"), "24");
+    let err = eval_err("(+ 1)");
+    assert!(err.starts_with("error: expected at least 2 arguments, but got 1"), "{err}");
+}
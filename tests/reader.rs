@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use yal::Reader;
+
+/// Pieces random programs are made of, picked to hit every kind of token
+/// and the ways they go wrong.
+const PIECES: &[&str] = &[
+    "(", ")", "(", ")", "'", "\"", "\\", " ", "\n", ";", "a", "-", "+", "1", "2.5", "1e", ".", "#", "#t",
+    "#+feature(ffi)", "#-feature(ffi)", "#+feature(", "é", "\"\\u{", "\\n", "nil", "0x", "-9223372036854775809",
+];
+
+/// Reads `src` every way the reader can, and shows any error, which must
+/// not panic.
+fn read_all_ways(src: &str) {
+    if let Err(err) = Reader::new(src).parse_sexprs() {
+        let _ = err.to_string();
+    }
+    if let Err(err) = Reader::new(src).keep_comments().parse_sexprs() {
+        let _ = err.to_string();
+    }
+}
+
+#[test]
+fn the_reader_never_panics_on_random_input() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..20_000 {
+        let len = rng.below(40);
+        let src: String = (0..len).map(|_| PIECES[rng.below(PIECES.len())]).collect();
+        read_all_ways(&src);
+    }
+}
+
+#[test]
+fn the_reader_never_panics_on_truncated_programs() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let src = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        for (end, _) in src.char_indices() {
+            read_all_ways(&src[..end]);
+            read_all_ways(&src[end..]);
+        }
+    }
+}
+
+#[test]
+fn input_nested_too_deep_is_an_error() {
+    with_stack(DEFAULT_DEPTH_STACK, || {
+        let n = 1_000_000;
+        let src = format!("{}{}", "(".repeat(n), ")".repeat(n));
+        let err = Reader::new(&src).parse_sexprs().unwrap_err().to_string();
+        assert!(err.contains("levels deep"), "{err}");
+
+        let src = format!("{}1", "'".repeat(n));
+        let err = Reader::new(&src).parse_sexprs().unwrap_err().to_string();
+        assert!(err.contains("levels deep"), "{err}");
+    });
+}
+
+#[test]
+fn the_reader_goes_as_deep_as_the_environment() {
+    let nested = |n: usize| format!("{}0{}", "(+ 1 ".repeat(n), ")".repeat(n));
+    let mut shallow = env();
+    shallow.set_max_depth(100);
+    assert!(shallow.reader(&nested(99)).parse_sexprs().is_ok());
+    let err = shallow.reader(&nested(101)).parse_sexprs().unwrap_err().to_string();
+    assert!(err.contains("nested more than 100 levels deep"), "{err}");
+
+    let deep = with_stack(256 << 20, move || {
+        let mut env = env();
+        env.set_max_depth(50_000);
+        eval_in(&mut env, &nested(50_000))
+    });
+    assert_eq!(deep, "50000");
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+
+/// A file in the temporary directory holding `src`, removed when dropped.
+struct TempFile(std::path::PathBuf);
+
+impl TempFile {
+    fn new(name: &str, src: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("yal-repl-{}-{}", std::process::id(), name));
+        std::fs::write(&path, src).unwrap();
+        TempFile(path)
+    }
+
+    fn load(&self) -> &'static str {
+        Box::leak(format!(":load {}", self.0.display()).into_boxed_str())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn env_lists_names_and_their_types() {
+    let (_, out) = repl(&mut env(), &["(let 'answer 42)", "(let 'greet (fn '() '\"hi\"))", ":env"]);
+    assert!(out.contains("answer : int\n"), "{out}");
+    assert!(out.contains("greet : function\n"), "{out}");
+    assert!(out.contains("car : function\n"), "{out}");
+}
+
+#[test]
+fn type_shows_only_the_type() {
+    let (_, out) = repl(&mut env(), &[":type 1", ":type \"s\"", ":type '(1 2)", ":type (car 1)"]);
+    let mut lines = out.lines();
+    assert_eq!(lines.next(), Some("int"));
+    assert_eq!(lines.next(), Some("string"));
+    assert_eq!(lines.next(), Some("quote"));
+    assert!(out.contains("expected a list in 'car'"), "{out}");
+}
+
+#[test]
+fn load_evaluates_a_file_into_the_session() {
+    let file = TempFile::new("defs.yal", "(let 'loaded 7)\n(let 'twice (fn '(x) '(* 2 x)))\n");
+    let (_, out) = repl(&mut env(), &[file.load(), "(twice loaded)"]);
+    assert_eq!(out, "14\n");
+}
+
+#[test]
+fn load_errors_dont_end_the_session() {
+    let broken = TempFile::new("broken.yal", "(let 'before 1)\n(car 1)\n(let 'after 2)\n");
+    let (status, out) = repl(&mut env(), &[broken.load(), ":load /nowhere/at/all.yal", "before", "(+ 1 2)"]);
+    assert_eq!(status, 0);
+    assert!(out.contains("expected a list in 'car'"), "{out}");
+    assert!(out.contains("couldn't read '/nowhere/at/all.yal'"), "{out}");
+    assert!(out.ends_with("1\n3\n"), "{out}");
+}
+
+#[test]
+fn reset_starts_over_with_the_standard_library() {
+    let (_, out) = repl(&mut env(), &["(let 'x 1)", ":reset", "x", "(+ 1 1)"]);
+    assert!(out.contains("name 'x' was not defined"), "{out}");
+    assert!(out.ends_with("2\n"), "{out}");
+}
+
+#[test]
+fn quit_ends_the_session() {
+    let (status, out) = repl(&mut env(), &["1", ":quit", "2"]);
+    assert_eq!(status, 0);
+    assert_eq!(out, "1\n");
+}
+
+#[test]
+fn unknown_commands_list_the_available_ones() {
+    let (_, out) = repl(&mut env(), &[":bogus"]);
+    assert!(out.starts_with("unknown command ':bogus', the commands are:\n"), "{out}");
+    for command in [":env", ":load <path>", ":reset", ":type <expr>", ":quit"] {
+        assert!(out.contains(&format!("  {}", command)), "{command} in {out}");
+    }
+}
@@ -0,0 +1,78 @@
+//! The plain rendering of errors, which has to stay stable.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn a_runtime_error_shows_the_line_and_a_caret() {
+    assert_eq!(eval_err("(let 'x 1)\n\n(+ x \"a\")"), "\
+error: expected two numbers in '+', got int and string
+ --> 3:1
+  |
+3 | (+ x \"a\")
+  | ^
+  = note: in '+'");
+}
+
+#[test]
+fn notes_and_help_come_after_the_snippet() {
+    assert_eq!(eval_err("(prnt 1)"), "\
+error: name 'prnt' was not defined
+ --> 1:2
+  |
+1 | (prnt 1)
+  |  ^
+  = note: in call position of `(prnt 1)`
+  = help: did you mean 'print'?");
+}
+
+#[test]
+fn parse_errors_are_rendered_the_same() {
+    assert_eq!(eval_err("(+ 1"), "\
+error: expected a closing paren
+ --> 1:5
+  |
+1 | (+ 1
+  |     ^");
+}
+
+#[test]
+fn the_gutter_is_as_wide_as_the_line_number() {
+    let src = format!("{}(car 1)", "\n".repeat(11));
+    assert_eq!(eval_err(&src), "\
+error: expected a list in 'car', got int `1`
+  --> 12:1
+   |
+12 | (car 1)
+   | ^
+   = note: in 'car'");
+}
+
+#[test]
+fn long_lines_are_cut_around_the_caret() {
+    let src = format!("(let 'a '({})) (car 1) (let 'b '({}))", "1 ".repeat(5000), "2 ".repeat(5000));
+    let report = eval_err(&src);
+    let lines: Vec<&str> = report.lines().collect();
+    assert!(lines.iter().all(|line| line.chars().count() < 120), "{report}");
+
+    let snippet = lines[3];
+    let caret = lines[4];
+    assert!(snippet.starts_with("1 | ... 1 1 1") && snippet.ends_with("2 2 ..."), "{snippet}");
+    let at = caret.find('^').unwrap();
+    assert_eq!(&snippet[at..at + 7], "(car 1)", "{report}");
+}
+
+#[test]
+fn the_cli_names_the_file_and_colors_nothing_off_a_terminal() {
+    let path = "tests/fixtures/strict/typo.yal";
+    let (status, _, stderr) = yal(&[path]);
+    assert_eq!(status, 1);
+    assert_eq!(stderr.trim_end(), format!("\
+error: name 'totl' was not defined
+ --> {path}:5:8
+  |
+5 | (print totl)
+  |        ^
+  = help: did you mean 'total'?"));
+}
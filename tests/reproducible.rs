@@ -0,0 +1,67 @@
+//! Runs coming out the same every time: random numbers drawn after the same
+//! seed, the time with the clock frozen, and names listed in order.
+
+mod common;
+
+use common::*;
+
+const SNAPSHOT: &str = "tests/fixtures/reproducible/snapshot.yal";
+
+/// What the snapshot fixture prints in a fresh environment with the clock
+/// frozen at `millis`.
+fn snapshot(millis: u64) -> String {
+    let src = std::fs::read_to_string(SNAPSHOT).unwrap();
+    let mut env = env();
+    env.set_clock(Box::new(move || millis));
+    let (result, printed) = env.capture_output(|env| env.eval_str(&src).map(|_| ()));
+    result.unwrap();
+    printed
+}
+
+#[test]
+fn a_program_prints_the_same_when_run_twice() {
+    let first = snapshot(951_825_845_000);
+    assert!(first.contains("(2000 2 29 12 4 5)"), "{first}");
+    assert_eq!(snapshot(951_825_845_000), first);
+}
+
+#[test]
+fn the_same_seed_draws_the_same_numbers() {
+    let draws = "(random-seed 7) (cons (random 1000000) (cons (random 1000000) (cons (random 1.0) '())))";
+    let first = eval(draws);
+    assert_eq!(eval(draws), first);
+    assert_ne!(eval("(random-seed 8) (random 1000000)"), eval("(random-seed 7) (random 1000000)"));
+}
+
+#[test]
+fn seeding_again_starts_over() {
+    let mut env = env();
+    let first = eval_in(&mut env, "(random-seed 1) (random 1000000)");
+    eval_in(&mut env, "(random 1000000)");
+    assert_eq!(eval_in(&mut env, "(random-seed 1) (random 1000000)"), first);
+}
+
+#[test]
+fn without_a_seed_the_frozen_clock_seeds() {
+    let draw = |millis: u64| {
+        let mut env = env();
+        env.set_clock(Box::new(move || millis));
+        eval_in(&mut env, "(random 1000000)")
+    };
+    assert_eq!(draw(1), draw(1));
+}
+
+#[test]
+fn draws_stay_in_range() {
+    let mut env = env();
+    eval_in(&mut env, "(random-seed 3)");
+    for _ in 0..1000 {
+        let int: i64 = eval_in(&mut env, "(random 6)").parse().unwrap();
+        assert!((0..6).contains(&int), "{int}");
+        let float: f64 = eval_in(&mut env, "(random 0.5)").parse().unwrap();
+        assert!((0.0..0.5).contains(&float), "{float}");
+    }
+    for src in ["(random 0)", "(random -1)", "(random 'a)", "(random +inf.0)"] {
+        assert!(eval_err(src).starts_with("error: expected a positive number in 'random'"), "{src}");
+    }
+}
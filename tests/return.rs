@@ -0,0 +1,40 @@
+//! `return`, which ends the innermost call of a function defined in yal
+//! from anywhere in its body.
+
+mod common;
+
+use common::*;
+
+#[test]
+fn returning_from_inside_a_loop_skips_the_rest_of_the_call() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'find (fn '(n) '(+ 1000 (loop '((i 0)) '(if (= i n) '(return (* i 10)) '(recur (+ i 1)))))))");
+    assert_eq!(eval_in(&mut env, "(find 5)"), "50");
+    // And the call after it starts from scratch.
+    assert_eq!(eval_in(&mut env, "(find 2)"), "20");
+}
+
+#[test]
+fn returning_from_a_nested_scope_unwinds_its_bindings() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 'global)");
+    eval_in(&mut env, "(let 'f (fn '(v) '(+ 1 (match v '((x (match (cons x '()) '(((y) (return (* x y)))))))))))");
+    assert_eq!(eval_in(&mut env, "(f 3)"), "9");
+    assert_eq!(eval_in(&mut env, "x"), "global");
+    assert!(env.lookup_var("y").is_none());
+    assert!(env.lookup_var("v").is_none());
+}
+
+#[test]
+fn only_the_innermost_call_returns() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'inner (fn '(n) '(+ 1 (return n))))");
+    eval_in(&mut env, "(let 'outer (fn '(n) '(* 2 (inner n))))");
+    assert_eq!(eval_in(&mut env, "(outer 4)"), "8");
+}
+
+#[test]
+fn returning_at_the_top_level_is_an_error() {
+    let err = eval_err("(return 1)");
+    assert!(err.starts_with("error: 'return' can only be used in a function"), "{err}");
+}
@@ -0,0 +1,75 @@
+//! Sandboxes, which run code with some of the bindings of an environment
+//! and nothing it does there reaching back, nor the other way around.
+
+mod common;
+
+use common::*;
+use yal::Value;
+
+#[test]
+fn only_the_allowed_bindings_are_there() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'secret 42) (let 'shared 1)");
+    let sandbox = env.sandbox(&["shared", "+"]);
+    assert!(sandbox.lookup_var("secret").is_none());
+    assert!(sandbox.lookup_var("print").is_none());
+    assert!(sandbox.builtin("+").is_some());
+    assert!(sandbox.builtin("print").is_none());
+
+    let err = env.eval_str_in_sandbox(&["shared", "+"], "(print secret)").unwrap_err().to_string();
+    assert!(err.starts_with("error: name 'print' was not defined"), "{err}");
+    assert_eq!(env.eval_str_in_sandbox(&["shared", "+"], "(+ shared 1)").unwrap().to_string(), "2");
+}
+
+#[test]
+fn definitions_in_the_sandbox_stay_there() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'shared 1)");
+    env.eval_str_in_sandbox(&["let"], "(let 'shared 2) (let 'made 3)").unwrap();
+    assert_eq!(eval_in(&mut env, "shared"), "1");
+    assert!(env.lookup_var("made").is_none());
+}
+
+#[test]
+fn definitions_after_the_sandbox_is_made_stay_out() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'shared 1)");
+    let mut sandbox = env.sandbox(&["shared"]);
+    eval_in(&mut env, "(let 'shared 2) (let 'later 3)");
+    assert_eq!(eval_in(&mut sandbox, "shared"), "1");
+    assert!(sandbox.lookup_var("later").is_none());
+}
+
+#[test]
+fn forcing_a_promise_in_the_sandbox_leaves_the_hosts_alone() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'n 1) (let 'p (delay 'n))");
+    let allowed = ["p", "n", "force", "let"];
+    let forced = env.eval_str_in_sandbox(&allowed, "(let 'n 2) (force p)").unwrap();
+    assert_eq!(forced.to_string(), "2");
+    assert_eq!(eval_in(&mut env, "(force p)"), "1");
+
+    // And what the host forced comes in forced.
+    assert_eq!(env.eval_str_in_sandbox(&allowed, "(let 'n 3) (force p)").unwrap().to_string(), "1");
+}
+
+#[test]
+fn handles_are_left_out() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'ch (make-channel)) (let 'p (delay 'ch)) (force p)");
+    let sandbox = env.sandbox(&["ch", "p", "channel-send"]);
+    assert!(sandbox.lookup_var("ch").is_none());
+    assert!(sandbox.lookup_var("p").is_none());
+    assert!(matches!(&**sandbox.lookup_var("channel-send").unwrap(), Value::Function(_)));
+}
+
+#[test]
+fn limits_are_the_sandboxs_own() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'spin (fn '(n) '(if (= n 0) 'n '(recur (- n 1)))))");
+    let mut sandbox = env.sandbox(&["spin", "if", "=", "-", "recur"]);
+    sandbox.set_fuel(100);
+    assert!(eval_err_in(&mut sandbox, "(spin 1000)").starts_with("error: ran out of fuel"));
+    assert_eq!(env.fuel(), None);
+    assert_eq!(eval_in(&mut env, "(spin 1000)"), "0");
+}
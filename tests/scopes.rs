@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+use yal::RefVal;
+
+#[test]
+fn parameters_shadow_globals_during_the_call_only() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 1) (let 'f (fn '(x) 'x))");
+    assert_eq!(eval_in(&mut env, "(f 2)"), "2");
+    assert_eq!(eval_in(&mut env, "x"), "1");
+}
+
+#[test]
+fn the_innermost_binding_wins() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 1) (let 'f (fn '(x) '((fn '(x) 'x) 3)))");
+    assert_eq!(eval_in(&mut env, "(f 2)"), "3");
+    eval_in(&mut env, "(let 'g (fn '(x) '(+ ((fn '(x) 'x) 3) x)))");
+    assert_eq!(eval_in(&mut env, "(g 2)"), "5");
+    assert_eq!(eval_in(&mut env, "x"), "1");
+}
+
+#[test]
+fn scopes_are_popped_when_the_body_fails() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 1) (let 'f (fn '(x y) '(car y)))");
+    eval_err_in(&mut env, "(f 2 3)");
+    assert_eq!(eval_in(&mut env, "x"), "1");
+    assert!(env.lookup_var("y").is_none());
+    assert_eq!(eval_in(&mut env, "(f 2 '(3))"), "3");
+}
+
+#[test]
+fn scopes_are_popped_by_their_guards() {
+    let mut env = env();
+    let x = env.intern("x");
+    {
+        let mut scope = env.scope();
+        scope.bind_var(x.clone(), RefVal::from(1i64)).unwrap();
+        {
+            let mut inner = scope.scope();
+            inner.bind_var(x.clone(), RefVal::from(2i64)).unwrap();
+            assert_eq!(inner.lookup_var("x").unwrap().to_string(), "2");
+        }
+        assert_eq!(scope.lookup_var("x").unwrap().to_string(), "1");
+    }
+    assert!(env.lookup_var("x").is_none());
+}
+
+#[test]
+fn a_failed_call_leaves_nothing_behind() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'f (fn '(param) '(+ param (car param))))");
+    eval_err_in(&mut env, "(+ 1 (f 2))");
+    assert!(env.lookup_var("param").is_none());
+    assert!(eval_err_in(&mut env, "param").contains("name 'param' was not defined"));
+    // Nor arguments that were waiting on the stack.
+    assert!(env.pop_stack().is_err());
+}
+
+#[test]
+fn rebinding_a_global_replaces_it() {
+    let mut env = env();
+    let before = env.bound_names().count();
+    eval_in(&mut env, "(let 'snd (fn '(a b) 'b))");
+    eval_in(&mut env, "(loop '((i 0)) '(if (= i 100000) 'i '(recur (+ (snd (let 'x i) i) 1))))");
+    assert_eq!(eval_in(&mut env, "x"), "99999");
+    assert_eq!(env.bound_names().count(), before + 2);
+}
+
+#[test]
+fn rebinding_in_the_same_scope_replaces() {
+    let mut env = env();
+    let x = env.intern("x");
+    let before = env.bound_names().count();
+    let mut scope = env.scope();
+    for i in 0..100_000i64 {
+        scope.bind_var(x.clone(), RefVal::from(i)).unwrap();
+    }
+    assert_eq!(scope.lookup_var("x").unwrap().to_string(), "99999");
+    assert_eq!(scope.bound_names().count(), before + 1);
+}
+
+#[test]
+fn shadowing_a_parameter_still_restores_it() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'x 'outer) (let 'inner (fn '(x) 'x)) (let 'outer (fn '(x) '(+ (* 10 (inner 2)) x)))");
+    assert_eq!(eval_in(&mut env, "(outer 1)"), "21");
+    assert_eq!(eval_in(&mut env, "x"), "outer");
+}
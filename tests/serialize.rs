@@ -0,0 +1,75 @@
+//! Round trips of expressions and values through JSON, with the `serde`
+//! feature.
+
+#![cfg(feature = "serde")]
+
+mod common;
+
+use common::*;
+use serde_json::json;
+use yal::printer::Written;
+use yal::{ Reader, SExpr, Value };
+
+fn read(src: &str) -> SExpr {
+    Reader::new(src).parse_sexpr().map_err(|err| err.to_string()).unwrap()
+}
+
+#[test]
+fn expressions_map_to_json_as_documented() {
+    let json = serde_json::to_value(read("(a 1 1.5 \"hi\" 'x 1/3 ())")).unwrap();
+    assert_eq!(json, json!([
+        { "ident": "a" },
+        { "int": 1 },
+        { "float": 1.5 },
+        { "string": "hi" },
+        { "quote": { "ident": "x" } },
+        { "rational": [1, 3] },
+        [],
+    ]));
+}
+
+#[test]
+fn expressions_round_trip() {
+    let sources = [
+        "x",
+        "-42",
+        "\"with \\\"escapes\\\"\\n\"",
+        "()",
+        "(let 'f (fn '(x) '(* x x)))",
+        "((((deep)))) ",
+        "'(a 'b ''(c \"d\" 2.5))",
+        "(1/2 -3/4 9223372036854775807 -0.0)",
+    ];
+    for src in sources {
+        let expr = read(src);
+        let text = serde_json::to_string(&expr).unwrap();
+        let back: SExpr = serde_json::from_str(&text).unwrap();
+        assert_eq!(back, expr, "{} as {}", src, text);
+    }
+}
+
+#[test]
+fn data_values_round_trip() {
+    for src in ["1", "2.5", "(/ 1 3)", "\"s\"", "(= 1 1)", "(= 1 2)", "nil", "'(1 (2 \"x\") y)", "''z"] {
+        let val = env().eval_str(src).unwrap();
+        let text = serde_json::to_string(&val).unwrap();
+        let back: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(Written(&back).to_string(), Written(&*val).to_string(), "{} as {}", src, text);
+    }
+    assert_eq!(serde_json::to_value(&*env().eval_str("nil").unwrap()).unwrap(), json!("nil"));
+    assert_eq!(serde_json::to_value(&*env().eval_str("(= 1 1)").unwrap()).unwrap(), json!({ "bool": true }));
+}
+
+#[test]
+fn functions_are_an_error() {
+    let val = env().eval_str("(fn '(x) 'x)").unwrap();
+    let err = serde_json::to_string(&val).unwrap_err();
+    assert!(err.to_string().contains("can't serialize the function"), "{err}");
+}
+
+#[test]
+fn invalid_json_is_an_error() {
+    assert!(serde_json::from_str::<SExpr>("{\"nope\": 1}").is_err());
+    assert!(serde_json::from_str::<SExpr>("{\"rational\": [1, 0]}").is_err());
+    assert!(serde_json::from_str::<Value>("{\"ident\": \"x\"}").is_err());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::*;
+
+/// What `--strict` reports for `path`, without the file name.
+fn strict(path: &str) -> (i32, Vec<String>) {
+    let (status, _, stderr) = yal(&["--strict", path]);
+    let prefix = format!("{}:", path);
+    let reported = stderr.lines().map(|line| line.strip_prefix(&prefix).unwrap_or(line).to_string()).collect();
+    (status, reported)
+}
+
+#[test]
+fn strict_reports_what_the_fixtures_expect() {
+    for path in fixtures("strict") {
+        let expected = expected_diagnostics(&path);
+        let (status, reported) = strict(path.to_str().unwrap());
+        assert_eq!(reported, expected, "in {}", path.display());
+        assert_eq!(status, if expected.is_empty() { 0 } else { 1 }, "in {}", path.display());
+    }
+}
+
+#[test]
+fn strict_counts_globals_defined_in_function_bodies_of_other_files() {
+    let dir = std::env::temp_dir().join(format!("yal-strict-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (defines, uses) = (dir.join("defines.yal"), dir.join("uses.yal"));
+    std::fs::write(&defines, "(let 'setup (fn '() '(let 'config 1)))\n(setup)\n").unwrap();
+    std::fs::write(&uses, "(print config)\n").unwrap();
+
+    let (status, stdout, stderr) = yal(&["--strict", defines.to_str().unwrap(), uses.to_str().unwrap()]);
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!((status, stdout.as_str()), (0, "1"), "{stderr}");
+}
+
+#[test]
+fn check_reports_undefined_names_too() {
+    let path = "tests/fixtures/strict/typo.yal";
+    let (status, stdout, _) = yal(&["--check", path]);
+    assert_eq!(status, 1);
+    assert!(stdout.contains(&format!("{path}:5:8: name 'totl' is not defined")), "{stdout}");
+}
@@ -0,0 +1,50 @@
+//! Strings are shared, not copied, as they are evaluated and passed around.
+
+mod common;
+
+use std::rc::Rc;
+
+use common::*;
+use yal::{ Environment, RefVal, Value };
+
+fn string(env: &mut Environment, src: &str) -> Rc<str> {
+    match &*env.eval_str(src).unwrap() {
+        Value::String(s) => s.clone(),
+        other => panic!("{:?} gave {} instead of a string", src, other),
+    }
+}
+
+#[test]
+fn passing_a_string_around_does_not_copy_it() {
+    // Each call nests a few evaluations deep.
+    with_stack(64 << 20, || {
+        let mut env = env();
+        let big: Rc<str> = "x".repeat(1 << 20).into();
+        env.define_var("big", RefVal::owned(Value::String(big.clone()))).unwrap();
+        eval_in(&mut env, "
+            (let 'id (fn '(s) 's))
+            (let 'pass (fn '(n s) '(if (= n 0) 's '(pass (- n 1) (id s)))))");
+        assert!(Rc::ptr_eq(&string(&mut env, "big"), &big));
+        assert!(Rc::ptr_eq(&string(&mut env, "(id big)"), &big));
+        assert!(Rc::ptr_eq(&string(&mut env, "(pass 100 big)"), &big));
+        assert!(Rc::ptr_eq(&string(&mut env, "(car (cons big '()))"), &big));
+    });
+}
+
+#[test]
+fn evaluating_a_literal_again_does_not_copy_it() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'greeting (fn '() '\"hello\"))");
+    let first = string(&mut env, "(greeting)");
+    assert!(Rc::ptr_eq(&first, &string(&mut env, "(greeting)")));
+    assert_eq!(&*first, "hello");
+}
+
+#[test]
+fn strings_still_compare_and_print_by_contents() {
+    assert_eq!(eval("(= \"abc\" \"abc\")"), "t");
+    assert_eq!(eval("(= \"abc\" \"abd\")"), "f");
+    assert_eq!(eval("(equal? \"\" \"\")"), "t");
+    assert_eq!(output("(print \"a \\\"quoted\\\" line\")"), "a \"quoted\" line");
+    assert_eq!(eval("(car '(\"in a list\"))"), "in a list");
+}
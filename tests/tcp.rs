@@ -0,0 +1,79 @@
+//! The TCP builtins, with both ends of each connection in the same
+//! environment.
+
+mod common;
+
+use common::*;
+use yal::Environment;
+
+/// An environment with a listener `server`, a connection `client` to it, and
+/// `conn`, the end of that connection `server` accepted.
+fn connected() -> Environment {
+    let mut env = env();
+    eval_in(&mut env, "
+        (let 'server (tcp-listen 0))
+        (let 'client (tcp-connect \"127.0.0.1\" (tcp-local-port server)))
+        (let 'conn (tcp-accept server))");
+    env
+}
+
+#[test]
+fn lines_go_both_ways() {
+    let mut env = connected();
+    eval_in(&mut env, "(tcp-send client \"hello\\nsecond line\\r\\n\")");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line conn)"), "hello");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line conn)"), "second line");
+    eval_in(&mut env, "(tcp-send conn \"hi back\\n\")");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line client)"), "hi back");
+}
+
+#[test]
+fn receiving_after_the_other_end_closes_gives_nil() {
+    let mut env = connected();
+    eval_in(&mut env, "(tcp-send client \"last\\n\") (tcp-close client)");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line conn)"), "last");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line conn)"), "nil");
+}
+
+#[test]
+fn closed_handles_fail_from_then_on() {
+    let mut env = connected();
+    eval_in(&mut env, "(tcp-close client) (tcp-close client) (tcp-close server)");
+    let err = eval_err_in(&mut env, "(tcp-send client \"x\\n\")");
+    assert!(err.starts_with("error: the connection to 127.0.0.1:") && err.contains(" is closed"), "{err}");
+    let err = eval_err_in(&mut env, "(tcp-accept server)");
+    assert!(err.starts_with("error: the listener on 0.0.0.0:") && err.contains(" is closed"), "{err}");
+}
+
+#[test]
+fn reads_time_out() {
+    let mut env = connected();
+    eval_in(&mut env, "(tcp-set-timeout conn 50)");
+    let err = eval_err_in(&mut env, "(tcp-recv-line conn)");
+    assert!(err.starts_with("error: couldn't receive from 127.0.0.1:") && err.contains(": timed out"), "{err}");
+
+    // The connection can still be used afterwards.
+    eval_in(&mut env, "(tcp-set-timeout conn nil) (tcp-send client \"late\\n\")");
+    assert_eq!(eval_in(&mut env, "(tcp-recv-line conn)"), "late");
+    assert!(eval_err_in(&mut env, "(tcp-set-timeout conn 0)").contains("a positive number of milliseconds or nil"));
+}
+
+#[test]
+fn handles_are_only_equal_to_themselves() {
+    let mut env = connected();
+    assert_eq!(eval_in(&mut env, "(= client client)"), "t");
+    assert_eq!(eval_in(&mut env, "(= client conn)"), "f");
+    assert_eq!(eval_in(&mut env, "(let 'same client) (= same client)"), "t");
+}
+
+#[test]
+fn connecting_to_nothing_fails_with_the_os_message() {
+    // Nothing listens on a port that was just freed.
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let err = eval_err(&format!("(tcp-connect \"127.0.0.1\" {port})"));
+    assert!(err.starts_with(&format!("error: couldn't connect to 127.0.0.1:{port}: ")), "{err}");
+    assert!(err.to_lowercase().contains("refused"), "{err}");
+
+    assert!(eval_err("(tcp-listen 70000)").contains("expected a port from 0 to 65535, got 70000"));
+    assert!(eval_err("(tcp-send 1 \"x\")").contains("expected a connection"));
+}
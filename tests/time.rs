@@ -0,0 +1,60 @@
+//! `time-now`, `time-format` and `time-parse`, in UTC.
+
+mod common;
+
+use common::*;
+
+const FULL: &str = "\"%Y-%m-%d %H:%M:%S\"";
+
+#[test]
+fn timestamps_round_trip_through_format_and_parse() {
+    let timestamps = [
+        0i64,
+        -1000,
+        951_782_400_000,       // 2000-02-29, a leap day in a year divisible by 400
+        1_709_164_800_000,     // 2024-02-29
+        2_147_483_647_000,     // the end of 32-bit time
+        4_102_444_799_000,     // 2099-12-31 23:59:59
+        253_402_300_799_000,   // 9999-12-31 23:59:59
+        -62_135_596_800_000,   // 0001-01-01
+    ];
+    let mut env = env();
+    for millis in timestamps {
+        let formatted = eval_in(&mut env, &format!("(time-format {millis} {FULL})"));
+        let parsed = eval_in(&mut env, &format!("(time-parse (time-format {millis} {FULL}) {FULL})"));
+        assert_eq!(parsed, millis.to_string(), "{millis} formatted as {formatted}");
+    }
+}
+
+#[test]
+fn formats_dates() {
+    assert_eq!(eval(&format!("(time-format 0 {FULL})")), "1970-01-01 00:00:00");
+    assert_eq!(eval(&format!("(time-format -1000 {FULL})")), "1969-12-31 23:59:59");
+    assert_eq!(eval(&format!("(time-format 2147483647000 {FULL})")), "2038-01-19 03:14:07");
+    assert_eq!(eval("(time-format '(2024 2 29 12 0 0) \"%d/%m/%Y %H%%\")"), "29/02/2024 12%");
+    assert_eq!(eval("(time-format 1999 \"%S\")"), "01");
+}
+
+#[test]
+fn parse_gives_false_for_text_not_matching_or_impossible_dates() {
+    assert_eq!(eval("(time-parse \"2000-02-29\" \"%Y-%m-%d\")"), "951782400000");
+    assert_eq!(eval("(time-parse \"1900-02-29\" \"%Y-%m-%d\")"), "f");
+    assert_eq!(eval("(time-parse \"2023-13-01\" \"%Y-%m-%d\")"), "f");
+    assert_eq!(eval("(time-parse \"2023-01-01\" \"%d/%m/%Y\")"), "f");
+    assert_eq!(eval("(time-parse \"yesterday\" \"%Y\")"), "f");
+}
+
+#[test]
+fn bad_arguments_are_errors() {
+    assert!(eval_err("(time-format 0 \"%Q\")").starts_with("error: unknown directive '%Q' in 'time-format'"));
+    assert!(eval_err("(time-format 0 1)").contains("expected a format string in 'time-format'"));
+    assert!(eval_err("(time-parse 1 \"%Y\")").contains("expected a string in 'time-parse'"));
+}
+
+#[test]
+fn time_now_reads_the_clock() {
+    let mut env = env();
+    env.set_clock(Box::new(|| 951_825_845_000));
+    assert_eq!(eval_in(&mut env, "(time-now)"), "(2000 2 29 12 4 5)");
+    assert_eq!(eval_in(&mut env, &format!("(time-format (time-now) {FULL})")), "2000-02-29 12:04:05");
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use yal::RuntimeError;
+
+/// The error evaluating `src` in `env` fails with.
+fn runtime_err(env: &mut yal::Environment, src: &str) -> RuntimeError {
+    match env.eval_str(src) {
+        Err(yal::EvalError::Runtime { error, .. }) => *error,
+        Err(err) => panic!("evaluating {:?} didn't fail at runtime: {}", src, err),
+        Ok(val) => panic!("evaluating {:?} gave {} instead of failing", src, val),
+    }
+}
+
+#[test]
+fn errors_say_which_calls_they_were_raised_in() {
+    let src = "
+        (let 'helper (fn '(x) '(+ x 'a)))
+        (let 'fact (fn '(n) '(helper n)))
+        (fact 1)";
+    let err = runtime_err(&mut env(), src);
+    assert_eq!(err.trace().unwrap(), ["fact", "helper", "+"]);
+    assert!(err.to_string().starts_with("in 'fact' → in 'helper' → in '+' → error: expected two numbers"), "{err}");
+    let report = eval_err(src);
+    assert!(report.contains("note: in 'fact' → 'helper' → '+'"), "{report}");
+}
+
+#[test]
+fn the_call_stack_is_empty_again_after_an_error() {
+    let mut env = env();
+    eval_in(&mut env, "(let 'f (fn '(n) '(if (= n 0) '(car 1) '(f (- n 1)))))");
+    runtime_err(&mut env, "(unwind-protect '(f 2) 'nil)");
+    let err = runtime_err(&mut env, "(car 2)");
+    assert_eq!(err.trace().unwrap(), ["car"]);
+    assert_eq!(eval_in(&mut env, "(+ 1 2)"), "3");
+}
+
+#[test]
+fn recursion_is_shown_once_with_how_many_times_it_repeats() {
+    let src = "(let 'cnt (fn '(n) '(if (= n 0) '(car 1) '(+ 1 (cnt (- n 1)))))) (cnt 100)";
+    let report = with_stack(64 << 20, move || eval_err(src));
+    assert!(report.contains("note: in ['cnt' → 'if'] × 101 → 'car'"), "{report}");
+
+    let src = "(let 'f (fn '(n) '(if (= n 0) '(car 1) '(f (- n 1))))) (f 100)";
+    let (len, message) = with_stack(64 << 20, move || {
+        let err = runtime_err(&mut env(), src);
+        (err.trace().unwrap().len(), err.to_string())
+    });
+    assert_eq!(len, 2 * 101 + 1);
+    assert!(message.starts_with("in ['f' → 'if'] × 101 → in 'car' → error:"), "{message}");
+}
+
+#[test]
+fn a_call_repeated_alone_is_shown_once() {
+    let src = "
+        (let 'down (fn '(n) '(if (= n 0) '(car 1) '(down (- n 1)))))
+        (let 'twice (fn '(n) '(if (= n 0) '(down 0) '(twice (- n 1)))))
+        (twice 5)";
+    let report = eval_err(src);
+    assert!(report.contains("note: in ['twice' → 'if'] × 6 → 'down' → 'if' → 'car'"), "{report}");
+}
+
+#[test]
+fn long_traces_keep_only_both_ends() {
+    // Twenty-six functions calling each other in turn, never repeating.
+    let names: Vec<String> = (b'a'..=b'z').map(|c| format!("to-{}", c as char)).collect();
+    let mut src = String::from("(let 'to-z (fn '() '(car 1)))\n");
+    for pair in names.windows(2) {
+        src.push_str(&format!("(let '{} (fn '() '({})))\n", pair[0], pair[1]));
+    }
+    src.push_str("(to-a)");
+    let report = eval_err(&src);
+    let note = report.lines().find(|line| line.contains("note: in")).unwrap();
+    assert!(note.contains("in 'to-a' → 'to-b'"), "{note}");
+    assert!(note.contains("'to-j' → ... 7 more ... → 'to-r'"), "{note}");
+    assert!(note.ends_with("'to-z' → 'car'"), "{note}");
+}
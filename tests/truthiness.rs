@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use yal::{ Environment, Reader, RefVal, Value };
+
+/// Registers `owned-false`, which gives a false that isn't the shared one.
+fn with_owned_false(env: &mut Environment) {
+    env.register_external_fun("owned-false", 0, |_| Ok(RefVal::owned(Value::Bool(false))));
+}
+
+#[test]
+fn an_owned_false_is_false() {
+    let mut env = env();
+    with_owned_false(&mut env);
+    assert_eq!(eval_in(&mut env, "(if (owned-false) ''yes ''no)"), "no");
+    assert_eq!(eval_in(&mut env, "(= (owned-false) (= 1 2))"), "t");
+}
+
+#[test]
+fn a_false_taken_out_of_a_list_is_false() {
+    assert_eq!(eval("(if (car '(false)) ''yes ''no)"), "no");
+    assert_eq!(eval("(if (car '(f)) ''yes ''no)"), "no");
+    assert_eq!(eval("(if (car '(true)) ''yes ''no)"), "yes");
+}
+
+#[test]
+fn a_freshly_parsed_false_is_false() {
+    let mut env = env();
+    let cond = Reader::new("'false").parse_sexpr().map_err(|err| err.to_string()).unwrap();
+    let val = yal::evaluate(&cond, &mut env).unwrap();
+    assert!(!val.is_truthy());
+    assert_eq!(eval_in(&mut env, "(if 'false ''yes ''no)"), "no");
+}
+
+#[test]
+fn nil_and_the_empty_list_are_false() {
+    assert_eq!(eval("(if nil ''yes ''no)"), "no");
+    assert_eq!(eval("(if '() ''yes ''no)"), "no");
+    assert_eq!(eval("(if (cdr '(1)) ''yes ''no)"), "no");
+}
+
+#[test]
+fn everything_else_is_true() {
+    for cond in ["0", "0.0", "\"\"", "'(f)", "'x", "t"] {
+        assert_eq!(eval(&format!("(if {} ''yes ''no)", cond)), "yes", "{}", cond);
+    }
+}
+
+#[test]
+fn comparisons_give_bools() {
+    assert!(matches!(&*env().eval_str("(= 1 1)").unwrap(), Value::Bool(true)));
+    assert!(matches!(&*env().eval_str("(equal? '(1) '(2))").unwrap(), Value::Bool(false)));
+    // A bool is not the symbol it prints as.
+    assert_eq!(eval("(= (= 1 2) 'f)"), "f");
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::*;
+
+/// The warnings `--warn-unused` gives for `path`, without the file name and
+/// the `warning: `.
+fn warnings(path: &str) -> Vec<String> {
+    let (status, _, stderr) = yal(&["--warn-unused", path]);
+    assert_eq!(status, 0, "{stderr}");
+    let prefix = format!("{}:", path);
+    stderr
+        .lines()
+        .map(|line| line.strip_prefix(&prefix).unwrap_or(line).replacen("warning: ", "", 1))
+        .collect()
+}
+
+#[test]
+fn warn_unused_reports_what_the_fixtures_expect() {
+    for path in fixtures("unused") {
+        assert_eq!(warnings(path.to_str().unwrap()), expected_diagnostics(&path), "in {}", path.display());
+    }
+}
+
+#[test]
+fn the_examples_have_nothing_unused() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "yal") {
+            let (_, _, stderr) = yal(&["--check", path.to_str().unwrap()]);
+            assert!(!stderr.contains("never used"), "{stderr}");
+        }
+    }
+}
+
+#[test]
+fn check_includes_the_warnings() {
+    let (status, _, stderr) = yal(&["--check", "tests/fixtures/unused/shadowing.yal"]);
+    assert_eq!(status, 0);
+    assert!(stderr.contains("shadowing.yal:4:19: warning: parameter 'x' is never used"), "{stderr}");
+}
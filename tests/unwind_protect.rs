@@ -0,0 +1,58 @@
+//! `unwind-protect`, whose cleanup runs however its body ends: with a value,
+//! with an error, or with a `return` out of it.
+
+mod common;
+
+use common::*;
+
+/// What evaluating `src` gives, or the error it fails with, along with
+/// what it printed.
+fn outcome(src: &str) -> (Result<String, String>, String) {
+    env().capture_output(|env| env.eval_str(src).map(|val| val.to_string()).map_err(|err| err.to_string()))
+}
+
+#[test]
+fn the_cleanup_runs_after_a_normal_exit() {
+    let (result, printed) = outcome("(unwind-protect '(+ 1 2) '(print 'cleanup))");
+    assert_eq!(result.unwrap(), "3");
+    assert_eq!(printed, "cleanup");
+}
+
+#[test]
+fn the_cleanup_runs_after_an_error_which_is_raised_again() {
+    let (result, printed) = outcome("(unwind-protect '(car 1) '(print 'cleanup))");
+    let err = result.unwrap_err();
+    assert!(err.starts_with("error: expected a list in 'car', got int `1`"), "{err}");
+    assert_eq!(printed, "cleanup");
+}
+
+#[test]
+fn the_cleanup_runs_after_a_return() {
+    let (result, printed) = outcome("(let 'f (fn '() '(+ 1 (unwind-protect '(return 1) '(print 'cleanup))))) (f)");
+    assert_eq!(result.unwrap(), "1");
+    assert_eq!(printed, "cleanup");
+}
+
+#[test]
+fn nested_cleanups_run_inner_to_outer() {
+    let src = "(unwind-protect '(unwind-protect '(car 1) '(print 'inner)) '(print 'outer))";
+    let (result, printed) = outcome(src);
+    assert!(result.is_err());
+    assert_eq!(printed, "innerouter");
+
+    let src = "(let 'f (fn '() '(unwind-protect '(unwind-protect '(return 1) '(print 'inner)) '(print 'outer)))) (f)";
+    let (result, printed) = outcome(src);
+    assert_eq!(result.unwrap(), "1");
+    assert_eq!(printed, "innerouter");
+}
+
+#[test]
+fn a_failing_cleanup_replaces_the_error_and_mentions_it() {
+    let err = eval_err("(unwind-protect '(car 1) '(cdr 2))");
+    assert!(err.starts_with(
+        "error: expected a list in 'cdr', got int `2` \
+         (in the cleanup, which suppressed the earlier error: expected a list in 'car', got int `1`)"
+    ), "{err}");
+    let err = eval_err("(unwind-protect '(+ 1 2) '(cdr 2))");
+    assert!(err.starts_with("error: expected a list in 'cdr', got int `2`\n"), "{err}");
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn the_vm_runs_the_fixtures_like_the_tree_walker() {
+    for path in fixtures("programs") {
+        let path = path.to_str().unwrap();
+        let walked = yal(&[path]);
+        let compiled = yal(&["--vm", path]);
+        assert_eq!(walked, compiled, "running {}", path);
+    }
+}
+
+#[test]
+fn the_vm_gives_the_same_values_when_embedded() {
+    // The fixtures recurse deeper than test threads have stack for.
+    with_stack(64 << 20, || {
+        for path in fixtures("programs") {
+            let src = std::fs::read_to_string(&path).unwrap();
+            let run = |use_vm| {
+                let mut env = env();
+                env.set_use_vm(use_vm);
+                let (result, printed) = env.capture_output(|env| env.eval_str(&src).map(|val| val.to_string()));
+                (result.map_err(|err| err.to_string()), printed)
+            };
+            assert_eq!(run(false), run(true), "running {}", path.display());
+        }
+    });
+}
+
+/// What `src` gives in the VM, checked against the tree-walker.
+fn in_both(src: &str) -> String {
+    let run = |use_vm| {
+        let mut env = env();
+        env.set_use_vm(use_vm);
+        eval_in(&mut env, src)
+    };
+    let compiled = run(true);
+    assert_eq!(run(false), compiled, "evaluating {src:?}");
+    compiled
+}
+
+#[test]
+fn if_is_only_compiled_to_jumps_when_it_is_the_builtin() {
+    const LIST3: &str = "(let 'list3 (fn '(a b c) '(cons a (cons b (cons c '())))))";
+    assert_eq!(in_both(&format!("{LIST3} (let 'f (fn '(if) '(if 1 'a 'b))) (f list3)")), "(1 a b)");
+    assert_eq!(in_both(&format!("{LIST3} (let 'f (fn '() '(if 1 'a 'b))) (let 'if list3) (f)")), "(1 a b)");
+    assert_eq!(in_both("(let 'f (fn '(x) '(if x '1 '2))) (f f)"), "1");
+}
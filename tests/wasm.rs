@@ -0,0 +1,47 @@
+//! The JavaScript interface, run in Node with
+//! `wasm-pack test --node -- --features wasm`.
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+use yal::wasm::YalInterpreter;
+
+#[wasm_bindgen_test]
+fn arithmetic_gives_its_value() {
+    let mut yal = YalInterpreter::new().unwrap();
+    assert_eq!(yal.eval("(+ 1 (* 2 3))"), Ok("7".to_string()));
+    assert_eq!(yal.eval("(/ 1 3)"), Ok("1/3".to_string()));
+}
+
+#[wasm_bindgen_test]
+fn bindings_last_between_calls() {
+    let mut yal = YalInterpreter::new().unwrap();
+    yal.eval("(let 'square (fn '(x) '(* x x)))").unwrap();
+    assert_eq!(yal.eval("(square 12)"), Ok("144".to_string()));
+}
+
+#[wasm_bindgen_test]
+fn parse_errors_are_messages() {
+    let mut yal = YalInterpreter::new().unwrap();
+    let err = yal.eval("(+ 1").unwrap_err();
+    assert!(err.contains("expected a closing paren"), "{}", err);
+}
+
+#[wasm_bindgen_test]
+fn runtime_errors_are_located() {
+    let mut yal = YalInterpreter::new().unwrap();
+    let err = yal.eval("(car 1)").unwrap_err();
+    assert!(err.contains("expected a list in 'car'"), "{}", err);
+    assert!(err.contains("1:1"), "{}", err);
+}
+
+#[wasm_bindgen_test]
+fn printing_goes_to_the_callback() {
+    let mut yal = YalInterpreter::new().unwrap();
+    let callback = js_sys::Function::new_with_args("text", "globalThis.printed = (globalThis.printed || '') + text");
+    yal.set_print_callback(callback);
+    yal.eval("(print \"hello\") (print 42) (flush)").unwrap();
+    let printed = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("printed")).unwrap();
+    assert_eq!(printed.as_string().as_deref(), Some("hello42"));
+}